@@ -1,223 +1,2363 @@
 use std::fs;
-use std::path::Path;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
 
 use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Utc};
 
-use crate::cli::{Cli, Command, Profile};
+use crate::backup_manifest::{BackupManifest, find_backup};
+use crate::baseline::Baseline;
+use crate::check::{check_config, lint_skills, print_issues};
+use crate::cli::{
+    BackupCommand, Cli, Command, ConfigCommand, ConfigFormat, ExportFormat, GuardMode,
+    ImportSource, LinkType, LogAction, LogCommand, LogStatus, MappingArgs, OutputArgs, Profile,
+    SkillsCommand, TargetCommand,
+};
 use crate::config::{
-    ConfigFile, build_bootstrap_config, build_default_config, build_resolve_context, load_config,
+    CONFIG_VERSION, ConfigFile, DEFAULT_CONFIG_FILE, LoadedConfig, build_bootstrap_config,
+    build_default_config, build_resolve_context, config_layers, load_config, load_configs,
+    merge_resolved, parse_config, repo_roots, retain_targets, serialize_config,
+};
+use crate::config_edit::{RuleEdit, add_link, add_targets, remove_link};
+use crate::discover::discover_existing;
+use crate::edit::{open_in_editor, pick_source};
+use crate::engine::{
+    MappingFilter, RecordStream, annotate_content_match, apply_link, apply_repair, apply_unlink,
+    build_mappings, build_merged_mappings, build_prune_scope, check_protection, guard_unowned,
+    inspect_mapping, is_drifted, is_plain_file_name, plan_mapping, print_plan, print_report,
+    protect_mapping, prune_orphan, repair_inspected, skills_set_roots,
+};
+use crate::explain::explain;
+use crate::export::export_stow;
+use crate::hooks::{Stage, run_hooks};
+use crate::import::{adopt_symlinks, import_managed, is_symlink_to_source};
+use crate::journal::{Journal, JournalEntry};
+use crate::logging::Rotation;
+use crate::migrate::migrate_config;
+use crate::model::{
+    Issue, Mapping, MasterSync, REPORT_SCHEMA_VERSION, Record, Report, ResolveContext, Severity,
+    Status, Summary, Timer, Timing, run_id, timed,
+};
+use crate::pathing::{absolute_path, normalize_lexically, resolve_path};
+use crate::plan::{Action, Plan};
+use crate::progress::Progress;
+use crate::safe_fs::{BackupDir, DEFAULT_BACKUP_MIN_FREE, restore_backup, set_read_only};
+use crate::schema::{config_schema, report_schema};
+use crate::skills::{diff_skills, has_differences, print_skill_diffs};
+use crate::stat_cache;
+use crate::state::{ManagedTarget, RunLock, State, StateStore, default_state_dir};
+use crate::trace::{self, info};
+use crate::transaction::Transaction;
+use crate::update::self_update;
+use crate::vcs::{
+    COMMIT_GUARD_VERSION, Vcs, commit_guard_status, install_commit_guard, install_repair_hooks,
+    sync_master_repo, uninstall_commit_guard, update_git_excludes,
 };
-use crate::engine::{apply_link, apply_repair, build_mappings, inspect_mapping, print_report};
-use crate::model::{Report, ResolveContext, Summary};
-use crate::pathing::{absolute_path, resolve_path};
-use crate::vcs::install_commit_guard;
+use crate::vendors::{Vendor, vendors};
+use crate::watch;
 
 pub(crate) fn run(cli: Cli) -> Result<i32> {
-    let config_path = absolute_path(&cli.config)?;
+    trace::init(cli.verbose);
+    // The cache is process-wide; a second `run` in one process starts afresh.
+    stat_cache::clear();
+    if cli
+        .command
+        .output_args()
+        .is_some_and(|output| output.print_schema)
+    {
+        println!("{}", serde_json::to_string_pretty(&report_schema())?);
+        return Ok(0);
+    }
+    let config_paths = config_layers(&cli.config, !cli.no_user_config)
+        .iter()
+        .map(|path| absolute_path(path))
+        .collect::<Result<Vec<_>>>()?;
+    let state_store = open_state_store(cli.state_dir.as_deref())?;
+    let mutating = match &cli.command {
+        Command::Link { dry_run, .. }
+        | Command::Sync { dry_run, .. }
+        | Command::Repair { dry_run, .. }
+        | Command::Generate { dry_run, .. }
+        | Command::Unlink { dry_run, .. }
+        | Command::Bootstrap { dry_run, .. } => !dry_run,
+        Command::Import {
+            convert_symlinks,
+            dry_run,
+            ..
+        } => *convert_symlinks && !dry_run,
+        Command::Add { link, dry_run, .. }
+        | Command::Target {
+            action: TargetCommand::Add { link, dry_run, .. },
+        } => *link && !dry_run,
+        Command::Remove {
+            unlink, dry_run, ..
+        } => *unlink && !dry_run,
+        Command::Verify { fix, .. } => *fix,
+        Command::Apply { .. } | Command::Protect { .. } => true,
+        Command::Status { tui, .. } => *tui,
+        Command::Backup {
+            action: BackupCommand::Restore { .. },
+        } => true,
+        _ => false,
+    };
+    let _lock = if mutating {
+        lock_run(state_store.as_ref(), cli.no_lock, cli.wait)?
+    } else {
+        None
+    };
 
-    match cli.command {
-        Command::Init { force, profiles } => run_init(&config_path, force, profiles),
+    // `sync` is the master step, then a `link` reported under its name.
+    let mut link_command = "link";
+    let mut master = None;
+    let mut command = match cli.command {
+        Command::Sync {
+            force,
+            dry_run,
+            output,
+            backup_dir,
+            mapping,
+            no_prune,
+        } => {
+            master = sync_master(&load_configs(&config_paths)?, dry_run)?;
+            link_command = "sync";
+            Command::Link {
+                only_missing: false,
+                force: true,
+                owned_only: !force,
+                dry_run,
+                atomic: false,
+                output,
+                backup_dir,
+                mapping,
+                prune: !no_prune && state_store.is_some(),
+            }
+        }
+        command => command,
+    };
+    // Only the commands that link download URL sources; the others, the
+    // shell-prompt `status --porcelain` among them, read the cached copy.
+    if let Command::Link { mapping, .. }
+    | Command::Repair { mapping, .. }
+    | Command::Plan { mapping, .. }
+    | Command::Watch { mapping, .. }
+    | Command::Daemon { mapping, .. } = &mut command
+    {
+        mapping.fetch = true;
+    }
+
+    let timer = Timer::start();
+    match command {
+        Command::Init {
+            force,
+            profiles,
+            format,
+            from_existing,
+        } => run_init(
+            &single_config(&cli.config, "init")?,
+            force,
+            profiles,
+            format,
+            from_existing,
+        ),
+        Command::Import {
+            convert_symlinks: true,
+            dry_run,
+            ..
+        } => {
+            let mut configs = load_configs(&config_paths)?;
+            let progress = Progress::for_output(&OutputArgs::default());
+            let mut mappings = build_merged_mappings(&configs, &MappingArgs::default(), &progress)?;
+            // Untracked symlinks go into the layer with the highest precedence.
+            let layer = configs
+                .last_mut()
+                .ok_or_else(|| anyhow!("import --convert-symlinks needs a config"))?;
+            let adopted = adopt_symlinks(&mut layer.config, &layer.ctx, &mappings);
+            for (target, source) in &adopted {
+                println!("adopted: {} -> {}", target.display(), source.display());
+            }
+            if !adopted.is_empty() {
+                if !dry_run {
+                    // The loaded layer has its `[hosts]` section folded in;
+                    // the file keeps it as written.
+                    let format = ConfigFormat::from_path(&layer.path);
+                    let mut file = fs::read_to_string(&layer.path)
+                        .map_err(anyhow::Error::from)
+                        .and_then(|text| parse_config(&text, format))
+                        .with_context(|| {
+                            format!("failed to read config: {}", layer.path.display())
+                        })?;
+                    adopt_symlinks(&mut file, &layer.ctx, &mappings);
+                    let text =
+                        serialize_config(&file, format).context("failed to serialize config")?;
+                    fs::write(&layer.path, text).with_context(|| {
+                        format!("failed to write config file: {}", layer.path.display())
+                    })?;
+                    println!("updated config: {}", layer.path.display());
+                }
+                mappings = build_merged_mappings(&configs, &MappingArgs::default(), &progress)?;
+            }
+            mappings.retain(is_symlink_to_source);
+            let records = mappings
+                .iter()
+                .map(|mapping| apply_link(mapping, true, false, dry_run, None, None))
+                .collect::<Vec<_>>();
+            if !dry_run {
+                update_state(state_store.as_ref(), &mappings, &records, &[]);
+                journal_records(&configs, state_store.as_ref(), "import", &records);
+            }
+            let report = Report {
+                schema_version: REPORT_SCHEMA_VERSION,
+                command: "import".to_owned(),
+                run_id: run_id(),
+                master: None,
+                started_at: timer.started_at,
+                elapsed_ms: timer.elapsed_ms(),
+                timing: Timing::take(&records),
+                summary: Summary::from_records(&records),
+                records,
+            };
+            print_report(&report, &OutputArgs::default(), cli.verbose > 0)?;
+            Ok(exit_code(&report.summary, false))
+        }
+        Command::Import {
+            from,
+            path,
+            force,
+            format,
+            dry_run,
+            ..
+        } => run_import(
+            &single_config(&cli.config, "import")?,
+            from.ok_or_else(|| anyhow!("import needs --from or --convert-symlinks"))?,
+            &path.ok_or_else(|| anyhow!("import --from needs a path"))?,
+            force,
+            format,
+            dry_run,
+        ),
         Command::Link {
             only_missing,
             force,
+            owned_only,
             dry_run,
-            json,
+            atomic,
+            output,
             backup_dir,
+            mapping,
+            prune,
         } => {
-            let (config, ctx) = load_config(&config_path)?;
-            let backup_dir = resolve_backup_dir(backup_dir.as_deref())?;
-            let mappings = build_mappings(&config, &ctx, cli.verbose)?;
-            let records = mappings
+            let configs = load_configs(&config_paths)?;
+            if !dry_run {
+                run_hooks(&configs, Stage::PreLink, link_command, None)?;
+            }
+            let backup_dir =
+                resolve_backup_dir(backup_dir.as_deref(), cli.backup_min_free, &config_paths)?;
+            let progress = Progress::for_output(&output);
+            let mappings = build_merged_mappings(&configs, &mapping, &progress)?;
+            let ownership = load_ownership(state_store.as_ref(), force && owned_only)?;
+            let transaction = atomic.then(Transaction::default);
+            let stream = RecordStream::new(&output);
+            progress.start("link", Some(mappings.len()));
+            let mut records = mappings
                 .iter()
                 .map(|mapping| {
-                    apply_link(mapping, force, only_missing, dry_run, backup_dir.as_deref())
+                    timed(|| {
+                        ownership
+                            .as_ref()
+                            .and_then(|state| guard_unowned(mapping, state.owns(&mapping.target)))
+                            .unwrap_or_else(|| {
+                                apply_link(
+                                    mapping,
+                                    force,
+                                    only_missing,
+                                    dry_run,
+                                    backup_dir.as_ref(),
+                                    transaction.as_ref(),
+                                )
+                            })
+                    })
+                })
+                .inspect(|record| {
+                    stream.emit(record);
+                    progress.tick();
                 })
                 .collect::<Vec<_>>();
+            let mut pruned = if prune {
+                prune_candidates(state_store.as_ref(), &configs, &mappings, &mapping)?
+                    .iter()
+                    .map(|(target, entry)| {
+                        timed(|| prune_orphan(target, entry, dry_run, transaction.as_ref()))
+                    })
+                    .inspect(|record| {
+                        stream.emit(record);
+                        progress.tick();
+                    })
+                    .collect::<Vec<_>>()
+            } else {
+                Vec::new()
+            };
+            if let Some(transaction) = transaction {
+                transaction.finish(records.iter_mut().chain(pruned.iter_mut()));
+            }
+            if !dry_run {
+                update_state(state_store.as_ref(), &mappings, &records, &pruned);
+                journal_records(&configs, state_store.as_ref(), link_command, &records);
+                journal_records(&configs, state_store.as_ref(), link_command, &pruned);
+            }
+            records.extend(pruned);
             let report = Report {
-                command: "link".to_owned(),
+                schema_version: REPORT_SCHEMA_VERSION,
+                command: link_command.to_owned(),
+                run_id: run_id(),
+                master,
+                started_at: timer.started_at,
+                elapsed_ms: timer.elapsed_ms(),
+                timing: Timing::take(&records),
                 summary: Summary::from_records(&records),
                 records,
             };
-            print_report(&report, json, cli.verbose)?;
+            progress.finish();
+            print_report(&report, &output, cli.verbose > 0)?;
+            if !dry_run {
+                run_post_hooks(&configs, Stage::PostLink, &report);
+            }
             Ok(exit_code(&report.summary, false))
         }
-        Command::Verify { json } => {
-            let (config, ctx) = load_config(&config_path)?;
-            let mappings = build_mappings(&config, &ctx, cli.verbose)?;
-            let records = mappings.iter().map(inspect_mapping).collect::<Vec<_>>();
+        Command::Plan {
+            out,
+            only_missing,
+            force,
+            prune,
+            output,
+            mapping,
+        } => run_plan(
+            &config_paths,
+            &out,
+            only_missing,
+            force,
+            prune,
+            &output,
+            &mapping,
+            state_store.as_ref(),
+            cli.verbose > 0,
+        ),
+        Command::Apply {
+            plan,
+            backup_dir,
+            output,
+        } => run_apply(
+            &plan,
+            resolve_backup_dir(backup_dir.as_deref(), cli.backup_min_free, &config_paths)?.as_ref(),
+            &output,
+            state_store.as_ref(),
+            cli.verbose > 0,
+        ),
+        Command::Verify {
+            hash,
+            fix,
+            force,
+            backup_dir,
+            baseline,
+            update_baseline,
+            output,
+            mapping,
+        } => {
+            let mut baseline = baseline.as_deref().map(Baseline::load).transpose()?;
+            let configs = load_configs(&config_paths)?;
+            let backup_dir =
+                resolve_backup_dir(backup_dir.as_deref(), cli.backup_min_free, &config_paths)?;
+            let progress = Progress::for_output(&output);
+            let mappings = build_merged_mappings(&configs, &mapping, &progress)?;
+            let stream = RecordStream::new(&output);
+            progress.start("verify", Some(mappings.len()));
+            let mut records = mappings
+                .iter()
+                .map(|mapping| {
+                    timed(|| {
+                        let mut record = check_protection(mapping, inspect_mapping(mapping), fix);
+                        let fixable = match record.status {
+                            Status::Missing | Status::Broken => true,
+                            Status::Conflict => force,
+                            _ => false,
+                        };
+                        if fix && fixable {
+                            record = repair_inspected(
+                                mapping,
+                                record,
+                                force,
+                                false,
+                                backup_dir.as_ref(),
+                                None,
+                            );
+                        }
+                        if let Some(baseline) = baseline.as_ref().filter(|_| !update_baseline) {
+                            baseline.accept(&mut record);
+                        }
+                        if hash {
+                            record = annotate_content_match(mapping, record);
+                        }
+                        record
+                    })
+                })
+                .inspect(|record| {
+                    stream.emit(record);
+                    progress.tick();
+                })
+                .collect::<Vec<_>>();
+            if fix {
+                update_state(state_store.as_ref(), &mappings, &records, &[]);
+                journal_records(&configs, state_store.as_ref(), "verify", &records);
+            }
+            if update_baseline && let Some(current) = &baseline {
+                let count = current.save(&records)?;
+                println!("baseline: accepted {count} target(s)");
+                let updated = Baseline::load(current.path())?;
+                for record in &mut records {
+                    updated.accept(record);
+                }
+                baseline = Some(updated);
+            }
+            if let Some(baseline) = &baseline {
+                baseline.report_unused(&records);
+            }
             let report = Report {
+                schema_version: REPORT_SCHEMA_VERSION,
                 command: "verify".to_owned(),
+                run_id: run_id(),
+                master: None,
+                started_at: timer.started_at,
+                elapsed_ms: timer.elapsed_ms(),
+                timing: Timing::take(&records),
                 summary: Summary::from_records(&records),
                 records,
             };
-            print_report(&report, json, true)?;
+            progress.finish();
+            print_report(&report, &output, true)?;
             Ok(exit_code(&report.summary, true))
         }
+        Command::Edit { source, output } => run_edit(
+            &config_paths,
+            source.as_deref(),
+            &output,
+            state_store.as_ref(),
+            cli.no_lock,
+            cli.wait,
+            cli.verbose > 0,
+        ),
         Command::Repair {
             force,
+            owned_only,
             dry_run,
-            json,
+            atomic,
+            output,
             backup_dir,
+            mapping,
         } => {
-            let (config, ctx) = load_config(&config_path)?;
-            let backup_dir = resolve_backup_dir(backup_dir.as_deref())?;
-            let mappings = build_mappings(&config, &ctx, cli.verbose)?;
-            let records = mappings
+            let configs = load_configs(&config_paths)?;
+            let backup_dir =
+                resolve_backup_dir(backup_dir.as_deref(), cli.backup_min_free, &config_paths)?;
+            let progress = Progress::for_output(&output);
+            let mappings = build_merged_mappings(&configs, &mapping, &progress)?;
+            let ownership = load_ownership(state_store.as_ref(), owned_only)?;
+            let transaction = atomic.then(Transaction::default);
+            let stream = RecordStream::new(&output);
+            progress.start("repair", Some(mappings.len()));
+            let mut records = mappings
                 .iter()
-                .map(|mapping| apply_repair(mapping, force, dry_run, backup_dir.as_deref()))
+                .map(|mapping| {
+                    timed(|| {
+                        ownership
+                            .as_ref()
+                            .and_then(|state| guard_unowned(mapping, state.owns(&mapping.target)))
+                            .unwrap_or_else(|| {
+                                apply_repair(
+                                    mapping,
+                                    force,
+                                    dry_run,
+                                    backup_dir.as_ref(),
+                                    transaction.as_ref(),
+                                )
+                            })
+                    })
+                })
+                .inspect(|record| {
+                    stream.emit(record);
+                    progress.tick();
+                })
                 .collect::<Vec<_>>();
+            if let Some(transaction) = transaction {
+                transaction.finish(&mut records);
+            }
+            if !dry_run {
+                update_state(state_store.as_ref(), &mappings, &records, &[]);
+                journal_records(&configs, state_store.as_ref(), "repair", &records);
+            }
             let report = Report {
+                schema_version: REPORT_SCHEMA_VERSION,
                 command: "repair".to_owned(),
+                run_id: run_id(),
+                master: None,
+                started_at: timer.started_at,
+                elapsed_ms: timer.elapsed_ms(),
+                timing: Timing::take(&records),
                 summary: Summary::from_records(&records),
                 records,
             };
-            print_report(&report, json, cli.verbose)?;
+            progress.finish();
+            print_report(&report, &output, cli.verbose > 0)?;
+            if !dry_run {
+                run_post_hooks(&configs, Stage::PostRepair, &report);
+            }
             Ok(exit_code(&report.summary, true))
         }
-        Command::Status { json } => {
-            let (config, ctx) = load_config(&config_path)?;
-            let mappings = build_mappings(&config, &ctx, cli.verbose)?;
-            let records = mappings.iter().map(inspect_mapping).collect::<Vec<_>>();
+        Command::Generate {
+            force,
+            dry_run,
+            output,
+            backup_dir,
+            mapping,
+        } => {
+            let configs = load_configs(&config_paths)?;
+            let backup_dir =
+                resolve_backup_dir(backup_dir.as_deref(), cli.backup_min_free, &config_paths)?;
+            let progress = Progress::for_output(&output);
+            let mut mappings = build_merged_mappings(&configs, &mapping, &progress)?;
+            mappings.retain(|mapping| mapping.link_type == LinkType::Generate);
+            let stream = RecordStream::new(&output);
+            progress.start("generate", Some(mappings.len()));
+            let records = mappings
+                .iter()
+                .map(|mapping| {
+                    timed(|| apply_repair(mapping, force, dry_run, backup_dir.as_ref(), None))
+                })
+                .inspect(|record| {
+                    stream.emit(record);
+                    progress.tick();
+                })
+                .collect::<Vec<_>>();
+            if !dry_run {
+                update_state(state_store.as_ref(), &mappings, &records, &[]);
+                journal_records(&configs, state_store.as_ref(), "generate", &records);
+            }
+            let report = Report {
+                schema_version: REPORT_SCHEMA_VERSION,
+                command: "generate".to_owned(),
+                run_id: run_id(),
+                master: None,
+                started_at: timer.started_at,
+                elapsed_ms: timer.elapsed_ms(),
+                timing: Timing::take(&records),
+                summary: Summary::from_records(&records),
+                records,
+            };
+            progress.finish();
+            print_report(&report, &output, cli.verbose > 0)?;
+            Ok(exit_code(&report.summary, true))
+        }
+        Command::Explain { path, mapping } => {
+            let configs = load_configs(&config_paths)?;
+            let mappings = build_merged_mappings(&configs, &mapping, &Progress::disabled())?;
+            explain(&configs, &mappings, &mapping, &path)
+        }
+        Command::List { json, mapping } => {
+            let configs = load_configs(&config_paths)?;
+            let mappings = build_merged_mappings(&configs, &mapping, &Progress::disabled())?;
+            let entries = mappings.iter().map(plan_mapping).collect::<Vec<_>>();
+            print_plan(&entries, json)?;
+            Ok(0)
+        }
+        Command::Status {
+            output,
+            mapping,
+            since,
+            dump_config,
+            tui,
+            porcelain,
+        } => {
+            let configs = load_configs(&config_paths)?;
+            if dump_config {
+                print_merged_config(&configs, output.json)?;
+                return Ok(0);
+            }
+            let progress = if porcelain {
+                Progress::disabled()
+            } else {
+                Progress::for_output(&output)
+            };
+            let mut mappings = build_merged_mappings(&configs, &mapping, &progress)?;
+            if let Some(since) = since {
+                let state = load_ownership(state_store.as_ref(), true)?.unwrap_or_default();
+                mappings.retain(|mapping| state.linked_since(&mapping.target, since));
+            }
+            if porcelain {
+                let drifted = mappings
+                    .iter()
+                    .filter(|mapping| is_drifted(mapping))
+                    .count();
+                if drifted == 0 {
+                    println!("prompt-sync: ok");
+                    return Ok(0);
+                }
+                println!("prompt-sync: {drifted} drifted");
+                return Ok(1);
+            }
+            if tui {
+                run_dashboard(&configs, &mapping, &mappings, state_store.as_ref())?;
+                return Ok(0);
+            }
+            let stream = RecordStream::new(&output);
+            progress.start("status", Some(mappings.len()));
+            let records = mappings
+                .iter()
+                .map(|mapping| timed(|| inspect_mapping(mapping)))
+                .inspect(|record| {
+                    stream.emit(record);
+                    progress.tick();
+                })
+                .collect::<Vec<_>>();
             let report = Report {
+                schema_version: REPORT_SCHEMA_VERSION,
                 command: "status".to_owned(),
+                run_id: run_id(),
+                master: None,
+                started_at: timer.started_at,
+                elapsed_ms: timer.elapsed_ms(),
+                timing: Timing::take(&records),
                 summary: Summary::from_records(&records),
                 records,
             };
-            print_report(&report, json, false)?;
+            progress.finish();
+            print_report(&report, &output, false)?;
             Ok(exit_code(&report.summary, true))
         }
+        Command::Protect { unprotect, mapping } => {
+            let configs = load_configs(&config_paths)?;
+            let mappings = build_merged_mappings(&configs, &mapping, &Progress::disabled())?;
+            let label = if unprotect { "writable" } else { "read-only" };
+            let mut failed = false;
+            for mapping in &mappings {
+                match protect_mapping(mapping, !unprotect) {
+                    Ok(changed) => {
+                        for path in changed {
+                            println!("{label}: {}", path.display());
+                        }
+                    }
+                    Err(err) => {
+                        eprintln!("error: {err:#}");
+                        failed = true;
+                    }
+                }
+            }
+            Ok(if failed { 2 } else { 0 })
+        }
+        Command::Unlink {
+            dry_run,
+            output,
+            mapping,
+        } => {
+            let configs = load_configs(&config_paths)?;
+            let progress = Progress::for_output(&output);
+            let mappings = build_merged_mappings(&configs, &mapping, &progress)?;
+            progress.start("unlink", Some(mappings.len()));
+            let records = mappings
+                .iter()
+                .map(|mapping| timed(|| apply_unlink(mapping, dry_run)))
+                .inspect(|_| progress.tick())
+                .collect::<Vec<_>>();
+            if !dry_run {
+                update_state(state_store.as_ref(), &mappings, &records, &[]);
+                journal_records(&configs, state_store.as_ref(), "unlink", &records);
+            }
+            let report = Report {
+                schema_version: REPORT_SCHEMA_VERSION,
+                command: "unlink".to_owned(),
+                run_id: run_id(),
+                master: None,
+                started_at: timer.started_at,
+                elapsed_ms: timer.elapsed_ms(),
+                timing: Timing::take(&records),
+                summary: Summary::from_records(&records),
+                records,
+            };
+            progress.finish();
+            print_report(&report, &output, cli.verbose > 0)?;
+            Ok(exit_code(&report.summary, false))
+        }
+        Command::Add {
+            source,
+            targets,
+            link_type,
+            link,
+            dry_run,
+        } => {
+            let (path, text, ctx) = editable_config(&config_paths, "add", true)?;
+            let (edited, edit) = add_link(&text, &source, &targets, link_type, &ctx)
+                .with_context(|| format!("failed to edit config: {}", path.display()))?;
+            if dry_run {
+                print!("{edited}");
+                return Ok(0);
+            }
+            finish_add(
+                "add",
+                &config_paths,
+                &path,
+                &edited,
+                &edit,
+                &targets,
+                link,
+                state_store.as_ref(),
+                &timer,
+                cli.verbose > 0,
+            )
+        }
+        Command::Target {
+            action:
+                TargetCommand::Add {
+                    source,
+                    targets,
+                    link,
+                    dry_run,
+                },
+        } => {
+            let (path, text, ctx) = editable_config(&config_paths, "target add", false)?;
+            let (edited, edit) = add_targets(&text, &source, &targets, &ctx)
+                .with_context(|| format!("failed to edit config: {}", path.display()))?;
+            // Only problems the new targets bring in refuse the edit.
+            let issues = |text: &str| -> Result<Vec<(String, String)>> {
+                let config = parse_config(text, ConfigFormat::Toml)?;
+                Ok(check_config(&config, &ctx)
+                    .into_iter()
+                    .filter(|issue| issue.severity == Severity::Error)
+                    .map(|issue| (issue.rule, issue.message))
+                    .collect())
+            };
+            let before = issues(&text)?;
+            let introduced = issues(&edited)?
+                .into_iter()
+                .filter(|issue| !before.contains(issue))
+                .collect::<Vec<_>>();
+            if !introduced.is_empty() {
+                for (rule, message) in &introduced {
+                    eprintln!("error: {rule}: {message}");
+                }
+                return Err(anyhow!(
+                    "not adding targets that fail `config check`: {}",
+                    path.display()
+                ));
+            }
+            if dry_run {
+                print!("{edited}");
+                return Ok(0);
+            }
+            finish_add(
+                "target add",
+                &config_paths,
+                &path,
+                &edited,
+                &edit,
+                &targets,
+                link,
+                state_store.as_ref(),
+                &timer,
+                cli.verbose > 0,
+            )
+        }
+        Command::Remove {
+            source,
+            targets,
+            unlink,
+            dry_run,
+        } => {
+            let (path, text, ctx) = editable_config(&config_paths, "remove", false)?;
+            let (edited, edits) = remove_link(&text, &source, &targets, &ctx)
+                .with_context(|| format!("failed to edit config: {}", path.display()))?;
+            if dry_run {
+                print!("{edited}");
+                return Ok(0);
+            }
+            // The mappings to unlink come from the config before the edit.
+            let unlinked = if unlink {
+                let configs = load_configs(&config_paths)?;
+                let mut mappings = build_merged_mappings(
+                    &configs,
+                    &MappingArgs::default(),
+                    &Progress::disabled(),
+                )?;
+                mappings.retain(|mapping| is_edited(mapping, &path, &edits));
+                Some((configs, mappings))
+            } else {
+                None
+            };
+            for edit in &edits {
+                if edit.whole_rule {
+                    println!("removed rule links[{}]: {}", edit.index, edit.source);
+                } else {
+                    for target in &edit.targets {
+                        println!("removed target from links[{}]: {target}", edit.index);
+                    }
+                }
+            }
+            write_edited_config(&path, &edited)?;
+            let Some((configs, mappings)) = unlinked else {
+                return Ok(0);
+            };
+
+            let records = mappings
+                .iter()
+                .map(|mapping| timed(|| apply_unlink(mapping, false)))
+                .collect::<Vec<_>>();
+            update_state(state_store.as_ref(), &mappings, &records, &[]);
+            journal_records(&configs, state_store.as_ref(), "remove", &records);
+            let report = Report {
+                schema_version: REPORT_SCHEMA_VERSION,
+                command: "remove".to_owned(),
+                run_id: run_id(),
+                master: None,
+                started_at: timer.started_at,
+                elapsed_ms: timer.elapsed_ms(),
+                timing: Timing::take(&records),
+                summary: Summary::from_records(&records),
+                records,
+            };
+            print_report(&report, &OutputArgs::default(), cli.verbose > 0)?;
+            Ok(exit_code(&report.summary, false))
+        }
+        Command::Watch {
+            interval,
+            max_passes,
+            force,
+            output,
+            backup_dir,
+            mapping,
+        } => run_watch(
+            &config_paths,
+            interval,
+            max_passes,
+            force,
+            &output,
+            resolve_backup_dir(backup_dir.as_deref(), cli.backup_min_free, &config_paths)?.as_ref(),
+            &mapping,
+            state_store.as_ref(),
+            cli.no_lock,
+            cli.verbose > 0,
+        ),
+        Command::Daemon {
+            interval,
+            once,
+            detach,
+            backup_dir,
+            mapping,
+        } => {
+            let backup_dir =
+                resolve_backup_dir(backup_dir.as_deref(), cli.backup_min_free, &config_paths)?;
+            if detach && detach_process()? {
+                return Ok(0);
+            }
+            run_daemon(
+                &config_paths,
+                interval,
+                once,
+                backup_dir.as_ref(),
+                &mapping,
+                state_store.as_ref(),
+                cli.no_lock,
+            )
+        }
         Command::Bootstrap {
             force,
             dry_run,
-            json,
+            output,
             write_config,
             backup_dir,
+            link_type,
+            profiles,
+            include,
+            exclude,
+            yes,
+            home_only,
+            repo_only,
         } => run_bootstrap(
-            &config_path,
+            &single_config(&cli.config, "bootstrap")?,
             force,
             dry_run,
-            json,
+            &output,
             write_config,
-            backup_dir.as_deref(),
-            cli.verbose,
+            resolve_backup_dir(backup_dir.as_deref(), cli.backup_min_free, &config_paths)?.as_ref(),
+            link_type,
+            &profiles,
+            &include,
+            &exclude,
+            yes,
+            home_only,
+            repo_only,
+            state_store.as_ref(),
+            cli.verbose > 0,
+        ),
+        Command::Skills {
+            action: SkillsCommand::Lint { json },
+        } => {
+            let configs = load_configs(&config_paths)?;
+            let issues = config_issues(&configs, lint_skills);
+            print_issues(&issues, json, "skills lint")?;
+            let has_error = issues.iter().any(|issue| issue.severity == Severity::Error);
+            Ok(i32::from(has_error))
+        }
+        Command::Skills {
+            action: SkillsCommand::Diff { json, mapping },
+        } => {
+            let configs = load_configs(&config_paths)?;
+            let diffs = diff_skills(&configs, &mapping)?;
+            print_skill_diffs(&diffs, json)?;
+            Ok(i32::from(has_differences(&diffs)))
+        }
+        Command::Config { action } => match action {
+            ConfigCommand::Check { json } => {
+                let configs = load_configs(&config_paths)?;
+                let issues = config_issues(&configs, check_config);
+                print_issues(&issues, json, "config check")?;
+                let has_error = issues.iter().any(|issue| issue.severity == Severity::Error);
+                Ok(i32::from(has_error))
+            }
+            ConfigCommand::Migrate { dry_run } => {
+                run_migrate(&single_config(&cli.config, "config migrate")?, dry_run)
+            }
+            ConfigCommand::Schema { output } => {
+                let text = serde_json::to_string_pretty(&config_schema())?;
+                match output {
+                    Some(path) => fs::write(&path, format!("{text}\n"))
+                        .with_context(|| format!("failed to write schema: {}", path.display()))?,
+                    None => println!("{text}"),
+                }
+                Ok(0)
+            }
+        },
+        Command::Backup { action } => match action {
+            BackupCommand::List { backup_dir, json } => {
+                run_backup_list(&absolute_path(&backup_dir)?, json)
+            }
+            BackupCommand::Restore {
+                target,
+                backup_dir,
+                at,
+            } => run_backup_restore(
+                // Only `[logging]` is read here, so a missing config is fine.
+                &load_configs(&config_paths).unwrap_or_default(),
+                &absolute_path(&backup_dir)?,
+                &target,
+                at,
+                state_store.as_ref(),
+            ),
+        },
+        Command::Log {
+            action:
+                LogCommand::Show {
+                    since,
+                    target,
+                    status,
+                    action,
+                    json,
+                },
+        } => {
+            let target = target.as_deref().map(absolute_path).transpose()?;
+            run_log_show(
+                &load_configs(&config_paths).unwrap_or_default(),
+                state_store.as_ref(),
+                &LogFilter {
+                    since,
+                    target,
+                    status,
+                    action,
+                },
+                json,
+            )
+        }
+        Command::InstallCommitGuard {
+            repo,
+            all_repos,
+            status: true,
+            ..
+        } => for_each_repo(
+            &guard_repos(&config_paths, &repo, all_repos)?,
+            run_commit_guard_status,
         ),
         Command::InstallCommitGuard {
             repo,
+            all_repos,
+            force,
+            chain,
+            mode,
+            uninstall,
+            status: false,
+            dry_run,
+        } => for_each_repo(&guard_repos(&config_paths, &repo, all_repos)?, |repo| {
+            run_install_commit_guard(repo, force, chain, mode, uninstall, dry_run)
+        }),
+        Command::InstallRepairHooks {
+            repo,
+            force,
+            dry_run,
+        } => run_install_repair_hooks(&repo, force, dry_run),
+        Command::Export {
+            format: ExportFormat::Stow,
+            dir,
+            package,
+            target_dir,
+            force,
+            dry_run,
+            mapping,
+        } => run_export_stow(
+            &load_configs(&config_paths)?,
+            &dir,
+            &package,
+            target_dir.as_deref(),
+            force,
+            dry_run,
+            &mapping,
+        ),
+        Command::SelfUpdate {
+            check,
+            version,
+            force,
+        } => self_update(check, version.as_deref(), force),
+        Command::Sync { .. } => unreachable!("sync runs as link"),
+    }
+}
+
+/// The `[master]` step of `sync`, skipped without a `repo`; URL sources
+/// are refetched by the link that follows, which also reloads the config.
+fn sync_master(configs: &[LoadedConfig], dry_run: bool) -> Result<Option<MasterSync>> {
+    let master = merge_resolved(configs).master.unwrap_or_default();
+    let Some(repo) = master.repo else {
+        return Ok(None);
+    };
+    let root = master
+        .root
+        .ok_or_else(|| anyhow!("[master] repo needs a root to clone into"))?;
+    sync_master_repo(&repo, Path::new(&root), master.branch.as_deref(), dry_run).map(Some)
+}
+
+fn run_backup_list(backup_dir: &Path, json: bool) -> Result<i32> {
+    let mut entries = BackupManifest::new(backup_dir).load()?;
+    entries.retain(|entry| entry.backup.exists());
+    if json {
+        let json_text =
+            serde_json::to_string_pretty(&entries).context("failed to serialize JSON")?;
+        println!("{json_text}");
+        return Ok(0);
+    }
+    if entries.is_empty() {
+        println!("no backups in {}", backup_dir.display());
+        return Ok(0);
+    }
+    for entry in &entries {
+        println!(
+            "{} {} -> {} ({} bytes)",
+            entry
+                .created_at
+                .to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            entry.original.display(),
+            entry.id(backup_dir),
+            entry.size,
+        );
+    }
+    Ok(0)
+}
+
+/// The `log show` filters; every given one must match.
+struct LogFilter {
+    since: Option<DateTime<Utc>>,
+    target: Option<PathBuf>,
+    status: Option<LogStatus>,
+    action: Option<LogAction>,
+}
+
+impl LogFilter {
+    fn matches(&self, entry: &JournalEntry) -> bool {
+        let failed = entry.action == "ERROR";
+        self.since.is_none_or(|since| entry.timestamp >= since)
+            && self
+                .target
+                .as_ref()
+                .is_none_or(|target| &entry.target == target)
+            && self.status.is_none_or(|status| match status {
+                LogStatus::Ok => !failed,
+                LogStatus::Failed => failed,
+            })
+            && self
+                .action
+                .is_none_or(|action| entry.action == action.journal_label())
+    }
+}
+
+fn run_log_show(
+    configs: &[LoadedConfig],
+    state_store: Option<&StateStore>,
+    filter: &LogFilter,
+    json: bool,
+) -> Result<i32> {
+    let journal = Journal::for_reading(configs, state_store.and_then(StateStore::dir))
+        .ok_or_else(|| anyhow!("no operation journal: set [logging] path or a state directory"))?;
+    let mut entries = journal.read()?;
+    entries.retain(|entry| filter.matches(entry));
+    if json {
+        let json_text =
+            serde_json::to_string_pretty(&entries).context("failed to serialize JSON")?;
+        println!("{json_text}");
+        return Ok(0);
+    }
+    if entries.is_empty() {
+        println!("no matching entries in {}", journal.path().display());
+        return Ok(0);
+    }
+
+    let rows = entries
+        .iter()
+        .map(|entry| {
+            [
+                entry
+                    .timestamp
+                    .to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+                entry.command.clone(),
+                entry.action.clone(),
+                entry.target.display().to_string(),
+                entry.message.clone().unwrap_or_default(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let header = ["TIMESTAMP", "COMMAND", "ACTION", "TARGET", "MESSAGE"].map(str::to_owned);
+    let mut widths = header.clone().map(|cell| cell.chars().count());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    for row in std::iter::once(&header).chain(&rows) {
+        let cells = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect::<Vec<_>>();
+        println!("{}", cells.join("  ").trim_end());
+    }
+    Ok(0)
+}
+
+fn run_backup_restore(
+    configs: &[LoadedConfig],
+    backup_dir: &Path,
+    target: &str,
+    at: Option<DateTime<Utc>>,
+    state_store: Option<&StateStore>,
+) -> Result<i32> {
+    let entries = BackupManifest::new(backup_dir).load()?;
+    let original = absolute_path(Path::new(target))?;
+    let entry = find_backup(&entries, backup_dir, target, &original, at).ok_or_else(|| {
+        anyhow!(
+            "no backup of {target} in {}{}",
+            backup_dir.display(),
+            at.map(|at| format!(" at or before {}", at.to_rfc3339()))
+                .unwrap_or_default()
+        )
+    })?;
+    restore_backup(entry)?;
+    if let Some(journal) = Journal::from_configs(configs, state_store.and_then(StateStore::dir)) {
+        journal.record_restore(entry);
+    }
+
+    // The restored file is the user's again, so prune must not touch it.
+    if let Some(store) = state_store {
+        let result = store.load().and_then(|mut state| {
+            state.forget(&entry.original);
+            store.save(&state)
+        });
+        if let Err(err) = result {
+            eprintln!("warn: failed to update state: {err:#}");
+        }
+    }
+    println!(
+        "restored {} from {}",
+        entry.original.display(),
+        entry.backup.display()
+    );
+    Ok(0)
+}
+
+fn run_migrate(config_path: &Path, dry_run: bool) -> Result<i32> {
+    let text = fs::read_to_string(config_path)
+        .with_context(|| format!("failed to read config: {}", config_path.display()))?;
+    let migration = migrate_config(&text, ConfigFormat::from_path(config_path))
+        .with_context(|| format!("failed to migrate config: {}", config_path.display()))?;
+
+    if migration.from_version == CONFIG_VERSION {
+        println!(
+            "config is already at version {CONFIG_VERSION}: {}",
+            config_path.display()
+        );
+        return Ok(0);
+    }
+    for change in &migration.changes {
+        println!("migrate: {change}");
+    }
+    if dry_run {
+        print!("{}", migration.text);
+        return Ok(0);
+    }
+
+    let mut backup = config_path.as_os_str().to_owned();
+    backup.push(format!(".v{}.bak", migration.from_version));
+    let backup = PathBuf::from(backup);
+    fs::copy(config_path, &backup)
+        .with_context(|| format!("failed to back up config to {}", backup.display()))?;
+    fs::write(config_path, &migration.text)
+        .with_context(|| format!("failed to write config file: {}", config_path.display()))?;
+
+    println!(
+        "migrated config to version {CONFIG_VERSION}: {} (backup: {})",
+        config_path.display(),
+        backup.display()
+    );
+    Ok(0)
+}
+
+fn run_init(
+    config_path: &Path,
+    force: bool,
+    profiles: Vec<Profile>,
+    format: Option<ConfigFormat>,
+    from_existing: bool,
+) -> Result<i32> {
+    let (config_path, format) = &new_config_path(config_path, format, force)?;
+
+    let config = if from_existing {
+        let ctx = build_resolve_context(config_path)?;
+        let discovery = discover_existing(&ctx, &profiles).ok_or_else(|| {
+            anyhow!("no existing instruction files found; run init without --from-existing")
+        })?;
+        println!(
+            "master: {} (most recently modified)",
+            discovery.master.display()
+        );
+        for path in &discovery.found {
+            println!("found: {}", path.display());
+        }
+        for path in &discovery.differing {
+            println!(
+                "note: {} differs from the master; `link --force` would replace it",
+                path.display()
+            );
+        }
+        discovery.config
+    } else {
+        build_default_config(&default_profiles(profiles))
+    };
+    write_new_config(config_path, *format, &config)?;
+    Ok(0)
+}
+
+fn run_import(
+    config_path: &Path,
+    from: ImportSource,
+    dir: &Path,
+    force: bool,
+    format: Option<ConfigFormat>,
+    dry_run: bool,
+) -> Result<i32> {
+    let (config_path, format) = &if dry_run {
+        let format = format.unwrap_or_else(|| ConfigFormat::from_path(config_path));
+        (config_path.to_path_buf(), format)
+    } else {
+        new_config_path(config_path, format, force)?
+    };
+    let ctx = build_resolve_context(config_path)?;
+    let imported = import_managed(from, dir, ctx.home_dir.as_deref())?;
+    for (source, target) in &imported.found {
+        println!("found: {} -> ~/{target}", source.display());
+    }
+    for (source, reason) in &imported.skipped {
+        eprintln!(
+            "warn: skipping {}: {reason}; link its rendered target instead",
+            source.display()
+        );
+    }
+    if imported.found.is_empty() {
+        return Err(anyhow!(
+            "no AI instruction files or skills found in {}",
+            dir.display()
+        ));
+    }
+    if dry_run {
+        print!(
+            "{}",
+            serialize_config(&imported.config, *format).context("failed to serialize config")?
+        );
+        return Ok(0);
+    }
+    write_new_config(config_path, *format, &imported.config)?;
+    let (tool, remove) = match from {
+        ImportSource::Chezmoi => ("chezmoi", "`chezmoi forget` them"),
+        ImportSource::Stow => ("stow", "`stow -D` their package"),
+    };
+    println!(
+        "note: targets are still managed by {tool}; {remove} before `prompt-sync link --force`"
+    );
+    Ok(0)
+}
+
+/// The path a new config is written to: an explicit `--format` swaps the
+/// extension so later loads pick it up.
+fn new_config_path(
+    config_path: &Path,
+    format: Option<ConfigFormat>,
+    force: bool,
+) -> Result<(PathBuf, ConfigFormat)> {
+    let format = format.unwrap_or_else(|| ConfigFormat::from_path(config_path));
+    let config_path = if ConfigFormat::from_path(config_path) == format {
+        config_path.to_path_buf()
+    } else {
+        config_path.with_extension(format.extension())
+    };
+
+    if config_path.exists() && !force {
+        return Err(anyhow!(
+            "config already exists: {} (use --force to overwrite)",
+            config_path.display()
+        ));
+    }
+    Ok((config_path, format))
+}
+
+fn write_new_config(config_path: &Path, format: ConfigFormat, config: &ConfigFile) -> Result<()> {
+    if let Some(parent) = config_path.parent() {
+        fs::create_dir_all(parent).with_context(|| {
+            format!(
+                "failed to create config directory: {}",
+                parent.to_string_lossy()
+            )
+        })?;
+    }
+
+    let text = serialize_config(config, format).context("failed to serialize config")?;
+    fs::write(config_path, text).with_context(|| {
+        format!(
+            "failed to write config file: {}",
+            config_path.to_string_lossy()
+        )
+    })?;
+
+    println!("created config: {}", config_path.display());
+    Ok(())
+}
+
+fn default_profiles(profiles: Vec<Profile>) -> Vec<Profile> {
+    if profiles.is_empty() {
+        vendors()
+            .iter()
+            .filter(|vendor| vendor.default)
+            .map(Profile::of)
+            .collect()
+    } else {
+        profiles
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_bootstrap(
+    config_path: &Path,
+    force: bool,
+    dry_run: bool,
+    output: &OutputArgs,
+    write_config: bool,
+    backup_dir: Option<&BackupDir>,
+    link_type: Option<LinkType>,
+    profiles: &[Profile],
+    include: &[Profile],
+    exclude: &[Profile],
+    yes: bool,
+    home_only: bool,
+    repo_only: bool,
+    state_store: Option<&StateStore>,
+    verbose: bool,
+) -> Result<i32> {
+    let timer = Timer::start();
+    let ctx = build_resolve_context(config_path)?;
+    let selected = bootstrap_vendors(&ctx, profiles, include, exclude);
+    if selected.is_empty() {
+        eprintln!("warn: no vendor is selected, only shared skills are linked");
+    }
+    let mut config = build_bootstrap_config(&selected);
+    if home_only {
+        retain_targets(&mut config, |path| path.starts_with('~'));
+    } else if repo_only {
+        retain_targets(&mut config, |path| path.starts_with("<repo>"));
+    }
+    if write_config && config_path.exists() && !force {
+        return Err(anyhow!(
+            "config already exists: {} (use --force to overwrite)",
+            config_path.display()
+        ));
+    }
+    let mapping_args = MappingArgs {
+        link_type,
+        ..MappingArgs::default()
+    };
+
+    // Asked only when someone is there to answer; scripts pass --yes or
+    // run without a terminal.
+    if !dry_run && !yes && io::stdin().is_terminal() && io::stderr().is_terminal() {
+        let mappings = build_mappings(&config, &ctx, &mapping_args, &Progress::disabled())?;
+        let plan = bootstrap_plan(
+            write_config.then_some(config_path),
+            &config,
+            &ctx,
+            &mappings,
+            force,
+        );
+        if !plan.is_empty() && !confirm(&plan)? {
+            eprintln!("bootstrap: aborted, nothing was changed");
+            return Ok(1);
+        }
+    }
+
+    if write_config {
+        let text = serialize_config(&config, ConfigFormat::from_path(config_path))
+            .context("failed to serialize config")?;
+        if !dry_run {
+            if let Some(parent) = config_path.parent() {
+                fs::create_dir_all(parent).with_context(|| {
+                    format!(
+                        "failed to create config directory: {}",
+                        parent.to_string_lossy()
+                    )
+                })?;
+            }
+            fs::write(config_path, text).with_context(|| {
+                format!(
+                    "failed to write config file: {}",
+                    config_path.to_string_lossy()
+                )
+            })?;
+        }
+        info!("bootstrap config prepared at: {}", config_path.display());
+    }
+
+    prepare_bootstrap_sources(&config, &ctx, dry_run)?;
+    let mappings = build_mappings(&config, &ctx, &mapping_args, &Progress::disabled())?;
+    let records = mappings
+        .iter()
+        .map(|mapping| timed(|| apply_link(mapping, force, false, dry_run, backup_dir, None)))
+        .collect::<Vec<_>>();
+    if !dry_run {
+        update_state(state_store, &mappings, &records, &[]);
+    }
+    let report = Report {
+        schema_version: REPORT_SCHEMA_VERSION,
+        command: "bootstrap".to_owned(),
+        run_id: run_id(),
+        master: None,
+        started_at: timer.started_at,
+        elapsed_ms: timer.elapsed_ms(),
+        timing: Timing::take(&records),
+        summary: Summary::from_records(&records),
+        records,
+    };
+    print_report(&report, output, verbose)?;
+    Ok(exit_code(&report.summary, false))
+}
+
+/// Opens a source in the editor, then repairs its targets. Those linked
+/// before are replaced even when they now look like conflicts: an editor
+/// that saves by renaming leaves them holding the old content.
+fn run_edit(
+    config_paths: &[PathBuf],
+    source: Option<&str>,
+    output: &OutputArgs,
+    state_store: Option<&StateStore>,
+    no_lock: bool,
+    wait: bool,
+    verbose: bool,
+) -> Result<i32> {
+    let configs = load_configs(config_paths)?;
+    let mappings = build_merged_mappings(&configs, &MappingArgs::default(), &Progress::disabled())?;
+    let path = pick_source(&configs, &mappings, source)?;
+    let mappings = mappings
+        .into_iter()
+        .filter(|mapping| mapping.source == path)
+        .collect::<Vec<_>>();
+    let linked = mappings
+        .iter()
+        .map(|mapping| inspect_mapping(mapping).status == Status::Ok)
+        .collect::<Vec<_>>();
+    // Repairing the targets protects the source again.
+    if mappings.iter().any(|mapping| mapping.protect) {
+        set_read_only(&path, false)?;
+    }
+    if let Err(err) = open_in_editor(&path) {
+        eprintln!("warn: {err:#}");
+    }
+
+    let _lock = lock_run(state_store, no_lock, wait)?;
+    let timer = Timer::start();
+    let records = mappings
+        .iter()
+        .zip(&linked)
+        .map(|(mapping, &linked)| timed(|| apply_repair(mapping, linked, false, None, None)))
+        .collect::<Vec<_>>();
+    update_state(state_store, &mappings, &records, &[]);
+    journal_records(&configs, state_store, "edit", &records);
+    let report = Report {
+        schema_version: REPORT_SCHEMA_VERSION,
+        command: "edit".to_owned(),
+        run_id: run_id(),
+        master: None,
+        started_at: timer.started_at,
+        elapsed_ms: timer.elapsed_ms(),
+        timing: Timing::take(&records),
+        summary: Summary::from_records(&records),
+        records,
+    };
+    print_report(&report, output, verbose)?;
+    Ok(exit_code(&report.summary, false))
+}
+
+/// Dry-runs `link` and writes what it would change to `out`.
+#[allow(clippy::too_many_arguments)]
+fn run_plan(
+    config_paths: &[PathBuf],
+    out: &Path,
+    only_missing: bool,
+    force: bool,
+    prune: bool,
+    output: &OutputArgs,
+    mapping_args: &MappingArgs,
+    state_store: Option<&StateStore>,
+    verbose: bool,
+) -> Result<i32> {
+    let timer = Timer::start();
+    let configs = load_configs(config_paths)?;
+    let mappings = build_merged_mappings(&configs, mapping_args, &Progress::for_output(output))?;
+    let mut records = mappings
+        .iter()
+        .map(|mapping| timed(|| apply_link(mapping, force, only_missing, true, None, None)))
+        .collect::<Vec<_>>();
+    if prune {
+        records.extend(
+            prune_candidates(state_store, &configs, &mappings, mapping_args)?
+                .iter()
+                .map(|(target, entry)| timed(|| prune_orphan(target, entry, true, None))),
+        );
+    }
+
+    let plan = Plan::new(
+        &configs
+            .iter()
+            .map(|loaded| loaded.path.clone())
+            .collect::<Vec<_>>(),
+        &records,
+    )?;
+    plan.save(out)?;
+    eprintln!(
+        "plan: {} action(s) written to {}",
+        plan.actions.len(),
+        out.display()
+    );
+    let report = Report {
+        schema_version: REPORT_SCHEMA_VERSION,
+        command: "plan".to_owned(),
+        run_id: run_id(),
+        master: None,
+        started_at: timer.started_at,
+        elapsed_ms: timer.elapsed_ms(),
+        timing: Timing::take(&records),
+        summary: Summary::from_records(&records),
+        records,
+    };
+    print_report(&report, output, verbose)?;
+    Ok(exit_code(&report.summary, false))
+}
+
+/// Carries out the plan at `plan_path` as one transaction, after checking
+/// that no config, source or target changed since it was made.
+fn run_apply(
+    plan_path: &Path,
+    backup_dir: Option<&BackupDir>,
+    output: &OutputArgs,
+    state_store: Option<&StateStore>,
+    verbose: bool,
+) -> Result<i32> {
+    let timer = Timer::start();
+    let plan = Plan::load(plan_path)?;
+    let drift = plan.drift();
+    if !drift.is_empty() {
+        for change in &drift {
+            eprintln!("changed since planning: {change}");
+        }
+        return Err(anyhow!(
+            "plan {} is out of date (run `plan` again)",
+            plan_path.display()
+        ));
+    }
+
+    let configs = load_configs(&plan.config_paths())?;
+    let mappings = build_merged_mappings(&configs, &MappingArgs::default(), &Progress::disabled())?;
+    let state = if plan
+        .actions
+        .iter()
+        .any(|action| action.action == Action::Prune)
+    {
+        let store = state_store
+            .ok_or_else(|| anyhow!("pruning needs a state directory (set --state-dir or HOME)"))?;
+        store.load()?
+    } else {
+        State::default()
+    };
+
+    // Every action is looked up before the first one runs, so a plan that
+    // cannot be carried out in full changes nothing.
+    let mut links = Vec::new();
+    let mut orphans = Vec::new();
+    for action in &plan.actions {
+        match action.action {
+            Action::Create | Action::Replace => {
+                let mapping = mappings
+                    .iter()
+                    .find(|mapping| {
+                        mapping.target == action.target && mapping.source == action.source
+                    })
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "no rule links {} to {} anymore",
+                            action.target.display(),
+                            action.source.display()
+                        )
+                    })?;
+                let mapping = Mapping {
+                    link_type: action.link_type,
+                    ..mapping.clone()
+                };
+                links.push((mapping, action.action == Action::Replace));
+            }
+            Action::Prune => {
+                let entry = state.targets.get(&action.target).ok_or_else(|| {
+                    anyhow!(
+                        "{} is no longer in the state to prune",
+                        action.target.display()
+                    )
+                })?;
+                orphans.push((action.target.clone(), entry.clone()));
+            }
+        }
+    }
+
+    let transaction = Transaction::default();
+    let mut records = links
+        .iter()
+        .map(|(mapping, replace)| {
+            timed(|| {
+                apply_link(
+                    mapping,
+                    *replace,
+                    false,
+                    false,
+                    backup_dir,
+                    Some(&transaction),
+                )
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut pruned = orphans
+        .iter()
+        .map(|(target, entry)| timed(|| prune_orphan(target, entry, false, Some(&transaction))))
+        .collect::<Vec<_>>();
+    transaction.finish(records.iter_mut().chain(pruned.iter_mut()));
+    let linked = links
+        .into_iter()
+        .map(|(mapping, _)| mapping)
+        .collect::<Vec<_>>();
+    update_state(state_store, &linked, &records, &pruned);
+    journal_records(&configs, state_store, "apply", &records);
+    journal_records(&configs, state_store, "apply", &pruned);
+
+    records.extend(pruned);
+    let report = Report {
+        schema_version: REPORT_SCHEMA_VERSION,
+        command: "apply".to_owned(),
+        run_id: run_id(),
+        master: None,
+        started_at: timer.started_at,
+        elapsed_ms: timer.elapsed_ms(),
+        timing: Timing::take(&records),
+        summary: Summary::from_records(&records),
+        records,
+    };
+    print_report(&report, output, verbose)?;
+    Ok(exit_code(&report.summary, false))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_watch(
+    config_paths: &[PathBuf],
+    interval: u64,
+    max_passes: Option<u64>,
+    force: bool,
+    output: &OutputArgs,
+    backup_dir: Option<&BackupDir>,
+    mapping_args: &MappingArgs,
+    state_store: Option<&StateStore>,
+    no_lock: bool,
+    verbose: bool,
+) -> Result<i32> {
+    let interval = Duration::from_secs(interval.max(1));
+    let mut last_snapshot = None;
+    let mut notifier = watch::Notifier::default();
+
+    for pass in 1.. {
+        watch_pass(
+            config_paths,
+            &mut last_snapshot,
             force,
-            dry_run,
-        } => run_install_commit_guard(&repo, force, dry_run),
+            output,
+            backup_dir,
+            mapping_args,
+            state_store,
+            no_lock,
+            verbose,
+            &mut notifier,
+        )?;
+        if max_passes.is_some_and(|max| pass >= max) {
+            break;
+        }
+        thread::sleep(interval);
     }
+    Ok(0)
 }
 
-fn run_init(config_path: &Path, force: bool, profiles: Vec<Profile>) -> Result<i32> {
-    if config_path.exists() && !force {
-        return Err(anyhow!(
-            "config already exists: {} (use --force to overwrite)",
-            config_path.display()
-        ));
+/// One poll of `watch`: repairs every mapping when the configs, sources or
+/// fragments differ from `last_snapshot`, which is then updated. Config
+/// and mapping errors are reported rather than returned, so polling goes
+/// on.
+#[allow(clippy::too_many_arguments)]
+fn watch_pass(
+    config_paths: &[PathBuf],
+    last_snapshot: &mut Option<watch::Snapshot>,
+    force: bool,
+    output: &OutputArgs,
+    backup_dir: Option<&BackupDir>,
+    mapping_args: &MappingArgs,
+    state_store: Option<&StateStore>,
+    no_lock: bool,
+    verbose: bool,
+    notifier: &mut watch::Notifier,
+) -> Result<()> {
+    // Idle cycles still load and snapshot; a report covers its own pass
+    // and sees sources replaced since the last one.
+    Timing::take(&[]);
+    stat_cache::clear();
+    // The config is reloaded every cycle so rule edits take effect without
+    // restarting; parse errors are reported and retried.
+    let configs = match load_configs(config_paths) {
+        Ok(configs) => configs,
+        Err(err) => {
+            eprintln!("watch: {err:#}");
+            return Ok(());
+        }
+    };
+    let current = configs
+        .iter()
+        .flat_map(|loaded| watch::snapshot(&loaded.path, &loaded.config, &loaded.ctx))
+        .collect::<watch::Snapshot>();
+    if last_snapshot.as_ref() == Some(&current) {
+        return Ok(());
+    }
+    if last_snapshot.is_some() {
+        info!("watch: change detected, repairing");
     }
+    *last_snapshot = Some(current);
+    let mappings = match build_merged_mappings(&configs, mapping_args, &Progress::disabled()) {
+        Ok(mappings) => mappings,
+        Err(err) => {
+            eprintln!("watch: {err:#}");
+            return Ok(());
+        }
+    };
 
-    if let Some(parent) = config_path.parent() {
-        fs::create_dir_all(parent).with_context(|| {
-            format!(
-                "failed to create config directory: {}",
-                parent.to_string_lossy()
-            )
-        })?;
+    // Each pass queues behind other runs instead of holding the lock
+    // between passes.
+    let _lock = lock_run(state_store, no_lock, true)?;
+    let timer = Timer::start();
+    let records = mappings
+        .iter()
+        .map(|mapping| timed(|| apply_repair(mapping, force, false, backup_dir, None)))
+        .collect::<Vec<_>>();
+    update_state(state_store, &mappings, &records, &[]);
+    journal_records(&configs, state_store, "watch", &records);
+    let report = Report {
+        schema_version: REPORT_SCHEMA_VERSION,
+        command: "watch".to_owned(),
+        run_id: run_id(),
+        master: None,
+        started_at: timer.started_at,
+        elapsed_ms: timer.elapsed_ms(),
+        timing: Timing::take(&records),
+        summary: Summary::from_records(&records),
+        records,
+    };
+    print_report(&report, output, verbose)?;
+    notifier.pass(&configs, &report.records);
+    Ok(())
+}
+
+/// Unlike `watch`, which waits for sources to change, every pass inspects
+/// the targets, so files a vendor app recreated are relinked within one
+/// interval. Conflicts are reported and left alone.
+fn run_daemon(
+    config_paths: &[PathBuf],
+    interval: u64,
+    once: bool,
+    backup_dir: Option<&BackupDir>,
+    mapping_args: &MappingArgs,
+    state_store: Option<&StateStore>,
+    no_lock: bool,
+) -> Result<i32> {
+    let interval = Duration::from_secs(interval.max(1));
+    let mut notifier = watch::Notifier::default();
+    loop {
+        let code = match daemon_pass(
+            config_paths,
+            backup_dir,
+            mapping_args,
+            state_store,
+            no_lock,
+            &mut notifier,
+        ) {
+            Ok(code) => code,
+            Err(err) if !once => {
+                eprintln!("daemon: {err:#}");
+                2
+            }
+            Err(err) => return Err(err),
+        };
+        if once {
+            return Ok(code);
+        }
+        thread::sleep(interval);
     }
+}
 
-    let selected_profiles = if profiles.is_empty() {
-        vec![
-            Profile::Codex,
-            Profile::Claude,
-            Profile::Gemini,
-            Profile::Copilot,
-            Profile::Kiro,
-        ]
+fn daemon_pass(
+    config_paths: &[PathBuf],
+    backup_dir: Option<&BackupDir>,
+    mapping_args: &MappingArgs,
+    state_store: Option<&StateStore>,
+    no_lock: bool,
+    notifier: &mut watch::Notifier,
+) -> Result<i32> {
+    // Reloaded every pass so config edits apply without a restart, and
+    // nothing looked up in the last pass is trusted.
+    stat_cache::clear();
+    let configs = load_configs(config_paths)?;
+    let mappings = build_merged_mappings(&configs, mapping_args, &Progress::disabled())?;
+    let _lock = lock_run(state_store, no_lock, true)?;
+    let records = mappings
+        .iter()
+        .map(|mapping| {
+            let record = inspect_mapping(mapping);
+            if matches!(record.status, Status::Missing | Status::Broken) {
+                repair_inspected(mapping, record, false, false, backup_dir, None)
+            } else {
+                record
+            }
+        })
+        .collect::<Vec<_>>();
+    update_state(state_store, &mappings, &records, &[]);
+    journal_records(&configs, state_store, "daemon", &records);
+    notifier.pass(&configs, &records);
+
+    let summary = Summary::from_records(&records);
+    let changed = summary.created + summary.replaced;
+    let message = format!(
+        "daemon: {} checked, {changed} repaired, {} conflict(s), {} error(s)",
+        summary.total, summary.conflict, summary.errors
+    );
+    if changed > 0 || summary.has_error() {
+        println!("{message}");
     } else {
-        profiles
-    };
+        info!("{message}");
+    }
+    for record in records
+        .iter()
+        .filter(|record| record.status == Status::Error)
+    {
+        eprintln!(
+            "daemon: {} -> {}: {}",
+            record.source.display(),
+            record.target.display(),
+            record.message.as_deref().unwrap_or_default()
+        );
+    }
+    Ok(exit_code(&summary, true))
+}
 
-    let config = build_default_config(&selected_profiles);
-    let toml_text = toml::to_string_pretty(&config).context("failed to serialize config")?;
+/// Forks, returning `true` in the parent, which reports the child's pid and
+/// exits. The child starts a new session with its standard streams on
+/// /dev/null, so closing the terminal does not stop it.
+#[cfg(unix)]
+fn detach_process() -> Result<bool> {
+    use std::os::fd::AsRawFd;
 
-    fs::write(config_path, toml_text).with_context(|| {
-        format!(
-            "failed to write config file: {}",
-            config_path.to_string_lossy()
-        )
-    })?;
+    let null = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/null")
+        .context("failed to open /dev/null")?;
+    // SAFETY: no other threads run yet, so the child gets a consistent copy
+    // of the process.
+    match unsafe { libc::fork() } {
+        -1 => Err(std::io::Error::last_os_error()).context("failed to fork the daemon"),
+        0 => {
+            // SAFETY: setsid and dup2 only act on this process's session
+            // and descriptors, all of which are valid here.
+            unsafe {
+                libc::setsid();
+                for fd in [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+                    libc::dup2(null.as_raw_fd(), fd);
+                }
+            }
+            Ok(false)
+        }
+        pid => {
+            println!("daemon: started (pid {pid})");
+            Ok(true)
+        }
+    }
+}
 
-    println!("created config: {}", config_path.display());
-    Ok(0)
+#[cfg(not(unix))]
+fn detach_process() -> Result<bool> {
+    Err(anyhow!(
+        "daemon --detach needs Unix; run it from a service manager instead"
+    ))
 }
 
-fn run_bootstrap(
-    config_path: &Path,
-    force: bool,
-    dry_run: bool,
-    json: bool,
-    write_config: bool,
-    backup_dir: Option<&Path>,
+/// Writes the config `add` or `target add` edited and reports which of
+/// `requested` it added; with `link`, links the added targets.
+#[allow(clippy::too_many_arguments)]
+fn finish_add(
+    command: &str,
+    config_paths: &[PathBuf],
+    path: &Path,
+    edited: &str,
+    edit: &RuleEdit,
+    requested: &[String],
+    link: bool,
+    state_store: Option<&StateStore>,
+    timer: &Timer,
     verbose: bool,
 ) -> Result<i32> {
-    let config = build_bootstrap_config();
-    let ctx = build_resolve_context(config_path)?;
-
-    if write_config {
-        if config_path.exists() && !force {
-            return Err(anyhow!(
-                "config already exists: {} (use --force to overwrite)",
-                config_path.display()
-            ));
-        }
-        let text = toml::to_string_pretty(&config).context("failed to serialize config")?;
-        if !dry_run {
-            if let Some(parent) = config_path.parent() {
-                fs::create_dir_all(parent).with_context(|| {
-                    format!(
-                        "failed to create config directory: {}",
-                        parent.to_string_lossy()
-                    )
-                })?;
+    if edit.whole_rule {
+        println!(
+            "added rule links[{}]: {} -> {}",
+            edit.index,
+            edit.source,
+            edit.targets.join(", ")
+        );
+    } else {
+        for target in requested {
+            if edit.targets.contains(target) {
+                println!("added target to links[{}]: {target}", edit.index);
+            } else {
+                println!(
+                    "unchanged: {target} is already a target of links[{}]",
+                    edit.index
+                );
             }
-            fs::write(config_path, text).with_context(|| {
-                format!(
-                    "failed to write config file: {}",
-                    config_path.to_string_lossy()
-                )
-            })?;
-        }
-        if verbose {
-            eprintln!("bootstrap config prepared at: {}", config_path.display());
         }
     }
+    if edit.targets.is_empty() {
+        return Ok(0);
+    }
+    write_edited_config(path, edited)?;
+    if !link {
+        return Ok(0);
+    }
 
-    prepare_bootstrap_sources(&config, &ctx, dry_run, verbose)?;
-    let backup_dir = resolve_backup_dir(backup_dir)?;
-    let mappings = build_mappings(&config, &ctx, verbose)?;
+    let configs = load_configs(config_paths)?;
+    let mut mappings =
+        build_merged_mappings(&configs, &MappingArgs::default(), &Progress::disabled())?;
+    mappings.retain(|mapping| is_edited(mapping, path, std::slice::from_ref(edit)));
     let records = mappings
         .iter()
-        .map(|mapping| apply_link(mapping, force, false, dry_run, backup_dir.as_deref()))
+        .map(|mapping| timed(|| apply_link(mapping, false, false, false, None, None)))
         .collect::<Vec<_>>();
+    update_state(state_store, &mappings, &records, &[]);
+    journal_records(&configs, state_store, command, &records);
     let report = Report {
-        command: "bootstrap".to_owned(),
+        schema_version: REPORT_SCHEMA_VERSION,
+        command: command.to_owned(),
+        run_id: run_id(),
+        master: None,
+        started_at: timer.started_at,
+        elapsed_ms: timer.elapsed_ms(),
+        timing: Timing::take(&records),
         summary: Summary::from_records(&records),
         records,
     };
-    print_report(&report, json, verbose)?;
-    Ok(exit_code(&report.summary, false))
+    print_report(&report, &OutputArgs::default(), verbose)?;
+    Ok(exit_code(&report.summary, true))
+}
+
+/// The layer `add` and `remove` edit, the one with the highest precedence,
+/// with its text and context. A missing config is started for `add`.
+fn editable_config(
+    config_paths: &[PathBuf],
+    command: &str,
+    create: bool,
+) -> Result<(PathBuf, String, ResolveContext)> {
+    let path = config_paths
+        .last()
+        .ok_or_else(|| anyhow!("{command} needs a config"))?
+        .clone();
+    if ConfigFormat::from_path(&path) != ConfigFormat::Toml {
+        return Err(anyhow!(
+            "{command} edits TOML configs only: {}",
+            path.display()
+        ));
+    }
+    if create && !path.exists() {
+        let ctx = build_resolve_context(&path)?;
+        return Ok((path, format!("version = {CONFIG_VERSION}\n"), ctx));
+    }
+    let text = fs::read_to_string(&path)
+        .with_context(|| format!("failed to read config: {}", path.display()))?;
+    let (_, ctx) = load_config(&path)?;
+    Ok((path, text, ctx))
+}
+
+fn write_edited_config(path: &Path, text: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create config directory: {}", parent.display()))?;
+    }
+    fs::write(path, text)
+        .with_context(|| format!("failed to write config file: {}", path.display()))?;
+    println!("updated config: {}", path.display());
+    Ok(())
+}
+
+/// Whether `mapping` is one of the targets `edits` changed in `config`.
+fn is_edited(mapping: &Mapping, config: &Path, edits: &[RuleEdit]) -> bool {
+    mapping.config == config
+        && mapping.kind.rule_kind() == "links"
+        && edits.iter().any(|edit| {
+            edit.index == mapping.rule_index && edit.targets.contains(&mapping.target_template)
+        })
+}
+
+/// Commands that write a config file work on exactly one `--config`, and
+/// on the repo-local file rather than the layered chain by default.
+fn single_config(explicit: &[PathBuf], command: &str) -> Result<PathBuf> {
+    match explicit {
+        [] => absolute_path(Path::new(DEFAULT_CONFIG_FILE)),
+        [path] => absolute_path(path),
+        _ => Err(anyhow!("{command} takes a single --config")),
+    }
+}
+
+fn print_merged_config(configs: &[LoadedConfig], json: bool) -> Result<()> {
+    let merged = merge_resolved(configs);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&merged)?);
+        return Ok(());
+    }
+    for loaded in configs {
+        println!("# layer: {}", loaded.path.display());
+    }
+    print!(
+        "{}",
+        serialize_config(&merged, ConfigFormat::Toml).context("failed to serialize config")?
+    );
+    Ok(())
+}
+
+/// The state entries `--prune` removes: targets no rule produces any more,
+/// within the `--kind`, `--target-contains` and `--source` filters. Targets
+/// of rules this run skipped for `--profile`, `--repo`, `only_on` or
+/// `when` are kept.
+fn prune_candidates(
+    state_store: Option<&StateStore>,
+    configs: &[LoadedConfig],
+    mappings: &[Mapping],
+    mapping_args: &MappingArgs,
+) -> Result<Vec<(PathBuf, ManagedTarget)>> {
+    let store = state_store
+        .ok_or_else(|| anyhow!("--prune needs a state directory (set --state-dir or HOME)"))?;
+    let filter = MappingFilter::new(mapping_args)?;
+    let mut known = build_prune_scope(configs)?;
+    known.extend_from_slice(mappings);
+    let config_paths = configs
+        .iter()
+        .map(|loaded| loaded.path.clone())
+        .collect::<Vec<_>>();
+    let mut orphans = store.load()?.orphans(&config_paths, &known);
+    orphans.retain(|(target, entry)| filter.matches(&entry.kind, &entry.source, target));
+    Ok(orphans)
+}
+
+/// Loads the state when an ownership-aware option needs it.
+fn load_ownership(state_store: Option<&StateStore>, needed: bool) -> Result<Option<State>> {
+    if !needed {
+        return Ok(None);
+    }
+    let store = state_store.ok_or_else(|| {
+        anyhow!("ownership checks need a state directory (set --state-dir or HOME)")
+    })?;
+    store.load().map(Some)
+}
+
+fn open_state_store(state_dir: Option<&Path>) -> Result<Option<StateStore>> {
+    let state_dir = match state_dir {
+        Some(dir) => Some(absolute_path(dir)?),
+        None => default_state_dir(),
+    };
+    Ok(state_dir.map(|dir| StateStore::new(&dir)))
+}
+
+/// Commands that change targets hold the state-dir lock, so a `watch`
+/// loop, a cron job and a manual run never race on the same targets or on
+/// the operations log.
+fn lock_run(
+    state_store: Option<&StateStore>,
+    no_lock: bool,
+    wait: bool,
+) -> Result<Option<RunLock>> {
+    match state_store {
+        Some(store) if !no_lock => store.lock(wait).map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// Appends what a command changed to the `[logging]` journal, if enabled.
+fn journal_records(
+    configs: &[LoadedConfig],
+    state_store: Option<&StateStore>,
+    command: &str,
+    records: &[Record],
+) {
+    if let Some(journal) = Journal::from_configs(configs, state_store.and_then(StateStore::dir)) {
+        journal.record(command, records);
+    }
+}
+
+/// Post hooks run once the changes are made, so a failing one is a warning
+/// and leaves the exit code alone.
+fn run_post_hooks(configs: &[LoadedConfig], stage: Stage, report: &Report) {
+    if let Err(err) = run_hooks(configs, stage, &report.command, Some(report)) {
+        eprintln!("warn: {err:#}");
+    }
+}
+
+/// Records what a mutating command did to the managed target set, and in
+/// `.git/info/exclude` for `git_exclude` rules. Both are bookkeeping only,
+/// so failures are reported without failing the command.
+fn update_state(
+    state_store: Option<&StateStore>,
+    mappings: &[Mapping],
+    records: &[Record],
+    pruned: &[Record],
+) {
+    update_git_excludes_for(mappings, records, pruned);
+    protect_linked(mappings, records);
+    let Some(store) = state_store else {
+        return;
+    };
+
+    let result = store.load().and_then(|mut state| {
+        for (mapping, record) in mappings.iter().zip(records) {
+            match record.status {
+                Status::Created | Status::Replaced => state.track(mapping),
+                Status::Removed => state.forget(&mapping.target),
+                _ => {}
+            }
+        }
+        for record in pruned {
+            if matches!(record.status, Status::Pruned | Status::Skipped) {
+                state.forget(&record.target);
+            }
+        }
+        store.save(&state)
+    });
+
+    if let Err(err) = result {
+        eprintln!("warn: failed to update state: {err:#}");
+    }
+}
+
+/// `protect` rules keep their source and the targets linked to it
+/// read-only, including those a run left alone as already linked.
+fn protect_linked(mappings: &[Mapping], records: &[Record]) {
+    for (mapping, record) in mappings.iter().zip(records) {
+        let linked = match record.status {
+            Status::Ok | Status::Created | Status::Replaced => true,
+            Status::Skipped => inspect_mapping(mapping).status == Status::Ok,
+            _ => false,
+        };
+        if mapping.protect
+            && linked
+            && let Err(err) = protect_mapping(mapping, true)
+        {
+            eprintln!("warn: {err:#}");
+        }
+    }
+}
+
+/// Targets in place are listed when their rule has `git_exclude` and
+/// dropped otherwise, so turning the option off cleans up; removed and
+/// pruned targets are always dropped.
+fn update_git_excludes_for(mappings: &[Mapping], records: &[Record], pruned: &[Record]) {
+    let linked = mappings
+        .iter()
+        .zip(records)
+        .filter_map(|(mapping, record)| match record.status {
+            Status::Ok | Status::Created | Status::Replaced => {
+                Some((mapping.target.as_path(), mapping.git_exclude))
+            }
+            Status::Removed => Some((mapping.target.as_path(), false)),
+            _ => None,
+        });
+    let pruned = pruned
+        .iter()
+        .filter(|record| record.status == Status::Pruned)
+        .map(|record| (record.target.as_path(), false));
+    if let Err(err) = update_git_excludes(linked.chain(pruned)) {
+        eprintln!("warn: failed to update .git/info/exclude: {err:#}");
+    }
+}
+
+/// `--backup-dir` with the `[logging]` rotation of its `.operations.log`. A
+/// config that fails to load keeps the default rotation; the command itself
+/// reports the error.
+fn resolve_backup_dir(
+    backup_dir: Option<&Path>,
+    min_free: Option<u64>,
+    config_paths: &[PathBuf],
+) -> Result<Option<BackupDir>> {
+    let Some(path) = backup_dir.map(absolute_path).transpose()? else {
+        return Ok(None);
+    };
+    Ok(Some(BackupDir {
+        path,
+        min_free: min_free.unwrap_or(DEFAULT_BACKUP_MIN_FREE),
+        rotation: Rotation::from_configs(&load_configs(config_paths).unwrap_or_default()),
+    }))
 }
 
-fn resolve_backup_dir(backup_dir: Option<&Path>) -> Result<Option<std::path::PathBuf>> {
-    backup_dir.map(absolute_path).transpose()
+/// The vendors `bootstrap` links into: the `--profile`s given, or else those
+/// detected as installed, plus the `--include`d ones and minus the
+/// `--exclude`d ones.
+fn bootstrap_vendors(
+    ctx: &ResolveContext,
+    profiles: &[Profile],
+    include: &[Profile],
+    exclude: &[Profile],
+) -> Vec<&'static Vendor> {
+    vendors()
+        .iter()
+        .filter(|vendor| {
+            let profile = Profile::of(vendor);
+            if exclude.contains(&profile) {
+                info!("bootstrap: {} excluded", vendor.name);
+                false
+            } else if include.contains(&profile) || profiles.contains(&profile) {
+                info!("bootstrap: linking {}", vendor.name);
+                true
+            } else if !profiles.is_empty() {
+                info!("bootstrap: {} not selected by --profile", vendor.name);
+                false
+            } else if vendor.is_installed(ctx) {
+                info!("bootstrap: linking {}", vendor.name);
+                true
+            } else {
+                info!(
+                    "bootstrap: {} not detected, skipped (use --include {0} to link it)",
+                    vendor.name
+                );
+                false
+            }
+        })
+        .collect()
+}
+
+/// What `bootstrap` is about to write, one line per file or directory.
+fn bootstrap_plan(
+    config_path: Option<&Path>,
+    config: &ConfigFile,
+    ctx: &ResolveContext,
+    mappings: &[Mapping],
+    force: bool,
+) -> Vec<String> {
+    let mut plan = Vec::new();
+    if let Some(path) = config_path {
+        plan.push(format!("write config  {}", path.display()));
+    }
+    let sources = config
+        .links
+        .iter()
+        .map(|rule| resolve_path(&rule.source, ctx))
+        .chain(
+            config
+                .skills_sets
+                .iter()
+                .map(|set| resolve_path(&set.source_root, ctx)),
+        );
+    for source in sources {
+        let line = format!("create        {}", source.display());
+        if !source.exists() && !plan.contains(&line) {
+            plan.push(line);
+        }
+    }
+    for mapping in mappings {
+        // A source created above cannot be checked yet; its targets are new.
+        let action = if !mapping.source.exists() {
+            "link   "
+        } else {
+            match apply_link(mapping, force, false, true, None, None).status {
+                Status::WouldCreate => "link   ",
+                Status::WouldReplace => "replace",
+                _ => continue,
+            }
+        };
+        plan.push(format!(
+            "{action}       {} -> {}",
+            mapping.target.display(),
+            mapping.source.display()
+        ));
+    }
+    plan
+}
+
+/// Prints `plan` and asks whether to go ahead; anything but `y` or `yes`
+/// declines.
+fn confirm(plan: &[String]) -> Result<bool> {
+    eprintln!("bootstrap will:");
+    for line in plan {
+        eprintln!("  {line}");
+    }
+    eprint!("Proceed? [y/N] ");
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin()
+        .read_line(&mut answer)
+        .context("failed to read the answer")?;
+    Ok(matches!(
+        answer.trim().to_ascii_lowercase().as_str(),
+        "y" | "yes"
+    ))
 }
 
 fn prepare_bootstrap_sources(
     config: &ConfigFile,
     ctx: &ResolveContext,
     dry_run: bool,
-    verbose: bool,
 ) -> Result<()> {
     for rule in &config.links {
         let source = resolve_path(&rule.source, ctx);
@@ -233,12 +2373,10 @@ fn prepare_bootstrap_sources(
             continue;
         }
         if dry_run {
-            if verbose {
-                eprintln!(
-                    "bootstrap dry-run: would create source file {}",
-                    source.display()
-                );
-            }
+            info!(
+                "bootstrap dry-run: would create source file {}",
+                source.display()
+            );
             continue;
         }
         if let Some(parent) = source.parent() {
@@ -254,9 +2392,7 @@ fn prepare_bootstrap_sources(
             "# master instructions\n\nUpdate this file to sync all linked instruction files.\n",
         )
         .with_context(|| format!("failed to create source file: {}", source.display()))?;
-        if verbose {
-            eprintln!("bootstrap: created source file {}", source.display());
-        }
+        info!("bootstrap: created source file {}", source.display());
     }
 
     for set in &config.skills_sets {
@@ -271,12 +2407,10 @@ fn prepare_bootstrap_sources(
             continue;
         }
         if dry_run {
-            if verbose {
-                eprintln!(
-                    "bootstrap dry-run: would create skills source root {}",
-                    source_root.display()
-                );
-            }
+            info!(
+                "bootstrap dry-run: would create skills source root {}",
+                source_root.display()
+            );
             continue;
         }
         fs::create_dir_all(&source_root).with_context(|| {
@@ -285,17 +2419,73 @@ fn prepare_bootstrap_sources(
                 source_root.display()
             )
         })?;
-        if verbose {
-            eprintln!(
-                "bootstrap: created skills source root {}",
-                source_root.display()
-            );
-        }
+        info!(
+            "bootstrap: created skills source root {}",
+            source_root.display()
+        );
     }
 
     Ok(())
 }
 
+/// `status --tui`, grouping targets under the skills sets' target roots.
+/// Every action is recorded in the state and the journal as it happens.
+#[cfg(unix)]
+fn run_dashboard(
+    configs: &[LoadedConfig],
+    options: &MappingArgs,
+    mappings: &[Mapping],
+    state_store: Option<&StateStore>,
+) -> Result<()> {
+    let mut roots = Vec::new();
+    for loaded in configs {
+        for (_, _, target_roots) in skills_set_roots(&loaded.config, &loaded.ctx, options)? {
+            roots.extend(target_roots);
+        }
+    }
+    crate::tui::run_dashboard(mappings, &roots, &mut |mapping, record| {
+        update_state(
+            state_store,
+            std::slice::from_ref(mapping),
+            std::slice::from_ref(record),
+            &[],
+        );
+        journal_records(configs, state_store, "status", std::slice::from_ref(record));
+    })
+}
+
+#[cfg(not(unix))]
+fn run_dashboard(
+    _configs: &[LoadedConfig],
+    _options: &MappingArgs,
+    _mappings: &[Mapping],
+    _state_store: Option<&StateStore>,
+) -> Result<()> {
+    Err(anyhow!("status --tui needs a Unix terminal"))
+}
+
+/// Runs `check` on each config, prefixing rule names with their file when
+/// several configs are checked.
+fn config_issues(
+    configs: &[LoadedConfig],
+    check: fn(&ConfigFile, &ResolveContext) -> Vec<Issue>,
+) -> Vec<Issue> {
+    if let [loaded] = configs {
+        return check(&loaded.config, &loaded.ctx);
+    }
+    configs
+        .iter()
+        .flat_map(|loaded| {
+            check(&loaded.config, &loaded.ctx)
+                .into_iter()
+                .map(|issue| Issue {
+                    rule: format!("{}: {}", loaded.path.display(), issue.rule),
+                    ..issue
+                })
+        })
+        .collect()
+}
+
 fn exit_code(summary: &Summary, include_inconsistency: bool) -> i32 {
     if summary.has_error() {
         2
@@ -306,13 +2496,174 @@ fn exit_code(summary: &Summary, include_inconsistency: bool) -> i32 {
     }
 }
 
-fn run_install_commit_guard(repo: &Path, force: bool, dry_run: bool) -> Result<i32> {
+/// The repositories a guard command works on: `--repo`, or with
+/// `--all-repos` every repository the configs' `[repos]` sections list or
+/// scan.
+fn guard_repos(config_paths: &[PathBuf], repo: &Path, all_repos: bool) -> Result<Vec<PathBuf>> {
+    if !all_repos {
+        return Ok(vec![repo.to_path_buf()]);
+    }
+    let mut repos = Vec::new();
+    for loaded in load_configs(config_paths)? {
+        let Some(config) = &loaded.config.repos else {
+            continue;
+        };
+        for root in repo_roots(config, &loaded.ctx)? {
+            if !repos.contains(&root) {
+                repos.push(root);
+            }
+        }
+    }
+    if repos.is_empty() {
+        return Err(anyhow!(
+            "--all-repos found no repositories: add [repos] roots or scan to the config"
+        ));
+    }
+    Ok(repos)
+}
+
+/// Runs `run` for each repository under a `repo:` heading. One failing
+/// repository is reported and the rest still run; the exit code is the
+/// worst of them.
+fn for_each_repo(repos: &[PathBuf], mut run: impl FnMut(&Path) -> Result<i32>) -> Result<i32> {
+    if let [repo] = repos {
+        return run(repo);
+    }
+    let mut exit_code = 0;
+    for repo in repos {
+        println!("repo: {}", repo.display());
+        let code = run(repo).unwrap_or_else(|err| {
+            eprintln!("error: {}: {err:#}", repo.display());
+            2
+        });
+        exit_code = exit_code.max(code);
+    }
+    Ok(exit_code)
+}
+
+/// Exits 1 unless the current guard is installed unmodified.
+fn run_commit_guard_status(repo: &Path) -> Result<i32> {
+    let status = commit_guard_status(&absolute_path(repo)?)?;
+    let state = match status.version {
+        Some(version) if version != COMMIT_GUARD_VERSION => format!(
+            "installed (v{version}, current is v{}; reinstall to upgrade)",
+            COMMIT_GUARD_VERSION
+        ),
+        Some(version) if status.modified => format!("installed (v{version}), modified locally"),
+        Some(version) => format!("installed (v{version})"),
+        None if status.present => "not installed (hook was not written by prompt-sync)".to_owned(),
+        None => "not installed".to_owned(),
+    };
+    println!("commit guard: {state}: {}", status.hook.display());
+    if let Some(mode) = status.mode {
+        println!("mode: {}", mode.as_str());
+    }
+    if let Some(chained) = &status.chained {
+        println!("chained hook: {}", chained.display());
+    }
+    Ok(if status.is_current() { 0 } else { 1 })
+}
+
+fn run_install_commit_guard(
+    repo: &Path,
+    force: bool,
+    chain: bool,
+    mode: GuardMode,
+    uninstall: bool,
+    dry_run: bool,
+) -> Result<i32> {
     let repo_root = absolute_path(repo)?;
-    let hook_path = install_commit_guard(&repo_root, force, dry_run)?;
-    if dry_run {
-        println!("would install commit guard hook: {}", hook_path.display());
+    if uninstall {
+        let guard = uninstall_commit_guard(&repo_root, dry_run)?;
+        let (restore, remove) = if dry_run {
+            ("would restore", "would remove")
+        } else {
+            ("restored", "removed")
+        };
+        match guard.chained {
+            Some(chained) => println!(
+                "{restore} {} from {}",
+                guard.hook.display(),
+                chained.display()
+            ),
+            None => println!("{remove} commit guard hook: {}", guard.hook.display()),
+        }
+        return Ok(0);
+    }
+
+    let guard = install_commit_guard(&repo_root, force, chain, mode, dry_run)?;
+    if guard.vcs == (Vcs::Jujutsu { colocated: true }) {
+        eprintln!(
+            "note: jj runs no commit hooks; the guard covers `git commit` in this colocated repo"
+        );
+    }
+    let verb = if dry_run {
+        "would install"
+    } else {
+        "installed"
+    };
+    match guard.chained {
+        Some(chained) => println!(
+            "{verb} commit guard hook: {} (chaining {})",
+            guard.hook.display(),
+            chained.display()
+        ),
+        None => println!("{verb} commit guard hook: {}", guard.hook.display()),
+    }
+    Ok(0)
+}
+
+fn run_export_stow(
+    configs: &[LoadedConfig],
+    dir: &Path,
+    package: &str,
+    target_dir: Option<&Path>,
+    force: bool,
+    dry_run: bool,
+    mapping: &MappingArgs,
+) -> Result<i32> {
+    if !is_plain_file_name(package) {
+        return Err(anyhow!(
+            "--package must be a plain directory name: {package}"
+        ));
+    }
+    let target_dir = match target_dir {
+        Some(dir) => absolute_path(dir)?,
+        None => configs
+            .iter()
+            .find_map(|loaded| loaded.ctx.home_dir.clone())
+            .ok_or_else(|| anyhow!("set --target-dir (no home directory to default to)"))?,
+    };
+    let package_dir = absolute_path(dir)?.join(package);
+    let mappings = build_merged_mappings(configs, mapping, &Progress::disabled())?;
+    let summary = export_stow(&mappings, &package_dir, &target_dir, force, dry_run);
+    println!(
+        "export: {} written, {} unchanged, {} skipped, {} conflict(s), {} error(s) in {}",
+        summary.written,
+        summary.unchanged,
+        summary.skipped,
+        summary.conflicts,
+        summary.errors,
+        package_dir.display()
+    );
+    Ok(if summary.errors > 0 {
+        2
+    } else if summary.conflicts > 0 {
+        1
     } else {
-        println!("installed commit guard hook: {}", hook_path.display());
+        0
+    })
+}
+
+fn run_install_repair_hooks(repo: &Path, force: bool, dry_run: bool) -> Result<i32> {
+    // The hook matches target paths against the root, so drop `.` parts.
+    let repo_root = normalize_lexically(&absolute_path(repo)?);
+    for hook_path in install_repair_hooks(&repo_root, force, dry_run)? {
+        if dry_run {
+            println!("would install repair hook: {}", hook_path.display());
+        } else {
+            println!("installed repair hook: {}", hook_path.display());
+        }
     }
     Ok(0)
 }
@@ -0,0 +1,97 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// One backup file and the target it was taken from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct BackupEntry {
+    pub(crate) backup: PathBuf,
+    pub(crate) original: PathBuf,
+    #[serde(default)]
+    pub(crate) hash: Option<String>,
+    pub(crate) size: u64,
+    /// Shared by every backup taken in the same invocation.
+    pub(crate) run_id: String,
+    pub(crate) created_at: DateTime<Utc>,
+}
+
+/// `manifest.json` in a backup root, indexing the loose backup files.
+pub(crate) struct BackupManifest {
+    path: PathBuf,
+}
+
+impl BackupManifest {
+    pub(crate) fn new(backup_dir: &Path) -> Self {
+        BackupManifest {
+            path: backup_dir.join(MANIFEST_FILE_NAME),
+        }
+    }
+
+    pub(crate) fn load(&self) -> Result<Vec<BackupEntry>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let text = fs::read_to_string(&self.path)
+            .with_context(|| format!("failed to read backup manifest: {}", self.path.display()))?;
+        serde_json::from_str(&text)
+            .with_context(|| format!("invalid backup manifest: {}", self.path.display()))
+    }
+
+    /// Appends `entry`, dropping entries whose backup file has since been
+    /// cleaned up or overwritten by this one.
+    pub(crate) fn add(&self, entry: BackupEntry) -> Result<()> {
+        let mut entries = self.load()?;
+        entries.retain(|existing| existing.backup.exists() && existing.backup != entry.backup);
+        entries.push(entry);
+        self.save(&entries)
+    }
+
+    pub(crate) fn remove(&self, backup: &Path) -> Result<()> {
+        let mut entries = self.load()?;
+        entries.retain(|existing| existing.backup != backup);
+        self.save(&entries)
+    }
+
+    fn save(&self, entries: &[BackupEntry]) -> Result<()> {
+        let json_str =
+            serde_json::to_string_pretty(entries).context("failed to serialize backup manifest")?;
+        let tmp_path = self.path.with_extension("json.tmp");
+        fs::write(&tmp_path, json_str)
+            .with_context(|| format!("failed to write backup manifest {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, &self.path)
+            .with_context(|| format!("failed to write backup manifest {}", self.path.display()))
+    }
+}
+
+impl BackupEntry {
+    /// The backup's path under the backup root, `<run-id>/<target path>`.
+    pub(crate) fn id(&self, backup_dir: &Path) -> String {
+        self.backup
+            .strip_prefix(backup_dir)
+            .unwrap_or(&self.backup)
+            .to_string_lossy()
+            .replace('\\', "/")
+    }
+}
+
+/// The newest entry whose backup id or original path is `key`, taken at or
+/// before `at` when given.
+pub(crate) fn find_backup<'a>(
+    entries: &'a [BackupEntry],
+    backup_dir: &Path,
+    key: &str,
+    original: &Path,
+    at: Option<DateTime<Utc>>,
+) -> Option<&'a BackupEntry> {
+    let key = key.replace('\\', "/");
+    entries
+        .iter()
+        .filter(|entry| entry.original == original || entry.id(backup_dir) == key)
+        .filter(|entry| at.is_none_or(|at| entry.created_at <= at))
+        .max_by_key(|entry| entry.created_at)
+}
@@ -0,0 +1,145 @@
+//! `verify --baseline`: target/status pairs known to diverge on purpose,
+//! reported as ACCEPTED instead of failing the run.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::engine::serde_label;
+use crate::model::{Record, Status};
+use crate::pathing::{absolute_path, home_dir};
+use crate::trace::info;
+
+/// One accepted target. Targets are written relative to the baseline's
+/// directory, or with `~`, when they are under it, so a baseline checked
+/// into a repository holds on every clone.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct BaselineEntry {
+    target: String,
+    status: Status,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct BaselineFile {
+    #[serde(default)]
+    accepted: Vec<BaselineEntry>,
+}
+
+/// The statuses a baseline can accept; errors always fail the run.
+const fn is_acceptable(status: Status) -> bool {
+    matches!(status, Status::Missing | Status::Broken | Status::Conflict)
+}
+
+pub(crate) struct Baseline {
+    path: PathBuf,
+    accepted: Vec<(PathBuf, Status)>,
+}
+
+impl Baseline {
+    /// Reads `path`; a file that does not exist yet accepts nothing.
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        let path = absolute_path(path)?;
+        let file = match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str::<BaselineFile>(&text)
+                .with_context(|| format!("invalid baseline: {}", path.display()))?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => BaselineFile::default(),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("failed to read baseline: {}", path.display()));
+            }
+        };
+        let dir = path.parent().unwrap_or(Path::new("/")).to_path_buf();
+        let accepted = file
+            .accepted
+            .into_iter()
+            .map(|entry| (resolve_target(&entry.target, &dir), entry.status))
+            .collect();
+        Ok(Self { path, accepted })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Turns a record the baseline lists with its current status into
+    /// ACCEPTED, keeping the original status in the message.
+    pub(crate) fn accept(&self, record: &mut Record) {
+        if !self
+            .accepted
+            .iter()
+            .any(|(target, status)| *target == record.target && *status == record.status)
+        {
+            return;
+        }
+        let status = serde_label(&record.status);
+        record.message = Some(match record.message.take() {
+            Some(message) => format!("baseline {status}: {message}"),
+            None => format!("baseline {status}"),
+        });
+        record.status = Status::Accepted;
+    }
+
+    /// Mentions, with `--verbose`, entries no record matched, which
+    /// `--update-baseline` would drop.
+    pub(crate) fn report_unused(&self, records: &[Record]) {
+        for (target, status) in &self.accepted {
+            let used = records
+                .iter()
+                .any(|record| record.status == Status::Accepted && record.target == *target);
+            if !used {
+                info!(
+                    "baseline entry no longer matches: {} {}",
+                    serde_label(status),
+                    target.display()
+                );
+            }
+        }
+    }
+
+    /// Replaces the baseline with the records that would fail the run now.
+    pub(crate) fn save(&self, records: &[Record]) -> Result<usize> {
+        let dir = self.path.parent().unwrap_or(Path::new("/"));
+        let home = home_dir();
+        let mut accepted = records
+            .iter()
+            .filter(|record| is_acceptable(record.status))
+            .map(|record| BaselineEntry {
+                target: display_target(&record.target, dir, home.as_deref()),
+                status: record.status,
+            })
+            .collect::<Vec<_>>();
+        accepted.sort_by(|a, b| a.target.cmp(&b.target));
+        accepted.dedup();
+        let count = accepted.len();
+        let text = serde_json::to_string_pretty(&BaselineFile { accepted })
+            .context("failed to serialize baseline")?;
+        if !dir.as_os_str().is_empty() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        fs::write(&self.path, format!("{text}\n"))
+            .with_context(|| format!("failed to write baseline: {}", self.path.display()))?;
+        Ok(count)
+    }
+}
+
+fn resolve_target(raw: &str, dir: &Path) -> PathBuf {
+    if (raw == "~" || raw.starts_with("~/"))
+        && let Some(home) = home_dir()
+    {
+        return home.join(raw.trim_start_matches('~').trim_start_matches('/'));
+    }
+    dir.join(raw)
+}
+
+fn display_target(target: &Path, dir: &Path, home: Option<&Path>) -> String {
+    if let Ok(rest) = target.strip_prefix(dir) {
+        return rest.to_string_lossy().replace('\\', "/");
+    }
+    match home.and_then(|home| target.strip_prefix(home).ok()) {
+        Some(rest) => format!("~/{}", rest.to_string_lossy().replace('\\', "/")),
+        None => target.display().to_string(),
+    }
+}
@@ -0,0 +1,378 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Component, Path};
+
+use anyhow::{Context, Result};
+
+use crate::cli::MappingArgs;
+use crate::config::{ConfigFile, When, applies_on_current_os};
+use crate::engine::{build_mappings, is_glob_pattern};
+use crate::model::{Issue, MappingKind, ResolveContext, Severity};
+use crate::pathing::{resolve_path, unresolved_tokens};
+use crate::progress::Progress;
+use crate::remote::is_url;
+use crate::render::split_frontmatter;
+use crate::yaml;
+
+const SKILL_MANIFEST: &str = "SKILL.md";
+const MAX_SKILL_NAME_LEN: usize = 64;
+const MAX_SKILL_DESCRIPTION_LEN: usize = 1024;
+
+/// Validates a parsed config against the filesystem without modifying it.
+pub(crate) fn check_config(config: &ConfigFile, ctx: &ResolveContext) -> Vec<Issue> {
+    let mut issues = Vec::new();
+
+    if config.version.is_none() {
+        issues.push(warning(
+            "config",
+            "config has no version (run `prompt-sync config migrate`)".to_owned(),
+        ));
+    }
+
+    for (index, rule) in config.links.iter().enumerate() {
+        let rule_name = format!("links[{index}]");
+        check_tokens(&mut issues, &rule_name, &rule.source, ctx);
+        for target in &rule.targets {
+            check_tokens(&mut issues, &rule_name, target.path(), ctx);
+        }
+        if rule.targets.is_empty() {
+            issues.push(warning(&rule_name, "rule has no targets".to_owned()));
+        }
+        if !applies_on_current_os(&rule.only_on, &rule.skip_on)
+            || !rule.when.as_ref().is_none_or(When::holds)
+        {
+            continue;
+        }
+
+        if is_url(&rule.source) {
+            if !rule.source.starts_with("https://") && !rule.source.starts_with("file://") {
+                issues.push(error(
+                    &rule_name,
+                    format!("URL source must use https: {}", rule.source),
+                ));
+            }
+            continue;
+        }
+        let source = resolve_path(&rule.source, ctx);
+        if is_glob_pattern(&rule.source) {
+            continue;
+        }
+        if !source.exists() {
+            issues.push(error(
+                &rule_name,
+                format!("source does not exist: {}", source.display()),
+            ));
+        } else if !source.is_file() {
+            issues.push(error(
+                &rule_name,
+                format!("source is not a regular file: {}", source.display()),
+            ));
+        }
+    }
+
+    for (index, set) in config.skills_sets.iter().enumerate() {
+        let rule_name = format!("skills_sets[{index}]");
+        check_tokens(&mut issues, &rule_name, &set.source_root, ctx);
+        for target_root in &set.target_roots {
+            check_tokens(&mut issues, &rule_name, target_root, ctx);
+        }
+        if set.target_roots.is_empty() {
+            issues.push(warning(
+                &rule_name,
+                "skills set has no target_roots".to_owned(),
+            ));
+        }
+        if !set.only_skills.is_empty() && !set.exclude_skills.is_empty() {
+            issues.push(warning(
+                &rule_name,
+                "exclude_skills is ignored because only_skills is set".to_owned(),
+            ));
+        }
+        if !applies_on_current_os(&set.only_on, &set.skip_on)
+            || !set.when.as_ref().is_none_or(When::holds)
+        {
+            continue;
+        }
+
+        let source_root = resolve_path(&set.source_root, ctx);
+        if !source_root.exists() {
+            issues.push(warning(
+                &rule_name,
+                format!("source_root does not exist: {}", source_root.display()),
+            ));
+        }
+    }
+
+    let mappings = match build_mappings(config, ctx, &MappingArgs::default(), &Progress::disabled())
+    {
+        Ok(mappings) => mappings,
+        Err(err) => {
+            issues.push(error("config", format!("{err:#}")));
+            return issues;
+        }
+    };
+
+    let mut sources_by_target: BTreeMap<&Path, BTreeSet<&Path>> = BTreeMap::new();
+    let mut productive_rules = BTreeSet::new();
+    for mapping in &mappings {
+        sources_by_target
+            .entry(&mapping.target)
+            .or_default()
+            .insert(&mapping.source);
+        productive_rules.insert(rule_label(&mapping.kind, mapping.rule_index));
+
+        if is_inside_git_dir(&mapping.target) {
+            issues.push(error(
+                &rule_label(&mapping.kind, mapping.rule_index),
+                format!(
+                    "target is inside a .git directory: {}",
+                    mapping.target.display()
+                ),
+            ));
+        }
+    }
+
+    for (target, sources) in &sources_by_target {
+        if sources.len() > 1 {
+            let sources = sources
+                .iter()
+                .map(|source| source.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            issues.push(error(
+                "config",
+                format!(
+                    "target {} is mapped from multiple sources: {sources}",
+                    target.display()
+                ),
+            ));
+        }
+    }
+
+    // A rule that resolves but produces nothing is either fully shadowed by an
+    // earlier rule or filtered down to zero files.
+    let candidates = config
+        .links
+        .iter()
+        .enumerate()
+        .filter(|(_, rule)| {
+            !rule.targets.is_empty()
+                && applies_on_current_os(&rule.only_on, &rule.skip_on)
+                && rule.when.as_ref().is_none_or(When::holds)
+        })
+        .map(|(index, _)| rule_label(&MappingKind::ConfigFile, index))
+        .chain(
+            config
+                .skills_sets
+                .iter()
+                .enumerate()
+                .filter(|(_, set)| {
+                    !set.target_roots.is_empty()
+                        && applies_on_current_os(&set.only_on, &set.skip_on)
+                        && set.when.as_ref().is_none_or(When::holds)
+                        && resolve_path(&set.source_root, ctx).is_dir()
+                })
+                .map(|(index, _)| rule_label(&MappingKind::SkillFile, index)),
+        );
+    for rule_name in candidates {
+        if !productive_rules.contains(&rule_name) {
+            issues.push(warning(
+                &rule_name,
+                "rule produces no mappings (filtered out or shadowed by an earlier rule)"
+                    .to_owned(),
+            ));
+        }
+    }
+
+    issues
+}
+
+/// Checks each skill directory of the config's skills sets for the
+/// `SKILL.md` agents load it by: a frontmatter `name` matching the
+/// directory and a `description`.
+pub(crate) fn lint_skills(config: &ConfigFile, ctx: &ResolveContext) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    for (index, set) in config.skills_sets.iter().enumerate() {
+        if !applies_on_current_os(&set.only_on, &set.skip_on)
+            || !set.when.as_ref().is_none_or(When::holds)
+        {
+            continue;
+        }
+        let source_root = resolve_path(&set.source_root, ctx);
+        let entries = match fs::read_dir(&source_root) {
+            Ok(entries) => entries,
+            Err(err) => {
+                issues.push(warning(
+                    &format!("skills_sets[{index}]"),
+                    format!("cannot read source_root {}: {err}", source_root.display()),
+                ));
+                continue;
+            }
+        };
+        let mut skills = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_dir())
+            .filter_map(|entry| entry.file_name().to_str().map(str::to_owned))
+            .filter(|name| !name.starts_with('.'))
+            .filter(|name| {
+                if set.only_skills.is_empty() {
+                    !set.exclude_skills.contains(name)
+                } else {
+                    set.only_skills.contains(name)
+                }
+            })
+            .collect::<Vec<_>>();
+        skills.sort();
+        for skill in skills {
+            let rule_name = format!("skills_sets[{index}]: {skill}");
+            let manifest = source_root.join(&skill).join(SKILL_MANIFEST);
+            match fs::read_to_string(&manifest) {
+                Ok(text) => lint_manifest(&mut issues, &rule_name, &skill, &text),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                    issues.push(error(&rule_name, format!("skill has no {SKILL_MANIFEST}")));
+                }
+                Err(err) => issues.push(error(
+                    &rule_name,
+                    format!("cannot read {}: {err}", manifest.display()),
+                )),
+            }
+        }
+    }
+    issues
+}
+
+fn lint_manifest(issues: &mut Vec<Issue>, rule_name: &str, skill: &str, text: &str) {
+    let Some((frontmatter, _, _)) = split_frontmatter(text) else {
+        issues.push(error(
+            rule_name,
+            format!("{SKILL_MANIFEST} does not open with a --- frontmatter block"),
+        ));
+        return;
+    };
+    let meta = match yaml::from_str(frontmatter) {
+        Ok(serde_json::Value::Object(meta)) => meta,
+        Ok(_) => {
+            issues.push(error(
+                rule_name,
+                format!("{SKILL_MANIFEST} frontmatter is not a mapping"),
+            ));
+            return;
+        }
+        Err(err) => {
+            issues.push(error(
+                rule_name,
+                format!("{SKILL_MANIFEST} frontmatter is invalid: {err:#}"),
+            ));
+            return;
+        }
+    };
+
+    match meta
+        .get("name")
+        .and_then(|name| name.as_str())
+        .map(str::trim)
+    {
+        None | Some("") => issues.push(error(
+            rule_name,
+            format!("{SKILL_MANIFEST} frontmatter has no name"),
+        )),
+        Some(name) => {
+            let well_formed = name.len() <= MAX_SKILL_NAME_LEN
+                && name
+                    .bytes()
+                    .all(|byte| byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'-');
+            if !well_formed {
+                issues.push(error(
+                    rule_name,
+                    format!(
+                        "name `{name}` must be lowercase letters, digits and hyphens, at most {MAX_SKILL_NAME_LEN} characters"
+                    ),
+                ));
+            }
+            if name != skill {
+                issues.push(warning(
+                    rule_name,
+                    format!("name `{name}` does not match the skill directory"),
+                ));
+            }
+        }
+    }
+
+    match meta
+        .get("description")
+        .and_then(|description| description.as_str())
+        .map(str::trim)
+    {
+        None | Some("") => issues.push(error(
+            rule_name,
+            format!("{SKILL_MANIFEST} frontmatter has no description"),
+        )),
+        Some(description) if description.chars().count() > MAX_SKILL_DESCRIPTION_LEN => {
+            issues.push(error(
+                rule_name,
+                format!("description is over {MAX_SKILL_DESCRIPTION_LEN} characters"),
+            ));
+        }
+        Some(_) => {}
+    }
+}
+
+pub(crate) fn print_issues(issues: &[Issue], json: bool, command: &str) -> Result<()> {
+    if json {
+        let json_text = serde_json::to_string_pretty(issues).context("failed to serialize JSON")?;
+        println!("{json_text}");
+        return Ok(());
+    }
+
+    for issue in issues {
+        let severity = match issue.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        println!("{severity}: {}: {}", issue.rule, issue.message);
+    }
+    let errors = issues
+        .iter()
+        .filter(|issue| issue.severity == Severity::Error)
+        .count();
+    println!(
+        "{command}: {errors} error(s), {} warning(s)",
+        issues.len() - errors
+    );
+
+    Ok(())
+}
+
+fn check_tokens(issues: &mut Vec<Issue>, rule_name: &str, raw: &str, ctx: &ResolveContext) {
+    for token in unresolved_tokens(raw, ctx) {
+        issues.push(error(
+            rule_name,
+            format!("cannot resolve {token} in path: {raw}"),
+        ));
+    }
+}
+
+fn is_inside_git_dir(path: &Path) -> bool {
+    path.components()
+        .any(|component| component == Component::Normal(".git".as_ref()))
+}
+
+fn rule_label(kind: &MappingKind, index: usize) -> String {
+    format!("{}[{index}]", kind.rule_kind())
+}
+
+fn error(rule: &str, message: String) -> Issue {
+    Issue {
+        severity: Severity::Error,
+        rule: rule.to_owned(),
+        message,
+    }
+}
+
+fn warning(rule: &str, message: String) -> Issue {
+    Issue {
+        severity: Severity::Warning,
+        rule: rule.to_owned(),
+        message,
+    }
+}
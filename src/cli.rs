@@ -1,8 +1,15 @@
-use std::path::PathBuf;
+use std::env;
+use std::fmt;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-use clap::{Parser, Subcommand, ValueEnum};
+use chrono::{DateTime, Duration, Utc};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use serde::{Deserialize, Serialize};
 
+use crate::vendors::{Vendor, vendor, vendors};
+
 #[derive(Debug, Parser)]
 #[command(
     name = "prompt-sync",
@@ -10,13 +17,38 @@ use serde::{Deserialize, Serialize};
     about = "Hardlink manager for AI instruction/skills files"
 )]
 pub struct Cli {
-    /// Path to config TOML.
-    #[arg(long, default_value = "prompt-sync.toml")]
-    pub config: PathBuf,
+    /// Path to config file (.toml, .yaml/.yml or .json). Repeat to merge
+    /// several configs; later files win for the same target.
+    /// [default: user config, then ./prompt-sync.toml]
+    #[arg(long)]
+    pub config: Vec<PathBuf>,
+
+    /// Skip $XDG_CONFIG_HOME/prompt-sync/config.toml when no --config is given.
+    #[arg(long)]
+    pub no_user_config: bool,
+
+    /// Verbose output; repeat for span timings (-vv) and per-record
+    /// detail (-vvv). `RUST_LOG=debug` etc. works too.
+    #[arg(long, short, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Directory for prompt-sync state [default: $XDG_STATE_HOME/prompt-sync].
+    #[arg(long)]
+    pub state_dir: Option<PathBuf>,
+
+    /// Free space to leave on the backup volume, in bytes or with a K, M or G
+    /// suffix [default: 64M].
+    #[arg(long, value_parser = parse_size)]
+    pub backup_min_free: Option<u64>,
+
+    /// Wait for another running prompt-sync instead of failing when the
+    /// state-dir lock is held.
+    #[arg(long)]
+    pub wait: bool,
 
-    /// Verbose output.
-    #[arg(long, short)]
-    pub verbose: bool,
+    /// Do not take the state-dir lock for commands that change targets.
+    #[arg(long, conflicts_with = "wait")]
+    pub no_lock: bool,
 
     #[command(subcommand)]
     pub command: Command,
@@ -31,8 +63,16 @@ pub enum Command {
         force: bool,
 
         /// Include vendor profile(s) in the generated template.
-        #[arg(long = "profile", value_enum)]
+        #[arg(long = "profile")]
         profiles: Vec<Profile>,
+
+        /// Config file format [default: from the --config extension].
+        #[arg(long, value_enum)]
+        format: Option<ConfigFormat>,
+
+        /// Build the config from instruction files already on this machine.
+        #[arg(long)]
+        from_existing: bool,
     },
     /// Create/update hardlinks based on config.
     Link {
@@ -44,23 +84,149 @@ pub enum Command {
         #[arg(long)]
         force: bool,
 
+        /// With --force, only replace targets that prompt-sync created.
+        #[arg(long)]
+        owned_only: bool,
+
         /// Show planned changes without touching files.
         #[arg(long)]
         dry_run: bool,
 
-        /// Emit JSON output.
+        /// Roll back every change of this run if any target fails.
+        #[arg(long, conflicts_with = "dry_run")]
+        atomic: bool,
+
+        #[command(flatten)]
+        output: OutputArgs,
+
+        /// Backup directory for files replaced by --force.
         #[arg(long)]
-        json: bool,
+        backup_dir: Option<PathBuf>,
+
+        #[command(flatten)]
+        mapping: MappingArgs,
+
+        /// Remove previously linked targets that the config no longer produces.
+        #[arg(long)]
+        prune: bool,
+    },
+    /// Write the changes `link` would make to a plan file, to review and
+    /// carry out later with `apply`.
+    Plan {
+        /// Plan file to write (JSON).
+        #[arg(long)]
+        out: PathBuf,
+
+        /// Only plan links that do not exist yet.
+        #[arg(long)]
+        only_missing: bool,
+
+        /// Plan to replace conflicting targets.
+        #[arg(long)]
+        force: bool,
+
+        /// Plan to remove previously linked targets that the config no
+        /// longer produces.
+        #[arg(long)]
+        prune: bool,
+
+        #[command(flatten)]
+        output: OutputArgs,
+
+        #[command(flatten)]
+        mapping: MappingArgs,
+    },
+    /// Carry out a plan written by `plan`, refusing it when a config, source
+    /// or target changed since.
+    Apply {
+        /// Plan file written by `plan --out`.
+        #[arg(long)]
+        plan: PathBuf,
+
+        /// Backup directory for files the plan replaces.
+        #[arg(long)]
+        backup_dir: Option<PathBuf>,
+
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Pull the `[master] repo` and URL sources, link, prune orphans and
+    /// report it all at once. Targets prompt-sync linked before are
+    /// replaced, as a pull leaves them stale.
+    Sync {
+        /// Also replace conflicting targets prompt-sync did not create.
+        #[arg(long)]
+        force: bool,
+
+        /// Show planned changes without touching the clone or any target.
+        #[arg(long)]
+        dry_run: bool,
+
+        #[command(flatten)]
+        output: OutputArgs,
 
         /// Backup directory for files replaced by --force.
         #[arg(long)]
         backup_dir: Option<PathBuf>,
+
+        #[command(flatten)]
+        mapping: MappingArgs,
+
+        /// Keep previously linked targets that the config no longer produces.
+        #[arg(long)]
+        no_prune: bool,
     },
     /// Verify link integrity.
     Verify {
-        /// Emit JSON output.
+        /// Compare SHA-256 of source and target and report `content_match`.
         #[arg(long)]
-        json: bool,
+        hash: bool,
+
+        /// Repair MISSING/BROKEN targets in the same pass.
+        #[arg(long)]
+        fix: bool,
+
+        /// With --fix, also overwrite CONFLICT targets.
+        #[arg(long, requires = "fix")]
+        force: bool,
+
+        /// Backup directory for files replaced by --fix --force.
+        #[arg(long, requires = "fix")]
+        backup_dir: Option<PathBuf>,
+
+        /// JSON file of target/status pairs reported as ACCEPTED, which do
+        /// not fail the run.
+        #[arg(long)]
+        baseline: Option<PathBuf>,
+
+        /// Write the current MISSING/BROKEN/CONFLICT targets to --baseline.
+        #[arg(long, requires = "baseline", conflicts_with = "fix")]
+        update_baseline: bool,
+
+        #[command(flatten)]
+        output: OutputArgs,
+
+        #[command(flatten)]
+        mapping: MappingArgs,
+    },
+    /// Show how a target or source path resolves, its state and why, and
+    /// what link and repair would do with it.
+    Explain {
+        /// A target or source path.
+        path: PathBuf,
+
+        #[command(flatten)]
+        mapping: MappingArgs,
+    },
+    /// Open the master, or another rule's source, in $EDITOR and repair its
+    /// targets afterwards.
+    Edit {
+        /// Source to open, as written in the config or as a path
+        /// [default: the single `[[links]]` source].
+        source: Option<String>,
+
+        #[command(flatten)]
+        output: OutputArgs,
     },
     /// Repair missing/broken links.
     Repair {
@@ -68,23 +234,192 @@ pub enum Command {
         #[arg(long)]
         force: bool,
 
+        /// Only replace targets that prompt-sync created.
+        #[arg(long)]
+        owned_only: bool,
+
         /// Show planned changes without touching files.
         #[arg(long)]
         dry_run: bool,
 
-        /// Emit JSON output.
+        /// Roll back every change of this run if any target fails.
+        #[arg(long, conflicts_with = "dry_run")]
+        atomic: bool,
+
+        #[command(flatten)]
+        output: OutputArgs,
+
+        /// Backup directory for files replaced by --force.
         #[arg(long)]
-        json: bool,
+        backup_dir: Option<PathBuf>,
+
+        #[command(flatten)]
+        mapping: MappingArgs,
+    },
+    /// Write the targets of `generate` rules with a managed header.
+    Generate {
+        /// Also overwrite generated targets that were edited by hand.
+        #[arg(long)]
+        force: bool,
+
+        /// Show planned changes without touching files.
+        #[arg(long)]
+        dry_run: bool,
+
+        #[command(flatten)]
+        output: OutputArgs,
 
         /// Backup directory for files replaced by --force.
         #[arg(long)]
         backup_dir: Option<PathBuf>,
+
+        #[command(flatten)]
+        mapping: MappingArgs,
     },
-    /// Print short status summary.
-    Status {
+    /// Print resolved mappings and the action link would take.
+    List {
         /// Emit JSON output.
         #[arg(long)]
         json: bool,
+
+        #[command(flatten)]
+        mapping: MappingArgs,
+    },
+    /// Print short status summary.
+    Status {
+        #[command(flatten)]
+        output: OutputArgs,
+
+        #[command(flatten)]
+        mapping: MappingArgs,
+
+        /// Only report targets linked since a time (RFC 3339 or age like 7d, 12h, 30m).
+        #[arg(long, value_parser = parse_since)]
+        since: Option<DateTime<Utc>>,
+
+        /// Print the merged config layers instead of link status.
+        #[arg(long)]
+        dump_config: bool,
+
+        /// Open an interactive dashboard to repair, force or adopt targets.
+        #[arg(long, conflicts_with = "dump_config")]
+        tui: bool,
+
+        /// Print one line like `prompt-sync: 2 drifted` from a stat-only
+        /// check, fast enough for a shell prompt.
+        #[arg(long, conflicts_with_all = ["dump_config", "tui"])]
+        porcelain: bool,
+    },
+    /// Make the sources and targets read-only, or with --unprotect writable
+    /// again for an editing session.
+    Protect {
+        /// Give write access back instead.
+        #[arg(long)]
+        unprotect: bool,
+
+        #[command(flatten)]
+        mapping: MappingArgs,
+    },
+    /// Remove targets that are still linked to their configured source.
+    Unlink {
+        /// Show planned changes without touching files.
+        #[arg(long)]
+        dry_run: bool,
+
+        #[command(flatten)]
+        output: OutputArgs,
+
+        #[command(flatten)]
+        mapping: MappingArgs,
+    },
+    /// Add targets to the `[[links]]` rule for a source, or a new rule,
+    /// keeping the config's comments and layout.
+    Add {
+        /// Source file, written into the config as given.
+        #[arg(long)]
+        source: String,
+
+        /// Target path; repeat for several.
+        #[arg(long = "target", required = true)]
+        targets: Vec<String>,
+
+        /// Link type of the rule; a rule for the source with another type
+        /// is left alone.
+        #[arg(long = "type", value_enum)]
+        link_type: Option<LinkType>,
+
+        /// Link the added targets afterwards.
+        #[arg(long)]
+        link: bool,
+
+        /// Print the edited config instead of writing it.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Remove targets, or without --target the whole rule, from the
+    /// `[[links]]` rules for a source.
+    Remove {
+        /// Source file of the rule.
+        #[arg(long)]
+        source: String,
+
+        /// Target path to remove; repeat for several.
+        #[arg(long = "target")]
+        targets: Vec<String>,
+
+        /// Unlink the removed targets that are still linked to the source.
+        #[arg(long)]
+        unlink: bool,
+
+        /// Print the edited config instead of writing it.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Watch sources and re-run repair whenever they change.
+    Watch {
+        /// Polling interval in seconds.
+        #[arg(long, default_value_t = 1)]
+        interval: u64,
+
+        /// Exit after this many polls; for tests.
+        #[arg(long, hide = true)]
+        max_passes: Option<u64>,
+
+        /// Also overwrite CONFLICT targets.
+        #[arg(long)]
+        force: bool,
+
+        #[command(flatten)]
+        output: OutputArgs,
+
+        /// Backup directory for files replaced by --force.
+        #[arg(long)]
+        backup_dir: Option<PathBuf>,
+
+        #[command(flatten)]
+        mapping: MappingArgs,
+    },
+    /// Re-verify every mapping on an interval and repair missing or broken
+    /// targets, journaling each pass.
+    Daemon {
+        /// Seconds between passes.
+        #[arg(long, default_value_t = 300)]
+        interval: u64,
+
+        /// Run a single pass and exit, e.g. from cron.
+        #[arg(long)]
+        once: bool,
+
+        /// Fork into the background and detach from the terminal.
+        #[arg(long, conflicts_with = "once")]
+        detach: bool,
+
+        /// Backup directory for files replaced while repairing.
+        #[arg(long)]
+        backup_dir: Option<PathBuf>,
+
+        #[command(flatten)]
+        mapping: MappingArgs,
     },
     /// One-tap setup for common vendor paths (alias: magic).
     #[command(visible_alias = "magic")]
@@ -97,9 +432,8 @@ pub enum Command {
         #[arg(long)]
         dry_run: bool,
 
-        /// Emit JSON output.
-        #[arg(long)]
-        json: bool,
+        #[command(flatten)]
+        output: OutputArgs,
 
         /// Persist discovered config into --config path.
         #[arg(long)]
@@ -108,6 +442,59 @@ pub enum Command {
         /// Backup directory for files replaced by --force.
         #[arg(long)]
         backup_dir: Option<PathBuf>,
+
+        /// Override the link type of every rule.
+        #[arg(long, value_enum)]
+        link_type: Option<LinkType>,
+
+        /// Only set up this vendor, installed or not (repeatable).
+        #[arg(long = "profile")]
+        profiles: Vec<Profile>,
+
+        /// Link into this vendor even when it is not detected as installed (repeatable).
+        #[arg(long = "include", value_name = "PROFILE")]
+        include: Vec<Profile>,
+
+        /// Skip this vendor even when it is installed (repeatable).
+        #[arg(long = "exclude", value_name = "PROFILE")]
+        exclude: Vec<Profile>,
+
+        /// Go ahead without showing the plan and asking for confirmation.
+        #[arg(short = 'y', long)]
+        yes: bool,
+
+        /// Only link targets in the home directory (`~/...`).
+        #[arg(long, conflicts_with = "repo_only")]
+        home_only: bool,
+
+        /// Only link targets in the current repository (`<repo>/...`).
+        #[arg(long)]
+        repo_only: bool,
+    },
+    /// Change the targets of the config's existing rules.
+    Target {
+        #[command(subcommand)]
+        action: TargetCommand,
+    },
+    /// Inspect or validate the config file.
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+    /// Check the skills in skills_sets.
+    Skills {
+        #[command(subcommand)]
+        action: SkillsCommand,
+    },
+    /// Browse backups taken by --backup-dir.
+    Backup {
+        #[command(subcommand)]
+        action: BackupCommand,
+    },
+    /// Query the operation journal written by `[logging]`.
+    Log {
+        #[command(subcommand)]
+        action: LogCommand,
     },
     /// Install commit-msg hook to block AI co-author trailers.
     InstallCommitGuard {
@@ -115,22 +502,610 @@ pub enum Command {
         #[arg(long, default_value = ".")]
         repo: PathBuf,
 
+        /// Act on every repository the config's `[repos]` section lists or scans.
+        #[arg(long, conflicts_with = "repo")]
+        all_repos: bool,
+
         /// Overwrite existing hook file.
         #[arg(long)]
         force: bool,
 
+        /// Keep an existing commit-msg hook: move it aside and run it before the guard.
+        #[arg(long, conflicts_with = "force")]
+        chain: bool,
+
+        /// Strip AI attribution lines, or reject the commit and list them.
+        #[arg(long, value_enum, default_value_t = GuardMode::Strip)]
+        mode: GuardMode,
+
+        /// Remove the guard and put back the hook --chain moved aside.
+        #[arg(long, conflicts_with_all = ["force", "chain"])]
+        uninstall: bool,
+
+        /// Report whether the guard is installed, its version and local edits.
+        #[arg(long, conflicts_with_all = ["force", "chain", "uninstall", "dry_run"])]
+        status: bool,
+
         /// Show planned changes without touching files.
         #[arg(long)]
         dry_run: bool,
     },
+    /// Install post-merge and post-checkout hooks that repair the repo's targets.
+    InstallRepairHooks {
+        /// Repository root path. Defaults to current directory.
+        #[arg(long, default_value = ".")]
+        repo: PathBuf,
+
+        /// Overwrite existing hook files.
+        #[arg(long)]
+        force: bool,
+
+        /// Show planned changes without touching files.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Write a config taking over the AI instruction files and skills
+    /// another dotfile manager keeps, or turn symlinked targets into links.
+    Import {
+        /// Dotfile manager whose tree is read.
+        #[arg(long, value_enum, required_unless_present = "convert_symlinks")]
+        from: Option<ImportSource>,
+
+        /// chezmoi source directory, or stow directory or package.
+        #[arg(required_unless_present = "convert_symlinks")]
+        path: Option<PathBuf>,
+
+        /// Replace targets that are symlinks to their source with the rule's
+        /// link type, adding untracked ones to the config.
+        #[arg(long, conflicts_with_all = ["from", "path", "force", "format"])]
+        convert_symlinks: bool,
+
+        /// Overwrite existing config.
+        #[arg(long)]
+        force: bool,
+
+        /// Config file format [default: from the --config extension].
+        #[arg(long, value_enum)]
+        format: Option<ConfigFormat>,
+
+        /// Print the config instead of writing it; with --convert-symlinks,
+        /// report what would change.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Write the configured targets out in another tool's layout.
+    Export {
+        /// Layout to write.
+        #[arg(long, value_enum)]
+        format: ExportFormat,
+
+        /// Stow directory the package is created in.
+        dir: PathBuf,
+
+        /// Package name inside the stow directory.
+        #[arg(long, default_value = "prompt-sync")]
+        package: String,
+
+        /// Directory stow links into [default: home directory].
+        #[arg(long)]
+        target_dir: Option<PathBuf>,
+
+        /// Replace package files that differ from their source.
+        #[arg(long)]
+        force: bool,
+
+        /// Show planned changes without touching files.
+        #[arg(long)]
+        dry_run: bool,
+
+        #[command(flatten)]
+        mapping: MappingArgs,
+    },
+    /// Replace this executable with the latest GitHub release build.
+    SelfUpdate {
+        /// Only report whether a newer release exists (exit 1 if so).
+        #[arg(long)]
+        check: bool,
+
+        /// Install this release tag instead of the latest, even if older.
+        #[arg(long, value_name = "TAG")]
+        version: Option<String>,
+
+        /// Reinstall even when already up to date.
+        #[arg(long)]
+        force: bool,
+    },
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ValueEnum, Serialize, Deserialize)]
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Validate the config without touching any file.
+    Check {
+        /// Emit JSON output.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Upgrade the config file to the current version, keeping a backup.
+    Migrate {
+        /// Print the migrated config without writing it.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Print the JSON Schema of the config format.
+    Schema {
+        /// Write the schema to this file instead of stdout.
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SkillsCommand {
+    /// Flag skills whose SKILL.md is missing or lacks name and description.
+    Lint {
+        /// Emit JSON output.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show what each target root holds of every skill: missing, drifted
+    /// and extra files.
+    Diff {
+        /// Emit JSON output.
+        #[arg(long)]
+        json: bool,
+
+        #[command(flatten)]
+        mapping: MappingArgs,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TargetCommand {
+    /// Append targets to the `[[links]]` rule for a source, skipping ones it
+    /// has and refusing ones `config check` would reject.
+    Add {
+        /// Source file of the rule.
+        #[arg(long)]
+        source: String,
+
+        /// Target paths to append.
+        #[arg(required = true)]
+        targets: Vec<String>,
+
+        /// Link the appended targets afterwards.
+        #[arg(long)]
+        link: bool,
+
+        /// Print the edited config instead of writing it.
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum BackupCommand {
+    /// List backups recorded in the backup directory's manifest.
+    List {
+        /// Backup directory to read.
+        #[arg(long)]
+        backup_dir: PathBuf,
+
+        /// Emit JSON output.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Put a replaced target's original content back from its backup.
+    Restore {
+        /// Original target path, or a backup id (the backup file name).
+        target: String,
+
+        /// Backup directory to read.
+        #[arg(long)]
+        backup_dir: PathBuf,
+
+        /// Restore the newest backup taken at or before this time (RFC 3339
+        /// or age like 7d) instead of the newest one.
+        #[arg(long, value_parser = parse_since)]
+        at: Option<DateTime<Utc>>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum LogCommand {
+    /// Print journal entries, oldest first, matching every given filter.
+    Show {
+        /// Only entries since a time (RFC 3339 or age like 7d, 12h, 30m).
+        #[arg(long, value_parser = parse_since)]
+        since: Option<DateTime<Utc>>,
+
+        /// Only entries for this target path.
+        #[arg(long)]
+        target: Option<PathBuf>,
+
+        /// Only entries that succeeded or failed.
+        #[arg(long, value_enum)]
+        status: Option<LogStatus>,
+
+        /// Only entries of this action.
+        #[arg(long, value_enum)]
+        action: Option<LogAction>,
+
+        /// Emit JSON output.
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// Outcomes selectable with `log show --status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogStatus {
+    Ok,
+    Failed,
+}
+
+/// Journal actions selectable with `log show --action`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogAction {
+    Create,
+    Replace,
+    Remove,
+    Prune,
+    Rollback,
+    Restore,
+}
+
+impl LogAction {
+    /// The `action` field this value matches in the journal.
+    pub(crate) const fn journal_label(self) -> &'static str {
+        match self {
+            LogAction::Create => "CREATED",
+            LogAction::Replace => "REPLACED",
+            LogAction::Remove => "REMOVED",
+            LogAction::Prune => "PRUNED",
+            LogAction::Rollback => "ROLLED_BACK",
+            LogAction::Restore => "RESTORED",
+        }
+    }
+}
+
+/// What the commit guard does with AI attribution lines.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum GuardMode {
+    /// Remove them from the message and let the commit through.
+    #[default]
+    Strip,
+    /// Abort the commit, listing the offending lines.
+    Reject,
+}
+
+impl GuardMode {
+    pub(crate) const fn as_str(self) -> &'static str {
+        match self {
+            GuardMode::Strip => "strip",
+            GuardMode::Reject => "reject",
+        }
+    }
+}
+
+/// Options shaping which mappings a command works on.
+#[derive(Debug, Clone, Default, Args)]
+pub struct MappingArgs {
+    /// Override the link type of every rule.
+    #[arg(long, value_enum)]
+    pub link_type: Option<LinkType>,
+
+    /// Only include rules tagged with this profile (repeatable).
+    #[arg(long = "profile")]
+    pub profiles: Vec<Profile>,
+
+    /// Only include config-file or skill-file mappings.
+    #[arg(long, value_enum)]
+    pub kind: Option<KindFilter>,
+
+    /// Only include mappings whose target path contains this text.
+    #[arg(long)]
+    pub target_contains: Option<String>,
+
+    /// Only include mappings whose source is this file or lies under this directory.
+    #[arg(long)]
+    pub source: Option<PathBuf>,
+
+    /// Expand `<repo>` rules across this repository instead of `[repos]`
+    /// roots or the current directory (repeatable).
+    #[arg(long = "repo")]
+    pub repos: Vec<PathBuf>,
+
+    /// Not a flag: download URL sources. Set for the commands that link;
+    /// the others read the cached copy.
+    #[arg(skip)]
+    pub fetch: bool,
+
+    /// Not a flag: build every rule, whatever its `only_on`, `skip_on` and
+    /// `when`, to know which targets `--prune` must leave alone.
+    #[arg(skip)]
+    pub every_rule: bool,
+}
+
+/// Mapping kinds selectable with `--kind`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum KindFilter {
+    /// Mappings produced by `links` rules.
+    Config,
+    /// Mappings produced by `skills_sets`.
+    Skill,
+}
+
+/// Options controlling how a command reports its records.
+#[derive(Debug, Clone, Default, Args)]
+pub struct OutputArgs {
+    /// Emit JSON output.
+    #[arg(long)]
+    pub json: bool,
+
+    /// Emit one JSON object per record as it is processed, then a summary line.
+    #[arg(long, conflicts_with = "json")]
+    pub json_lines: bool,
+
+    /// Report format; `--json` is short for `--output json`.
+    #[arg(long = "output", value_enum, conflicts_with_all = ["json", "json_lines"])]
+    pub format: Option<OutputFormat>,
+
+    /// Print nothing but error records (on stderr); rely on the exit code.
+    #[arg(long, short, conflicts_with_all = ["json", "json_lines", "format", "summary_only"])]
+    pub quiet: bool,
+
+    /// Print only the one-line status counts.
+    #[arg(long, conflicts_with_all = ["json", "json_lines", "format"])]
+    pub summary_only: bool,
+
+    /// Print the JSON Schema of the `--json` report instead of running.
+    #[arg(long)]
+    pub print_schema: bool,
+
+    /// After the report, print the run time and the slowest records.
+    #[arg(long, conflicts_with_all = ["json", "json_lines", "quiet"])]
+    pub timing: bool,
+
+    /// Never draw the progress line (shown on terminals for text output).
+    #[arg(long)]
+    pub no_progress: bool,
+
+    /// Color statuses in text output (auto honours NO_COLOR and TTY detection).
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    pub color: ColorChoice,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Table,
+    /// One status glyph per record, then the counts and any errors.
+    Compact,
+    Json,
+    Yaml,
+    /// GitHub Actions `::error`/`::warning` annotations for problem records.
+    Github,
+    /// Porcelain v1: status, kind, source and target per record, tab
+    /// separated and stable across versions.
+    Porcelain,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl Command {
+    /// The report options of commands that print a `Report`.
+    pub(crate) const fn output_args(&self) -> Option<&OutputArgs> {
+        match self {
+            Command::Link { output, .. }
+            | Command::Sync { output, .. }
+            | Command::Verify { output, .. }
+            | Command::Repair { output, .. }
+            | Command::Generate { output, .. }
+            | Command::Status { output, .. }
+            | Command::Unlink { output, .. }
+            | Command::Edit { output, .. }
+            | Command::Plan { output, .. }
+            | Command::Apply { output, .. }
+            | Command::Watch { output, .. }
+            | Command::Bootstrap { output, .. } => Some(output),
+            _ => None,
+        }
+    }
+}
+
+impl OutputArgs {
+    pub(crate) fn use_color(&self) -> bool {
+        match self.color {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                    && env::var_os("TERM").is_none_or(|term| term != "dumb")
+                    && io::stdout().is_terminal()
+            }
+        }
+    }
+
+    pub(crate) fn format(&self) -> OutputFormat {
+        if self.json {
+            OutputFormat::Json
+        } else {
+            self.format.unwrap_or_default()
+        }
+    }
+}
+
+/// A vendor in the registry (`src/vendors.toml` plus the user's
+/// `vendors.toml`), naming the tool a rule, target or section is for.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(transparent)]
+pub struct Profile(String);
+
+impl Profile {
+    pub(crate) fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The profile of a registered vendor.
+    pub(crate) fn of(vendor: &Vendor) -> Self {
+        Self(vendor.name.clone())
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for Profile {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> anyhow::Result<Self> {
+        match vendor(name) {
+            Some(vendor) => Ok(Self::of(vendor)),
+            None => Err(anyhow::anyhow!(
+                "unknown profile `{name}` (known: {})",
+                vendors()
+                    .iter()
+                    .map(|vendor| vendor.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Profile {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ImportSource {
+    /// A chezmoi source directory (`chezmoi source-path`).
+    Chezmoi,
+    /// A GNU Stow directory or package, stowed into the home directory.
+    Stow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// A GNU Stow package mirroring the targets below `--target-dir`.
+    Stow,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ConfigFormat {
+    #[default]
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// Picks the format from a config file extension, defaulting to TOML.
+    pub(crate) fn from_path(path: &Path) -> Self {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("yaml" | "yml") => ConfigFormat::Yaml,
+            Some("json") => ConfigFormat::Json,
+            _ => ConfigFormat::Toml,
+        }
+    }
+
+    pub(crate) const fn extension(self) -> &'static str {
+        match self {
+            ConfigFormat::Toml => "toml",
+            ConfigFormat::Yaml => "yaml",
+            ConfigFormat::Json => "json",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, ValueEnum, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
-pub enum Profile {
-    Codex,
-    Claude,
-    Gemini,
-    Copilot,
-    Kiro,
+pub enum LinkType {
+    #[default]
+    Hardlink,
+    Symlink,
+    Copy,
+    /// Hardlink off Windows; on Windows a symlink when the account may
+    /// create one (Developer Mode or elevation), otherwise a copy.
+    Auto,
+    /// Copy-on-write clone (Btrfs, XFS, APFS); a plain copy elsewhere.
+    Reflink,
+    /// A copy rendered per target, keeping only the `<!-- only: ... -->`
+    /// sections for the target's profile.
+    Generate,
+}
+
+impl LinkType {
+    pub(crate) const fn as_str(self) -> &'static str {
+        match self {
+            LinkType::Hardlink => "hardlink",
+            LinkType::Symlink => "symlink",
+            LinkType::Copy => "copy",
+            LinkType::Auto => "auto",
+            LinkType::Reflink => "reflink",
+            LinkType::Generate => "generate",
+        }
+    }
+}
+
+/// Parses `--backup-min-free` values: bytes, or a number with K, M or G.
+pub(crate) fn parse_size(raw: &str) -> Result<u64, String> {
+    let (amount, scale) = match raw.char_indices().last() {
+        Some((index, unit)) if unit.is_ascii_alphabetic() => {
+            let scale = match unit.to_ascii_uppercase() {
+                'K' => 1 << 10,
+                'M' => 1 << 20,
+                'G' => 1 << 30,
+                _ => return Err(format!("invalid size unit in {raw} (use K, M or G)")),
+            };
+            (&raw[..index], scale)
+        }
+        _ => (raw, 1),
+    };
+    amount
+        .parse::<u64>()
+        .ok()
+        .and_then(|amount| amount.checked_mul(scale))
+        .ok_or_else(|| format!("invalid size: {raw} (use bytes or e.g. 512K, 64M, 1G)"))
+}
+
+/// Parses `--since` and `--at` values: an RFC 3339 timestamp or an age
+/// such as `7d`.
+pub(crate) fn parse_since(raw: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(raw) {
+        return Ok(timestamp.with_timezone(&Utc));
+    }
+
+    let split = raw.len().saturating_sub(1);
+    let (amount, unit) = raw.split_at(split);
+    let amount = amount
+        .parse::<i64>()
+        .map_err(|_| format!("invalid time or age: {raw} (use RFC 3339 or e.g. 7d, 12h, 30m)"))?;
+    let age = match unit {
+        "w" => Duration::try_weeks(amount),
+        "d" => Duration::try_days(amount),
+        "h" => Duration::try_hours(amount),
+        "m" => Duration::try_minutes(amount),
+        "s" => Duration::try_seconds(amount),
+        _ => return Err(format!("invalid age unit in {raw} (use w, d, h, m or s)")),
+    };
+    age.and_then(|age| Utc::now().checked_sub_signed(age))
+        .ok_or_else(|| format!("age out of range: {raw}"))
 }
@@ -1,35 +1,319 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::env;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, anyhow};
+use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
 
-use crate::cli::Profile;
-use crate::model::ResolveContext;
+use crate::cli::{ConfigFormat, LinkType, Profile};
+use crate::engine::build_glob_set;
+use crate::model::{PhaseTimer, ResolveContext, Tally};
+use crate::pathing::{absolute_path, expand_vars, home_dir, normalize_lexically, resolve_path};
+use crate::remote::is_url;
+use crate::render::hostname;
+use crate::trace::{self, Level, debug, info};
+use crate::vcs::scan_repositories;
+use crate::vendors::{Vendor, vendors};
+use crate::yaml;
+
+/// Current config layout version; `config migrate` upgrades older files.
+pub(crate) const CONFIG_VERSION: u32 = 1;
 
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub(crate) struct ConfigFile {
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) version: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub(crate) master: Option<MasterConfig>,
+    /// Default `on_cross_device` for rules that do not set their own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) on_cross_device: Option<CrossDevice>,
+    /// Default `git_exclude` for rules that do not set their own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) git_exclude: Option<bool>,
+    /// Default `protect` for rules that do not set their own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) protect: Option<bool>,
+    /// Default `substitute` for rules that do not set their own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) substitute: Option<bool>,
+    /// `[vars]`: `<name>` tokens substituted into generated content.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) vars: BTreeMap<String, String>,
+    /// `[transforms.<profile>]`: how generated targets are reshaped for
+    /// each tool.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) transforms: BTreeMap<Profile, TransformConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) logging: Option<LoggingConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) hooks: Option<HooksConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) watch: Option<WatchConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) repos: Option<ReposConfig>,
     #[serde(default)]
     pub(crate) links: Vec<LinkRule>,
     #[serde(default)]
     pub(crate) skills_sets: Vec<SkillsSet>,
+    /// `[hosts."<name>"]`: per-machine changes, applied by `load_config`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) hosts: BTreeMap<String, HostConfig>,
+}
+
+/// Changes for one machine, matched by its full hostname or first label.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct HostConfig {
+    /// `[master]` fields to override; sources under the old root move to
+    /// the new one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) master: Option<MasterConfig>,
+    /// Targets and target roots dropped from this file's rules.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) remove_targets: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) links: Vec<LinkRule>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) skills_sets: Vec<SkillsSet>,
+}
+
+/// `[transforms.<profile>]`: applied to `generate` output for targets of
+/// the profile, after sections and tokens.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct TransformConfig {
+    /// Keys added to the output's frontmatter; the master's own keys win.
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    pub(crate) frontmatter: serde_json::Map<String, serde_json::Value>,
+    /// Headings renamed by their text, e.g. `"Rules" = "Instructions"`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) headings: BTreeMap<String, String>,
+    /// Levels added to every heading, clamped to `#` .. `######`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) heading_offset: Option<i8>,
+    /// Largest output in bytes; longer output is cut at a line break.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) max_bytes: Option<usize>,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub(crate) struct MasterConfig {
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub(crate) root: Option<String>,
+    /// Git remote `root` is a clone of; `sync` clones or pulls it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) repo: Option<String>,
+    /// Branch `sync` clones; defaults to the remote's HEAD.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) branch: Option<String>,
+}
+
+/// `[logging]`: the append-only operation journal.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct LoggingConfig {
+    /// Defaults to on once the section is present.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) enabled: Option<bool>,
+    /// Journal file [default: `operations.ndjson` in the state directory].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) sink: Option<LogSink>,
+    /// Size the journal and a backup `.operations.log` grow to before they
+    /// are rotated, in bytes or with K, M or G [default: 1M]. The journal
+    /// only rotates once this or `max_files` is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) max_size: Option<String>,
+    /// Rotated, gzip-compressed generations kept [default: 5].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) max_files: Option<usize>,
+}
+
+/// `[hooks]`: shell commands run around `link`, `sync` and `repair`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct HooksConfig {
+    /// Run before linking; one that fails stops the run.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) pre_link: Vec<String>,
+    /// Run after `link` or `sync`, whether or not it changed anything.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) post_link: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) post_repair: Vec<String>,
+}
+
+/// `[watch]`: settings for `watch` and `daemon`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct WatchConfig {
+    /// Send a desktop notification when a pass finds drifted targets.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) notify: Option<bool>,
+}
+
+/// Where `[logging]` writes journal lines.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum LogSink {
+    /// The NDJSON journal file.
+    #[default]
+    File,
+    /// The system logger via `syslog(3)` (needs the `syslog` feature).
+    Syslog,
+    /// The systemd journal's native socket (needs the `syslog` feature).
+    Journald,
+}
+
+/// `[repos]`: repositories each `<repo>` rule is expanded across, instead
+/// of only the current directory.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct ReposConfig {
+    #[serde(default)]
+    pub(crate) roots: Vec<String>,
+    /// Directories searched for repositories, so new clones are picked up.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) scan: Vec<String>,
+    /// How deep `scan` looks [default: 3].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) scan_depth: Option<usize>,
+    /// Globs for directories `scan` does not enter.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) exclude: Vec<String>,
+}
+
+const DEFAULT_SCAN_DEPTH: usize = 3;
+
+impl LoggingConfig {
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled.unwrap_or(true)
+    }
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub(crate) struct LinkRule {
     pub(crate) source: String,
     #[serde(default)]
-    pub(crate) targets: Vec<String>,
+    pub(crate) targets: Vec<LinkTarget>,
+    #[serde(default, alias = "mode", skip_serializing_if = "Option::is_none")]
+    pub(crate) link_type: Option<LinkType>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) on_cross_device: Option<CrossDevice>,
+    /// List targets inside a git repo in its `.git/info/exclude`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) git_exclude: Option<bool>,
+    /// Keep the source and targets read-only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) protect: Option<bool>,
+    /// Substitute content tokens into `copy` targets too.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) substitute: Option<bool>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) only_on: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) skip_on: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) when: Option<When>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) profiles: Vec<Profile>,
+}
+
+/// `when = { env = "NAME", equals = "1" }`: the rule applies only while the
+/// variable has that value, or without `equals` while it is set and not
+/// empty.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct When {
+    pub(crate) env: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) equals: Option<String>,
+}
+
+impl When {
+    pub(crate) fn holds(&self) -> bool {
+        let value = env::var_os(&self.env).unwrap_or_default();
+        match &self.equals {
+            Some(expected) => value == expected.as_str(),
+            None => !value.is_empty(),
+        }
+    }
+}
+
+impl std::fmt::Display for When {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.equals {
+            Some(expected) => write!(f, "{}={expected}", self.env),
+            None => write!(f, "{} set", self.env),
+        }
+    }
+}
+
+/// What a hardlink rule does when source and target are on different
+/// filesystems, where hardlinks cannot be created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum CrossDevice {
+    /// Report the mapping as an error.
+    #[default]
+    Error,
+    /// Fall back to a symlink.
+    Symlink,
+    /// Fall back to a copy.
+    Copy,
+}
+
+impl CrossDevice {
+    /// The link type to use instead of a hardlink, if any.
+    pub(crate) const fn fallback(self) -> Option<LinkType> {
+        match self {
+            CrossDevice::Error => None,
+            CrossDevice::Symlink => Some(LinkType::Symlink),
+            CrossDevice::Copy => Some(LinkType::Copy),
+        }
+    }
+}
+
+/// A link target: either a file path, or a table with the path and, when
+/// `name` is set, the file name the source lands under inside `path`.
+/// `profile` picks the sections a `generate` rule keeps.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum LinkTarget {
+    Path(String),
+    Table {
+        path: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        profile: Option<Profile>,
+    },
+}
+
+impl LinkTarget {
+    pub(crate) fn path(&self) -> &str {
+        match self {
+            LinkTarget::Path(path) | LinkTarget::Table { path, .. } => path,
+        }
+    }
+
+    pub(crate) fn name(&self) -> Option<&str> {
+        match self {
+            LinkTarget::Path(_) => None,
+            LinkTarget::Table { name, .. } => name.as_deref(),
+        }
+    }
+
+    pub(crate) fn profile(&self) -> Option<Profile> {
+        match self {
+            LinkTarget::Path(_) => None,
+            LinkTarget::Table { profile, .. } => profile.clone(),
+        }
+    }
+}
+
+impl From<&str> for LinkTarget {
+    fn from(path: &str) -> Self {
+        LinkTarget::Path(path.to_owned())
+    }
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
@@ -43,86 +327,404 @@ pub(crate) struct SkillsSet {
     pub(crate) only_skills: Vec<String>,
     #[serde(default)]
     pub(crate) exclude_skills: Vec<String>,
+    /// Walk into symlinked directories (and pick up symlinked files) under
+    /// `source_root` instead of skipping them.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) follow_symlinks: bool,
+    /// Deepest level walked below `source_root` (1 = files directly in it).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) max_depth: Option<usize>,
+    /// Globs of directories to walk; others are skipped unless a directory
+    /// above them matched.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) include_dirs: Vec<String>,
+    /// Globs of directories never entered, e.g. `node_modules` or `.git`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) exclude_dirs: Vec<String>,
+    #[serde(default, alias = "mode", skip_serializing_if = "Option::is_none")]
+    pub(crate) link_type: Option<LinkType>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) on_cross_device: Option<CrossDevice>,
+    /// List targets inside a git repo in its `.git/info/exclude`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) git_exclude: Option<bool>,
+    /// Keep the source and targets read-only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) protect: Option<bool>,
+    /// Substitute content tokens into `copy` targets too.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) substitute: Option<bool>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) only_on: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) skip_on: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) when: Option<When>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) profiles: Vec<Profile>,
 }
 
 pub(crate) fn load_config(config_path: &Path) -> Result<(ConfigFile, ResolveContext)> {
     let config_text = fs::read_to_string(config_path)
         .with_context(|| format!("failed to read config: {}", config_path.display()))?;
-    let config: ConfigFile = toml::from_str(&config_text)
-        .with_context(|| format!("invalid TOML config: {}", config_path.display()))?;
-    let ctx = build_resolve_context(config_path)?;
+    let format = ConfigFormat::from_path(config_path);
+    let config = parse_config(&config_text, format).with_context(|| {
+        format!(
+            "invalid {} config: {}",
+            format.extension().to_ascii_uppercase(),
+            config_path.display()
+        )
+    })?;
+    if let Some(version) = config.version.filter(|&version| version > CONFIG_VERSION) {
+        return Err(anyhow!(
+            "config version {version} is newer than this prompt-sync supports ({CONFIG_VERSION}): {}",
+            config_path.display()
+        ));
+    }
+    let mut ctx = build_resolve_context(config_path)?;
+    ctx.vars = Arc::new(
+        expand_vars(&config.vars)
+            .with_context(|| format!("invalid [vars] in {}", config_path.display()))?,
+    );
+    let mut config = config;
+    apply_host_overrides(&mut config, hostname(), &ctx);
 
     Ok((config, ctx))
 }
 
-pub(crate) fn build_default_config(profiles: &[Profile]) -> ConfigFile {
-    let profile_set = profiles.iter().copied().collect::<HashSet<_>>();
+/// Folds the `[hosts]` section for `hostname` into the rest of the config:
+/// its `[master]` fields win, `remove_targets` drop targets, and its rules
+/// are added after the shared ones.
+fn apply_host_overrides(config: &mut ConfigFile, hostname: Option<&str>, ctx: &ResolveContext) {
+    let Some(hostname) = hostname else {
+        return;
+    };
+    let short = hostname.split('.').next().unwrap_or(hostname);
+    let Some(name) = config
+        .hosts
+        .keys()
+        .find(|name| name.eq_ignore_ascii_case(hostname) || name.eq_ignore_ascii_case(short))
+        .cloned()
+    else {
+        return;
+    };
+    let Some(host) = config.hosts.remove(&name) else {
+        return;
+    };
+    debug!("applying [hosts.\"{name}\"]");
 
-    let mut link_targets = Vec::new();
-    if profile_set.contains(&Profile::Codex) {
-        link_targets.push("~/.codex/AGENTS.md".to_owned());
-    }
-    if profile_set.contains(&Profile::Claude) {
-        link_targets.push("~/.claude/CLAUDE.md".to_owned());
-    }
-    if profile_set.contains(&Profile::Gemini) {
-        link_targets.push("~/.gemini/GEMINI.md".to_owned());
-    }
-    if profile_set.contains(&Profile::Copilot) {
-        link_targets.push("<repo>/.github/copilot-instructions.md".to_owned());
+    if let Some(master) = host.master {
+        let previous = config.master.take().unwrap_or_default();
+        if let (Some(old), Some(new)) = (&previous.root, &master.root) {
+            let (old, new) = (old.trim_end_matches('/'), new.trim_end_matches('/'));
+            let sources = config.links.iter_mut().map(|rule| &mut rule.source).chain(
+                config
+                    .skills_sets
+                    .iter_mut()
+                    .map(|set| &mut set.source_root),
+            );
+            for source in sources {
+                if let Some(rest) = source
+                    .strip_prefix(old)
+                    .filter(|rest| rest.is_empty() || rest.starts_with('/'))
+                {
+                    *source = format!("{new}{rest}");
+                }
+            }
+        }
+        config.master = Some(MasterConfig {
+            root: master.root.or(previous.root),
+            repo: master.repo.or(previous.repo),
+            branch: master.branch.or(previous.branch),
+        });
     }
-    if profile_set.contains(&Profile::Kiro) {
-        link_targets.push("~/.kiro/steering/master.md".to_owned());
+    if !host.remove_targets.is_empty() {
+        let removed = host
+            .remove_targets
+            .iter()
+            .map(|raw| resolve_path(raw, ctx))
+            .collect::<HashSet<_>>();
+        for rule in &mut config.links {
+            rule.targets.retain(|target| {
+                let path = resolve_path(target.path(), ctx);
+                !removed.contains(&path)
+                    && !target
+                        .name()
+                        .is_some_and(|name| removed.contains(&path.join(name)))
+            });
+        }
+        for set in &mut config.skills_sets {
+            set.target_roots
+                .retain(|root| !removed.contains(&resolve_path(root, ctx)));
+        }
     }
+    config.links.extend(host.links);
+    config.skills_sets.extend(host.skills_sets);
+}
 
-    let mut target_roots = Vec::new();
-    if profile_set.contains(&Profile::Claude) {
-        target_roots.push("~/.claude/skills".to_owned());
-    }
-    if profile_set.contains(&Profile::Gemini) {
-        target_roots.push("~/.gemini/skills".to_owned());
+/// A config file together with the context its relative paths resolve in.
+#[derive(Debug)]
+pub(crate) struct LoadedConfig {
+    pub(crate) path: PathBuf,
+    pub(crate) config: ConfigFile,
+    pub(crate) ctx: ResolveContext,
+}
+
+/// Loads every `--config` file in order; later files take precedence when
+/// mappings are merged.
+pub(crate) fn load_configs(config_paths: &[PathBuf]) -> Result<Vec<LoadedConfig>> {
+    let _span = trace::span(Level::Debug, format_args!("config load"));
+    let _phase = PhaseTimer::start(Tally::ConfigMicros);
+    config_paths
+        .iter()
+        .map(|path| {
+            debug!("loading config {}", path.display());
+            let (config, ctx) = load_config(path)?;
+            Ok(LoadedConfig {
+                path: path.clone(),
+                config,
+                ctx,
+            })
+        })
+        .collect()
+}
+
+/// Repo-local config used when no `--config` is given.
+pub(crate) const DEFAULT_CONFIG_FILE: &str = "prompt-sync.toml";
+
+/// `$XDG_CONFIG_HOME/prompt-sync/config.toml`, falling back to
+/// `~/.config/prompt-sync/config.toml`.
+pub(crate) fn user_config_path() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| home_dir().map(|home| home.join(".config")))?;
+    Some(config_home.join("prompt-sync").join("config.toml"))
+}
+
+/// The config layers to load, lowest precedence first. Explicit `--config`
+/// paths are used as given; otherwise the user config (when present and not
+/// disabled) is followed by the repo-local `prompt-sync.toml`, which may be
+/// absent if the user config exists.
+pub(crate) fn config_layers(explicit: &[PathBuf], use_user_config: bool) -> Vec<PathBuf> {
+    if !explicit.is_empty() {
+        return explicit.to_vec();
     }
-    if profile_set.contains(&Profile::Codex) {
-        target_roots.push("~/.codex/skills".to_owned());
+
+    let repo_config = PathBuf::from(DEFAULT_CONFIG_FILE);
+    let user_config = user_config_path().filter(|path| use_user_config && path.is_file());
+    match user_config {
+        Some(user_config) if repo_config.exists() => vec![user_config, repo_config],
+        Some(user_config) => vec![user_config],
+        None => vec![repo_config],
     }
-    if profile_set.contains(&Profile::Kiro) {
-        target_roots.push("~/.kiro/steering".to_owned());
+}
+
+/// Flattens loaded layers into one config with every path resolved, so the
+/// result no longer depends on each file's directory.
+pub(crate) fn merge_resolved(configs: &[LoadedConfig]) -> ConfigFile {
+    let resolve = |raw: &str, ctx: &ResolveContext| resolve_path(raw, ctx).display().to_string();
+    let mut merged = ConfigFile {
+        version: Some(CONFIG_VERSION),
+        ..ConfigFile::default()
+    };
+
+    for loaded in configs {
+        let ctx = &loaded.ctx;
+        if let Some(master) = &loaded.config.master {
+            let previous = merged.master.take().unwrap_or_default();
+            let master = MasterConfig {
+                root: master
+                    .root
+                    .as_deref()
+                    .map(|root| resolve(root, ctx))
+                    .or(previous.root),
+                repo: master.repo.clone().or(previous.repo),
+                branch: master.branch.clone().or(previous.branch),
+            };
+            merged.master = (master.root.is_some() || master.repo.is_some()).then_some(master);
+        }
+        merged.vars.extend(loaded.config.vars.clone());
+        merged.transforms.extend(loaded.config.transforms.clone());
+        if let Some(logging) = &loaded.config.logging {
+            merged.logging = Some(LoggingConfig {
+                enabled: logging.enabled,
+                path: logging.path.as_deref().map(|path| resolve(path, ctx)),
+                sink: logging.sink,
+                max_size: logging.max_size.clone(),
+                max_files: logging.max_files,
+            });
+        }
+        if let Some(hooks) = &loaded.config.hooks {
+            let merged = merged.hooks.get_or_insert_default();
+            merged.pre_link.extend(hooks.pre_link.clone());
+            merged.post_link.extend(hooks.post_link.clone());
+            merged.post_repair.extend(hooks.post_repair.clone());
+        }
+        if let Some(watch) = &loaded.config.watch {
+            merged.watch = Some(WatchConfig {
+                notify: watch.notify.or(merged.watch.and_then(|watch| watch.notify)),
+            });
+        }
+        if let Some(repos) = &loaded.config.repos {
+            merged.repos = Some(ReposConfig {
+                roots: repos.roots.iter().map(|root| resolve(root, ctx)).collect(),
+                scan: repos.scan.iter().map(|dir| resolve(dir, ctx)).collect(),
+                scan_depth: repos.scan_depth,
+                exclude: repos.exclude.clone(),
+            });
+        }
+        for rule in &loaded.config.links {
+            merged.links.push(LinkRule {
+                source: if is_url(&rule.source) {
+                    rule.source.clone()
+                } else {
+                    resolve(&rule.source, ctx)
+                },
+                targets: rule
+                    .targets
+                    .iter()
+                    .map(|target| match target {
+                        LinkTarget::Path(path) => LinkTarget::Path(resolve(path, ctx)),
+                        LinkTarget::Table {
+                            path,
+                            name,
+                            profile,
+                        } => LinkTarget::Table {
+                            path: resolve(path, ctx),
+                            name: name.clone(),
+                            profile: profile.clone(),
+                        },
+                    })
+                    .collect(),
+                link_type: rule.link_type,
+                on_cross_device: rule.on_cross_device.or(loaded.config.on_cross_device),
+                git_exclude: rule.git_exclude.or(loaded.config.git_exclude),
+                protect: rule.protect.or(loaded.config.protect),
+                substitute: rule.substitute.or(loaded.config.substitute),
+                only_on: rule.only_on.clone(),
+                skip_on: rule.skip_on.clone(),
+                when: rule.when.clone(),
+                profiles: rule.profiles.clone(),
+            });
+        }
+        for set in &loaded.config.skills_sets {
+            merged.skills_sets.push(SkillsSet {
+                source_root: resolve(&set.source_root, ctx),
+                target_roots: set
+                    .target_roots
+                    .iter()
+                    .map(|root| resolve(root, ctx))
+                    .collect(),
+                exclude: set.exclude.clone(),
+                only_skills: set.only_skills.clone(),
+                exclude_skills: set.exclude_skills.clone(),
+                follow_symlinks: set.follow_symlinks,
+                max_depth: set.max_depth,
+                include_dirs: set.include_dirs.clone(),
+                exclude_dirs: set.exclude_dirs.clone(),
+                link_type: set.link_type,
+                on_cross_device: set.on_cross_device.or(loaded.config.on_cross_device),
+                git_exclude: set.git_exclude.or(loaded.config.git_exclude),
+                protect: set.protect.or(loaded.config.protect),
+                substitute: set.substitute.or(loaded.config.substitute),
+                only_on: set.only_on.clone(),
+                skip_on: set.skip_on.clone(),
+                when: set.when.clone(),
+                profiles: set.profiles.clone(),
+            });
+        }
     }
+    merged
+}
+
+pub(crate) fn parse_config(text: &str, format: ConfigFormat) -> Result<ConfigFile> {
+    let config = match format {
+        ConfigFormat::Toml => toml::from_str(text)?,
+        ConfigFormat::Yaml => serde_json::from_value(yaml::from_str(text)?)?,
+        ConfigFormat::Json => serde_json::from_str(text)?,
+    };
+    Ok(config)
+}
+
+pub(crate) fn serialize_config(config: &ConfigFile, format: ConfigFormat) -> Result<String> {
+    let text = match format {
+        ConfigFormat::Toml => toml::to_string_pretty(config)?,
+        ConfigFormat::Yaml => yaml::to_string(&serde_json::to_value(config)?),
+        ConfigFormat::Json => format!("{}\n", serde_json::to_string_pretty(config)?),
+    };
+    Ok(text)
+}
+
+/// The skills directory shared by every tool, linked into theirs.
+pub(crate) const SHARED_SKILLS: &str = "~/.agents/skills";
+
+pub(crate) fn build_default_config(profiles: &[Profile]) -> ConfigFile {
+    let selected = vendors()
+        .iter()
+        .filter(|vendor| profiles.contains(&Profile::of(vendor)))
+        .collect::<Vec<_>>();
+    let link_targets = selected
+        .iter()
+        .filter_map(|vendor| vendor.instructions.first())
+        .map(|target| target.as_str().into())
+        .collect::<Vec<_>>();
+    let target_roots = selected
+        .iter()
+        .filter_map(|vendor| vendor.skills.first())
+        .cloned()
+        .collect::<Vec<_>>();
 
     let mut skills_sets = Vec::new();
     if !target_roots.is_empty() {
         skills_sets.push(SkillsSet {
-            source_root: "~/.agents/skills".to_owned(),
+            source_root: SHARED_SKILLS.to_owned(),
             target_roots,
             exclude: Vec::new(),
             only_skills: Vec::new(),
             exclude_skills: Vec::new(),
+            ..Default::default()
         });
     }
 
-    let mut legacy_targets = Vec::new();
-    if profile_set.contains(&Profile::Claude) {
-        legacy_targets.push("~/.claude/skills".to_owned());
-    }
-    if !legacy_targets.is_empty() {
+    // Skills written for Codex before the shared directory existed.
+    if let Some(claude) = selected.iter().find(|vendor| vendor.name == "claude")
+        && let Some(target_root) = claude.skills.first()
+    {
         skills_sets.push(SkillsSet {
             source_root: "~/.codex/skills".to_owned(),
-            target_roots: legacy_targets,
+            target_roots: vec![target_root.clone()],
             exclude: vec!["*/.system/**".to_owned()],
             only_skills: Vec::new(),
             exclude_skills: Vec::new(),
+            ..Default::default()
         });
     }
 
     ConfigFile {
+        version: Some(CONFIG_VERSION),
         master: Some(MasterConfig {
             root: Some("~/.ai_settings".to_owned()),
+            repo: None,
+            branch: None,
         }),
+        on_cross_device: None,
+        git_exclude: None,
+        protect: None,
+        substitute: None,
+        vars: BTreeMap::new(),
+        transforms: BTreeMap::new(),
+        logging: None,
+        hooks: None,
+        watch: None,
+        repos: None,
         links: vec![LinkRule {
             source: "~/.ai_settings/master.md".to_owned(),
             targets: link_targets,
+            ..Default::default()
         }],
         skills_sets,
+        hosts: BTreeMap::new(),
     }
 }
 
@@ -132,61 +734,175 @@ pub(crate) fn build_resolve_context(config_path: &Path) -> Result<ResolveContext
         .map(Path::to_path_buf)
         .unwrap_or_else(|| PathBuf::from("."));
     let repo_root = env::current_dir().context("failed to resolve current directory")?;
-    let home_dir = env::var_os("HOME").map(PathBuf::from);
+    let home_dir = home_dir();
     let repo_root_text = repo_root.to_string_lossy().into_owned();
     let home_dir_text = home_dir
         .as_ref()
         .map(|dir| dir.to_string_lossy().into_owned());
 
     Ok(ResolveContext {
+        config_path: config_path.to_path_buf(),
         config_dir,
         repo_root_text,
         home_dir,
         home_dir_text,
+        vars: Arc::default(),
     })
 }
 
-pub(crate) fn build_bootstrap_config() -> ConfigFile {
+/// One context per repository `<repo>` rules expand across: the `--repo`
+/// flags, else `config`'s `[repos]`. `None` when neither is set, so
+/// `<repo>` stays the current directory.
+pub(crate) fn repo_contexts(
+    config: &ConfigFile,
+    ctx: &ResolveContext,
+    cli_repos: &[PathBuf],
+) -> Result<Option<Vec<ResolveContext>>> {
+    let roots = if !cli_repos.is_empty() {
+        let roots = cli_repos
+            .iter()
+            .map(|root| absolute_path(root))
+            .collect::<Result<Vec<_>>>()?;
+        existing_repos(roots)
+    } else if let Some(repos) = &config.repos {
+        repo_roots(repos, ctx)?
+    } else {
+        return Ok(None);
+    };
+    Ok(Some(
+        roots
+            .into_iter()
+            .map(|root| ResolveContext {
+                repo_root_text: root.to_string_lossy().into_owned(),
+                ..ctx.clone()
+            })
+            .collect(),
+    ))
+}
+
+/// The `[repos]` roots followed by the repositories found under `scan`.
+/// Roots that do not exist are skipped.
+pub(crate) fn repo_roots(repos: &ReposConfig, ctx: &ResolveContext) -> Result<Vec<PathBuf>> {
+    let mut roots = repos
+        .roots
+        .iter()
+        .map(|root| resolve_path(root, ctx))
+        .collect::<Vec<_>>();
+    if !repos.scan.is_empty() {
+        let exclude = build_glob_set(&repos.exclude)?;
+        let depth = repos.scan_depth.unwrap_or(DEFAULT_SCAN_DEPTH);
+        for dir in &repos.scan {
+            let dir = normalize_lexically(&resolve_path(dir, ctx));
+            let found = scan_repositories(&dir, depth, &exclude);
+            debug!("scan {}: {} repositories", dir.display(), found.len());
+            roots.extend(found);
+        }
+    }
+    Ok(existing_repos(roots))
+}
+
+fn existing_repos(roots: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut seen = HashSet::new();
+    roots
+        .into_iter()
+        .map(|root| normalize_lexically(&root))
+        .filter(|root| {
+            if !root.is_dir() {
+                info!(
+                    "warn: repository root does not exist, skipped: {}",
+                    root.display()
+                );
+                return false;
+            }
+            seen.insert(root.clone())
+        })
+        .collect()
+}
+
+/// `bootstrap`'s config: every instruction file and skills directory of
+/// the `selected` vendors.
+pub(crate) fn build_bootstrap_config(selected: &[&Vendor]) -> ConfigFile {
+    let link_targets = selected
+        .iter()
+        .flat_map(|vendor| &vendor.instructions)
+        .map(|target| target.as_str().into())
+        .collect::<Vec<_>>();
+    let mut links = Vec::new();
+    if !link_targets.is_empty() {
+        links.push(LinkRule {
+            source: "~/.ai_settings/master.md".to_owned(),
+            targets: link_targets,
+            ..Default::default()
+        });
+    }
+
+    let mut skills_sets = vec![SkillsSet {
+        source_root: SHARED_SKILLS.to_owned(),
+        target_roots: selected
+            .iter()
+            .flat_map(|vendor| vendor.skills.iter().cloned())
+            .chain(["<repo>/.agents/skills".to_owned()])
+            .collect(),
+        exclude: Vec::new(),
+        only_skills: Vec::new(),
+        exclude_skills: Vec::new(),
+        ..Default::default()
+    }];
+    if selected.iter().any(|vendor| vendor.name == "claude") {
+        skills_sets.push(SkillsSet {
+            source_root: "~/.codex/skills".to_owned(),
+            target_roots: vec!["~/.claude/skills".to_owned()],
+            exclude: vec!["*/.system/**".to_owned()],
+            only_skills: Vec::new(),
+            exclude_skills: Vec::new(),
+            ..Default::default()
+        });
+    }
+
     ConfigFile {
+        version: Some(CONFIG_VERSION),
         master: Some(MasterConfig {
             root: Some("~/.ai_settings".to_owned()),
+            repo: None,
+            branch: None,
         }),
-        links: vec![LinkRule {
-            source: "~/.ai_settings/master.md".to_owned(),
-            targets: vec![
-                "~/.codex/AGENTS.md".to_owned(),
-                "~/.claude/CLAUDE.md".to_owned(),
-                "~/.gemini/GEMINI.md".to_owned(),
-                "<repo>/AGENTS.md".to_owned(),
-                "<repo>/CLAUDE.md".to_owned(),
-                "<repo>/GEMINI.md".to_owned(),
-                "<repo>/.github/copilot-instructions.md".to_owned(),
-                "~/.kiro/steering/master.md".to_owned(),
-            ],
-        }],
-        skills_sets: vec![
-            SkillsSet {
-                source_root: "~/.agents/skills".to_owned(),
-                target_roots: vec![
-                    "~/.claude/skills".to_owned(),
-                    "~/.gemini/skills".to_owned(),
-                    "~/.codex/skills".to_owned(),
-                    "<repo>/.claude/skills".to_owned(),
-                    "<repo>/.gemini/skills".to_owned(),
-                    "<repo>/.agents/skills".to_owned(),
-                    "~/.kiro/steering".to_owned(),
-                ],
-                exclude: Vec::new(),
-                only_skills: Vec::new(),
-                exclude_skills: Vec::new(),
-            },
-            SkillsSet {
-                source_root: "~/.codex/skills".to_owned(),
-                target_roots: vec!["~/.claude/skills".to_owned()],
-                exclude: vec!["*/.system/**".to_owned()],
-                only_skills: Vec::new(),
-                exclude_skills: Vec::new(),
-            },
-        ],
+        on_cross_device: None,
+        git_exclude: None,
+        protect: None,
+        substitute: None,
+        vars: BTreeMap::new(),
+        transforms: BTreeMap::new(),
+        logging: None,
+        hooks: None,
+        watch: None,
+        repos: None,
+        links,
+        skills_sets,
+        hosts: BTreeMap::new(),
+    }
+}
+
+/// Keeps the targets and target roots for which `keep` holds, dropping
+/// rules and skills sets left with none.
+pub(crate) fn retain_targets(config: &mut ConfigFile, keep: impl Fn(&str) -> bool) {
+    for rule in &mut config.links {
+        rule.targets.retain(|target| keep(target.path()));
     }
+    config.links.retain(|rule| !rule.targets.is_empty());
+    for set in &mut config.skills_sets {
+        set.target_roots.retain(|root| keep(root));
+    }
+    config
+        .skills_sets
+        .retain(|set| !set.target_roots.is_empty());
+}
+
+/// Whether a rule with these `only_on` / `skip_on` lists applies to the
+/// current platform. Entries match `std::env::consts::OS` (`linux`, `macos`,
+/// `windows`, ...) or `FAMILY` (`unix`, `windows`).
+pub(crate) fn applies_on_current_os(only_on: &[String], skip_on: &[String]) -> bool {
+    let matches = |name: &String| {
+        name.eq_ignore_ascii_case(env::consts::OS) || name.eq_ignore_ascii_case(env::consts::FAMILY)
+    };
+    (only_on.is_empty() || only_on.iter().any(matches)) && !skip_on.iter().any(matches)
 }
@@ -0,0 +1,273 @@
+//! `add` / `remove`: edits the `[[links]]` rules of a TOML config in place,
+//! so its comments, ordering and layout survive the change.
+
+use anyhow::{Context, Result, anyhow};
+use toml_edit::{Array, ArrayOfTables, DocumentMut, Item, Table, Value, value};
+
+use crate::cli::LinkType;
+use crate::model::ResolveContext;
+use crate::pathing::resolve_path;
+
+/// What an edit did to rule `links[index]`: the targets it added or
+/// removed, with the rule's source as written.
+#[derive(Debug)]
+pub(crate) struct RuleEdit {
+    pub(crate) index: usize,
+    pub(crate) source: String,
+    pub(crate) targets: Vec<String>,
+    /// The rule was created by `add`, or dropped by `remove`.
+    pub(crate) whole_rule: bool,
+}
+
+/// Adds `targets` to the first rule for `source` (with `link_type`, when
+/// given), or appends a new rule. Targets the rule already has are skipped.
+pub(crate) fn add_link(
+    text: &str,
+    source: &str,
+    targets: &[String],
+    link_type: Option<LinkType>,
+    ctx: &ResolveContext,
+) -> Result<(String, RuleEdit)> {
+    let mut doc = parse(text)?;
+    let rules = links_mut(&mut doc, true)?.expect("created above");
+    let existing = rules.iter().position(|rule| {
+        rule_source(rule).is_some_and(|raw| same_path(raw, source, ctx))
+            && link_type.is_none_or(|link_type| rule_link_type(rule) == Some(link_type.as_str()))
+    });
+
+    ensure_not_source(source, targets, ctx)?;
+    let edit = match existing {
+        Some(index) => append_targets(rules, index, targets, ctx)?,
+        None => {
+            let mut deduped = Vec::new();
+            for target in targets {
+                if !deduped.contains(target) {
+                    deduped.push(target.clone());
+                }
+            }
+            let mut rule = Table::new();
+            rule["source"] = value(source);
+            rule["targets"] = value(deduped.iter().map(String::as_str).collect::<Array>());
+            if let Some(link_type) = link_type {
+                rule["link_type"] = value(link_type.as_str());
+            }
+            rules.push(rule);
+            RuleEdit {
+                index: rules.len() - 1,
+                source: source.to_owned(),
+                targets: deduped,
+                whole_rule: true,
+            }
+        }
+    };
+    Ok((doc.to_string(), edit))
+}
+
+/// Appends `targets` to the first rule for `source`, which has to exist.
+pub(crate) fn add_targets(
+    text: &str,
+    source: &str,
+    targets: &[String],
+    ctx: &ResolveContext,
+) -> Result<(String, RuleEdit)> {
+    let mut doc = parse(text)?;
+    let index = links_mut(&mut doc, false)?.and_then(|rules| {
+        rules
+            .iter()
+            .position(|rule| rule_source(rule).is_some_and(|raw| same_path(raw, source, ctx)))
+    });
+    let Some(index) = index else {
+        return Err(anyhow!(
+            "no [[links]] rule has source {source} (`prompt-sync add` creates one)"
+        ));
+    };
+    ensure_not_source(source, targets, ctx)?;
+    let rules = links_mut(&mut doc, false)?.expect("found above");
+    let edit = append_targets(rules, index, targets, ctx)?;
+    Ok((doc.to_string(), edit))
+}
+
+/// Appends the targets rule `index` does not have yet, each formatted like
+/// the rule's last target.
+fn append_targets(
+    rules: &mut ArrayOfTables,
+    index: usize,
+    targets: &[String],
+    ctx: &ResolveContext,
+) -> Result<RuleEdit> {
+    let rule = rules.get_mut(index).expect("index in range");
+    let source = rule_source(rule).unwrap_or_default().to_owned();
+    let array = targets_mut(rule, index)?;
+    let mut added = Vec::new();
+    for target in targets {
+        let known = array
+            .iter()
+            .filter_map(target_path)
+            .any(|raw| same_path(raw, target, ctx));
+        if known || added.contains(target) {
+            continue;
+        }
+        // The first target's spacing only fits a later one in a
+        // multi-line array. What follows the last target, such as the
+        // newline before `]` without a trailing comma, moves after the new one.
+        let len = array.len();
+        let last = array
+            .get_mut(len.wrapping_sub(1))
+            .filter(|last| len > 1 || decor_has_newline(last));
+        match last {
+            Some(last) => {
+                let mut item = Value::from(target.as_str());
+                *item.decor_mut() = last.decor().clone();
+                last.decor_mut().set_suffix("");
+                array.push_formatted(item);
+            }
+            None => array.push(target.as_str()),
+        }
+        added.push(target.clone());
+    }
+    Ok(RuleEdit {
+        index,
+        source,
+        targets: added,
+        whole_rule: false,
+    })
+}
+
+/// Removes `targets` from every rule for `source`, or with no targets the
+/// rules themselves. A rule left without targets is dropped. Every given
+/// target has to be in one of the rules.
+pub(crate) fn remove_link(
+    text: &str,
+    source: &str,
+    targets: &[String],
+    ctx: &ResolveContext,
+) -> Result<(String, Vec<RuleEdit>)> {
+    let mut doc = parse(text)?;
+    let Some(rules) = links_mut(&mut doc, false)? else {
+        return Err(anyhow!("no [[links]] rule has source {source}"));
+    };
+    let mut edits = Vec::new();
+    for index in (0..rules.len()).rev() {
+        let rule = rules.get_mut(index).expect("index in range");
+        let Some(rule_source) = rule_source(rule)
+            .filter(|raw| same_path(raw, source, ctx))
+            .map(str::to_owned)
+        else {
+            continue;
+        };
+        let array = targets_mut(rule, index)?;
+        let first_decor = array.iter().next().map(|first| first.decor().clone());
+        let mut removed = Vec::new();
+        array.retain(|item| match target_path(item) {
+            Some(raw)
+                if targets.is_empty()
+                    || targets.iter().any(|target| same_path(raw, target, ctx)) =>
+            {
+                removed.push(raw.to_owned());
+                false
+            }
+            _ => true,
+        });
+        if removed.is_empty() && !targets.is_empty() {
+            continue;
+        }
+        // The new first element keeps the spacing the old one had.
+        if let (Some(decor), Some(first)) = (first_decor, array.iter_mut().next()) {
+            *first.decor_mut() = decor;
+        }
+        let whole_rule = array.is_empty();
+        if whole_rule {
+            rules.remove(index);
+        }
+        edits.push(RuleEdit {
+            index,
+            source: rule_source,
+            targets: removed,
+            whole_rule,
+        });
+    }
+    edits.reverse();
+
+    if edits.is_empty() {
+        return Err(anyhow!("no [[links]] rule has source {source}"));
+    }
+    for target in targets {
+        let found = edits
+            .iter()
+            .flat_map(|edit| &edit.targets)
+            .any(|raw| same_path(raw, target, ctx));
+        if !found {
+            return Err(anyhow!(
+                "no [[links]] rule for {source} has target {target}"
+            ));
+        }
+    }
+    Ok((doc.to_string(), edits))
+}
+
+fn decor_has_newline(item: &Value) -> bool {
+    item.decor()
+        .prefix()
+        .and_then(|prefix| prefix.as_str())
+        .is_some_and(|prefix| prefix.contains('\n'))
+}
+
+fn ensure_not_source(source: &str, targets: &[String], ctx: &ResolveContext) -> Result<()> {
+    match targets.iter().find(|target| same_path(target, source, ctx)) {
+        Some(target) => Err(anyhow!("target {target} is the rule's source")),
+        None => Ok(()),
+    }
+}
+
+fn parse(text: &str) -> Result<DocumentMut> {
+    text.parse::<DocumentMut>().context("invalid TOML")
+}
+
+/// The top-level `[[links]]` tables, created when `create` is set and the
+/// config has none.
+fn links_mut(doc: &mut DocumentMut, create: bool) -> Result<Option<&mut ArrayOfTables>> {
+    if !doc.contains_key("links") {
+        if !create {
+            return Ok(None);
+        }
+        doc.insert("links", Item::ArrayOfTables(ArrayOfTables::new()));
+    }
+    doc["links"]
+        .as_array_of_tables_mut()
+        .map(Some)
+        .ok_or_else(|| anyhow!("links is not written as [[links]] tables"))
+}
+
+fn rule_source(rule: &Table) -> Option<&str> {
+    rule.get("source").and_then(Item::as_str)
+}
+
+fn rule_link_type(rule: &Table) -> Option<&str> {
+    rule.get("link_type")
+        .or_else(|| rule.get("mode"))
+        .and_then(Item::as_str)
+}
+
+fn targets_mut(rule: &mut Table, index: usize) -> Result<&mut Array> {
+    if !rule.contains_key("targets") {
+        rule["targets"] = value(Array::new());
+    }
+    rule["targets"]
+        .as_array_mut()
+        .ok_or_else(|| anyhow!("links[{index}].targets is not an array"))
+}
+
+/// A target as written: a string, or an inline table's `path`.
+fn target_path(item: &Value) -> Option<&str> {
+    match item {
+        Value::String(_) => item.as_str(),
+        Value::InlineTable(table) => table.get("path").and_then(Value::as_str),
+        _ => None,
+    }
+}
+
+/// Paths written differently that resolve to the same file, such as `~/x`
+/// and its absolute path, are the same rule or target.
+fn same_path(raw: &str, other: &str, ctx: &ResolveContext) -> bool {
+    raw == other || resolve_path(raw, ctx) == resolve_path(other, ctx)
+}
@@ -0,0 +1,131 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use crate::cli::Profile;
+use crate::config::{CONFIG_VERSION, ConfigFile, LinkRule, MasterConfig, SHARED_SKILLS, SkillsSet};
+use crate::model::ResolveContext;
+use crate::pathing::resolve_path;
+use crate::vendors::vendors;
+
+/// Instruction file locations `init --from-existing` and `import` look at,
+/// written the way they appear in a config.
+pub(crate) fn instruction_files() -> Vec<(Profile, &'static str)> {
+    vendors()
+        .iter()
+        .flat_map(|vendor| {
+            vendor
+                .instructions
+                .iter()
+                .chain(&vendor.legacy)
+                .map(|raw| (Profile::of(vendor), raw.as_str()))
+        })
+        .collect()
+}
+
+/// Skills directories, in order of preference as the shared source root.
+pub(crate) fn skills_dirs() -> Vec<(Option<Profile>, &'static str)> {
+    let vendor_dirs = vendors().iter().flat_map(|vendor| {
+        vendor
+            .skills
+            .iter()
+            .map(|raw| (Some(Profile::of(vendor)), raw.as_str()))
+    });
+    [(None, SHARED_SKILLS)]
+        .into_iter()
+        .chain(vendor_dirs)
+        .collect()
+}
+
+#[derive(Debug)]
+pub(crate) struct Discovery {
+    pub(crate) config: ConfigFile,
+    pub(crate) master: PathBuf,
+    pub(crate) found: Vec<PathBuf>,
+    /// Found files whose content differs from the proposed master.
+    pub(crate) differing: Vec<PathBuf>,
+}
+
+/// Scans the known vendor locations and builds a config around what exists.
+/// The most recently modified instruction file is proposed as the master.
+/// Returns `None` when no instruction file is found.
+pub(crate) fn discover_existing(ctx: &ResolveContext, profiles: &[Profile]) -> Option<Discovery> {
+    let selected = |profile: &Profile| profiles.is_empty() || profiles.contains(profile);
+
+    let mut found = instruction_files()
+        .into_iter()
+        .filter(|(profile, _)| selected(profile))
+        .filter_map(|(_, raw)| {
+            let path = resolve_path(raw, ctx);
+            let meta = fs::metadata(&path).ok().filter(fs::Metadata::is_file)?;
+            let modified = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            Some((raw, path, modified))
+        })
+        .collect::<Vec<_>>();
+    let master_index = found
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, (_, _, modified))| *modified)
+        .map(|(index, _)| index)?;
+    let (master_raw, master, _) = found.remove(master_index);
+
+    let master_content = fs::read(&master).ok();
+    let differing = found
+        .iter()
+        .filter(|(_, path, _)| fs::read(path).ok() != master_content)
+        .map(|(_, path, _)| path.clone())
+        .collect();
+
+    let skills_dirs = skills_dirs()
+        .into_iter()
+        .filter(|(profile, _)| profile.as_ref().is_none_or(selected))
+        .filter(|(_, raw)| resolve_path(raw, ctx).is_dir())
+        .map(|(_, raw)| raw.to_owned())
+        .collect::<Vec<_>>();
+    let skills_sets = match skills_dirs.split_first() {
+        Some((source_root, target_roots)) if !target_roots.is_empty() => vec![SkillsSet {
+            source_root: source_root.clone(),
+            target_roots: target_roots.to_vec(),
+            ..Default::default()
+        }],
+        _ => Vec::new(),
+    };
+
+    let master_root = master_raw
+        .rsplit_once('/')
+        .map(|(dir, _)| dir.to_owned())
+        .filter(|dir| !dir.is_empty());
+    let config = ConfigFile {
+        version: Some(CONFIG_VERSION),
+        master: master_root.map(|root| MasterConfig {
+            root: Some(root),
+            repo: None,
+            branch: None,
+        }),
+        on_cross_device: None,
+        git_exclude: None,
+        protect: None,
+        substitute: None,
+        vars: BTreeMap::new(),
+        transforms: BTreeMap::new(),
+        logging: None,
+        hooks: None,
+        watch: None,
+        repos: None,
+        links: vec![LinkRule {
+            source: master_raw.to_owned(),
+            targets: found.iter().map(|&(raw, _, _)| raw.into()).collect(),
+            ..Default::default()
+        }],
+        skills_sets,
+        hosts: BTreeMap::new(),
+    };
+
+    Some(Discovery {
+        config,
+        master,
+        found: found.into_iter().map(|(_, path, _)| path).collect(),
+        differing,
+    })
+}
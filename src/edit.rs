@@ -0,0 +1,99 @@
+//! `edit [source]`: which source to open, and opening it in the user's
+//! editor. Relinking afterwards is up to the caller.
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result, anyhow};
+
+use crate::config::LoadedConfig;
+use crate::model::{Mapping, MappingKind};
+use crate::pathing::{absolute_path, normalize_lexically, resolve_path};
+
+/// The source `edit` opens: `source` as written in a rule or as a path, or
+/// without one the single `[[links]]` source, preferring those under the
+/// `[master] root`.
+pub(crate) fn pick_source(
+    configs: &[LoadedConfig],
+    mappings: &[Mapping],
+    source: Option<&str>,
+) -> Result<PathBuf> {
+    if let Some(source) = source {
+        let path = normalize_lexically(&absolute_path(Path::new(source))?);
+        return mappings
+            .iter()
+            .find(|mapping| mapping.source == path || mapping.source_template == source)
+            .map(|mapping| mapping.source.clone())
+            .ok_or_else(|| anyhow!("no rule has source {source}"));
+    }
+
+    let mut sources = Vec::new();
+    for mapping in mappings {
+        if matches!(mapping.kind, MappingKind::ConfigFile) && !sources.contains(&mapping.source) {
+            sources.push(mapping.source.clone());
+        }
+    }
+    let roots = configs
+        .iter()
+        .filter_map(|loaded| {
+            let root = loaded.config.master.as_ref()?.root.as_deref()?;
+            Some(resolve_path(root, &loaded.ctx))
+        })
+        .collect::<Vec<_>>();
+    let under_root = sources
+        .iter()
+        .filter(|source| roots.iter().any(|root| source.starts_with(root)))
+        .cloned()
+        .collect::<Vec<_>>();
+    if !under_root.is_empty() {
+        sources = under_root;
+    }
+    match sources.as_slice() {
+        [source] => Ok(source.clone()),
+        [] => Err(anyhow!("no [[links]] rule to edit the source of")),
+        _ => Err(anyhow!(
+            "several sources, name the one to edit: {}",
+            sources
+                .iter()
+                .map(|source| source.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+/// Runs `$VISUAL`, `$EDITOR` or the platform's default editor on `path` and
+/// waits for it. The variable may hold arguments, as in `code --wait`.
+pub(crate) fn open_in_editor(path: &Path) -> Result<()> {
+    let editor = ["VISUAL", "EDITOR"]
+        .into_iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.trim().is_empty())
+        .unwrap_or_else(|| if cfg!(windows) { "notepad" } else { "vi" }.to_owned());
+    let status = editor_command(&editor, path)
+        .status()
+        .with_context(|| format!("failed to start editor `{editor}`"))?;
+    if !status.success() {
+        return Err(anyhow!("editor `{editor}` exited with {status}"));
+    }
+    Ok(())
+}
+
+#[cfg(not(windows))]
+fn editor_command(editor: &str, path: &Path) -> Command {
+    let mut command = Command::new("sh");
+    command
+        .arg("-c")
+        .arg(format!("{editor} \"$1\""))
+        .arg("sh")
+        .arg(path);
+    command
+}
+
+#[cfg(windows)]
+fn editor_command(editor: &str, path: &Path) -> Command {
+    let mut command = Command::new("cmd");
+    command.arg("/C").arg(editor).arg(path);
+    command
+}
@@ -1,106 +1,366 @@
-use std::collections::HashSet;
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::env;
 use std::fs;
 use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result, anyhow};
-use globset::{Glob, GlobSet, GlobSetBuilder};
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::Serialize;
 use walkdir::WalkDir;
 
-use crate::config::ConfigFile;
+use crate::cli::{KindFilter, LinkType, MappingArgs, OutputArgs, OutputFormat, Profile};
+use crate::config::{
+    ConfigFile, CrossDevice, LoadedConfig, When, applies_on_current_os, repo_contexts,
+};
+use crate::ignore_file::{IGNORE_FILE_NAME, IgnoreFile};
 use crate::logging::{self, Action, OperationLog};
-use crate::model::{Mapping, MappingKind, Record, Report, ResolveContext, Status};
-use crate::pathing::{hardlink_count, resolve_path, same_file};
+use crate::model::{
+    Mapping, MappingKind, PhaseTimer, PlanAction, PlanEntry, Record, Report, ResolveContext,
+    Status, Summary, Tally, tally,
+};
+use crate::pathing::{
+    absolute_path, canonical_path, file_identity, hardlink_count, normalize_lexically,
+    on_same_device, resolve_path, same_file, substitute_vars, unresolved_tokens,
+};
+use crate::progress::Progress;
+use crate::remote::{cache_path, cached_source, is_url};
+use crate::render::{
+    content_vars, fragments, is_rendered, is_untouched, master_profiles, render_source,
+    target_profile, transform_for,
+};
 use crate::safe_fs::{
-    calculate_sha256, create_hard_link_checked, ensure_parent_dir, remove_existing_target_file,
+    BackupDir, backup_existing_target, calculate_sha256, clone_file_checked, copy_file_checked,
+    create_hard_link_checked, create_symlink_checked, ensure_parent_dir, set_read_only, sha256_hex,
+    stash_path, stash_target, symlinks_allowed, temp_sibling_path,
 };
+use crate::state::ManagedTarget;
+use crate::trace::{self, Level, debug, info};
+use crate::transaction::{Mutation, Transaction};
+use crate::yaml;
 
 pub(crate) fn build_mappings(
     config: &ConfigFile,
     ctx: &ResolveContext,
-    verbose: bool,
+    options: &MappingArgs,
+    progress: &Progress,
 ) -> Result<Vec<Mapping>> {
     let mut mappings = Vec::new();
     let mut dedup: HashSet<(PathBuf, PathBuf)> = HashSet::new();
+    let config_ignore = IgnoreFile::load(&ctx.config_dir)?;
+    let ignored_by_config = |path: &Path, is_dir: bool| {
+        config_ignore
+            .as_ref()
+            .is_some_and(|ignore| ignore.is_ignored(path, is_dir))
+    };
+    let repo_ctxs = repo_contexts(config, ctx, &options.repos)?;
+    // Each URL is looked up once per build, however many repos use it.
+    let mut fetched: HashMap<&str, PathBuf> = HashMap::new();
 
-    for rule in &config.links {
-        let source = resolve_path(&rule.source, ctx);
-        for target_raw in &rule.targets {
-            let target = resolve_path(target_raw, ctx);
-            if dedup.insert((source.clone(), target.clone())) {
-                mappings.push(Mapping {
-                    kind: MappingKind::ConfigFile,
-                    source: source.clone(),
-                    target,
+    for (rule_index, rule) in config.links.iter().enumerate() {
+        if !options.every_rule && !applies_on_current_os(&rule.only_on, &rule.skip_on) {
+            info!(
+                "skip: links[{rule_index}] ({}) does not apply on {}",
+                rule.source,
+                std::env::consts::OS
+            );
+            continue;
+        }
+        if let Some(when) = rule
+            .when
+            .as_ref()
+            .filter(|when| !options.every_rule && !when.holds())
+        {
+            info!("skip: links[{rule_index}] ({}) needs {when}", rule.source);
+            continue;
+        }
+        if !matches_profiles(&rule.profiles, &options.profiles) {
+            info!(
+                "skip: links[{rule_index}] ({}) is not tagged with the selected profiles",
+                rule.source
+            );
+            continue;
+        }
+        let paths = rule.targets.iter().map(|target| target.path());
+        if is_url(&rule.source) {
+            ensure_tokens_resolve(&format!("links[{rule_index}]"), paths, ctx)?;
+        } else {
+            let paths = paths.chain([rule.source.as_str()]);
+            ensure_tokens_resolve(&format!("links[{rule_index}]"), paths, ctx)?;
+        }
+        let uses_repo = mentions_repo(&rule.source, ctx)
+            || rule
+                .targets
+                .iter()
+                .any(|target| mentions_repo(target.path(), ctx));
+        let remote = is_url(&rule.source);
+        let is_glob = !remote && is_glob_pattern(&rule.source);
+        for ctx in rule_contexts(uses_repo, ctx, repo_ctxs.as_deref()) {
+            let source = if remote {
+                match fetched.get(rule.source.as_str()) {
+                    Some(cached) => cached.clone(),
+                    None => {
+                        // A source never fetched is reported missing.
+                        let cached = if options.fetch {
+                            cached_source(&rule.source)?
+                        } else {
+                            cache_path(&rule.source)?
+                        };
+                        fetched.insert(&rule.source, cached.clone());
+                        cached
+                    }
+                }
+            } else {
+                resolve_path(&rule.source, ctx)
+            };
+            let link_type = resolve_auto(options.link_type.or(rule.link_type).unwrap_or_default());
+            let git_exclude = rule.git_exclude.or(config.git_exclude).unwrap_or(false);
+            let protect = rule.protect.or(config.protect).unwrap_or(false);
+            let substitute = rule.substitute.or(config.substitute).unwrap_or(false);
+            let vars = content_vars(&config.vars, ctx);
+            let on_cross_device = rule
+                .on_cross_device
+                .or(config.on_cross_device)
+                .unwrap_or_default();
+            let source_pattern = (is_glob || remote).then(|| rule.source.clone());
+
+            // A glob source fans out into one mapping per matched file; targets
+            // are then directories receiving the path relative to the glob base.
+            let sources = if is_glob {
+                let mut matched = expand_source_glob(&source)?;
+                matched.retain(|(source, _)| {
+                    source.file_name() != Some(IGNORE_FILE_NAME.as_ref())
+                        && !ignored_by_config(source, false)
                 });
+                if matched.is_empty() {
+                    info!("warn: source glob matched no files: {}", rule.source);
+                }
+                matched
+            } else {
+                vec![(source, None)]
+            };
+
+            for (source, rel) in &sources {
+                let master_profiles = master_profiles(source);
+                for link_target in &rule.targets {
+                    let mut target = resolve_path(link_target.path(), ctx);
+                    if let Some(name) = link_target.name() {
+                        if is_glob {
+                            return Err(anyhow!(
+                                "target name cannot be combined with a glob source: {}",
+                                rule.source
+                            ));
+                        }
+                        if !is_plain_file_name(name) {
+                            return Err(anyhow!("target name must be a plain file name: {name}"));
+                        }
+                        target.push(name);
+                    }
+                    if let Some(rel) = rel {
+                        target.push(rel);
+                    }
+                    let profile = link_target.profile().or_else(|| target_profile(&target));
+                    if let Some(allowed) = &master_profiles
+                        && !profile
+                            .as_ref()
+                            .is_some_and(|profile| allowed.contains(profile))
+                    {
+                        info!(
+                            "skip: {} is not for the profiles in the frontmatter of {}",
+                            target.display(),
+                            source.display()
+                        );
+                        continue;
+                    }
+                    if dedup.insert((source.clone(), target.clone())) {
+                        let link_type =
+                            cross_device_link_type(link_type, on_cross_device, source, &target);
+                        mappings.push(Mapping {
+                            config: ctx.config_path.clone(),
+                            kind: MappingKind::ConfigFile,
+                            rule_index,
+                            link_type,
+                            source: source.clone(),
+                            target,
+                            source_template: rule.source.clone(),
+                            target_template: link_target.path().to_owned(),
+                            source_pattern: source_pattern.clone(),
+                            git_exclude,
+                            protect,
+                            profile: profile.clone(),
+                            vars: substitutes(link_type, substitute).then(|| vars.clone()),
+                            transform: transform_for(
+                                &config.transforms,
+                                link_type,
+                                profile.as_ref(),
+                            ),
+                        });
+                    }
+                }
             }
         }
     }
 
-    for set in &config.skills_sets {
-        let source_root = resolve_path(&set.source_root, ctx);
-        if !source_root.exists() {
-            if verbose {
-                eprintln!(
+    for (rule_index, set) in config.skills_sets.iter().enumerate() {
+        if !options.every_rule && !applies_on_current_os(&set.only_on, &set.skip_on) {
+            info!(
+                "skip: skills_sets[{rule_index}] ({}) does not apply on {}",
+                set.source_root,
+                std::env::consts::OS
+            );
+            continue;
+        }
+        if let Some(when) = set
+            .when
+            .as_ref()
+            .filter(|when| !options.every_rule && !when.holds())
+        {
+            info!(
+                "skip: skills_sets[{rule_index}] ({}) needs {when}",
+                set.source_root
+            );
+            continue;
+        }
+        if !matches_profiles(&set.profiles, &options.profiles) {
+            info!(
+                "skip: skills_sets[{rule_index}] ({}) is not tagged with the selected profiles",
+                set.source_root
+            );
+            continue;
+        }
+        ensure_tokens_resolve(
+            &format!("skills_sets[{rule_index}]"),
+            set.target_roots
+                .iter()
+                .map(String::as_str)
+                .chain([set.source_root.as_str()]),
+            ctx,
+        )?;
+        let uses_repo = mentions_repo(&set.source_root, ctx)
+            || set.target_roots.iter().any(|root| mentions_repo(root, ctx));
+        for ctx in rule_contexts(uses_repo, ctx, repo_ctxs.as_deref()) {
+            let source_root = resolve_path(&set.source_root, ctx);
+            if !source_root.exists() {
+                info!(
                     "warn: source_root does not exist, skipped: {}",
                     source_root.display()
                 );
+                continue;
+            }
+            if !source_root.is_dir() {
+                return Err(anyhow!(
+                    "source_root is not a directory: {}",
+                    source_root.display()
+                ));
             }
-            continue;
-        }
-        if !source_root.is_dir() {
-            return Err(anyhow!(
-                "source_root is not a directory: {}",
-                source_root.display()
-            ));
-        }
 
-        let exclude_globs = build_glob_set(&set.exclude)?;
+            let exclude_globs = build_glob_set(&set.exclude)?;
+            let dir_filter = DirFilter {
+                include: build_glob_set(&set.include_dirs)?,
+                include_all: set.include_dirs.is_empty(),
+                exclude: build_glob_set(&set.exclude_dirs)?,
+            };
+            let root_ignore = IgnoreFile::load(&source_root)?;
+            let ignored = |path: &Path, is_dir: bool| {
+                ignored_by_config(path, is_dir)
+                    || root_ignore
+                        .as_ref()
+                        .is_some_and(|ignore| ignore.is_ignored(path, is_dir))
+            };
+            let link_type = resolve_auto(options.link_type.or(set.link_type).unwrap_or_default());
+            let git_exclude = set.git_exclude.or(config.git_exclude).unwrap_or(false);
+            let protect = set.protect.or(config.protect).unwrap_or(false);
+            let substitute = set.substitute.or(config.substitute).unwrap_or(false);
+            let vars = content_vars(&config.vars, ctx);
+            let on_cross_device = set
+                .on_cross_device
+                .or(config.on_cross_device)
+                .unwrap_or_default();
 
-        for entry_result in WalkDir::new(&source_root) {
-            let entry = entry_result.with_context(|| {
-                format!("failed to walk source_root: {}", source_root.display())
-            })?;
-            if !entry.file_type().is_file() {
-                continue;
+            let mut walker = WalkDir::new(&source_root).follow_links(set.follow_symlinks);
+            if let Some(max_depth) = set.max_depth {
+                walker = walker.max_depth(max_depth);
             }
+            let entries = walker.into_iter().filter_entry(|entry| {
+                !entry.file_type().is_dir()
+                    || entry
+                        .path()
+                        .strip_prefix(&source_root)
+                        .is_ok_and(|rel| dir_filter.enters(rel) && !ignored(entry.path(), true))
+            });
+            for entry_result in entries {
+                let entry = entry_result.with_context(|| {
+                    format!("failed to walk source_root: {}", source_root.display())
+                })?;
+                tally(Tally::WalkedEntries, 1);
+                if !entry.file_type().is_file()
+                    || entry.file_name() == IGNORE_FILE_NAME
+                    || ignored(entry.path(), false)
+                {
+                    continue;
+                }
+                progress.tick();
 
-            let source_file = entry.into_path();
-            let rel = source_file.strip_prefix(&source_root).with_context(|| {
-                format!(
-                    "failed to compute relative path: {} in {}",
-                    source_file.display(),
-                    source_root.display()
-                )
-            })?;
+                let source_file = entry.into_path();
+                let rel = source_file.strip_prefix(&source_root).with_context(|| {
+                    format!(
+                        "failed to compute relative path: {} in {}",
+                        source_file.display(),
+                        source_root.display()
+                    )
+                })?;
 
-            // Skill name filter (first path component = skill directory name)
-            if let Some(skill_name) = extract_skill_name(rel) {
-                if !set.only_skills.is_empty() {
-                    if !set.only_skills.iter().any(|s| s == skill_name) {
+                // Skill name filter (first path component = skill directory name)
+                if let Some(skill_name) = extract_skill_name(rel) {
+                    if !set.only_skills.is_empty() {
+                        if !set.only_skills.iter().any(|s| s == skill_name) {
+                            continue;
+                        }
+                    } else if !set.exclude_skills.is_empty()
+                        && set.exclude_skills.iter().any(|s| s == skill_name)
+                    {
                         continue;
                     }
-                } else if !set.exclude_skills.is_empty()
-                    && set.exclude_skills.iter().any(|s| s == skill_name)
-                {
-                    continue;
                 }
-            }
 
-            // Exclude glob filter
-            let rel_str = rel.to_string_lossy();
-            if exclude_globs.is_match(rel_str.as_ref()) {
-                continue;
-            }
+                // Exclude glob filter
+                let rel_str = rel.to_string_lossy();
+                if exclude_globs.is_match(rel_str.as_ref()) {
+                    continue;
+                }
 
-            for target_root_raw in &set.target_roots {
-                let target_root = resolve_path(target_root_raw, ctx);
-                let target = target_root.join(rel);
-                if dedup.insert((source_file.clone(), target.clone())) {
-                    mappings.push(Mapping {
-                        kind: MappingKind::SkillFile,
-                        source: source_file.clone(),
-                        target,
-                    });
+                for target_root_raw in &set.target_roots {
+                    let target_root = resolve_path(target_root_raw, ctx);
+                    let target = target_root.join(rel);
+                    if dedup.insert((source_file.clone(), target.clone())) {
+                        let profile = target_profile(&target);
+                        let link_type = cross_device_link_type(
+                            link_type,
+                            on_cross_device,
+                            &source_file,
+                            &target,
+                        );
+                        mappings.push(Mapping {
+                            config: ctx.config_path.clone(),
+                            kind: MappingKind::SkillFile,
+                            rule_index,
+                            link_type,
+                            source: source_file.clone(),
+                            target,
+                            source_template: set.source_root.clone(),
+                            target_template: target_root_raw.clone(),
+                            source_pattern: None,
+                            git_exclude,
+                            protect,
+                            profile: profile.clone(),
+                            vars: substitutes(link_type, substitute).then(|| vars.clone()),
+                            transform: transform_for(
+                                &config.transforms,
+                                link_type,
+                                profile.as_ref(),
+                            ),
+                        });
+                    }
                 }
             }
         }
@@ -109,13 +369,193 @@ pub(crate) fn build_mappings(
     Ok(mappings)
 }
 
+/// Generated targets always get their tokens substituted, copies only when
+/// the rule asks for it.
+fn substitutes(link_type: LinkType, substitute: bool) -> bool {
+    link_type == LinkType::Generate || (substitute && link_type == LinkType::Copy)
+}
+
+/// Refuses `<token>`s nothing defines, which would otherwise end up as
+/// literal directory names.
+fn ensure_tokens_resolve<'a>(
+    rule: &str,
+    raws: impl IntoIterator<Item = &'a str>,
+    ctx: &ResolveContext,
+) -> Result<()> {
+    let unknown = raws
+        .into_iter()
+        .flat_map(|raw| unresolved_tokens(raw, ctx))
+        .filter(|token| token.starts_with('<'))
+        .collect::<BTreeSet<_>>();
+    if unknown.is_empty() {
+        return Ok(());
+    }
+    let (noun, pronoun) = match unknown.len() {
+        1 => ("token", "it"),
+        _ => ("tokens", "them"),
+    };
+    Err(anyhow!(
+        "{rule}: unknown {noun} {} (define {pronoun} in [vars])",
+        unknown.into_iter().collect::<Vec<_>>().join(", ")
+    ))
+}
+
+fn mentions_repo(raw: &str, ctx: &ResolveContext) -> bool {
+    substitute_vars(Cow::Borrowed(raw), ctx).contains("<repo>")
+}
+
+/// The contexts a rule resolves against: one per `[repos]` root when the
+/// rule mentions `<repo>`, otherwise just `ctx`.
+fn rule_contexts<'a>(
+    uses_repo: bool,
+    ctx: &'a ResolveContext,
+    repo_ctxs: Option<&'a [ResolveContext]>,
+) -> Vec<&'a ResolveContext> {
+    match repo_ctxs {
+        Some(repo_ctxs) if uses_repo => repo_ctxs.iter().collect(),
+        _ => vec![ctx],
+    }
+}
+
+/// `(set index, source_root, target_roots)` of each skills set that
+/// applies, once per repository for sets that name `<repo>`.
+pub(crate) fn skills_set_roots(
+    config: &ConfigFile,
+    ctx: &ResolveContext,
+    options: &MappingArgs,
+) -> Result<Vec<(usize, PathBuf, Vec<PathBuf>)>> {
+    let repo_ctxs = repo_contexts(config, ctx, &options.repos)?;
+    let mut roots = Vec::new();
+    for (rule_index, set) in config.skills_sets.iter().enumerate() {
+        if !applies_on_current_os(&set.only_on, &set.skip_on)
+            || !set.when.as_ref().is_none_or(When::holds)
+            || !matches_profiles(&set.profiles, &options.profiles)
+        {
+            continue;
+        }
+        let uses_repo = mentions_repo(&set.source_root, ctx)
+            || set.target_roots.iter().any(|root| mentions_repo(root, ctx));
+        for ctx in rule_contexts(uses_repo, ctx, repo_ctxs.as_deref()) {
+            let target_roots = set
+                .target_roots
+                .iter()
+                .map(|root| resolve_path(root, ctx))
+                .collect();
+            roots.push((
+                rule_index,
+                resolve_path(&set.source_root, ctx),
+                target_roots,
+            ));
+        }
+    }
+    Ok(roots)
+}
+
+/// Builds mappings for every loaded config in order. When several files map
+/// the same target, the mapping from the later file wins.
+pub(crate) fn build_merged_mappings(
+    configs: &[LoadedConfig],
+    options: &MappingArgs,
+    progress: &Progress,
+) -> Result<Vec<Mapping>> {
+    let _span = trace::span(Level::Debug, format_args!("mapping build"));
+    let _phase = PhaseTimer::start(Tally::MappingsMicros);
+    let filter = MappingFilter::new(options)?;
+    progress.start("scanning skills", None);
+    let mut merged: Vec<Mapping> = Vec::new();
+    for loaded in configs {
+        let mappings = build_mappings(&loaded.config, &loaded.ctx, options, progress)?;
+        let overridden = mappings
+            .iter()
+            .map(|mapping| normalize_lexically(&mapping.target))
+            .collect::<HashSet<_>>();
+        merged.retain(|existing| {
+            let keep = !overridden.contains(&normalize_lexically(&existing.target));
+            if !keep {
+                info!(
+                    "override: {} from {} replaced by {}",
+                    existing.target.display(),
+                    existing.config.display(),
+                    loaded.path.display()
+                );
+            }
+            keep
+        });
+        merged.extend(mappings);
+    }
+    merged.retain(|mapping| filter.matches(&mapping.kind, &mapping.source, &mapping.target));
+    debug!(
+        "built {} mappings from {} configs",
+        merged.len(),
+        configs.len()
+    );
+    Ok(merged)
+}
+
+/// Every target the configs produce from any rule, whatever its profiles,
+/// `only_on`, `skip_on` and `when`, across the `[repos]` roots. `--prune`
+/// removes only state entries outside this and the run's own mappings, so
+/// narrowing a run with `--profile` or `--repo` never prunes the rest.
+pub(crate) fn build_prune_scope(configs: &[LoadedConfig]) -> Result<Vec<Mapping>> {
+    let scope = MappingArgs {
+        every_rule: true,
+        ..MappingArgs::default()
+    };
+    build_merged_mappings(configs, &scope, &Progress::disabled())
+}
+
+/// The `--kind`, `--target-contains` and `--source` filters, applied after
+/// the mappings are built so rule indexes and overrides stay unchanged.
+#[derive(Debug)]
+pub(crate) struct MappingFilter {
+    kind: Option<KindFilter>,
+    target_contains: Option<String>,
+    source: Option<PathBuf>,
+}
+
+impl MappingFilter {
+    pub(crate) fn new(options: &MappingArgs) -> Result<Self> {
+        let source = match &options.source {
+            Some(source) => Some(normalize_lexically(&absolute_path(source)?)),
+            None => None,
+        };
+        Ok(Self {
+            kind: options.kind,
+            target_contains: options.target_contains.clone(),
+            source,
+        })
+    }
+
+    pub(crate) fn matches(&self, kind: &MappingKind, source: &Path, target: &Path) -> bool {
+        let kind_matches = match self.kind {
+            None => true,
+            Some(KindFilter::Skill) => matches!(kind, MappingKind::SkillFile),
+            Some(KindFilter::Config) => !matches!(kind, MappingKind::SkillFile),
+        };
+        let target_matches = self
+            .target_contains
+            .as_deref()
+            .is_none_or(|needle| target.to_string_lossy().contains(needle));
+        let source_matches = self
+            .source
+            .as_deref()
+            .is_none_or(|root| normalize_lexically(source).starts_with(root));
+        kind_matches && target_matches && source_matches
+    }
+}
+
 pub(crate) fn apply_link(
     mapping: &Mapping,
     force: bool,
     only_missing: bool,
     dry_run: bool,
-    backup_dir: Option<&std::path::Path>,
+    backup_dir: Option<&BackupDir>,
+    transaction: Option<&Transaction>,
 ) -> Record {
+    let _span = trace::span(
+        Level::Trace,
+        format_args!("link {}", mapping.target.display()),
+    );
     let current = inspect_mapping(mapping);
 
     match current.status {
@@ -124,7 +564,7 @@ pub(crate) fn apply_link(
             message: Some("already linked".to_owned()),
             ..current
         },
-        Status::Missing => link_create(mapping, dry_run),
+        Status::Missing => link_create(mapping, dry_run, transaction),
         Status::Broken | Status::Conflict => {
             if only_missing {
                 return Record {
@@ -140,7 +580,7 @@ pub(crate) fn apply_link(
                     ..current
                 };
             }
-            link_replace(mapping, dry_run, backup_dir)
+            link_replace(mapping, dry_run, backup_dir, transaction)
         }
         Status::Error => current,
         _ => Record {
@@ -155,21 +595,85 @@ pub(crate) fn apply_repair(
     mapping: &Mapping,
     force_conflict: bool,
     dry_run: bool,
-    backup_dir: Option<&std::path::Path>,
+    backup_dir: Option<&BackupDir>,
+    transaction: Option<&Transaction>,
 ) -> Record {
+    repair_inspected(
+        mapping,
+        inspect_mapping(mapping),
+        force_conflict,
+        dry_run,
+        backup_dir,
+        transaction,
+    )
+}
+
+/// Copies a target's content over its source, to keep an edit made in the
+/// vendor's file, then links the target back to the source.
+pub(crate) fn adopt_target(mapping: &Mapping) -> Record {
     let current = inspect_mapping(mapping);
+    let unadoptable = |message: &str| Record {
+        status: Status::Error,
+        message: Some(message.to_owned()),
+        ..base_record(mapping)
+    };
+    if current.status == Status::Ok {
+        return Record {
+            status: Status::Skipped,
+            message: Some("already healthy".to_owned()),
+            ..current
+        };
+    }
+    if is_rendered(mapping) {
+        return unadoptable("a rendered target cannot be adopted; edit the source instead");
+    }
+    if mapping.source_pattern.as_deref().is_some_and(is_url) {
+        return unadoptable("a URL source is replaced on every fetch; edit it on the server");
+    }
+    let (Ok(source_meta), Ok(target_meta)) = (
+        fs::metadata(&mapping.source),
+        fs::symlink_metadata(&mapping.target),
+    ) else {
+        return unadoptable("both source and target must exist to adopt");
+    };
+    if !target_meta.file_type().is_file() {
+        return unadoptable("only a regular file target can be adopted");
+    }
+    // Copying a file onto itself would truncate it first.
+    if same_file(&mapping.source, &source_meta, &mapping.target, &target_meta) {
+        return unadoptable("target is already the source file");
+    }
+    if let Err(err) = fs::copy(&mapping.target, &mapping.source) {
+        return unadoptable(&format!("failed to copy target over source: {err}"));
+    }
+    apply_repair(mapping, true, false, None, None)
+}
 
+/// Repairs a mapping from a record `inspect_mapping` already produced, so
+/// `verify --fix` does not inspect each target twice.
+pub(crate) fn repair_inspected(
+    mapping: &Mapping,
+    current: Record,
+    force_conflict: bool,
+    dry_run: bool,
+    backup_dir: Option<&BackupDir>,
+    transaction: Option<&Transaction>,
+) -> Record {
+    let _span = trace::span(
+        Level::Trace,
+        format_args!("repair {}", mapping.target.display()),
+    );
     match current.status {
         Status::Ok => Record {
             status: Status::Skipped,
             message: Some("already healthy".to_owned()),
             ..current
         },
-        Status::Missing => link_create(mapping, dry_run),
-        Status::Broken => link_replace(mapping, dry_run, backup_dir),
+        Status::Missing => link_create(mapping, dry_run, transaction),
+        Status::Broken => link_replace(mapping, dry_run, backup_dir, transaction),
         Status::Conflict => {
             if force_conflict {
-                link_replace(mapping, dry_run, backup_dir)
+                link_replace(mapping, dry_run, backup_dir, transaction)
             } else {
                 Record {
                     status: Status::Skipped,
@@ -187,11 +691,195 @@ pub(crate) fn apply_repair(
     }
 }
 
+/// With `--owned-only`, targets prompt-sync never created are left alone even
+/// when `--force` would otherwise replace them.
+pub(crate) fn guard_unowned(mapping: &Mapping, owned: bool) -> Option<Record> {
+    if owned {
+        return None;
+    }
+
+    let current = inspect_mapping(mapping);
+    matches!(current.status, Status::Broken | Status::Conflict).then(|| Record {
+        status: Status::Skipped,
+        message: Some("target was not created by prompt-sync (--owned-only)".to_owned()),
+        ..current
+    })
+}
+
+pub(crate) fn apply_unlink(mapping: &Mapping, dry_run: bool) -> Record {
+    let _span = trace::span(
+        Level::Trace,
+        format_args!("unlink {}", mapping.target.display()),
+    );
+    let current = inspect_mapping(mapping);
+
+    match current.status {
+        Status::Ok => {
+            if dry_run {
+                return Record {
+                    status: Status::WouldRemove,
+                    message: Some(format!("would remove {}", mapping.link_type.as_str())),
+                    ..current
+                };
+            }
+            if let Err(err) = fs::remove_file(&mapping.target) {
+                return Record {
+                    status: Status::Error,
+                    message: Some(format!(
+                        "failed to remove target {}: {}",
+                        mapping.target.display(),
+                        err
+                    )),
+                    ..current
+                };
+            }
+            Record {
+                status: Status::Removed,
+                message: Some(format!("removed {}", mapping.link_type.as_str())),
+                ..current
+            }
+        }
+        Status::Missing => Record {
+            status: Status::Skipped,
+            message: Some("target already absent".to_owned()),
+            ..current
+        },
+        Status::Broken | Status::Conflict => Record {
+            status: Status::Skipped,
+            message: Some("target is not linked to source; left in place".to_owned()),
+            ..current
+        },
+        Status::Error => current,
+        _ => Record {
+            status: Status::Error,
+            message: Some("unexpected state".to_owned()),
+            ..current
+        },
+    }
+}
+
+pub(crate) fn prune_orphan(
+    target: &Path,
+    entry: &ManagedTarget,
+    dry_run: bool,
+    transaction: Option<&Transaction>,
+) -> Record {
+    let mapping = Mapping {
+        config: entry.config.clone(),
+        kind: entry.kind.clone(),
+        rule_index: 0,
+        link_type: entry.link_type,
+        source: entry.source.clone(),
+        target: target.to_path_buf(),
+        source_template: String::new(),
+        target_template: String::new(),
+        source_pattern: None,
+        git_exclude: false,
+        protect: false,
+        profile: None,
+        vars: None,
+        transform: None,
+    };
+    let base = Record {
+        rule: None,
+        ..base_record(&mapping)
+    };
+
+    let target_meta = match fs::symlink_metadata(target) {
+        Ok(meta) => meta,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Record {
+                status: Status::Skipped,
+                message: Some("orphaned target already absent".to_owned()),
+                ..base
+            };
+        }
+        Err(err) => {
+            return Record {
+                status: Status::Error,
+                message: Some(format!(
+                    "target metadata error {}: {}",
+                    target.display(),
+                    err
+                )),
+                ..base
+            };
+        }
+    };
+
+    // The source of a pruned skill file is usually gone, so ownership is
+    // decided by the identity recorded at link time when available.
+    let owned = match (entry.identity, file_identity(target, &target_meta)) {
+        (Some(recorded), Some(current)) => recorded == current,
+        _ => inspect_mapping(&mapping).status == Status::Ok,
+    };
+    if !owned {
+        return Record {
+            status: Status::Skipped,
+            message: Some("orphaned target changed since it was linked; left in place".to_owned()),
+            ..base
+        };
+    }
+
+    if dry_run {
+        return Record {
+            status: Status::WouldPrune,
+            message: Some("would remove orphaned target".to_owned()),
+            ..base
+        };
+    }
+
+    // Under --atomic the target is moved aside rather than deleted, so a
+    // rollback can move it back.
+    let removed = match transaction {
+        Some(transaction) => {
+            let original = stash_path(target);
+            fs::rename(target, &original).map(|()| {
+                transaction.record(Mutation::Removed {
+                    target: target.to_path_buf(),
+                    original,
+                });
+            })
+        }
+        None => fs::remove_file(target),
+    };
+    if let Err(err) = removed {
+        return Record {
+            status: Status::Error,
+            message: Some(format!(
+                "failed to remove orphaned target {}: {}",
+                target.display(),
+                err
+            )),
+            ..base
+        };
+    }
+
+    Record {
+        status: Status::Pruned,
+        message: Some("removed orphaned target".to_owned()),
+        ..base
+    }
+}
+
 pub(crate) fn inspect_mapping(mapping: &Mapping) -> Record {
     let base = base_record(mapping);
 
     let source_meta = match fs::symlink_metadata(&mapping.source) {
         Ok(meta) => meta,
+        Err(err)
+            if err.kind() == std::io::ErrorKind::NotFound
+                && mapping.source_pattern.as_deref().is_some_and(is_url) =>
+        {
+            return Record {
+                status: Status::Error,
+                message: Some(format!(
+                    "source missing: {} has not been fetched yet (`link` or `sync` downloads it)",
+                    mapping.source_template
+                )),
+                ..base
+            };
+        }
         Err(err) => {
             return Record {
                 status: Status::Error,
@@ -227,14 +915,210 @@ pub(crate) fn inspect_mapping(mapping: &Mapping) -> Record {
         }
     };
 
-    if !source_meta.file_type().is_file() {
+    if !source_meta.file_type().is_file() {
+        return Record {
+            status: Status::Error,
+            message: Some("source is not a regular file".to_owned()),
+            ..base
+        };
+    }
+
+    match mapping.link_type {
+        LinkType::Hardlink => inspect_hardlink(mapping, base, &source_meta, &target_meta),
+        LinkType::Symlink => inspect_symlink(mapping, base, &source_meta, &target_meta),
+        LinkType::Generate => inspect_rendered(mapping, base, &target_meta),
+        LinkType::Copy if mapping.vars.is_some() => inspect_rendered(mapping, base, &target_meta),
+        LinkType::Copy | LinkType::Reflink => inspect_copy(mapping, base, &target_meta),
+        LinkType::Auto => unreachable!("auto is resolved when mappings are built"),
+    }
+}
+
+/// A stat-only `inspect_mapping` for `status --porcelain`: whether the
+/// target is anything but OK. Links are compared by inode; as nothing is
+/// hashed, a copied or generated target only has to be a regular file no
+/// older than its source.
+pub(crate) fn is_drifted(mapping: &Mapping) -> bool {
+    let (Ok(source_meta), Ok(target_meta)) = (
+        fs::symlink_metadata(&mapping.source),
+        fs::symlink_metadata(&mapping.target),
+    ) else {
+        return true;
+    };
+    if !source_meta.file_type().is_file() {
+        return true;
+    }
+    match mapping.link_type {
+        LinkType::Hardlink => {
+            !target_meta.file_type().is_file()
+                || !same_file(&mapping.source, &source_meta, &mapping.target, &target_meta)
+        }
+        LinkType::Symlink => {
+            !target_meta.file_type().is_symlink()
+                || !fs::metadata(&mapping.target).is_ok_and(|resolved| {
+                    same_file(&mapping.source, &source_meta, &mapping.target, &resolved)
+                })
+        }
+        LinkType::Copy | LinkType::Reflink | LinkType::Generate | LinkType::Auto => {
+            !target_meta.file_type().is_file()
+                || match (source_meta.modified(), target_meta.modified()) {
+                    (Ok(source), Ok(target)) => target < source,
+                    _ => false,
+                }
+        }
+    }
+}
+
+/// Makes the source and target of a `protect` mapping read-only, or with
+/// `read_only` unset writable again, and returns the files that changed.
+/// A symlink target is its source.
+pub(crate) fn protect_mapping(mapping: &Mapping, read_only: bool) -> Result<Vec<PathBuf>> {
+    let mut changed = Vec::new();
+    for path in [&mapping.source, &mapping.target] {
+        if path.exists() && set_read_only(path, read_only)? {
+            changed.push(path.clone());
+        }
+    }
+    Ok(changed)
+}
+
+/// Reports an OK `protect` mapping whose source or target is writable as
+/// BROKEN, or with `fix` makes them read-only again.
+pub(crate) fn check_protection(mapping: &Mapping, record: Record, fix: bool) -> Record {
+    if !mapping.protect || record.status != Status::Ok {
+        return record;
+    }
+    let writable = [&mapping.source, &mapping.target]
+        .into_iter()
+        .find(|path| fs::metadata(path).is_ok_and(|meta| !meta.permissions().readonly()));
+    let Some(writable) = writable else {
+        return record;
+    };
+    if !fix {
+        return Record {
+            status: Status::Broken,
+            message: Some(format!(
+                "{} is writable, but the rule is protected",
+                writable.display()
+            )),
+            ..record
+        };
+    }
+    match protect_mapping(mapping, true) {
+        Ok(_) => Record {
+            message: Some("made read-only again".to_owned()),
+            ..record
+        },
+        Err(err) => Record {
+            status: Status::Error,
+            message: Some(format!("{err:#}")),
+            ..record
+        },
+    }
+}
+
+/// Adds `content_match` to an inspected record by hashing both sides, so a
+/// conflict can be told apart from a target that was merely copied. Records
+/// without a readable target are left as they are.
+pub(crate) fn annotate_content_match(mapping: &Mapping, record: Record) -> Record {
+    if matches!(record.status, Status::Missing | Status::Error) {
+        return record;
+    }
+    let source_hash = if is_rendered(mapping) {
+        render_source(mapping).map(|rendered| sha256_hex(rendered.as_bytes()))
+    } else {
+        calculate_sha256(&mapping.source)
+    };
+    let hashes = source_hash
+        .and_then(|source| calculate_sha256(&mapping.target).map(|target| (source, target)));
+    match hashes {
+        Ok((source, target)) => Record {
+            content_match: Some(source == target),
+            ..record
+        },
+        Err(_) => record,
+    }
+}
+
+fn inspect_hardlink(
+    mapping: &Mapping,
+    base: Record,
+    source_meta: &fs::Metadata,
+    target_meta: &fs::Metadata,
+) -> Record {
+    if !target_meta.file_type().is_file() {
+        return Record {
+            status: Status::Conflict,
+            message: Some("target exists but is not a regular file".to_owned()),
+            ..base
+        };
+    }
+
+    if same_file(&mapping.source, source_meta, &mapping.target, target_meta) {
+        return Record {
+            status: Status::Ok,
+            message: Some("inode match".to_owned()),
+            ..base
+        };
+    }
+
+    if hardlink_count(&mapping.target, target_meta) > 1 {
+        return Record {
+            status: Status::Broken,
+            message: Some("target is hardlinked to a different source".to_owned()),
+            ..base
+        };
+    }
+
+    Record {
+        status: Status::Conflict,
+        message: Some("target differs and is not linked".to_owned()),
+        ..base
+    }
+}
+
+fn inspect_symlink(
+    mapping: &Mapping,
+    base: Record,
+    source_meta: &fs::Metadata,
+    target_meta: &fs::Metadata,
+) -> Record {
+    if !target_meta.file_type().is_symlink() {
         return Record {
-            status: Status::Error,
-            message: Some("source is not a regular file".to_owned()),
+            status: Status::Conflict,
+            message: Some("target exists but is not a symlink".to_owned()),
             ..base
         };
     }
 
+    match fs::metadata(&mapping.target) {
+        Ok(resolved_meta)
+            if same_file(
+                &mapping.source,
+                source_meta,
+                &mapping.target,
+                &resolved_meta,
+            ) =>
+        {
+            Record {
+                status: Status::Ok,
+                message: Some("symlink points to source".to_owned()),
+                ..base
+            }
+        }
+        Ok(_) => Record {
+            status: Status::Broken,
+            message: Some("symlink points to a different file".to_owned()),
+            ..base
+        },
+        Err(_) => Record {
+            status: Status::Broken,
+            message: Some("symlink target is dangling".to_owned()),
+            ..base
+        },
+    }
+}
+
+fn inspect_copy(mapping: &Mapping, base: Record, target_meta: &fs::Metadata) -> Record {
     if !target_meta.file_type().is_file() {
         return Record {
             status: Status::Conflict,
@@ -243,88 +1127,583 @@ pub(crate) fn inspect_mapping(mapping: &Mapping) -> Record {
         };
     }
 
-    if same_file(&source_meta, &target_meta) {
-        return Record {
+    let hashes = calculate_sha256(&mapping.source)
+        .and_then(|source_hash| Ok((source_hash, calculate_sha256(&mapping.target)?)));
+    match hashes {
+        Ok((source_hash, target_hash)) if source_hash == target_hash => Record {
             status: Status::Ok,
-            message: Some("inode match".to_owned()),
+            message: Some("content hash match".to_owned()),
             ..base
-        };
+        },
+        Ok(_) => Record {
+            status: Status::Broken,
+            message: Some("target content drifted from source".to_owned()),
+            ..base
+        },
+        Err(err) => Record {
+            status: Status::Error,
+            message: Some(err.to_string()),
+            ..base
+        },
     }
+}
 
-    if hardlink_count(&target_meta) > 1 {
+/// Like a copy, but compared with the source as rendered for the target.
+fn inspect_rendered(mapping: &Mapping, base: Record, target_meta: &fs::Metadata) -> Record {
+    if !target_meta.file_type().is_file() {
         return Record {
-            status: Status::Broken,
-            message: Some("target is hardlinked to a different source".to_owned()),
+            status: Status::Conflict,
+            message: Some("target exists but is not a regular file".to_owned()),
             ..base
         };
     }
 
-    Record {
-        status: Status::Conflict,
-        message: Some("target differs and is not linked".to_owned()),
-        ..base
+    let contents = render_source(mapping).and_then(|rendered| {
+        let target = fs::read(&mapping.target)
+            .with_context(|| format!("failed to read target {}", mapping.target.display()))?;
+        Ok((rendered, target))
+    });
+    match contents {
+        Ok((rendered, target)) if rendered.as_bytes() == target => Record {
+            status: Status::Ok,
+            message: Some("rendered hash match".to_owned()),
+            ..base
+        },
+        // A generated target that no longer matches its own header was
+        // edited by hand (or never generated), so it is not replaced
+        // without --force.
+        Ok((_, target))
+            if mapping.link_type == LinkType::Generate
+                && !is_untouched(&String::from_utf8_lossy(&target)) =>
+        {
+            Record {
+                status: Status::Conflict,
+                message: Some("generated target was edited by hand".to_owned()),
+                ..base
+            }
+        }
+        Ok(_) => Record {
+            status: Status::Broken,
+            message: Some(match changed_fragment(mapping, target_meta) {
+                Some(fragment) => format!(
+                    "fragment changed since the target was generated: {}",
+                    fragment.display()
+                ),
+                None => "target content drifted from rendered source".to_owned(),
+            }),
+            ..base
+        },
+        Err(err) => Record {
+            status: Status::Error,
+            message: Some(format!("{err:#}")),
+            ..base
+        },
+    }
+}
+
+/// An included fragment modified after the target was last written.
+fn changed_fragment(mapping: &Mapping, target_meta: &fs::Metadata) -> Option<PathBuf> {
+    if mapping.link_type != LinkType::Generate {
+        return None;
+    }
+    let generated = target_meta.modified().ok()?;
+    fragments(&mapping.source).into_iter().find(|fragment| {
+        fs::metadata(fragment)
+            .and_then(|meta| meta.modified())
+            .is_ok_and(|modified| modified > generated)
+    })
+}
+
+pub(crate) fn plan_mapping(mapping: &Mapping) -> PlanEntry {
+    let current = inspect_mapping(mapping);
+    let action = match current.status {
+        Status::Ok => PlanAction::Keep,
+        Status::Missing => PlanAction::Create,
+        Status::Broken | Status::Conflict => PlanAction::Replace,
+        _ => PlanAction::Error,
+    };
+
+    PlanEntry {
+        kind: current.kind,
+        rule_kind: mapping.kind.rule_kind(),
+        rule_index: mapping.rule_index,
+        source_template: mapping.source_template.clone(),
+        target_template: mapping.target_template.clone(),
+        link_type: current.link_type,
+        source: current.source,
+        target: current.target,
+        source_pattern: mapping.source_pattern.clone(),
+        status: current.status,
+        action,
+        message: current.message,
     }
 }
 
-pub(crate) fn print_report(report: &Report, json: bool, show_records_in_text: bool) -> Result<()> {
+pub(crate) fn print_plan(entries: &[PlanEntry], json: bool) -> Result<()> {
     if json {
-        let json_text = serde_json::to_string_pretty(report).context("failed to serialize JSON")?;
+        let json_text =
+            serde_json::to_string_pretty(entries).context("failed to serialize JSON")?;
         println!("{json_text}");
         return Ok(());
     }
 
-    println!("command: {}", report.command);
-    println!("total: {}", report.summary.total);
-    println!(
-        "ok={} missing={} broken={} conflict={} created={} replaced={} would_create={} would_replace={} skipped={} errors={}",
-        report.summary.ok,
-        report.summary.missing,
-        report.summary.broken,
-        report.summary.conflict,
-        report.summary.created,
-        report.summary.replaced,
-        report.summary.would_create,
-        report.summary.would_replace,
-        report.summary.skipped,
-        report.summary.errors,
-    );
+    for entry in entries {
+        let message = entry.message.as_deref().unwrap_or("");
+        println!(
+            "[{}] {}[{}] {} {} -> {} ({message})",
+            entry.action.as_str(),
+            entry.rule_kind,
+            entry.rule_index,
+            entry.link_type.as_str(),
+            entry.source.display(),
+            entry.target.display(),
+        );
+    }
 
-    if show_records_in_text {
-        for record in &report.records {
+    Ok(())
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamLine<'a> {
+    Record(&'a Record),
+    Summary {
+        schema_version: u32,
+        command: &'a str,
+        summary: &'a Summary,
+    },
+}
+
+/// Prints each record as one JSON line while a command runs (`--json-lines`);
+/// `print_report` then closes the stream with a summary line.
+pub(crate) struct RecordStream {
+    enabled: bool,
+}
+
+impl RecordStream {
+    pub(crate) const fn new(output: &OutputArgs) -> Self {
+        Self {
+            enabled: output.json_lines,
+        }
+    }
+
+    pub(crate) fn emit(&self, record: &Record) {
+        if !self.enabled {
+            return;
+        }
+        match serde_json::to_string(&StreamLine::Record(record)) {
+            Ok(line) => println!("{line}"),
+            Err(err) => eprintln!("warn: failed to serialize record: {err}"),
+        }
+    }
+}
+
+pub(crate) fn print_report(
+    report: &Report,
+    output: &OutputArgs,
+    show_records_in_text: bool,
+) -> Result<()> {
+    print_records(report, output, show_records_in_text)?;
+    // Machine-readable reports carry the same numbers in their fields.
+    if output.timing
+        && !matches!(
+            output.format(),
+            OutputFormat::Json
+                | OutputFormat::Yaml
+                | OutputFormat::Github
+                | OutputFormat::Porcelain
+        )
+    {
+        print_timing(report);
+    }
+    Ok(())
+}
+
+fn print_records(report: &Report, output: &OutputArgs, show_records_in_text: bool) -> Result<()> {
+    let painter = Painter {
+        enabled: output.use_color(),
+    };
+    if output.quiet {
+        for record in report
+            .records
+            .iter()
+            .filter(|record| record.status == Status::Error)
+        {
             let message = record.message.as_deref().unwrap_or("");
-            println!(
-                "[{:?}] {} -> {} ({message})",
+            eprintln!(
+                "[{:?}] {} -> {} ({message}){}",
                 record.status,
                 record.source.display(),
                 record.target.display(),
+                error_origin(record),
             );
         }
+        return Ok(());
+    }
+    if output.summary_only {
+        print_summary_counts(&report.summary);
+        return Ok(());
+    }
+    if output.json_lines {
+        let line = serde_json::to_string(&StreamLine::Summary {
+            schema_version: report.schema_version,
+            command: &report.command,
+            summary: &report.summary,
+        })
+        .context("failed to serialize JSON")?;
+        println!("{line}");
+        return Ok(());
+    }
+    match output.format() {
+        OutputFormat::Json => {
+            let json_text =
+                serde_json::to_string_pretty(report).context("failed to serialize JSON")?;
+            println!("{json_text}");
+            return Ok(());
+        }
+        OutputFormat::Yaml => {
+            let value = serde_json::to_value(report).context("failed to serialize YAML")?;
+            print!("{}", yaml::to_string(&value));
+            return Ok(());
+        }
+        OutputFormat::Table => {
+            print_master_line(report);
+            print_table(&report.records, painter);
+            print_summary_counts(&report.summary);
+            return Ok(());
+        }
+        OutputFormat::Compact => {
+            print_master_line(report);
+            let glyphs = report
+                .records
+                .iter()
+                .map(|record| painter.paint(record.status, status_glyph(record.status)))
+                .collect::<String>();
+            println!("{glyphs}");
+            print_summary_counts(&report.summary);
+            for record in report
+                .records
+                .iter()
+                .filter(|record| record.status == Status::Error)
+            {
+                print_record_line(record, painter);
+            }
+            return Ok(());
+        }
+        OutputFormat::Github => {
+            print_annotations(report);
+            print_summary_counts(&report.summary);
+            return Ok(());
+        }
+        OutputFormat::Porcelain => {
+            for record in &report.records {
+                println!("{}", porcelain_line(record));
+            }
+            return Ok(());
+        }
+        OutputFormat::Text => {}
+    }
+
+    println!("command: {}", report.command);
+    print_master_line(report);
+    println!("total: {}", report.summary.total);
+    print_summary_counts(&report.summary);
+
+    if show_records_in_text {
+        for record in &report.records {
+            print_record_line(record, painter);
+        }
     } else {
         for record in report
             .records
             .iter()
             .filter(|record| record.status == Status::Error)
         {
-            let message = record.message.as_deref().unwrap_or("");
-            println!(
-                "[{:?}] {} -> {} ({message})",
-                record.status,
-                record.source.display(),
-                record.target.display(),
-            );
+            print_record_line(record, painter);
         }
     }
 
     Ok(())
 }
 
-fn link_create(mapping: &Mapping, dry_run: bool) -> Record {
+/// One `--output porcelain` line, version 1: the record's status, kind,
+/// source and target separated by tabs, with `\`, tab and newline in the
+/// paths escaped as `\\`, `\t` and `\n`. Status and kind use their JSON
+/// report names. Unlike the text output this never changes; a different
+/// layout would be a new version.
+fn porcelain_line(record: &Record) -> String {
+    let escape = |path: &Path| {
+        path.to_string_lossy()
+            .replace('\\', "\\\\")
+            .replace('\t', "\\t")
+            .replace('\n', "\\n")
+    };
+    format!(
+        "{}\t{}\t{}\t{}",
+        serde_label(&record.status),
+        serde_label(&record.kind),
+        escape(&record.source),
+        escape(&record.target)
+    )
+}
+
+/// How many of the slowest records `--timing` lists.
+const SLOWEST_RECORDS: usize = 5;
+
+/// `timing: 812.4ms for 40 record(s), 790.2ms in the records`, the time
+/// in each phase with the walk and hash counts, then the slowest records,
+/// to tell a slow filesystem or a huge skills tree from a slow config load.
+fn print_timing(report: &Report) {
+    let measured = report
+        .records
+        .iter()
+        .filter_map(|record| record.duration_ms.map(|duration| (duration, record)))
+        .collect::<Vec<_>>();
+    let in_records = measured.iter().map(|(duration, _)| duration).sum::<f64>();
+    println!(
+        "timing: {:.1}ms for {} record(s), {:.1}ms in the records",
+        report.elapsed_ms,
+        report.records.len(),
+        in_records
+    );
+    let timing = &report.timing;
+    println!(
+        "phases: config {:.1}ms, mappings {:.1}ms, records {:.1}ms; {} entries walked, {} files hashed, {} stat cache hits",
+        timing.config_ms,
+        timing.mappings_ms,
+        timing.records_ms,
+        timing.walked_entries,
+        timing.hashed_files,
+        timing.stat_cache_hits
+    );
+    let mut slowest = measured;
+    slowest.sort_by(|a, b| b.0.total_cmp(&a.0));
+    for (duration, record) in slowest.into_iter().take(SLOWEST_RECORDS) {
+        println!(
+            "  {duration:>9.3}ms [{:?}] {} -> {}",
+            record.status,
+            record.source.display(),
+            record.target.display()
+        );
+    }
+}
+
+/// One workflow command per conflicting, broken, missing or failed record.
+/// Targets inside the checkout get a `file=` relative to it, so the
+/// annotation lands on that file in the pull request view.
+fn print_annotations(report: &Report) {
+    let workspace = env::var_os("GITHUB_WORKSPACE")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::current_dir().ok())
+        .map(|dir| canonical_path(&dir).unwrap_or(dir));
+    for record in &report.records {
+        let level = match record.status {
+            Status::Conflict | Status::Broken | Status::Error => "error",
+            Status::Missing => "warning",
+            _ => continue,
+        };
+        let status = serde_label(&record.status).to_lowercase();
+        let mut properties = format!(
+            "title={}",
+            annotation_property(&format!("prompt-sync: {status}"))
+        );
+        if let Some(file) = workspace
+            .as_deref()
+            .and_then(|dir| record.target.strip_prefix(dir).ok())
+        {
+            let file = file.to_string_lossy().replace('\\', "/");
+            properties = format!("file={},{properties}", annotation_property(&file));
+        }
+        let message = match &record.message {
+            Some(message) => format!(
+                "{} -> {} ({message})",
+                record.source.display(),
+                record.target.display()
+            ),
+            None => format!("{} -> {}", record.source.display(), record.target.display()),
+        };
+        println!("::{level} {properties}::{}", annotation_data(&message));
+    }
+}
+
+/// Escapes a workflow command message.
+fn annotation_data(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escapes a workflow command property value, which also ends at `,` or `:`.
+fn annotation_property(text: &str) -> String {
+    annotation_data(text)
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
+
+/// `master: pulled ~/.ai_settings (1a2b3c4..5d6e7f8)` for `sync`.
+fn print_master_line(report: &Report) {
+    if let Some(master) = &report.master {
+        let message = master
+            .message
+            .as_deref()
+            .map(|message| format!(" ({message})"))
+            .unwrap_or_default();
+        println!(
+            "master: {} {}{message}",
+            serde_label(&master.action).replace('_', " "),
+            master.root.display()
+        );
+    }
+}
+
+fn print_record_line(record: &Record, painter: Painter) {
+    let message = record.message.as_deref().unwrap_or("");
+    let content = match record.content_match {
+        Some(true) => " [content matches]",
+        Some(false) => " [content differs]",
+        None => "",
+    };
+    println!(
+        "{} {} -> {} ({message}){content}{}",
+        painter.paint(record.status, &format!("[{:?}]", record.status)),
+        record.source.display(),
+        record.target.display(),
+        error_origin(record),
+    );
+}
+
+/// ` [links[2] in <config>]` after a failed record, naming the rule to fix.
+fn error_origin(record: &Record) -> String {
+    match &record.rule {
+        Some(rule) if record.status == Status::Error => format!(" [{rule}]"),
+        _ => String::new(),
+    }
+}
+
+/// Wraps status text in ANSI colors when enabled: green for settled or
+/// applied targets, yellow for ones needing attention, red for failures.
+#[derive(Clone, Copy)]
+struct Painter {
+    enabled: bool,
+}
+
+impl Painter {
+    fn paint(self, status: Status, text: &str) -> String {
+        match status_color(status) {
+            Some(code) if self.enabled => format!("\x1b[{code}m{text}\x1b[0m"),
+            _ => text.to_owned(),
+        }
+    }
+}
+
+/// The ANSI color code of a status, if it has one.
+pub(crate) const fn status_color(status: Status) -> Option<&'static str> {
+    match status {
+        Status::Ok | Status::Created | Status::Replaced | Status::Removed | Status::Pruned => {
+            Some("32")
+        }
+        Status::Conflict | Status::Skipped | Status::Missing | Status::RolledBack => Some("33"),
+        Status::Error | Status::Broken => Some("31"),
+        Status::WouldCreate
+        | Status::WouldReplace
+        | Status::WouldRemove
+        | Status::WouldPrune
+        | Status::Accepted => None,
+    }
+}
+
+const fn status_glyph(status: Status) -> &'static str {
+    match status {
+        Status::Ok => ".",
+        Status::Missing => "?",
+        Status::Broken => "x",
+        Status::Conflict => "C",
+        Status::Created => "+",
+        Status::Replaced => "~",
+        Status::WouldCreate | Status::WouldReplace | Status::WouldRemove | Status::WouldPrune => {
+            "*"
+        }
+        Status::Removed | Status::Pruned => "-",
+        Status::Skipped => "s",
+        Status::RolledBack => "r",
+        Status::Accepted => "a",
+        Status::Error => "E",
+    }
+}
+
+fn print_summary_counts(summary: &Summary) {
+    println!(
+        "ok={} missing={} broken={} conflict={} created={} replaced={} would_create={} would_replace={} removed={} would_remove={} pruned={} would_prune={} skipped={} rolled_back={} accepted={} errors={}",
+        summary.ok,
+        summary.missing,
+        summary.broken,
+        summary.conflict,
+        summary.created,
+        summary.replaced,
+        summary.would_create,
+        summary.would_replace,
+        summary.removed,
+        summary.would_remove,
+        summary.pruned,
+        summary.would_prune,
+        summary.skipped,
+        summary.rolled_back,
+        summary.accepted,
+        summary.errors,
+    );
+}
+
+/// Column-aligned STATUS / KIND / SOURCE / TARGET view of all records.
+fn print_table(records: &[Record], painter: Painter) {
+    let rows = records
+        .iter()
+        .map(|record| {
+            [
+                serde_label(&record.status),
+                serde_label(&record.kind),
+                record.source.display().to_string(),
+                record.target.display().to_string(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let header = ["STATUS", "KIND", "SOURCE", "TARGET"].map(str::to_owned);
+    let mut widths = header.clone().map(|cell| cell.chars().count());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let statuses = std::iter::once(None).chain(records.iter().map(|record| Some(record.status)));
+    for (row, status) in std::iter::once(&header).chain(&rows).zip(statuses) {
+        let mut cells = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect::<Vec<_>>();
+        // Pad before coloring so escape codes do not skew the columns.
+        if let Some(status) = status {
+            cells[0] = painter.paint(status, &cells[0]);
+        }
+        println!("{}", cells.join("  ").trim_end());
+    }
+}
+
+pub(crate) fn serde_label<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(label)) => label,
+        _ => String::new(),
+    }
+}
+
+fn link_create(mapping: &Mapping, dry_run: bool, transaction: Option<&Transaction>) -> Record {
     let base = base_record(mapping);
 
     if dry_run {
         return Record {
             status: Status::WouldCreate,
-            message: Some("would create hardlink".to_owned()),
+            message: Some(format!("would create {}", mapping.link_type.as_str())),
             ..base
         };
     }
@@ -337,35 +1716,48 @@ fn link_create(mapping: &Mapping, dry_run: bool) -> Record {
         };
     }
 
-    if let Err(err) = create_hard_link_checked(&mapping.source, &mapping.target) {
+    if let Err(err) = create_link(mapping) {
         return Record {
             status: Status::Error,
             message: Some(err.to_string()),
             ..base
         };
     }
+    if let Some(transaction) = transaction {
+        transaction.record(Mutation::Created {
+            target: mapping.target.clone(),
+        });
+    }
 
     Record {
         status: Status::Created,
-        message: Some("created hardlink".to_owned()),
+        message: Some(format!("created {}", mapping.link_type.as_str())),
         ..base
     }
 }
 
-fn link_replace(mapping: &Mapping, dry_run: bool, backup_dir: Option<&std::path::Path>) -> Record {
+fn link_replace(
+    mapping: &Mapping,
+    dry_run: bool,
+    backup_dir: Option<&BackupDir>,
+    transaction: Option<&Transaction>,
+) -> Record {
     let base = base_record(mapping);
 
     if dry_run {
         return Record {
             status: Status::WouldReplace,
-            message: Some("would replace target with hardlink".to_owned()),
+            message: Some(format!(
+                "would replace target with {}",
+                mapping.link_type.as_str()
+            )),
             ..base
         };
     }
 
     if let Err(err) = ensure_parent_dir(&mapping.target) {
-        if let Some(backup_root) = backup_dir {
-            let logger = OperationLog::new(backup_root);
+        if let Some(backup) = backup_dir {
+            let logger = OperationLog::new(backup);
             let _ = logger.record(logging::LogEntry {
                 action: Action::Replace,
                 source: &mapping.source,
@@ -390,11 +1782,11 @@ fn link_replace(mapping: &Mapping, dry_run: bool, backup_dir: Option<&std::path:
         None
     };
 
-    let backup_outcome = match remove_existing_target_file(&mapping.target, backup_dir) {
+    let backup_outcome = match backup_existing_target(&mapping.target, backup_dir) {
         Ok(outcome) => outcome,
         Err(err) => {
-            if let Some(backup_root) = backup_dir {
-                let logger = OperationLog::new(backup_root);
+            if let Some(backup) = backup_dir {
+                let logger = OperationLog::new(backup);
                 let _ = logger.record(logging::LogEntry {
                     action: Action::Replace,
                     source: &mapping.source,
@@ -413,9 +1805,44 @@ fn link_replace(mapping: &Mapping, dry_run: bool, backup_dir: Option<&std::path:
         }
     };
 
-    if let Err(err) = create_hard_link_checked(&mapping.source, &mapping.target) {
-        if let Some(backup_root) = backup_dir {
-            let logger = OperationLog::new(backup_root);
+    let stashed = match transaction {
+        Some(transaction) => {
+            if let (Some(backup_dir), Some(backup)) = (backup_dir, &backup_outcome.backup_path) {
+                transaction.record(Mutation::BackedUp {
+                    root: backup_dir.path.clone(),
+                    backup: backup.clone(),
+                });
+            }
+            match stash_target(&mapping.target) {
+                Ok(original) => Some((transaction, original)),
+                Err(err) => {
+                    return Record {
+                        status: Status::Error,
+                        message: Some(err.to_string()),
+                        ..base
+                    };
+                }
+            }
+        }
+        None => None,
+    };
+
+    // Build the new link beside the target and rename it into place, so the
+    // target never goes missing if the process dies halfway.
+    let replaced = replace_link(mapping);
+    if let Some((transaction, original)) = stashed {
+        if replaced.is_ok() {
+            transaction.record(Mutation::Replaced {
+                target: mapping.target.clone(),
+                original,
+            });
+        } else {
+            let _ = fs::remove_file(original);
+        }
+    }
+    if let Err(err) = replaced {
+        if let Some(backup) = backup_dir {
+            let logger = OperationLog::new(backup);
             let _ = logger.record(logging::LogEntry {
                 action: Action::Replace,
                 source: &mapping.source,
@@ -434,8 +1861,8 @@ fn link_replace(mapping: &Mapping, dry_run: bool, backup_dir: Option<&std::path:
     }
 
     // Log successful replacement
-    if let Some(backup_root) = backup_dir {
-        let logger = OperationLog::new(backup_root);
+    if let Some(backup) = backup_dir {
+        let logger = OperationLog::new(backup);
         let _ = logger.record(logging::LogEntry {
             action: Action::Replace,
             source: &mapping.source,
@@ -449,21 +1876,191 @@ fn link_replace(mapping: &Mapping, dry_run: bool, backup_dir: Option<&std::path:
 
     Record {
         status: Status::Replaced,
-        message: Some("replaced target with hardlink".to_owned()),
+        message: Some(format!(
+            "replaced target with {}",
+            mapping.link_type.as_str()
+        )),
         ..base
     }
 }
 
-fn build_glob_set(patterns: &[String]) -> Result<GlobSet> {
+fn create_link(mapping: &Mapping) -> Result<()> {
+    create_link_at(mapping, &mapping.target)
+}
+
+fn replace_link(mapping: &Mapping) -> Result<()> {
+    let temp = temp_sibling_path(&mapping.target);
+    let _ = fs::remove_file(&temp);
+    let result = create_link_at(mapping, &temp).and_then(|()| {
+        fs::rename(&temp, &mapping.target).with_context(|| {
+            format!(
+                "failed to move new target into place {}",
+                mapping.target.display()
+            )
+        })
+    });
+    // rename(2) leaves both names in place when they already share an inode.
+    let _ = fs::remove_file(&temp);
+    result
+}
+
+fn create_link_at(mapping: &Mapping, path: &Path) -> Result<()> {
+    match mapping.link_type {
+        LinkType::Hardlink => create_hard_link_checked(&mapping.source, path),
+        LinkType::Symlink => create_symlink_checked(&mapping.source, path),
+        LinkType::Generate => write_rendered(mapping, path),
+        LinkType::Copy if mapping.vars.is_some() => write_rendered(mapping, path),
+        LinkType::Copy => copy_file_checked(&mapping.source, path),
+        LinkType::Reflink => clone_file_checked(&mapping.source, path),
+        LinkType::Auto => unreachable!("auto is resolved when mappings are built"),
+    }
+}
+
+fn write_rendered(mapping: &Mapping, path: &Path) -> Result<()> {
+    let rendered = render_source(mapping)?;
+    fs::write(path, rendered)
+        .with_context(|| format!("failed to write rendered {}", path.display()))
+}
+
+/// `link_type = "auto"` becomes what works on this platform, so mappings,
+/// reports and state always carry a concrete link type.
+fn resolve_auto(link_type: LinkType) -> LinkType {
+    match link_type {
+        LinkType::Auto if cfg!(windows) && symlinks_allowed() => LinkType::Symlink,
+        LinkType::Auto if cfg!(windows) => LinkType::Copy,
+        LinkType::Auto => LinkType::Hardlink,
+        other => other,
+    }
+}
+
+/// Hardlinks cannot span filesystems. When `on_cross_device` names a
+/// fallback, a hardlink mapping whose target lands on another device uses
+/// that link type instead of failing at link time.
+fn cross_device_link_type(
+    link_type: LinkType,
+    on_cross_device: CrossDevice,
+    source: &Path,
+    target: &Path,
+) -> LinkType {
+    let Some(fallback) = on_cross_device.fallback() else {
+        return link_type;
+    };
+    if link_type != LinkType::Hardlink || on_same_device(source, target) != Some(false) {
+        return link_type;
+    }
+    info!(
+        "fallback: {} is on another filesystem than {}; using {}",
+        target.display(),
+        source.display(),
+        fallback.as_str()
+    );
+    fallback
+}
+
+/// `include_dirs` / `exclude_dirs` of a skills set. A pattern matches a
+/// directory by its path relative to `source_root` or by its name alone.
+struct DirFilter {
+    include: GlobSet,
+    include_all: bool,
+    exclude: GlobSet,
+}
+
+impl DirFilter {
+    fn enters(&self, rel: &Path) -> bool {
+        if rel.as_os_str().is_empty() {
+            return true;
+        }
+        let matches = |globs: &GlobSet, dir: &Path| {
+            globs.is_match(dir) || dir.file_name().is_some_and(|name| globs.is_match(name))
+        };
+        if matches(&self.exclude, rel) {
+            return false;
+        }
+        self.include_all
+            || rel
+                .ancestors()
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .any(|dir| matches(&self.include, dir))
+    }
+}
+
+pub(crate) fn build_glob_set(patterns: &[String]) -> Result<GlobSet> {
     let mut builder = GlobSetBuilder::new();
     for pattern in patterns {
-        let glob = Glob::new(pattern)
-            .with_context(|| format!("invalid exclude glob pattern: {pattern}"))?;
+        let glob =
+            Glob::new(pattern).with_context(|| format!("invalid glob pattern: {pattern}"))?;
         builder.add(glob);
     }
     builder.build().context("failed to build glob set")
 }
 
+fn matches_profiles(rule_profiles: &[Profile], selected: &[Profile]) -> bool {
+    selected.is_empty()
+        || rule_profiles
+            .iter()
+            .any(|profile| selected.contains(profile))
+}
+
+pub(crate) fn is_plain_file_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    matches!(components.next(), Some(std::path::Component::Normal(_)))
+        && components.next().is_none()
+}
+
+pub(crate) fn is_glob_pattern(raw: &str) -> bool {
+    raw.contains(['*', '?', '[', '{'])
+}
+
+/// Expands a resolved glob source into `(file, path relative to the glob base)`
+/// pairs in a stable, sorted order.
+pub(crate) fn expand_source_glob(pattern: &Path) -> Result<Vec<(PathBuf, Option<PathBuf>)>> {
+    let mut base = PathBuf::new();
+    let mut rest = Vec::new();
+    for component in pattern.components() {
+        let text = component.as_os_str().to_string_lossy();
+        if rest.is_empty() && !is_glob_pattern(&text) {
+            base.push(component);
+        } else {
+            rest.push(text.into_owned());
+        }
+    }
+
+    let glob_text = rest.join("/");
+    let matcher = GlobBuilder::new(&glob_text)
+        .literal_separator(true)
+        .build()
+        .with_context(|| format!("invalid source glob pattern: {}", pattern.display()))?
+        .compile_matcher();
+
+    if !base.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut matched = Vec::new();
+    for entry_result in WalkDir::new(&base).sort_by_file_name() {
+        let entry = entry_result
+            .with_context(|| format!("failed to walk source glob base: {}", base.display()))?;
+        tally(Tally::WalkedEntries, 1);
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let Ok(rel) = entry.path().strip_prefix(&base) else {
+            continue;
+        };
+        let rel_text = rel
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        if matcher.is_match(rel_text.as_str()) {
+            let rel = rel.to_path_buf();
+            matched.push((entry.into_path(), Some(rel)));
+        }
+    }
+
+    Ok(matched)
+}
+
 fn extract_skill_name(rel: &Path) -> Option<&str> {
     rel.components().next().and_then(|c| c.as_os_str().to_str())
 }
@@ -471,9 +2068,13 @@ fn extract_skill_name(rel: &Path) -> Option<&str> {
 fn base_record(mapping: &Mapping) -> Record {
     Record {
         kind: mapping.kind.clone(),
+        link_type: mapping.link_type,
         source: mapping.source.clone(),
         target: mapping.target.clone(),
         status: Status::Error,
         message: None,
+        content_match: None,
+        duration_ms: None,
+        rule: Some(mapping.rule_ref()),
     }
 }
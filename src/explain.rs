@@ -0,0 +1,162 @@
+//! `explain <path>`: how a target or source resolves, what state it is in
+//! and why, and what `link` and `repair` would do with it.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use crate::cli::MappingArgs;
+use crate::config::{LoadedConfig, repo_contexts};
+use crate::engine::{apply_link, apply_repair, inspect_mapping, serde_label};
+use crate::model::{Mapping, Record, ResolveContext};
+use crate::pathing::{
+    absolute_path, canonical_path, file_identity, hardlink_count, normalize_lexically,
+    resolve_path, token_values,
+};
+use crate::safe_fs::calculate_sha256;
+
+/// Prints every mapping whose target or source is `path`. Exits 1 when no
+/// rule maps it.
+pub(crate) fn explain(
+    configs: &[LoadedConfig],
+    mappings: &[Mapping],
+    options: &MappingArgs,
+    path: &Path,
+) -> Result<i32> {
+    let path = normalize_lexically(&absolute_path(path)?);
+    let canonical = canonical_path(&path).ok();
+    let is = |candidate: &Path| {
+        candidate == path
+            || canonical
+                .as_ref()
+                .is_some_and(|canonical| canonical_path(candidate).is_ok_and(|c| c == *canonical))
+    };
+    let matched = mappings
+        .iter()
+        .filter(|mapping| is(&mapping.target) || is(&mapping.source))
+        .collect::<Vec<_>>();
+    if matched.is_empty() {
+        println!("no rule maps {}", path.display());
+        return Ok(1);
+    }
+
+    for (index, mapping) in matched.into_iter().enumerate() {
+        if index > 0 {
+            println!();
+        }
+        let role = if is(&mapping.target) {
+            "target"
+        } else {
+            "source"
+        };
+        println!("{} is the {role} of:", path.display());
+        explain_mapping(mapping, rule_context(configs, mapping, options)?.as_ref());
+    }
+    Ok(0)
+}
+
+fn explain_mapping(mapping: &Mapping, ctx: Option<&ResolveContext>) {
+    let line = |label: &str, value: &dyn std::fmt::Display| println!("  {label:<13} {value}");
+    let rule = mapping.rule_ref();
+    line("rule:", &rule);
+    line(
+        "source:",
+        &format!("{} -> {}", rule.source_template, mapping.source.display()),
+    );
+    line(
+        "target:",
+        &format!("{} -> {}", rule.target_template, mapping.target.display()),
+    );
+    if let Some(ctx) = ctx {
+        let mut tokens = token_values(&rule.source_template, ctx);
+        for entry in token_values(&rule.target_template, ctx) {
+            if !tokens.contains(&entry) {
+                tokens.push(entry);
+            }
+        }
+        for (token, value) in tokens {
+            let value = value.unwrap_or_else(|| "(undefined)".to_owned());
+            line("token:", &format!("{token} = {value}"));
+        }
+    }
+    line("link type:", &mapping.link_type.as_str());
+
+    line("status:", &outcome(&inspect_mapping(mapping)));
+    line("source file:", &describe_file(&mapping.source));
+    line("target file:", &describe_file(&mapping.target));
+
+    let link = apply_link(mapping, false, false, true, None, None);
+    line("link:", &outcome(&link));
+    if link
+        .message
+        .as_deref()
+        .is_some_and(|m| m.contains("--force"))
+    {
+        let forced = apply_link(mapping, true, false, true, None, None);
+        line("link --force:", &outcome(&forced));
+    }
+    line(
+        "repair:",
+        &outcome(&apply_repair(mapping, false, true, None, None)),
+    );
+}
+
+/// The context the mapping was resolved in: the config's own, or for a
+/// `<repo>` rule the repository whose root its target is under.
+fn rule_context(
+    configs: &[LoadedConfig],
+    mapping: &Mapping,
+    options: &MappingArgs,
+) -> Result<Option<ResolveContext>> {
+    let Some(loaded) = configs.iter().find(|loaded| loaded.path == mapping.config) else {
+        return Ok(None);
+    };
+    let repo_ctxs = repo_contexts(&loaded.config, &loaded.ctx, &options.repos)?;
+    let under = |ctx: &ResolveContext| {
+        mapping
+            .target
+            .starts_with(resolve_path(&mapping.target_template, ctx))
+    };
+    Ok(repo_ctxs
+        .into_iter()
+        .flatten()
+        .find(under)
+        .or_else(|| Some(loaded.ctx.clone())))
+}
+
+fn outcome(record: &Record) -> String {
+    match &record.message {
+        Some(message) => format!("{} ({message})", serde_label(&record.status)),
+        None => serde_label(&record.status),
+    }
+}
+
+/// What is at `path`: a symlink and where it points, or a file's identity,
+/// link count and hash.
+fn describe_file(path: &Path) -> String {
+    let meta = match fs::symlink_metadata(path) {
+        Ok(meta) => meta,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return "missing".to_owned(),
+        Err(err) => return format!("unreadable ({err})"),
+    };
+    if meta.file_type().is_symlink() {
+        let dest = fs::read_link(path).unwrap_or_else(|_| PathBuf::from("?"));
+        let state = if path.exists() { "" } else { ", dangling" };
+        return format!("symlink -> {}{state}", dest.display());
+    }
+    if meta.is_dir() {
+        return "directory".to_owned();
+    }
+    let identity = file_identity(path, &meta)
+        .map(|(device, inode)| format!("inode {inode} on device {device}, "))
+        .unwrap_or_default();
+    let hash = calculate_sha256(path)
+        .map(|hash| format!(", sha256 {}", &hash[..hash.len().min(16)]))
+        .unwrap_or_default();
+    format!(
+        "{identity}{} link(s), {} bytes{hash}",
+        hardlink_count(path, &meta),
+        meta.len()
+    )
+}
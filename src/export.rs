@@ -0,0 +1,96 @@
+//! `export --format stow`: the configured targets as a GNU Stow package, so
+//! `stow -d <dir> -t ~ <package>` puts the same content in the same places.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::model::Mapping;
+use crate::render::{is_rendered, render_source};
+
+#[derive(Debug, Default)]
+pub(crate) struct ExportSummary {
+    pub(crate) written: usize,
+    pub(crate) unchanged: usize,
+    /// Targets outside the stow target directory.
+    pub(crate) skipped: usize,
+    /// Package files that differ and were kept without `--force`.
+    pub(crate) conflicts: usize,
+    pub(crate) errors: usize,
+}
+
+/// Writes each mapping's content to `<package_dir>/<target relative to
+/// target_dir>`: the source file, or what prompt-sync would render for a
+/// generated target. Files already in the package are only replaced with
+/// `force`, as they may carry edits made through stow's symlinks.
+pub(crate) fn export_stow(
+    mappings: &[Mapping],
+    package_dir: &Path,
+    target_dir: &Path,
+    force: bool,
+    dry_run: bool,
+) -> ExportSummary {
+    let mut summary = ExportSummary::default();
+    for mapping in mappings {
+        let Ok(relative) = mapping.target.strip_prefix(target_dir) else {
+            eprintln!(
+                "warn: skipping {}: not under {}",
+                mapping.target.display(),
+                target_dir.display()
+            );
+            summary.skipped += 1;
+            continue;
+        };
+        let path = package_dir.join(relative);
+        let content = if is_rendered(mapping) {
+            render_source(mapping).map(String::into_bytes)
+        } else {
+            fs::read(&mapping.source)
+                .with_context(|| format!("failed to read source {}", mapping.source.display()))
+        };
+        let content = match content {
+            Ok(content) => content,
+            Err(err) => {
+                eprintln!("error: {}: {err:#}", relative.display());
+                summary.errors += 1;
+                continue;
+            }
+        };
+        match fs::read(&path) {
+            Ok(existing) if existing == content => {
+                summary.unchanged += 1;
+                continue;
+            }
+            Ok(_) if !force => {
+                println!(
+                    "conflict: {} differs from its source (use --force)",
+                    relative.display()
+                );
+                summary.conflicts += 1;
+                continue;
+            }
+            _ => {}
+        }
+        if dry_run {
+            println!("would export: {}", relative.display());
+            summary.written += 1;
+            continue;
+        }
+        let written = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(&path, &content));
+        match written {
+            Ok(()) => {
+                println!("exported: {}", relative.display());
+                summary.written += 1;
+            }
+            Err(err) => {
+                eprintln!("error: failed to write {}: {err}", path.display());
+                summary.errors += 1;
+            }
+        }
+    }
+    summary
+}
@@ -0,0 +1,118 @@
+//! `[hooks]`: shell commands run before and after `link`, `sync` and
+//! `repair`, for follow-up work such as restarting an agent daemon or
+//! sending a notification. Post hooks find the run's JSON report in the
+//! file named by `PROMPT_SYNC_REPORT`.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result, anyhow};
+
+use crate::config::{HooksConfig, LoadedConfig};
+use crate::model::Report;
+use crate::trace::info;
+
+/// Names the report file post hooks can read.
+pub(crate) const REPORT_ENV: &str = "PROMPT_SYNC_REPORT";
+/// The command being run: `link`, `sync` or `repair`.
+pub(crate) const COMMAND_ENV: &str = "PROMPT_SYNC_COMMAND";
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Stage {
+    PreLink,
+    PostLink,
+    PostRepair,
+}
+
+impl Stage {
+    fn name(self) -> &'static str {
+        match self {
+            Self::PreLink => "pre_link",
+            Self::PostLink => "post_link",
+            Self::PostRepair => "post_repair",
+        }
+    }
+
+    fn commands(self, hooks: &HooksConfig) -> &[String] {
+        match self {
+            Self::PreLink => &hooks.pre_link,
+            Self::PostLink => &hooks.post_link,
+            Self::PostRepair => &hooks.post_repair,
+        }
+    }
+}
+
+/// Runs `stage`'s commands from every config in order, each in the
+/// directory of the config that lists it, and stops at the first that
+/// fails. Their output goes to stderr so a `--json` report stays parseable.
+pub(crate) fn run_hooks(
+    configs: &[LoadedConfig],
+    stage: Stage,
+    command: &str,
+    report: Option<&Report>,
+) -> Result<()> {
+    let hooks = configs
+        .iter()
+        .filter_map(|loaded| {
+            let hooks = loaded.config.hooks.as_ref()?;
+            Some((stage.commands(hooks), &loaded.ctx.config_dir))
+        })
+        .flat_map(|(commands, dir)| commands.iter().map(move |hook| (hook, dir)))
+        .collect::<Vec<_>>();
+    if hooks.is_empty() {
+        return Ok(());
+    }
+
+    let report_path = match report {
+        Some(report) => {
+            let path = env::temp_dir().join(format!("prompt-sync-report-{}.json", report.run_id));
+            let text =
+                serde_json::to_string_pretty(report).context("failed to serialize report")?;
+            fs::write(&path, text + "\n")
+                .with_context(|| format!("failed to write report: {}", path.display()))?;
+            Some(path)
+        }
+        None => None,
+    };
+    let result = hooks.into_iter().try_for_each(|(hook, dir)| {
+        info!("{} hook: {hook}", stage.name());
+        let mut shell = shell_command(hook);
+        shell
+            .current_dir(dir)
+            .env(COMMAND_ENV, command)
+            .stdout(Stdio::from(io::stderr()));
+        if let Some(path) = &report_path {
+            shell.env(REPORT_ENV, path);
+        }
+        let status = shell
+            .status()
+            .with_context(|| format!("failed to run {} hook `{hook}`", stage.name()))?;
+        if !status.success() {
+            return Err(anyhow!(
+                "{} hook `{hook}` exited with {status}",
+                stage.name()
+            ));
+        }
+        Ok(())
+    });
+    if let Some(path) = report_path {
+        let _ = fs::remove_file(path);
+    }
+    result
+}
+
+#[cfg(not(windows))]
+fn shell_command(hook: &str) -> Command {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(hook);
+    command
+}
+
+#[cfg(windows)]
+fn shell_command(hook: &str) -> Command {
+    let mut command = Command::new("cmd");
+    command.arg("/C").arg(hook);
+    command
+}
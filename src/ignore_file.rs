@@ -0,0 +1,106 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobMatcher};
+
+use crate::pathing::normalize_lexically;
+
+pub(crate) const IGNORE_FILE_NAME: &str = ".promptsyncignore";
+
+/// Patterns from a `.promptsyncignore`, in gitignore syntax: `#` comments,
+/// `!` negation, a trailing `/` for directories only, and patterns with a
+/// `/` anchored to the file's directory. The last matching line wins.
+#[derive(Debug)]
+pub(crate) struct IgnoreFile {
+    root: PathBuf,
+    rules: Vec<IgnoreRule>,
+}
+
+#[derive(Debug)]
+struct IgnoreRule {
+    matcher: GlobMatcher,
+    negated: bool,
+    dir_only: bool,
+}
+
+impl IgnoreFile {
+    /// Reads `dir/.promptsyncignore`; `None` when there is no such file.
+    pub(crate) fn load(dir: &Path) -> Result<Option<Self>> {
+        let path = dir.join(IGNORE_FILE_NAME);
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("failed to read ignore file: {}", path.display()));
+            }
+        };
+        Self::parse(dir, &text)
+            .with_context(|| format!("invalid ignore file: {}", path.display()))
+            .map(Some)
+    }
+
+    fn parse(root: &Path, text: &str) -> Result<Self> {
+        let mut rules = Vec::new();
+        for line in text.lines() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (negated, pattern) = match line.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, line.strip_prefix('\\').unwrap_or(line)),
+            };
+            let (dir_only, pattern) = match pattern.strip_suffix('/') {
+                Some(rest) => (true, rest),
+                None => (false, pattern),
+            };
+            let glob = if pattern.contains('/') {
+                pattern.trim_start_matches('/').to_owned()
+            } else {
+                format!("**/{pattern}")
+            };
+            let matcher = GlobBuilder::new(&glob)
+                .literal_separator(true)
+                .build()
+                .with_context(|| format!("invalid ignore pattern: {line}"))?
+                .compile_matcher();
+            rules.push(IgnoreRule {
+                matcher,
+                negated,
+                dir_only,
+            });
+        }
+        Ok(Self {
+            root: normalize_lexically(root),
+            rules,
+        })
+    }
+
+    /// Whether `path` (or a directory above it, below the ignore file's
+    /// directory) is ignored. Paths outside that directory never are.
+    pub(crate) fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let path = normalize_lexically(path);
+        let Ok(rel) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        if rel.as_os_str().is_empty() {
+            return false;
+        }
+        rel.ancestors()
+            .skip(1)
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .any(|dir| self.matches(dir, true))
+            || self.matches(rel, is_dir)
+    }
+
+    fn matches(&self, rel: &Path, is_dir: bool) -> bool {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| (is_dir || !rule.dir_only) && rule.matcher.is_match(rel))
+            .is_some_and(|rule| !rule.negated)
+    }
+}
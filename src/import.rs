@@ -0,0 +1,343 @@
+//! `import --from chezmoi|stow`: rules for the AI instruction files and
+//! skills another dotfile manager already keeps, to move them to prompt-sync.
+//! `import --convert-symlinks` takes over a hand-made symlink farm instead.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow};
+use walkdir::WalkDir;
+
+use crate::cli::ImportSource;
+use crate::cli::LinkType;
+use crate::config::{CONFIG_VERSION, ConfigFile, LinkRule, LinkTarget, SkillsSet};
+use crate::discover::{instruction_files, skills_dirs};
+use crate::model::{Mapping, ResolveContext};
+use crate::pathing::{canonical_path, resolve_path};
+
+#[derive(Debug)]
+pub(crate) struct Imported {
+    pub(crate) config: ConfigFile,
+    /// Managed files and skills roots taken over, with their home-relative
+    /// target.
+    pub(crate) found: Vec<(PathBuf, String)>,
+    /// Entries at a known path that cannot become a link, with the reason.
+    pub(crate) skipped: Vec<(PathBuf, &'static str)>,
+}
+
+/// One managed file: where it lives in the manager's tree, where it ends up
+/// relative to the home directory, and why it cannot be linked as is.
+struct Entry {
+    source: PathBuf,
+    relative: String,
+    unsupported: Option<&'static str>,
+}
+
+/// Reads the manager's tree at `dir` and builds a rule per distinct
+/// instruction file (identical files share one source) and a skills set
+/// per skills directory. Sources under `home` are written with `~`.
+pub(crate) fn import_managed(
+    from: ImportSource,
+    dir: &Path,
+    home: Option<&Path>,
+) -> Result<Imported> {
+    if !dir.is_dir() {
+        return Err(anyhow!("not a directory: {}", dir.display()));
+    }
+    let entries = match from {
+        ImportSource::Chezmoi => chezmoi_entries(dir)?,
+        ImportSource::Stow => stow_entries(dir)?,
+    };
+    let instruction_paths = instruction_files()
+        .into_iter()
+        .filter_map(|(_, raw)| raw.strip_prefix("~/"))
+        .collect::<Vec<_>>();
+    let skills_paths = skills_dirs()
+        .into_iter()
+        .filter_map(|(_, raw)| raw.strip_prefix("~/"))
+        .collect::<Vec<_>>();
+
+    let mut by_content: BTreeMap<Vec<u8>, Vec<Entry>> = BTreeMap::new();
+    let mut skills_roots: BTreeMap<&str, PathBuf> = BTreeMap::new();
+    let mut skipped = Vec::new();
+    for entry in entries {
+        let is_instruction = instruction_paths.contains(&entry.relative.as_str());
+        // A skill is a directory below the skills root, not a file in it.
+        let skills_path = skills_paths.iter().find(|path| {
+            entry
+                .relative
+                .strip_prefix(**path)
+                .and_then(|rest| rest.strip_prefix('/'))
+                .is_some_and(|rest| rest.contains('/'))
+        });
+        if !is_instruction && skills_path.is_none() {
+            continue;
+        }
+        if let Some(reason) = entry.unsupported {
+            skipped.push((entry.source, reason));
+            continue;
+        }
+        if is_instruction {
+            let content = fs::read(&entry.source)
+                .with_context(|| format!("failed to read {}", entry.source.display()))?;
+            by_content.entry(content).or_default().push(entry);
+        } else if let Some(path) = skills_path {
+            let depth = entry.relative.split('/').count() - path.split('/').count();
+            if let Some(root) = entry.source.ancestors().nth(depth) {
+                skills_roots
+                    .entry(path)
+                    .or_insert_with(|| root.to_path_buf());
+            }
+        }
+    }
+
+    let display = |path: &Path| home_relative(path, home);
+    let mut found = Vec::new();
+    let mut links = by_content
+        .into_values()
+        .map(|mut group| {
+            group.sort_by(|a, b| a.relative.cmp(&b.relative));
+            for entry in &group {
+                found.push((entry.source.clone(), entry.relative.clone()));
+            }
+            LinkRule {
+                source: display(&group[0].source),
+                targets: group
+                    .iter()
+                    .map(|entry| LinkTarget::Path(format!("~/{}", entry.relative)))
+                    .collect(),
+                ..Default::default()
+            }
+        })
+        .collect::<Vec<_>>();
+    links.sort_by(|a, b| a.source.cmp(&b.source));
+    let skills_sets = skills_roots
+        .into_iter()
+        .map(|(path, root)| {
+            found.push((root.clone(), path.to_owned()));
+            SkillsSet {
+                source_root: display(&root),
+                target_roots: vec![format!("~/{path}")],
+                ..Default::default()
+            }
+        })
+        .collect();
+
+    Ok(Imported {
+        config: ConfigFile {
+            version: Some(CONFIG_VERSION),
+            links,
+            skills_sets,
+            ..ConfigFile::default()
+        },
+        found,
+        skipped,
+    })
+}
+
+/// `path` written with `~` when it is under `home`.
+fn home_relative(path: &Path, home: Option<&Path>) -> String {
+    match home.and_then(|home| path.strip_prefix(home).ok()) {
+        Some(rest) => format!("~/{}", rest.to_string_lossy().replace('\\', "/")),
+        None => path.display().to_string(),
+    }
+}
+
+/// A target that is a symlink resolving to its own source, which a
+/// non-symlink rule replaces with the link it asks for.
+pub(crate) fn is_symlink_to_source(mapping: &Mapping) -> bool {
+    mapping.link_type != LinkType::Symlink
+        && fs::symlink_metadata(&mapping.target).is_ok_and(|meta| meta.file_type().is_symlink())
+        && match (
+            canonical_path(&mapping.target),
+            canonical_path(&mapping.source),
+        ) {
+            (Ok(target), Ok(source)) => target == source,
+            _ => false,
+        }
+}
+
+/// Adds the symlinks at known home instruction paths that no mapping
+/// targets yet to `config`: as a target of the rule whose source they point
+/// at, or of a new rule with that file as the source. Returns each adopted
+/// target and the file it points at.
+pub(crate) fn adopt_symlinks(
+    config: &mut ConfigFile,
+    ctx: &ResolveContext,
+    mappings: &[Mapping],
+) -> Vec<(PathBuf, PathBuf)> {
+    let mut adopted = Vec::new();
+    for (_, raw) in instruction_files() {
+        if !raw.starts_with("~/") {
+            continue;
+        }
+        let target = resolve_path(raw, ctx);
+        if mappings.iter().any(|mapping| mapping.target == target)
+            || !fs::symlink_metadata(&target).is_ok_and(|meta| meta.file_type().is_symlink())
+        {
+            continue;
+        }
+        let Ok(source) = canonical_path(&target) else {
+            continue;
+        };
+        if !source.is_file() {
+            continue;
+        }
+        let rule = config.links.iter_mut().find(|rule| {
+            canonical_path(&resolve_path(&rule.source, ctx)).is_ok_and(|path| path == source)
+        });
+        match rule {
+            Some(rule) => rule.targets.push(LinkTarget::Path((*raw).to_owned())),
+            None => config.links.push(LinkRule {
+                source: home_relative(&source, ctx.home_dir.as_deref()),
+                targets: vec![LinkTarget::Path((*raw).to_owned())],
+                ..Default::default()
+            }),
+        }
+        adopted.push((target, source));
+    }
+    adopted
+}
+
+/// A chezmoi source directory, e.g. `~/.local/share/chezmoi`. Names carry
+/// chezmoi's attribute prefixes (`dot_`, `private_`, ...); its own
+/// `.chezmoi*` files and other dot entries are not targets.
+fn chezmoi_entries(dir: &Path) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let walker = WalkDir::new(dir)
+        .min_depth(1)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| {
+            entry.depth() == 0 || !entry.file_name().to_string_lossy().starts_with('.')
+        });
+    for entry in walker {
+        let entry = entry.with_context(|| format!("failed to walk {}", dir.display()))?;
+        if entry.file_type().is_dir() {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(dir).unwrap_or(entry.path());
+        let mut unsupported = None;
+        let mut names = Vec::new();
+        let components = relative.components().collect::<Vec<_>>();
+        for (index, component) in components.iter().enumerate() {
+            let name = component.as_os_str().to_string_lossy();
+            let (name, reason) = chezmoi_name(&name, index + 1 == components.len());
+            unsupported = unsupported.or(reason);
+            names.push(name);
+        }
+        entries.push(Entry {
+            source: entry.path().to_path_buf(),
+            relative: names.join("/"),
+            unsupported,
+        });
+    }
+    Ok(entries)
+}
+
+/// The target name for one chezmoi source name, and why its content is
+/// not a plain file when it is not.
+fn chezmoi_name(name: &str, is_file: bool) -> (String, Option<&'static str>) {
+    const PLAIN: &[&str] = &[
+        "create_",
+        "empty_",
+        "exact_",
+        "executable_",
+        "external_",
+        "private_",
+        "readonly_",
+        "once_",
+        "onchange_",
+        "before_",
+        "after_",
+    ];
+    const SPECIAL: &[(&str, &str)] = &[
+        ("encrypted_", "encrypted"),
+        ("modify_", "a modify script"),
+        ("remove_", "a remove entry"),
+        ("run_", "a script"),
+        ("symlink_", "a symlink"),
+    ];
+    let mut unsupported = None;
+    let mut rest = name;
+    if is_file && let Some(stem) = rest.strip_suffix(".tmpl") {
+        rest = stem;
+        unsupported = Some("a template");
+    }
+    loop {
+        if let Some(literal) = rest.strip_prefix("literal_") {
+            return (literal.to_owned(), unsupported);
+        }
+        if let Some(prefix) = PLAIN.iter().find(|prefix| rest.starts_with(**prefix)) {
+            rest = &rest[prefix.len()..];
+        } else if let Some((prefix, reason)) =
+            SPECIAL.iter().find(|(prefix, _)| rest.starts_with(prefix))
+        {
+            rest = &rest[prefix.len()..];
+            unsupported = unsupported.or(Some(*reason));
+        } else {
+            break;
+        }
+    }
+    match rest.strip_prefix("dot_") {
+        Some(stem) => (format!(".{stem}"), unsupported),
+        None => (rest.to_owned(), unsupported),
+    }
+}
+
+/// A stow directory (every subdirectory a package) or a single package.
+/// `dot-` prefixes, from `stow --dotfiles`, become dots.
+fn stow_entries(dir: &Path) -> Result<Vec<Entry>> {
+    let tops = instruction_files()
+        .into_iter()
+        .map(|(_, raw)| raw)
+        .chain(skills_dirs().into_iter().map(|(_, raw)| raw))
+        .filter_map(|raw| raw.strip_prefix("~/")?.split('/').next())
+        .collect::<Vec<_>>();
+    let is_package = fs::read_dir(dir)
+        .with_context(|| format!("failed to read {}", dir.display()))?
+        .filter_map(Result::ok)
+        .any(|entry| tops.contains(&stow_name(&entry.file_name().to_string_lossy()).as_str()));
+    let packages = if is_package {
+        vec![dir.to_path_buf()]
+    } else {
+        let mut packages = fs::read_dir(dir)
+            .with_context(|| format!("failed to read {}", dir.display()))?
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+            .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+            .map(|entry| entry.path())
+            .collect::<Vec<_>>();
+        packages.sort();
+        packages
+    };
+
+    let mut entries = Vec::new();
+    for package in packages {
+        for entry in WalkDir::new(&package).min_depth(1).sort_by_file_name() {
+            let entry = entry.with_context(|| format!("failed to walk {}", package.display()))?;
+            if entry.file_type().is_dir() {
+                continue;
+            }
+            let relative = entry.path().strip_prefix(&package).unwrap_or(entry.path());
+            entries.push(Entry {
+                source: entry.path().to_path_buf(),
+                relative: relative
+                    .components()
+                    .map(|component| stow_name(&component.as_os_str().to_string_lossy()))
+                    .collect::<Vec<_>>()
+                    .join("/"),
+                unsupported: entry.path_is_symlink().then_some("a symlink"),
+            });
+        }
+    }
+    Ok(entries)
+}
+
+fn stow_name(name: &str) -> String {
+    match name.strip_prefix("dot-") {
+        Some(stem) => format!(".{stem}"),
+        None => name.to_owned(),
+    }
+}
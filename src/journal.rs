@@ -0,0 +1,224 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+#[cfg(not(all(unix, feature = "syslog")))]
+use std::sync::Once;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::backup_manifest::BackupEntry;
+use crate::cli::LinkType;
+use crate::config::{LoadedConfig, LogSink};
+use crate::engine::serde_label;
+use crate::logging::{Rotation, lock_log, rotate_if_full};
+use crate::model::{Record, Status, run_id};
+use crate::pathing::resolve_path;
+
+const JOURNAL_FILE_NAME: &str = "operations.ndjson";
+
+/// One journal line. `action` is the record status (`CREATED`, `ERROR`,
+/// ...) or `RESTORED` for `backup restore`.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct JournalEntry {
+    pub(crate) timestamp: DateTime<Utc>,
+    pub(crate) run_id: String,
+    pub(crate) command: String,
+    pub(crate) action: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) link_type: Option<LinkType>,
+    pub(crate) source: PathBuf,
+    pub(crate) target: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) message: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) hash: Option<String>,
+}
+
+/// Append-only NDJSON log of every change prompt-sync makes, enabled by a
+/// `[logging]` config section. Unlike the backup operation log it does not
+/// need `--backup-dir` and covers all mutating commands.
+#[derive(Debug)]
+pub(crate) struct Journal {
+    path: PathBuf,
+    sink: LogSink,
+    /// Set when `[logging]` gives `max_size` or `max_files`; the journal
+    /// grows without bound otherwise.
+    rotation: Option<Rotation>,
+}
+
+impl Journal {
+    /// Uses the last config with a `[logging]` section; its `path` defaults
+    /// to `operations.ndjson` in the state directory.
+    pub(crate) fn from_configs(configs: &[LoadedConfig], state_dir: Option<&Path>) -> Option<Self> {
+        let (logging, ctx) = configs
+            .iter()
+            .rev()
+            .find_map(|loaded| Some((loaded.config.logging.as_ref()?, &loaded.ctx)))?;
+        if !logging.is_enabled() {
+            return None;
+        }
+        let path = match &logging.path {
+            Some(raw) => resolve_path(raw, ctx),
+            None => state_dir?.join(JOURNAL_FILE_NAME),
+        };
+        let rotates = logging.max_size.is_some() || logging.max_files.is_some();
+        Some(Self {
+            path,
+            sink: logging.sink.unwrap_or_default(),
+            rotation: rotates.then(|| Rotation::from_logging(logging)),
+        })
+    }
+
+    /// The journal file to read back: the configured one, or the default in
+    /// the state directory when logging is not configured (or turned off).
+    /// Lines sent to syslog or journald are not read back.
+    pub(crate) fn for_reading(configs: &[LoadedConfig], state_dir: Option<&Path>) -> Option<Self> {
+        Self::from_configs(configs, state_dir).or_else(|| {
+            state_dir.map(|dir| Self {
+                path: dir.join(JOURNAL_FILE_NAME),
+                sink: LogSink::File,
+                rotation: None,
+            })
+        })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Every entry in the journal; lines that do not parse are skipped with
+    /// a warning.
+    pub(crate) fn read(&self) -> Result<Vec<JournalEntry>> {
+        let text = match fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("failed to read journal: {}", self.path.display()));
+            }
+        };
+        Ok(text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .filter_map(|(index, line)| match serde_json::from_str(line) {
+                Ok(entry) => Some(entry),
+                Err(err) => {
+                    eprintln!(
+                        "warn: skipping journal line {} in {}: {}",
+                        index + 1,
+                        self.path.display(),
+                        err
+                    );
+                    None
+                }
+            })
+            .collect())
+    }
+
+    /// Logs the records of `command` that changed a target or failed.
+    pub(crate) fn record(&self, command: &str, records: &[Record]) {
+        let lines = records
+            .iter()
+            .filter(|record| {
+                matches!(
+                    record.status,
+                    Status::Created
+                        | Status::Replaced
+                        | Status::Removed
+                        | Status::Pruned
+                        | Status::RolledBack
+                        | Status::Error
+                )
+            })
+            .map(|record| JournalEntry {
+                timestamp: Utc::now(),
+                run_id: run_id().to_owned(),
+                command: command.to_owned(),
+                action: serde_label(&record.status),
+                link_type: Some(record.link_type),
+                source: record.source.clone(),
+                target: record.target.clone(),
+                message: record.message.clone(),
+                hash: None,
+            })
+            .collect::<Vec<_>>();
+        self.append(&lines);
+    }
+
+    pub(crate) fn record_restore(&self, entry: &BackupEntry) {
+        self.append(&[JournalEntry {
+            timestamp: Utc::now(),
+            run_id: run_id().to_owned(),
+            command: "backup restore".to_owned(),
+            action: "RESTORED".to_owned(),
+            link_type: None,
+            source: entry.backup.clone(),
+            target: entry.original.clone(),
+            message: None,
+            hash: entry.hash.clone(),
+        }]);
+    }
+
+    /// The journal is bookkeeping, so a failed write is only a warning.
+    fn append(&self, lines: &[JournalEntry]) {
+        if lines.is_empty() {
+            return;
+        }
+        if let Err(err) = self.write(lines) {
+            eprintln!("warn: failed to write operation journal: {err:#}");
+        }
+    }
+
+    fn write(&self, lines: &[JournalEntry]) -> Result<()> {
+        match self.sink {
+            LogSink::File => self.write_lines(lines),
+            #[cfg(all(unix, feature = "syslog"))]
+            LogSink::Syslog => crate::syslog::send_syslog(lines),
+            #[cfg(all(unix, feature = "syslog"))]
+            LogSink::Journald => crate::syslog::send_journald(lines),
+            #[cfg(not(all(unix, feature = "syslog")))]
+            LogSink::Syslog | LogSink::Journald => {
+                static WARNED: Once = Once::new();
+                WARNED.call_once(|| {
+                    eprintln!(
+                        "warn: [logging] sink = \"{}\" needs a Unix build with the `syslog` feature; writing {} instead",
+                        serde_label(&self.sink),
+                        self.path.display()
+                    );
+                });
+                self.write_lines(lines)
+            }
+        }
+    }
+
+    fn write_lines(&self, lines: &[JournalEntry]) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!("failed to create journal directory: {}", parent.display())
+            })?;
+        }
+        let mut text = String::new();
+        for line in lines {
+            text.push_str(&serde_json::to_string(line).context("failed to serialize journal")?);
+            text.push('\n');
+        }
+        let _lock = match self.rotation {
+            Some(rotation) => {
+                let lock = lock_log(&self.path)?;
+                rotate_if_full(&self.path, rotation)?;
+                Some(lock)
+            }
+            None => None,
+        };
+        // One write per batch keeps lines from concurrent runs whole.
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(text.as_bytes()))
+            .with_context(|| format!("failed to append to {}", self.path.display()))
+    }
+}
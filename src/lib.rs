@@ -1,14 +1,52 @@
 mod app;
+pub(crate) mod backup_manifest;
+pub(crate) mod baseline;
+pub(crate) mod check;
 mod cli;
 pub(crate) mod config;
+pub(crate) mod config_edit;
+pub(crate) mod discover;
+pub(crate) mod edit;
 pub(crate) mod engine;
+pub(crate) mod explain;
+pub(crate) mod export;
+pub(crate) mod gzip;
+pub(crate) mod hooks;
+pub(crate) mod ignore_file;
+pub(crate) mod import;
+pub(crate) mod journal;
 pub(crate) mod logging;
+pub(crate) mod migrate;
 pub(crate) mod model;
 pub(crate) mod pathing;
+pub(crate) mod plan;
+pub(crate) mod progress;
+pub(crate) mod remote;
+pub(crate) mod render;
 pub(crate) mod safe_fs;
+pub(crate) mod schema;
+pub(crate) mod skills;
+pub(crate) mod stat_cache;
+pub(crate) mod state;
+#[cfg(all(unix, feature = "syslog"))]
+pub(crate) mod syslog;
+pub(crate) mod trace;
+pub(crate) mod transaction;
+#[cfg(unix)]
+pub(crate) mod tui;
+pub(crate) mod update;
 pub(crate) mod vcs;
+pub(crate) mod vendors;
+pub(crate) mod watch;
+#[cfg(windows)]
+pub(crate) mod win32;
+pub(crate) mod yaml;
 
-pub use crate::cli::{Cli, Command, Profile};
+pub use crate::cli::{
+    BackupCommand, Cli, ColorChoice, Command, ConfigCommand, ConfigFormat, ExportFormat, GuardMode,
+    ImportSource, KindFilter, LinkType, LogAction, LogCommand, LogStatus, MappingArgs, OutputArgs,
+    OutputFormat, Profile, SkillsCommand, TargetCommand,
+};
 
 pub fn run(cli: Cli) -> anyhow::Result<i32> {
     app::run(cli)
@@ -1,11 +1,69 @@
+//! `.operations.log` in a backup directory: one JSON object per line,
+//! appended under a lock so concurrent runs never lose or tear entries.
+//! Also the locking and gzip rotation the `[logging]` journal shares.
+
 use anyhow::{Context, Result};
 use chrono::Utc;
 use serde_json::{Value, json};
-use std::fs;
-use std::path::Path;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::cli::parse_size;
+use crate::config::{LoadedConfig, LoggingConfig};
+use crate::gzip;
+use crate::model::run_id;
+use crate::safe_fs::BackupDir;
 
 const LOG_FILE_NAME: &str = ".operations.log";
-const LOG_SIZE_LIMIT: u64 = 1024 * 1024; // 1MB
+
+/// When a log is rotated and how many old generations are kept, from
+/// `[logging] max_size` and `max_files`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Rotation {
+    pub(crate) max_size: u64,
+    /// `<log>.1.gz` (newest) to `<log>.<max_files>.gz`.
+    pub(crate) max_files: usize,
+}
+
+impl Default for Rotation {
+    fn default() -> Self {
+        Self {
+            max_size: 1 << 20,
+            max_files: 5,
+        }
+    }
+}
+
+impl Rotation {
+    /// The settings of the last config with a `[logging]` section. An
+    /// invalid `max_size` is warned about and left at the default.
+    pub(crate) fn from_configs(configs: &[LoadedConfig]) -> Self {
+        configs
+            .iter()
+            .rev()
+            .find_map(|loaded| loaded.config.logging.as_ref())
+            .map(Self::from_logging)
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn from_logging(logging: &LoggingConfig) -> Self {
+        let default = Self::default();
+        let max_size = match logging.max_size.as_deref().map(parse_size) {
+            Some(Ok(size)) => size,
+            Some(Err(err)) => {
+                eprintln!("warn: [logging] max_size: {err}");
+                default.max_size
+            }
+            None => default.max_size,
+        };
+        Self {
+            max_size,
+            max_files: logging.max_files.unwrap_or(default.max_files),
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub(crate) enum Action {
@@ -24,7 +82,8 @@ impl Action {
 }
 
 pub(crate) struct OperationLog {
-    log_path: std::path::PathBuf,
+    log_path: PathBuf,
+    rotation: Rotation,
 }
 
 pub(crate) struct LogEntry<'a> {
@@ -38,14 +97,18 @@ pub(crate) struct LogEntry<'a> {
 }
 
 impl OperationLog {
-    pub(crate) fn new(backup_dir: &Path) -> Self {
-        let log_path = backup_dir.join(LOG_FILE_NAME);
-        OperationLog { log_path }
+    pub(crate) fn new(backup_dir: &BackupDir) -> Self {
+        let log_path = backup_dir.path.join(LOG_FILE_NAME);
+        OperationLog {
+            log_path,
+            rotation: backup_dir.rotation,
+        }
     }
 
     pub(crate) fn record(&self, entry_data: LogEntry<'_>) -> Result<()> {
         let entry = json!({
             "timestamp": Utc::now().to_rfc3339(),
+            "run_id": run_id(),
             "action": entry_data.action.as_str(),
             "source": entry_data.source.to_string_lossy(),
             "target": entry_data.target.to_string_lossy(),
@@ -55,48 +118,120 @@ impl OperationLog {
             "backup_location": entry_data.backup_location.map(|p| p.to_string_lossy())
         });
 
-        // Check if we need to rotate the log
-        if let Ok(meta) = fs::metadata(&self.log_path)
-            && meta.len() > LOG_SIZE_LIMIT
-        {
-            self.rotate_log()?;
+        let mut line = serde_json::to_string(&entry).context("failed to serialize log entry")?;
+        line.push('\n');
+
+        // Converting, rotating and appending all happen under the lock.
+        let _lock = lock_log(&self.log_path)?;
+        self.migrate_array_log()?;
+        rotate_if_full(&self.log_path, self.rotation)?;
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.log_path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .with_context(|| format!("failed to append to {}", self.log_path.display()))
+    }
+
+    /// Older versions kept the log as one JSON array, rewritten for every
+    /// entry. Such a log is converted to one entry per line; one that does
+    /// not parse is moved aside to `.operations.log.unreadable`.
+    fn migrate_array_log(&self) -> Result<()> {
+        let mut first = [0; 1];
+        let is_array = File::open(&self.log_path)
+            .and_then(|mut file| file.read(&mut first))
+            .is_ok_and(|read| read == 1 && first[0] == b'[');
+        if !is_array {
+            return Ok(());
         }
 
-        let log_contents = if self.log_path.exists() {
-            fs::read_to_string(&self.log_path).unwrap_or_else(|_| String::from("[]"))
-        } else {
-            String::from("[]")
+        let text = fs::read_to_string(&self.log_path)
+            .with_context(|| format!("failed to read {}", self.log_path.display()))?;
+        let Ok(entries) = serde_json::from_str::<Vec<Value>>(&text) else {
+            let aside = self.log_path.with_extension("log.unreadable");
+            eprintln!(
+                "warn: {} is not a valid JSON array; moved to {}",
+                self.log_path.display(),
+                aside.display()
+            );
+            return fs::rename(&self.log_path, &aside)
+                .with_context(|| format!("failed to move {}", self.log_path.display()));
         };
+        let mut lines = String::new();
+        for entry in &entries {
+            lines.push_str(&serde_json::to_string(entry).context("failed to serialize log entry")?);
+            lines.push('\n');
+        }
+        let tmp_path = self.log_path.with_extension("log.tmp");
+        fs::write(&tmp_path, lines)
+            .with_context(|| format!("failed to write {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, &self.log_path)
+            .with_context(|| format!("failed to convert {}", self.log_path.display()))
+    }
+}
 
-        // Parse as JSON array
-        let mut entries: Vec<Value> =
-            serde_json::from_str(&log_contents).unwrap_or_else(|_| Vec::new());
-
-        entries.push(entry);
-
-        // Write back
-        let json_str =
-            serde_json::to_string_pretty(&entries).context("failed to serialize log entries")?;
-
-        fs::write(&self.log_path, json_str)
-            .with_context(|| format!("failed to write log to {}", self.log_path.display()))?;
+/// An exclusive advisory lock on `<log>.lock`, held until the returned
+/// handle is dropped. A separate file keeps rotation from pulling the log
+/// out from under a run waiting for the lock.
+pub(crate) fn lock_log(path: &Path) -> Result<File> {
+    let lock_path = with_suffix(path, ".lock");
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&lock_path)
+        .with_context(|| format!("failed to open lock file: {}", lock_path.display()))?;
+    file.lock()
+        .with_context(|| format!("failed to lock {}", lock_path.display()))?;
+    Ok(file)
+}
 
-        Ok(())
+/// Once `path` has outgrown `max_size`, compresses it to `<log>.1.gz` and
+/// moves the older generations up by one, dropping the oldest. Expects the
+/// `lock_log` lock to be held. An uncompressed `<log>.1` from older versions
+/// becomes the first compressed generation.
+pub(crate) fn rotate_if_full(path: &Path, rotation: Rotation) -> Result<()> {
+    if fs::metadata(path).map_or(true, |meta| meta.len() <= rotation.max_size) {
+        return Ok(());
     }
 
-    fn rotate_log(&self) -> Result<()> {
-        let rotated_path = self.log_path.with_extension("log.1");
-
-        // If rotated file exists, remove it
-        if rotated_path.exists() {
-            fs::remove_file(&rotated_path)?;
+    let generation = |index: usize| with_suffix(path, &format!(".{index}.gz"));
+    let legacy = with_suffix(path, ".1");
+    if legacy.is_file() && !generation(1).exists() {
+        compress_to(&legacy, &generation(1))?;
+        fs::remove_file(&legacy)
+            .with_context(|| format!("failed to remove {}", legacy.display()))?;
+    }
+    for index in (1..=rotation.max_files.max(1)).rev() {
+        let from = generation(index);
+        if !from.exists() {
+            continue;
         }
+        let result = if index >= rotation.max_files {
+            fs::remove_file(&from)
+        } else {
+            fs::rename(&from, generation(index + 1))
+        };
+        result.with_context(|| format!("failed to rotate {}", from.display()))?;
+    }
+    if rotation.max_files > 0 {
+        compress_to(path, &generation(1))?;
+    }
+    fs::remove_file(path).with_context(|| format!("failed to rotate {}", path.display()))
+}
 
-        // Rename current log to .1
-        if self.log_path.exists() {
-            fs::rename(&self.log_path, &rotated_path)?;
-        }
+fn compress_to(from: &Path, to: &Path) -> Result<()> {
+    let data = fs::read(from).with_context(|| format!("failed to read {}", from.display()))?;
+    // Written beside the generation and renamed, so a crash leaves no
+    // truncated archive.
+    let tmp_path = with_suffix(to, ".tmp");
+    fs::write(&tmp_path, gzip::compress(&data))
+        .with_context(|| format!("failed to write {}", tmp_path.display()))?;
+    fs::rename(&tmp_path, to).with_context(|| format!("failed to write {}", to.display()))
+}
 
-        Ok(())
-    }
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
 }
@@ -0,0 +1,76 @@
+use anyhow::{Context, Result, anyhow};
+use serde_json::{Map, Value};
+
+use crate::cli::ConfigFormat;
+use crate::config::{CONFIG_VERSION, ConfigFile, serialize_config};
+use crate::yaml;
+
+/// Outcome of upgrading a config to `CONFIG_VERSION`.
+#[derive(Debug)]
+pub(crate) struct Migration {
+    pub(crate) from_version: u32,
+    pub(crate) changes: Vec<String>,
+    pub(crate) text: String,
+}
+
+/// Upgrades config text step by step to the current version. Configs without
+/// a `version` key are treated as version 0.
+pub(crate) fn migrate_config(text: &str, format: ConfigFormat) -> Result<Migration> {
+    let mut value: Value = match format {
+        ConfigFormat::Toml => toml::from_str(text)?,
+        ConfigFormat::Yaml => yaml::from_str(text)?,
+        ConfigFormat::Json => serde_json::from_str(text)?,
+    };
+    let root = value
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("config must be a table/mapping at the top level"))?;
+
+    let from_version = match root.get("version") {
+        None => 0,
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| anyhow!("config version must be a non-negative integer"))?,
+    };
+    if from_version > CONFIG_VERSION {
+        return Err(anyhow!(
+            "config version {from_version} is newer than this prompt-sync supports ({CONFIG_VERSION})"
+        ));
+    }
+
+    let mut changes = Vec::new();
+    if from_version < 1 {
+        migrate_v0_to_v1(root, &mut changes);
+    }
+    root.insert("version".to_owned(), Value::from(CONFIG_VERSION));
+
+    let config: ConfigFile =
+        serde_json::from_value(value).context("migrated config does not match the schema")?;
+    let text = serialize_config(&config, format).context("failed to serialize config")?;
+
+    Ok(Migration {
+        from_version,
+        changes,
+        text,
+    })
+}
+
+/// Version 1 spells the per-rule strategy `link_type`; `mode` was the
+/// earlier name.
+fn migrate_v0_to_v1(root: &mut Map<String, Value>, changes: &mut Vec<String>) {
+    for section in ["links", "skills_sets"] {
+        let Some(Value::Array(rules)) = root.get_mut(section) else {
+            continue;
+        };
+        for (index, rule) in rules.iter_mut().enumerate() {
+            let Some(rule) = rule.as_object_mut() else {
+                continue;
+            };
+            if let Some(mode) = rule.remove("mode") {
+                rule.entry("link_type").or_insert(mode);
+                changes.push(format!("{section}[{index}]: renamed `mode` to `link_type`"));
+            }
+        }
+    }
+    changes.push(format!("set version = {CONFIG_VERSION}"));
+}
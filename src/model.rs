@@ -1,8 +1,19 @@
+use std::collections::BTreeMap;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
-use serde::Serialize;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
-#[derive(Debug, Clone, Serialize)]
+use crate::cli::{LinkType, Profile};
+use crate::config::TransformConfig;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 #[allow(clippy::enum_variant_names)]
 pub(crate) enum MappingKind {
@@ -14,22 +25,64 @@ pub(crate) enum MappingKind {
     InstructionFile,
 }
 
+impl MappingKind {
+    pub(crate) const ALL: [MappingKind; 4] = [
+        MappingKind::ConfigFile,
+        MappingKind::SkillFile,
+        MappingKind::PromptFile,
+        MappingKind::InstructionFile,
+    ];
+
+    /// The config table of the rules producing this kind.
+    pub(crate) const fn rule_kind(&self) -> &'static str {
+        match self {
+            MappingKind::SkillFile => "skills_sets",
+            _ => "links",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct Mapping {
+    /// Config file whose rule produced this mapping.
+    pub(crate) config: PathBuf,
     pub(crate) kind: MappingKind,
+    pub(crate) rule_index: usize,
+    pub(crate) link_type: LinkType,
     pub(crate) source: PathBuf,
     pub(crate) target: PathBuf,
+    /// The rule's source (or `source_root`) and target (or target root) as
+    /// written, before tokens are resolved.
+    pub(crate) source_template: String,
+    pub(crate) target_template: String,
+    /// The source as written when it is a glob pattern or a URL, whose
+    /// matches or cached copy `source` then is.
+    pub(crate) source_pattern: Option<String>,
+    /// Whether the target is listed in its repo's `.git/info/exclude`.
+    pub(crate) git_exclude: bool,
+    /// Whether the source and target are kept read-only.
+    pub(crate) protect: bool,
+    /// Whose sections a `generate` mapping keeps.
+    pub(crate) profile: Option<Profile>,
+    /// Tokens substituted into generated and `substitute` copy targets;
+    /// `None` leaves the content as is.
+    pub(crate) vars: Option<Arc<BTreeMap<String, String>>>,
+    /// Reshapes a generated target for its tool.
+    pub(crate) transform: Option<Arc<TransformConfig>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct ResolveContext {
+    pub(crate) config_path: PathBuf,
     pub(crate) config_dir: PathBuf,
     pub(crate) repo_root_text: String,
     pub(crate) home_dir: Option<PathBuf>,
     pub(crate) home_dir_text: Option<String>,
+    /// The config's `[vars]`, expanded, for `<name>` path tokens.
+    pub(crate) vars: Arc<BTreeMap<String, String>>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub(crate) enum Status {
     Ok,
@@ -40,18 +93,92 @@ pub(crate) enum Status {
     Replaced,
     WouldCreate,
     WouldReplace,
+    Removed,
+    WouldRemove,
+    Pruned,
+    WouldPrune,
     Skipped,
+    RolledBack,
+    /// Listed in the `verify --baseline` file with the status it has.
+    Accepted,
     Error,
 }
 
+impl Status {
+    pub(crate) const ALL: [Status; 16] = [
+        Status::Ok,
+        Status::Missing,
+        Status::Broken,
+        Status::Conflict,
+        Status::Created,
+        Status::Replaced,
+        Status::WouldCreate,
+        Status::WouldReplace,
+        Status::Removed,
+        Status::WouldRemove,
+        Status::Pruned,
+        Status::WouldPrune,
+        Status::Skipped,
+        Status::RolledBack,
+        Status::Accepted,
+        Status::Error,
+    ];
+}
+
 #[derive(Debug, Serialize)]
 pub(crate) struct Record {
     pub(crate) kind: MappingKind,
+    pub(crate) link_type: LinkType,
     pub(crate) source: PathBuf,
     pub(crate) target: PathBuf,
     pub(crate) status: Status,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) message: Option<String>,
+    /// Whether source and target hold the same bytes; set by `verify --hash`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) content_match: Option<bool>,
+    /// Time spent inspecting or changing the target.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) duration_ms: Option<f64>,
+    /// The config rule the record came from; `None` for orphans pruned
+    /// after their rule was removed.
+    #[serde(flatten)]
+    pub(crate) rule: Option<RuleRef>,
+}
+
+/// Points a record back at the entry in the config that produced it.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct RuleRef {
+    pub(crate) config: PathBuf,
+    pub(crate) rule_kind: &'static str,
+    pub(crate) rule_index: usize,
+    pub(crate) source_template: String,
+    pub(crate) target_template: String,
+}
+
+impl Mapping {
+    pub(crate) fn rule_ref(&self) -> RuleRef {
+        RuleRef {
+            config: self.config.clone(),
+            rule_kind: self.kind.rule_kind(),
+            rule_index: self.rule_index,
+            source_template: self.source_template.clone(),
+            target_template: self.target_template.clone(),
+        }
+    }
+}
+
+impl std::fmt::Display for RuleRef {
+    /// `links[2] in /home/me/.config/prompt-sync/config.toml`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}[{}] in {}",
+            self.rule_kind,
+            self.rule_index,
+            self.config.display()
+        )
+    }
 }
 
 #[derive(Debug, Default, Serialize)]
@@ -65,17 +192,102 @@ pub(crate) struct Summary {
     pub(crate) replaced: usize,
     pub(crate) would_create: usize,
     pub(crate) would_replace: usize,
+    pub(crate) removed: usize,
+    pub(crate) would_remove: usize,
+    pub(crate) pruned: usize,
+    pub(crate) would_prune: usize,
     pub(crate) skipped: usize,
+    pub(crate) rolled_back: usize,
+    pub(crate) accepted: usize,
     pub(crate) errors: usize,
 }
 
 #[derive(Debug, Serialize)]
 pub(crate) struct Report {
+    pub(crate) schema_version: u32,
     pub(crate) command: String,
+    pub(crate) run_id: &'static str,
+    /// What `sync` did to the `[master]` clone before linking.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) master: Option<MasterSync>,
+    pub(crate) started_at: DateTime<Utc>,
+    pub(crate) elapsed_ms: f64,
+    /// Where `elapsed_ms` went, as `--timing` prints it.
+    pub(crate) timing: Timing,
     pub(crate) summary: Summary,
     pub(crate) records: Vec<Record>,
 }
 
+#[derive(Debug, Serialize)]
+pub(crate) struct MasterSync {
+    pub(crate) repo: String,
+    pub(crate) root: PathBuf,
+    pub(crate) action: MasterAction,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) message: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum MasterAction {
+    Cloned,
+    Pulled,
+    UpToDate,
+    WouldClone,
+    WouldPull,
+    /// The checkout was linked as it was, e.g. while offline.
+    PullFailed,
+}
+
+impl MasterAction {
+    pub(crate) const ALL: [MasterAction; 6] = [
+        MasterAction::Cloned,
+        MasterAction::Pulled,
+        MasterAction::UpToDate,
+        MasterAction::WouldClone,
+        MasterAction::WouldPull,
+        MasterAction::PullFailed,
+    ];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum PlanAction {
+    Keep,
+    Create,
+    Replace,
+    Error,
+}
+
+impl PlanAction {
+    pub(crate) const fn as_str(self) -> &'static str {
+        match self {
+            PlanAction::Keep => "keep",
+            PlanAction::Create => "create",
+            PlanAction::Replace => "replace",
+            PlanAction::Error => "error",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct PlanEntry {
+    pub(crate) kind: MappingKind,
+    pub(crate) rule_kind: &'static str,
+    pub(crate) rule_index: usize,
+    pub(crate) source_template: String,
+    pub(crate) target_template: String,
+    pub(crate) link_type: LinkType,
+    pub(crate) source: PathBuf,
+    pub(crate) target: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) source_pattern: Option<String>,
+    pub(crate) status: Status,
+    pub(crate) action: PlanAction,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) message: Option<String>,
+}
+
 impl Summary {
     pub(crate) fn from_records(records: &[Record]) -> Self {
         let mut summary = Self {
@@ -93,7 +305,13 @@ impl Summary {
                 Status::Replaced => summary.replaced += 1,
                 Status::WouldCreate => summary.would_create += 1,
                 Status::WouldReplace => summary.would_replace += 1,
+                Status::Removed => summary.removed += 1,
+                Status::WouldRemove => summary.would_remove += 1,
+                Status::Pruned => summary.pruned += 1,
+                Status::WouldPrune => summary.would_prune += 1,
                 Status::Skipped => summary.skipped += 1,
+                Status::RolledBack => summary.rolled_back += 1,
+                Status::Accepted => summary.accepted += 1,
                 Status::Error => summary.errors += 1,
             }
         }
@@ -109,3 +327,165 @@ impl Summary {
         self.errors > 0
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Issue {
+    pub(crate) severity: Severity,
+    pub(crate) rule: String,
+    pub(crate) message: String,
+}
+
+/// Layout version of the JSON/YAML report. Fields and enum values may be
+/// added without a bump; removing, renaming or retyping one bumps it.
+pub(crate) const REPORT_SCHEMA_VERSION: u32 = 1;
+
+/// When a command started, for the `started_at`/`elapsed_ms` of its report.
+pub(crate) struct Timer {
+    pub(crate) started_at: DateTime<Utc>,
+    start: Instant,
+}
+
+impl Timer {
+    pub(crate) fn start() -> Self {
+        Self {
+            started_at: Utc::now(),
+            start: Instant::now(),
+        }
+    }
+
+    pub(crate) fn elapsed_ms(&self) -> f64 {
+        millis(self.start)
+    }
+}
+
+/// The per-phase breakdown of a report: time in each phase, and counts of
+/// the filesystem work that dominates on slow network or FUSE mounts.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub(crate) struct Timing {
+    /// Reading and parsing the config layers.
+    pub(crate) config_ms: f64,
+    /// Expanding the rules into mappings, skills directory walks included.
+    pub(crate) mappings_ms: f64,
+    /// Applying or inspecting the mappings: the record durations summed.
+    pub(crate) records_ms: f64,
+    /// Directory entries walked while building mappings.
+    pub(crate) walked_entries: u64,
+    /// Files read in full to hash them.
+    pub(crate) hashed_files: u64,
+    /// Source and directory lookups answered by the per-run metadata cache
+    /// instead of the filesystem.
+    pub(crate) stat_cache_hits: u64,
+}
+
+/// Process-wide tallies behind `Timing`, kept as atomics so the code that
+/// does the work only has to bump them.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Tally {
+    ConfigMicros,
+    MappingsMicros,
+    WalkedEntries,
+    HashedFiles,
+    StatCacheHits,
+}
+
+static TALLIES: [AtomicU64; 5] = [const { AtomicU64::new(0) }; 5];
+
+pub(crate) fn tally(tally: Tally, amount: u64) {
+    TALLIES[tally as usize].fetch_add(amount, Ordering::Relaxed);
+}
+
+/// Adds the time until it is dropped to a `*Micros` tally.
+pub(crate) struct PhaseTimer {
+    tally: Tally,
+    start: Instant,
+}
+
+impl PhaseTimer {
+    pub(crate) fn start(tally: Tally) -> Self {
+        Self {
+            tally,
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Drop for PhaseTimer {
+    fn drop(&mut self) {
+        let micros = u64::try_from(self.start.elapsed().as_micros()).unwrap_or(u64::MAX);
+        tally(self.tally, micros);
+    }
+}
+
+impl Timing {
+    /// The tallies since the last call, which resets them, so every report
+    /// of a `watch` covers its own pass.
+    pub(crate) fn take(records: &[Record]) -> Self {
+        let take = |tally: Tally| TALLIES[tally as usize].swap(0, Ordering::Relaxed);
+        Self {
+            config_ms: take(Tally::ConfigMicros) as f64 / 1000.0,
+            mappings_ms: take(Tally::MappingsMicros) as f64 / 1000.0,
+            records_ms: records.iter().filter_map(|record| record.duration_ms).sum(),
+            walked_entries: take(Tally::WalkedEntries),
+            hashed_files: take(Tally::HashedFiles),
+            stat_cache_hits: take(Tally::StatCacheHits),
+        }
+    }
+}
+
+/// Runs `apply` and stamps the record it returns with its duration.
+pub(crate) fn timed(apply: impl FnOnce() -> Record) -> Record {
+    let start = Instant::now();
+    let mut record = apply();
+    record.duration_ms = Some(millis(start));
+    record
+}
+
+/// Milliseconds since `start`, to the microsecond.
+fn millis(start: Instant) -> f64 {
+    (start.elapsed().as_secs_f64() * 1_000_000.0).round() / 1000.0
+}
+
+/// Identifies this invocation in reports, logs and backups so external
+/// tooling can correlate them: a random (version 4) UUID, made once per
+/// process.
+pub(crate) fn run_id() -> &'static str {
+    static RUN_ID: OnceLock<String> = OnceLock::new();
+    RUN_ID.get_or_init(|| {
+        // `RandomState` is seeded by the OS; the clock and pid guard against
+        // a platform where it is not.
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos())
+            .unwrap_or_default();
+        let mut hasher = Sha256::new();
+        for _ in 0..2 {
+            hasher.update(RandomState::new().build_hasher().finish().to_le_bytes());
+        }
+        hasher.update(nanos.to_le_bytes());
+        hasher.update(std::process::id().to_le_bytes());
+        let mut bytes = [0u8; 16];
+        bytes.copy_from_slice(&hasher.finalize()[..16]);
+        bytes[6] = (bytes[6] & 0x0f) | 0x40;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+        let hex = bytes
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>();
+        format!(
+            "{}-{}-{}-{}-{}",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        )
+    })
+}
@@ -1,15 +1,21 @@
 use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::env;
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, anyhow};
 
 use crate::model::ResolveContext;
+use crate::render::hostname;
+use crate::stat_cache;
 
 #[cfg(unix)]
 use std::os::unix::fs::MetadataExt;
 
+#[cfg(windows)]
+use crate::win32;
+
 pub(crate) struct PathTemplate<'a> {
     raw: &'a str,
 }
@@ -22,9 +28,8 @@ impl<'a> PathTemplate<'a> {
     pub(crate) fn resolve(&self, ctx: &ResolveContext) -> PathBuf {
         let with_tokens = substitute_tokens(Cow::Borrowed(self.raw), ctx);
         if let Some(home) = &ctx.home_dir
-            && (with_tokens == "~" || with_tokens.starts_with("~/"))
+            && let Some(suffix) = home_suffix(&with_tokens)
         {
-            let suffix = with_tokens.trim_start_matches('~').trim_start_matches('/');
             let mut path = home.clone();
             if !suffix.is_empty() {
                 path.push(suffix);
@@ -45,14 +50,278 @@ pub(crate) fn resolve_path(raw: &str, ctx: &ResolveContext) -> PathBuf {
     PathTemplate::new(raw).resolve(ctx)
 }
 
-fn substitute_tokens<'a>(input: Cow<'a, str>, ctx: &ResolveContext) -> Cow<'a, str> {
-    let input = replace_token(input, "<repo>", &ctx.repo_root_text);
+/// The rest of a `~`, `~/...` (or on Windows `~\...`) template.
+fn home_suffix(text: &str) -> Option<&str> {
+    if text == "~" {
+        return Some("");
+    }
+    let rest = text.strip_prefix("~/");
+    #[cfg(windows)]
+    let rest = rest.or_else(|| text.strip_prefix("~\\"));
+    rest.map(|rest| rest.trim_start_matches(['/', '\\']))
+}
+
+/// `HOME`, then on Windows `USERPROFILE`, `HOMEDRIVE` + `HOMEPATH` and the
+/// profile known folder. Empty values count as unset.
+pub(crate) fn home_dir() -> Option<PathBuf> {
+    let var = |name: &str| env::var_os(name).filter(|value| !value.is_empty());
+    let home = var("HOME").map(PathBuf::from);
+    #[cfg(windows)]
+    let home = home
+        .or_else(|| var("USERPROFILE").map(PathBuf::from))
+        .or_else(|| {
+            let mut path = var("HOMEDRIVE")?;
+            path.push(var("HOMEPATH")?);
+            Some(PathBuf::from(path))
+        })
+        .or_else(win32::profile_dir);
+    home
+}
+
+/// `fs::canonicalize` without the `\\?\` prefix it adds on Windows, so the
+/// result compares equal to configured paths and prints as written.
+pub(crate) fn canonical_path(path: &Path) -> std::io::Result<PathBuf> {
+    fs::canonicalize(path).map(simplify_verbatim)
+}
+
+/// `\\?\C:\dir` as `C:\dir` and `\\?\UNC\server\share` as `\\server\share`.
+/// Paths that need the prefix keep it: those at or over `MAX_PATH`, and
+/// those with names Win32 parsing would alter (trailing dots or spaces).
+#[cfg(windows)]
+fn simplify_verbatim(path: PathBuf) -> PathBuf {
+    use std::ffi::OsString;
+    use std::path::Prefix;
+
+    const MAX_PATH: usize = 260;
+    let mut components = path.components();
+    let Some(Component::Prefix(prefix)) = components.next() else {
+        return path;
+    };
+    let mut simple = match prefix.kind() {
+        Prefix::VerbatimDisk(drive) => OsString::from(format!("{}:", char::from(drive))),
+        Prefix::VerbatimUNC(server, share) => {
+            let mut simple = OsString::from("\\\\");
+            simple.push(server);
+            simple.push("\\");
+            simple.push(share);
+            simple
+        }
+        _ => return path,
+    };
+    let rest = components.as_path();
+    let altered = rest.components().any(|component| match component {
+        Component::Normal(name) => {
+            let name = name.to_string_lossy();
+            name.ends_with('.') || name.ends_with(' ')
+        }
+        _ => false,
+    });
+    simple.push(rest.as_os_str());
+    if altered || simple.len() >= MAX_PATH {
+        return path;
+    }
+    PathBuf::from(simple)
+}
+
+#[cfg(not(windows))]
+fn simplify_verbatim(path: PathBuf) -> PathBuf {
+    path
+}
+
+/// Problems that would make `raw` resolve somewhere unintended: `<token>`
+/// placeholders left after substitution, or `~` without a home directory.
+pub(crate) fn unresolved_tokens(raw: &str, ctx: &ResolveContext) -> Vec<String> {
+    let mut problems = Vec::new();
+    let substituted = substitute_tokens(Cow::Borrowed(raw), ctx);
+    if ctx.home_dir.is_none() && home_suffix(&substituted).is_some() {
+        problems.push("~".to_owned());
+    }
+
+    let mut rest = substituted.as_ref();
+    while let Some((_, name, after)) = next_token(rest) {
+        problems.push(format!("<{name}>"));
+        rest = after;
+    }
 
+    problems
+}
+
+/// The text before the next `<name>` token, the name, and the text after
+/// it. Other angle-bracket text is not a token.
+fn next_token(input: &str) -> Option<(&str, &str, &str)> {
+    let mut offset = 0;
+    while let Some(start) = input[offset..].find('<') {
+        let start = offset + start;
+        let after = &input[start + 1..];
+        let end = after.find('>')?;
+        let name = &after[..end];
+        if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Some((&input[..start], name, &after[end + 1..]));
+        }
+        offset = start + 1;
+    }
+    None
+}
+
+/// `[vars]` with the other vars each value mentions filled in, so one
+/// substitution pass resolves a path. Other tokens are kept for later.
+pub(crate) fn expand_vars(vars: &BTreeMap<String, String>) -> Result<BTreeMap<String, String>> {
+    fn expand(
+        name: &str,
+        vars: &BTreeMap<String, String>,
+        expanded: &mut BTreeMap<String, String>,
+        stack: &mut Vec<String>,
+    ) -> Result<String> {
+        if let Some(value) = expanded.get(name) {
+            return Ok(value.clone());
+        }
+        if let Some(start) = stack.iter().position(|seen| seen == name) {
+            let mut cycle = stack[start..].to_vec();
+            cycle.push(name.to_owned());
+            return Err(anyhow!("[vars] form a cycle: {}", cycle.join(" -> ")));
+        }
+        stack.push(name.to_owned());
+        let mut value = String::new();
+        let mut rest = vars[name].as_str();
+        while let Some((before, token, after)) = next_token(rest) {
+            value.push_str(before);
+            if vars.contains_key(token) {
+                value.push_str(&expand(token, vars, expanded, stack)?);
+            } else {
+                value.push_str(&format!("<{token}>"));
+            }
+            rest = after;
+        }
+        value.push_str(rest);
+        stack.pop();
+        expanded.insert(name.to_owned(), value.clone());
+        Ok(value)
+    }
+
+    let mut expanded = BTreeMap::new();
+    for name in vars.keys() {
+        expand(name, vars, &mut expanded, &mut Vec::new())?;
+    }
+    Ok(expanded)
+}
+
+/// Fills in the config's `[vars]`; they win over the built-in tokens.
+pub(crate) fn substitute_vars<'a>(input: Cow<'a, str>, ctx: &ResolveContext) -> Cow<'a, str> {
+    if ctx.vars.is_empty() || !input.contains('<') {
+        return input;
+    }
+    let mut output = String::new();
+    let mut rest = input.as_ref();
+    let mut replaced = false;
+    while let Some((before, name, after)) = next_token(rest) {
+        output.push_str(before);
+        match ctx.vars.get(name) {
+            Some(value) => {
+                output.push_str(value);
+                replaced = true;
+            }
+            None => output.push_str(&format!("<{name}>")),
+        }
+        rest = after;
+    }
+    if !replaced {
+        return input;
+    }
+    output.push_str(rest);
+    Cow::Owned(output)
+}
+
+type TokenLookup = fn(&ResolveContext) -> Option<String>;
+
+/// Tokens whose value is only looked up when a path uses them.
+const LOOKUP_TOKENS: &[(&str, TokenLookup)] = &[
+    ("<hostname>", |_| hostname().map(str::to_owned)),
+    ("<user>", |_| {
+        env::var("USER")
+            .or_else(|_| env::var("USERNAME"))
+            .ok()
+            .filter(|name| !name.is_empty())
+    }),
+    ("<config_dir>", |ctx| {
+        Some(ctx.config_dir.to_string_lossy().into_owned())
+    }),
+    ("<xdg_config>", |ctx| {
+        xdg_dir(ctx, "XDG_CONFIG_HOME", &[".config"])
+    }),
+    ("<xdg_data>", |ctx| {
+        xdg_dir(ctx, "XDG_DATA_HOME", &[".local", "share"])
+    }),
+    ("<xdg_cache>", |ctx| {
+        xdg_dir(ctx, "XDG_CACHE_HOME", &[".cache"])
+    }),
+    ("<xdg_state>", |ctx| {
+        xdg_dir(ctx, "XDG_STATE_HOME", &[".local", "state"])
+    }),
+];
+
+fn substitute_tokens<'a>(input: Cow<'a, str>, ctx: &ResolveContext) -> Cow<'a, str> {
+    if !input.contains('<') {
+        return input;
+    }
+    let input = substitute_vars(input, ctx);
+    let mut input = replace_token(input, "<repo>", &ctx.repo_root_text);
     if let Some(home_text) = &ctx.home_dir_text {
-        replace_token(input, "<home>", home_text)
-    } else {
-        input
+        input = replace_token(input, "<home>", home_text);
     }
+    for (token, lookup) in LOOKUP_TOKENS {
+        if input.contains(token)
+            && let Some(value) = lookup(ctx)
+        {
+            input = replace_token(input, token, &value);
+        }
+    }
+    input
+}
+
+/// Each token `raw` uses, in order, with the value it resolves to (`None`
+/// when nothing defines it). `~` is listed when it stands for the home
+/// directory.
+pub(crate) fn token_values(raw: &str, ctx: &ResolveContext) -> Vec<(String, Option<String>)> {
+    let mut values = Vec::new();
+    if home_suffix(raw).is_some() {
+        values.push(("~".to_owned(), ctx.home_dir_text.clone()));
+    }
+    let mut rest = raw;
+    while let Some((_, name, after)) = next_token(rest) {
+        let token = format!("<{name}>");
+        if !values.iter().any(|(seen, _)| *seen == token) {
+            let value = match ctx.vars.get(name) {
+                Some(value) => Some(substitute_tokens(Cow::Borrowed(value), ctx).into_owned()),
+                None => match name {
+                    "repo" => Some(ctx.repo_root_text.clone()),
+                    "home" => ctx.home_dir_text.clone(),
+                    _ => LOOKUP_TOKENS
+                        .iter()
+                        .find(|(lookup, _)| *lookup == token)
+                        .and_then(|(_, lookup)| lookup(ctx)),
+                },
+            };
+            values.push((token, value));
+        }
+        rest = after;
+    }
+    values
+}
+
+/// An XDG base directory: the variable when set, else its default below
+/// the home directory.
+fn xdg_dir(ctx: &ResolveContext, var: &str, default: &[&str]) -> Option<String> {
+    let dir = env::var_os(var)
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            ctx.home_dir.as_ref().map(|home| {
+                default
+                    .iter()
+                    .fold(home.clone(), |dir, part| dir.join(part))
+            })
+        })?;
+    Some(dir.to_string_lossy().into_owned())
 }
 
 fn replace_token<'a>(input: Cow<'a, str>, token: &str, replacement: &str) -> Cow<'a, str> {
@@ -71,22 +340,94 @@ pub(crate) fn absolute_path(path: &Path) -> Result<PathBuf> {
     Ok(cwd.join(path))
 }
 
+/// Drops `.` components and folds `..` into its parent without touching the
+/// filesystem, so `a/../b` and `b` compare equal.
+pub(crate) fn normalize_lexically(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if normalized.file_name().is_some() => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// Whether two files are the same: `(dev, ino)` on Unix and
+/// `(volume serial, file index)` on Windows. Each metadata must have been
+/// read from the path next to it, so symlinks are treated consistently.
 #[cfg(unix)]
-pub(crate) fn same_file(a: &fs::Metadata, b: &fs::Metadata) -> bool {
-    a.ino() == b.ino() && a.dev() == b.dev()
+pub(crate) fn same_file(
+    _a: &Path,
+    a_meta: &fs::Metadata,
+    _b: &Path,
+    b_meta: &fs::Metadata,
+) -> bool {
+    a_meta.ino() == b_meta.ino() && a_meta.dev() == b_meta.dev()
 }
 
-#[cfg(not(unix))]
-pub(crate) fn same_file(a: &fs::Metadata, b: &fs::Metadata) -> bool {
-    a.len() == b.len()
+#[cfg(windows)]
+pub(crate) fn same_file(a: &Path, a_meta: &fs::Metadata, b: &Path, b_meta: &fs::Metadata) -> bool {
+    match (file_identity(a, a_meta), file_identity(b, b_meta)) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn same_file(
+    _a: &Path,
+    a_meta: &fs::Metadata,
+    _b: &Path,
+    b_meta: &fs::Metadata,
+) -> bool {
+    a_meta.len() == b_meta.len()
 }
 
+/// Pair identifying a file, where the platform exposes one: `(dev, ino)` on
+/// Unix, `(volume serial, file index)` on Windows.
 #[cfg(unix)]
-pub(crate) fn hardlink_count(meta: &fs::Metadata) -> u64 {
+pub(crate) fn file_identity(_path: &Path, meta: &fs::Metadata) -> Option<(u64, u64)> {
+    Some((meta.dev(), meta.ino()))
+}
+
+#[cfg(windows)]
+pub(crate) fn file_identity(path: &Path, meta: &fs::Metadata) -> Option<(u64, u64)> {
+    win32::file_info(path, meta.file_type().is_symlink())
+        .map(|info| (info.volume_serial, info.file_index))
+}
+
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn file_identity(_path: &Path, _meta: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Whether `source` and the directory `target` will be created in share a
+/// filesystem, judged from the nearest existing ancestor of the target.
+/// `None` when it cannot be determined.
+pub(crate) fn on_same_device(source: &Path, target: &Path) -> Option<bool> {
+    let source_device = stat_cache::stat(source).ok()?.device?;
+    let target_device = target
+        .ancestors()
+        .skip(1)
+        .find_map(|dir| stat_cache::stat(dir).ok()?.device)?;
+    Some(source_device == target_device)
+}
+
+#[cfg(unix)]
+pub(crate) fn hardlink_count(_path: &Path, meta: &fs::Metadata) -> u64 {
     meta.nlink()
 }
 
-#[cfg(not(unix))]
-pub(crate) fn hardlink_count(_meta: &fs::Metadata) -> u64 {
+#[cfg(windows)]
+pub(crate) fn hardlink_count(path: &Path, meta: &fs::Metadata) -> u64 {
+    win32::file_info(path, meta.file_type().is_symlink()).map_or(1, |info| info.links)
+}
+
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn hardlink_count(_path: &Path, _meta: &fs::Metadata) -> u64 {
     1
 }
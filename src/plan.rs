@@ -0,0 +1,186 @@
+//! `plan --out` / `apply --plan`: the changes `link` would make, written to
+//! a file for review and carried out later exactly as planned. Every action
+//! records the state of its source and target, so a plan that no longer
+//! matches the filesystem is refused instead of applied.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::cli::LinkType;
+use crate::model::{MappingKind, Record, Status};
+use crate::pathing::absolute_path;
+use crate::safe_fs::calculate_sha256;
+
+pub(crate) const PLAN_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Plan {
+    pub(crate) schema_version: u32,
+    pub(crate) created_at: DateTime<Utc>,
+    /// The config files the plan was made from, with their hashes.
+    pub(crate) configs: Vec<PlannedConfig>,
+    pub(crate) actions: Vec<PlannedAction>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct PlannedConfig {
+    pub(crate) path: PathBuf,
+    pub(crate) sha256: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Action {
+    Create,
+    Replace,
+    Prune,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct PlannedAction {
+    pub(crate) action: Action,
+    pub(crate) kind: MappingKind,
+    pub(crate) link_type: LinkType,
+    pub(crate) source: PathBuf,
+    pub(crate) target: PathBuf,
+    /// `path_state` of the source and target when the plan was made.
+    pub(crate) source_state: String,
+    pub(crate) target_state: String,
+}
+
+impl Plan {
+    /// A plan of the dry-run `records` that would change a target.
+    pub(crate) fn new(config_paths: &[PathBuf], records: &[Record]) -> Result<Self> {
+        let configs = config_paths
+            .iter()
+            .map(|path| {
+                Ok(PlannedConfig {
+                    path: absolute_path(path)?,
+                    sha256: calculate_sha256(path)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let actions = records
+            .iter()
+            .filter_map(|record| {
+                let action = match record.status {
+                    Status::WouldCreate => Action::Create,
+                    Status::WouldReplace => Action::Replace,
+                    Status::WouldPrune => Action::Prune,
+                    _ => return None,
+                };
+                Some(PlannedAction {
+                    action,
+                    kind: record.kind.clone(),
+                    link_type: record.link_type,
+                    source: record.source.clone(),
+                    target: record.target.clone(),
+                    source_state: path_state(&record.source),
+                    target_state: path_state(&record.target),
+                })
+            })
+            .collect();
+        Ok(Self {
+            schema_version: PLAN_SCHEMA_VERSION,
+            created_at: Utc::now(),
+            configs,
+            actions,
+        })
+    }
+
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("failed to read plan: {}", path.display()))?;
+        let plan = serde_json::from_str::<Self>(&text)
+            .with_context(|| format!("invalid plan: {}", path.display()))?;
+        if plan.schema_version != PLAN_SCHEMA_VERSION {
+            return Err(anyhow!(
+                "unsupported plan schema_version {} in {} (expected {PLAN_SCHEMA_VERSION})",
+                plan.schema_version,
+                path.display()
+            ));
+        }
+        // `auto` is resolved when mappings are built; a plan names what it
+        // resolved to, so one that says `auto` was edited by hand.
+        if let Some(action) = plan
+            .actions
+            .iter()
+            .find(|action| action.link_type == LinkType::Auto)
+        {
+            return Err(anyhow!(
+                "invalid plan: {}: link_type auto for {} (expected a concrete link type)",
+                path.display(),
+                action.target.display()
+            ));
+        }
+        Ok(plan)
+    }
+
+    pub(crate) fn save(&self, path: &Path) -> Result<()> {
+        let text = serde_json::to_string_pretty(self).context("failed to serialize plan")?;
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        fs::write(path, text + "\n")
+            .with_context(|| format!("failed to write plan: {}", path.display()))
+    }
+
+    pub(crate) fn config_paths(&self) -> Vec<PathBuf> {
+        self.configs
+            .iter()
+            .map(|config| config.path.clone())
+            .collect()
+    }
+
+    /// What changed since the plan was made, one line per config, source or
+    /// target; empty when the plan still holds.
+    pub(crate) fn drift(&self) -> Vec<String> {
+        let mut changes = Vec::new();
+        for config in &self.configs {
+            if calculate_sha256(&config.path).ok().as_ref() != Some(&config.sha256) {
+                changes.push(format!("config {} changed", config.path.display()));
+            }
+        }
+        for action in &self.actions {
+            for (role, path, planned) in [
+                ("source", &action.source, &action.source_state),
+                ("target", &action.target, &action.target_state),
+            ] {
+                let current = path_state(path);
+                if current != *planned {
+                    changes.push(format!(
+                        "{role} {} is {current}, planned with {planned}",
+                        path.display()
+                    ));
+                }
+            }
+        }
+        changes
+    }
+}
+
+/// What is at `path`: `missing`, `directory`, `symlink:<destination>` or
+/// `sha256:<hash>` of a file.
+pub(crate) fn path_state(path: &Path) -> String {
+    let Ok(meta) = fs::symlink_metadata(path) else {
+        return "missing".to_owned();
+    };
+    if meta.file_type().is_symlink() {
+        return match fs::read_link(path) {
+            Ok(dest) => format!("symlink:{}", dest.display()),
+            Err(_) => "symlink".to_owned(),
+        };
+    }
+    if meta.is_dir() {
+        return "directory".to_owned();
+    }
+    match calculate_sha256(path) {
+        Ok(hash) => format!("sha256:{hash}"),
+        Err(_) => "unreadable".to_owned(),
+    }
+}
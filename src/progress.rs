@@ -0,0 +1,101 @@
+use std::cell::Cell;
+use std::io::{self, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+use crate::cli::{OutputArgs, OutputFormat};
+
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+const BAR_WIDTH: usize = 30;
+
+/// A single-line progress indicator on stderr for long runs. Counts go
+/// through `Cell`s so it can be ticked from inside iterator closures.
+pub(crate) struct Progress {
+    enabled: bool,
+    label: Cell<&'static str>,
+    total: Cell<Option<usize>>,
+    done: Cell<usize>,
+    last_draw: Cell<Option<Instant>>,
+}
+
+impl Progress {
+    /// Enabled for human-readable reports when both stdout and stderr are
+    /// terminals, unless `--no-progress` is given.
+    pub(crate) fn for_output(output: &OutputArgs) -> Self {
+        let human = !output.json_lines
+            && !output.quiet
+            && !output.no_progress
+            && !matches!(
+                output.format(),
+                OutputFormat::Json
+                    | OutputFormat::Yaml
+                    | OutputFormat::Github
+                    | OutputFormat::Porcelain
+            );
+        Self::new(human && io::stdout().is_terminal() && io::stderr().is_terminal())
+    }
+
+    pub(crate) const fn disabled() -> Self {
+        Self::new(false)
+    }
+
+    const fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            label: Cell::new(""),
+            total: Cell::new(None),
+            done: Cell::new(0),
+            last_draw: Cell::new(None),
+        }
+    }
+
+    /// Starts a new phase; `total` is `None` while the amount is unknown.
+    pub(crate) fn start(&self, label: &'static str, total: Option<usize>) {
+        self.label.set(label);
+        self.total.set(total);
+        self.done.set(0);
+        self.last_draw.set(None);
+        self.draw();
+    }
+
+    pub(crate) fn tick(&self) {
+        self.done.set(self.done.get() + 1);
+        let due = self
+            .last_draw
+            .get()
+            .is_none_or(|last| last.elapsed() >= REDRAW_INTERVAL);
+        if due {
+            self.draw();
+        }
+    }
+
+    /// Clears the line so the report starts on a clean terminal row.
+    pub(crate) fn finish(&self) {
+        if self.enabled && self.last_draw.get().is_some() {
+            eprint!("\r\x1b[K");
+            let _ = io::stderr().flush();
+            self.last_draw.set(None);
+        }
+    }
+
+    fn draw(&self) {
+        if !self.enabled {
+            return;
+        }
+        let done = self.done.get();
+        let line = match self.total.get() {
+            Some(total) if total > 0 => {
+                let filled = BAR_WIDTH * done.min(total) / total;
+                format!(
+                    "{} [{}{}] {done}/{total}",
+                    self.label.get(),
+                    "#".repeat(filled),
+                    ".".repeat(BAR_WIDTH - filled)
+                )
+            }
+            _ => format!("{}: {done} files", self.label.get()),
+        };
+        eprint!("\r\x1b[K{line}");
+        let _ = io::stderr().flush();
+        self.last_draw.set(Some(Instant::now()));
+    }
+}
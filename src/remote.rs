@@ -0,0 +1,150 @@
+//! Downloads through `curl`: `self-update` and `source = "https://..."`
+//! rules, whose file is cached locally and linked from there.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result, anyhow};
+
+use crate::safe_fs::{calculate_sha256, sha256_hex, temp_sibling_path};
+use crate::state::default_cache_dir;
+use crate::trace::info;
+
+/// Sources fetched instead of read from disk. `file://` is accepted for
+/// mirrors on a shared drive.
+pub(crate) fn is_url(raw: &str) -> bool {
+    ["https://", "http://", "file://"]
+        .iter()
+        .any(|scheme| raw.starts_with(scheme))
+}
+
+/// Seconds curl may spend connecting, and on a whole source download, so an
+/// unreachable or stalled host cannot hang a run.
+const CONNECT_TIMEOUT: &str = "10";
+const SOURCE_MAX_TIME: &str = "60";
+
+/// Where the cached copy of `url` is kept, whether or not it was fetched.
+/// Looking it up touches neither the network nor the cache directory.
+pub(crate) fn cache_path(url: &str) -> Result<PathBuf> {
+    Ok(default_cache_dir()
+        .ok_or_else(|| anyhow!("URL sources need a cache directory (set XDG_CACHE_HOME or HOME)"))?
+        .join("sources")
+        .join(&sha256_hex(url.as_bytes())[..16])
+        .join(url_file_name(url)))
+}
+
+/// Refreshes the cached copy of `url` and returns its path. The request is
+/// conditional on the saved ETag and the copy's mtime, so an unchanged
+/// source costs one round trip. When the server cannot be reached the
+/// cached copy is used with a warning; without one it is an error.
+pub(crate) fn cached_source(url: &str) -> Result<PathBuf> {
+    let cached = cache_path(url)?;
+    let dir = cached.parent().unwrap_or(Path::new("."));
+    if let Err(err) = refresh(url, dir, &cached) {
+        if !cached.is_file() {
+            return Err(err);
+        }
+        eprintln!("warn: {err:#}; using the cached copy of {url}");
+    }
+    Ok(cached)
+}
+
+fn refresh(url: &str, dir: &Path, cached: &Path) -> Result<()> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("failed to create cache directory: {}", dir.display()))?;
+    let etag = dir.join(".etag").to_string_lossy().into_owned();
+    let temp = temp_sibling_path(cached);
+    let _ = fs::remove_file(&temp);
+    let temp_arg = temp.to_string_lossy();
+    let cached_arg = cached.to_string_lossy();
+    let mut args = vec![
+        "--max-time",
+        SOURCE_MAX_TIME,
+        "--remote-time",
+        "--etag-save",
+        &etag,
+        "--output",
+        &temp_arg,
+        "--write-out",
+        "%{http_code}",
+    ];
+    if cached.is_file() {
+        args.extend(["--etag-compare", &etag, "--time-cond", &cached_arg]);
+    }
+    args.push(url);
+    let status = curl(&args)?;
+
+    // `file://` has no status code; curl then just writes nothing.
+    if status == b"304" || !temp.exists() {
+        let _ = fs::remove_file(&temp);
+        info!("source unchanged: {url}");
+        return Ok(());
+    }
+    let result = update_in_place(&temp, cached);
+    let _ = fs::remove_file(&temp);
+    result
+}
+
+/// Writes into the cached file rather than renaming over it, so targets
+/// hardlinked to it see the new content.
+fn update_in_place(download: &Path, cached: &Path) -> Result<()> {
+    if cached.is_file() && calculate_sha256(download)? == calculate_sha256(cached)? {
+        return Ok(());
+    }
+    let modified = fs::metadata(download).and_then(|meta| meta.modified());
+    fs::copy(download, cached)
+        .with_context(|| format!("failed to update cached source: {}", cached.display()))?;
+    // The server's Last-Modified is what the next If-Modified-Since sends.
+    if let Ok(modified) = modified {
+        let _ = fs::File::options()
+            .write(true)
+            .open(cached)
+            .and_then(|file| file.set_modified(modified));
+    }
+    info!("source updated: {}", cached.display());
+    Ok(())
+}
+
+/// The last path segment, so the cached file keeps the master's name and
+/// extension.
+fn url_file_name(url: &str) -> String {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let rest = rest.split(['?', '#']).next().unwrap_or_default();
+    rest.split_once('/')
+        .and_then(|(_, path)| path.rsplit('/').next())
+        .filter(|name| !name.is_empty())
+        .unwrap_or("source")
+        .to_owned()
+}
+
+/// Runs `curl` with `args` (the URL last) and returns what it printed.
+/// Only HTTPS and `file://` are allowed, redirects included, so a plain
+/// `http://` URL fails here.
+pub(crate) fn curl(args: &[&str]) -> Result<Vec<u8>> {
+    let url = args.last().copied().unwrap_or_default();
+    let output = Command::new("curl")
+        .args([
+            "--fail",
+            "--silent",
+            "--show-error",
+            "--location",
+            "--proto",
+            "=https,file",
+            "--connect-timeout",
+            CONNECT_TIMEOUT,
+        ])
+        .args(args)
+        .output()
+        .map_err(|err| match err.kind() {
+            std::io::ErrorKind::NotFound => anyhow!("downloading {url} needs curl on PATH"),
+            _ => anyhow!(err).context("failed to run curl"),
+        })?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "failed to download {url}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(output.stdout)
+}
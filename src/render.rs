@@ -0,0 +1,560 @@
+//! `link_type = "generate"`: the master rendered per target, keeping only
+//! the sections meant for the target's tool, and the frontmatter that
+//! decides which tools a master flows to at all. `[transforms.<profile>]`
+//! then reshapes the output into the format each tool expects.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::cli::{LinkType, Profile};
+use crate::config::TransformConfig;
+use crate::model::{Mapping, ResolveContext};
+use crate::safe_fs::sha256_hex;
+use crate::trace::info;
+use crate::vendors::{vendor, vendors};
+use crate::yaml;
+
+const END_MARKER: &str = "<!-- end -->";
+
+/// The managed header of a generated target, around the hash of the rest
+/// of the file.
+const HEADER_OPEN: &str = "<!-- generated by prompt-sync — do not edit (sha256 ";
+const HEADER_CLOSE: &str = ") -->\n";
+const HEADER_LEN: usize = HEADER_OPEN.len() + 64 + HEADER_CLOSE.len();
+
+/// The tool a target is for, from the nearest path component a vendor
+/// names: `CLAUDE.md`, `.claude/...`, `.github/copilot-instructions.md`, ...
+pub(crate) fn target_profile(target: &Path) -> Option<Profile> {
+    target
+        .components()
+        .rev()
+        .find_map(|component| match component {
+            Component::Normal(name) => vendors()
+                .iter()
+                .find(|vendor| vendor.names.iter().any(|known| name == known.as_str()))
+                .map(Profile::of),
+            _ => None,
+        })
+}
+
+/// Master metadata in a leading `---` block. Other keys are left to the
+/// tools that read them, e.g. Copilot's `applyTo`.
+#[derive(Debug, Default, Deserialize)]
+struct Frontmatter {
+    /// Tools the master is for; targets of other tools are not mapped.
+    #[serde(default)]
+    profiles: Option<Vec<Profile>>,
+    /// Tools each `<!-- section: name -->` block is for.
+    #[serde(default)]
+    sections: BTreeMap<String, Vec<Profile>>,
+}
+
+/// Keys prompt-sync reads and drops from rendered frontmatter.
+const FRONTMATTER_KEYS: [&str; 2] = ["profiles", "sections"];
+
+/// A master split at its frontmatter.
+struct Master<'a> {
+    meta: Frontmatter,
+    /// The frontmatter without prompt-sync's keys, or empty when none are
+    /// left.
+    kept: String,
+    body: &'a str,
+    /// Line number the body starts at.
+    body_line: usize,
+}
+
+impl<'a> Master<'a> {
+    fn parse(text: &'a str) -> Result<Self> {
+        let Some((yaml, body, body_line)) = split_frontmatter(text) else {
+            return Ok(Self {
+                meta: Frontmatter::default(),
+                kept: String::new(),
+                body: text,
+                body_line: 1,
+            });
+        };
+        let meta = serde_json::from_value(yaml::from_str(yaml)?)
+            .map_err(|err| anyhow!("invalid frontmatter: {err}"))?;
+        Ok(Self {
+            meta,
+            kept: strip_frontmatter_keys(yaml),
+            body,
+            body_line,
+        })
+    }
+}
+
+/// `(frontmatter, body, body line)` when `text` opens with a `---` line and
+/// the block is closed by another `---` (or `...`).
+pub(crate) fn split_frontmatter(text: &str) -> Option<(&str, &str, usize)> {
+    let mut lines = text.split_inclusive('\n');
+    if lines.next()?.trim_end() != "---" {
+        return None;
+    }
+    let yaml_start = text.find('\n')? + 1;
+    let mut offset = yaml_start;
+    for (index, line) in lines.enumerate() {
+        if matches!(line.trim_end(), "---" | "...") {
+            return Some((
+                &text[yaml_start..offset],
+                &text[offset + line.len()..],
+                index + 3,
+            ));
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// Drops the top-level `profiles` and `sections` entries, with their
+/// indented or `- ` continuation lines, from the frontmatter text.
+fn strip_frontmatter_keys(yaml: &str) -> String {
+    let mut kept = String::new();
+    let mut skipping = false;
+    for line in yaml.split_inclusive('\n') {
+        let continuation = line.starts_with([' ', '-']) || line.trim().is_empty();
+        if !continuation {
+            let key = line.split(':').next().unwrap_or_default().trim();
+            skipping = FRONTMATTER_KEYS.contains(&key);
+        }
+        if !skipping {
+            kept.push_str(line);
+        }
+    }
+    if kept.trim().is_empty() {
+        String::new()
+    } else {
+        format!("---\n{kept}---\n")
+    }
+}
+
+/// A master with its `<!-- include: path -->` lines replaced by the files
+/// they name, resolved against the including file's directory.
+struct Composed {
+    text: String,
+    /// Every included file, in include order.
+    fragments: Vec<PathBuf>,
+}
+
+fn compose(source: &Path) -> Result<Composed> {
+    let mut composed = Composed {
+        text: String::new(),
+        fragments: Vec::new(),
+    };
+    let mut stack = Vec::new();
+    compose_into(source, &mut stack, &mut composed)?;
+    Ok(composed)
+}
+
+/// `stack` holds the files being expanded, so an include cycle is an error
+/// rather than endless recursion.
+fn compose_into(path: &Path, stack: &mut Vec<PathBuf>, composed: &mut Composed) -> Result<()> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let identity = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    if stack.contains(&identity) {
+        let cycle = stack
+            .iter()
+            .chain([&identity])
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>();
+        return Err(anyhow!("include cycle: {}", cycle.join(" -> ")));
+    }
+    stack.push(identity);
+
+    let dir = path.parent().unwrap_or(Path::new("."));
+    for (index, line) in text.split_inclusive('\n').enumerate() {
+        let Some(include) = marker_value(line.trim(), "include:") else {
+            composed.text.push_str(line);
+            continue;
+        };
+        let fragment = dir.join(include.trim());
+        composed.fragments.push(fragment.clone());
+        compose_into(&fragment, stack, composed)
+            .with_context(|| format!("{} line {}: include failed", path.display(), index + 1))?;
+        // Keep the line after the directive on its own line.
+        if line.ends_with('\n') && !composed.text.is_empty() && !composed.text.ends_with('\n') {
+            composed.text.push('\n');
+        }
+    }
+
+    stack.pop();
+    Ok(())
+}
+
+/// The files `source` includes, directly or through other fragments. A
+/// fragment that fails to load ends the list there.
+pub(crate) fn fragments(source: &Path) -> Vec<PathBuf> {
+    let mut composed = Composed {
+        text: String::new(),
+        fragments: Vec::new(),
+    };
+    let _ = compose_into(source, &mut Vec::new(), &mut composed);
+    composed.fragments
+}
+
+/// The frontmatter `profiles` of the master at `source`, when it lists
+/// them. A source that cannot be read or parsed is not filtered; `generate`
+/// and `verify` report its errors.
+pub(crate) fn master_profiles(source: &Path) -> Option<Vec<Profile>> {
+    let text = fs::read_to_string(source).ok()?;
+    match Master::parse(&text) {
+        Ok(master) => master.meta.profiles,
+        Err(err) => {
+            info!(
+                "warn: ignoring frontmatter of {}: {err:#}",
+                source.display()
+            );
+            None
+        }
+    }
+}
+
+/// Whether `mapping`'s target holds rendered text rather than the source
+/// bytes.
+pub(crate) fn is_rendered(mapping: &Mapping) -> bool {
+    mapping.link_type == LinkType::Generate || mapping.vars.is_some()
+}
+
+/// What a rendered target holds. A generated one is the source rendered
+/// for its profile, includes expanded first so fragments can hold sections
+/// of their own; a `substitute` copy is the source as is. Either then gets
+/// its tokens substituted.
+pub(crate) fn render_source(mapping: &Mapping) -> Result<String> {
+    let text = if mapping.link_type == LinkType::Generate {
+        let composed = compose(&mapping.source)?;
+        render(&composed.text, mapping.profile.as_ref())
+            .with_context(|| format!("failed to render {}", mapping.source.display()))?
+    } else {
+        fs::read_to_string(&mapping.source)
+            .with_context(|| format!("failed to read source {}", mapping.source.display()))?
+    };
+    let text = match &mapping.vars {
+        Some(vars) => substitute(&text, vars),
+        None => text,
+    };
+    let text = match &mapping.transform {
+        Some(transform) => apply_transform(&text, transform, &mapping.target)
+            .with_context(|| format!("failed to transform {}", mapping.source.display()))?,
+        None => text,
+    };
+    Ok(if mapping.link_type == LinkType::Generate {
+        stamp(&text)
+    } else {
+        text
+    })
+}
+
+/// Puts the managed header after the frontmatter, which tools expect on the
+/// first line.
+fn stamp(text: &str) -> String {
+    let hash = sha256_hex(text.as_bytes());
+    let (head, body) = split_at_body(text);
+    format!("{head}{HEADER_OPEN}{hash}{HEADER_CLOSE}{body}")
+}
+
+/// Whether `text` carries the managed header and the rest of it still
+/// hashes to what the header says, i.e. nobody edited it since it was
+/// generated.
+pub(crate) fn is_untouched(text: &str) -> bool {
+    let (head, body) = split_at_body(text);
+    let Some((hash, rest)) = body
+        .strip_prefix(HEADER_OPEN)
+        .and_then(|header| header.split_once(HEADER_CLOSE))
+    else {
+        return false;
+    };
+    sha256_hex(format!("{head}{rest}").as_bytes()) == hash
+}
+
+/// `text` split after its frontmatter block, or `("", text)` without one.
+fn split_at_body(text: &str) -> (&str, &str) {
+    match split_frontmatter(text) {
+        Some((_, body, _)) => text.split_at(text.len() - body.len()),
+        None => ("", text),
+    }
+}
+
+/// The transform for a target of `profile`, which only `generate` applies:
+/// the config's `[transforms.<profile>]`, plus the vendor's `frontmatter`
+/// keys (`alwaysApply: true` for Cursor, so its `.mdc` rules load in every
+/// chat) unless the config says otherwise.
+pub(crate) fn transform_for(
+    transforms: &BTreeMap<Profile, TransformConfig>,
+    link_type: LinkType,
+    profile: Option<&Profile>,
+) -> Option<Arc<TransformConfig>> {
+    if link_type != LinkType::Generate {
+        return None;
+    }
+    let profile = profile?;
+    let mut transform = transforms.get(profile).cloned();
+    let defaults = vendor(profile.as_str()).map(|vendor| &vendor.frontmatter);
+    for (key, value) in defaults.into_iter().flatten() {
+        transform
+            .get_or_insert_default()
+            .frontmatter
+            .entry(key.as_str())
+            .or_insert_with(|| value.clone());
+    }
+    transform.map(Arc::new)
+}
+
+/// Headings first, then the frontmatter, then the size cap, so the cap
+/// covers everything the tool reads.
+fn apply_transform(text: &str, transform: &TransformConfig, target: &Path) -> Result<String> {
+    let mut text = reshape_headings(text, transform);
+    if !transform.frontmatter.is_empty() {
+        text = add_frontmatter(&text, &transform.frontmatter)?;
+    }
+    if let Some(max_bytes) = transform.max_bytes {
+        text = cap_size(text, max_bytes, target);
+    }
+    Ok(text)
+}
+
+/// Renames and shifts the ATX headings of the body; fenced code and the
+/// frontmatter are left alone.
+fn reshape_headings(text: &str, transform: &TransformConfig) -> String {
+    let offset = transform.heading_offset.unwrap_or(0);
+    if transform.headings.is_empty() && offset == 0 {
+        return text.to_owned();
+    }
+    let (head, body) = split_at_body(text);
+    let mut reshaped = head.to_owned();
+    let mut fence = None;
+    for line in body.split_inclusive('\n') {
+        let marker = ["```", "~~~"]
+            .into_iter()
+            .find(|marker| line.trim_start().starts_with(marker));
+        if let Some(open) = fence {
+            if marker == Some(open) {
+                fence = None;
+            }
+            reshaped.push_str(line);
+        } else if marker.is_some() {
+            fence = marker;
+            reshaped.push_str(line);
+        } else if let Some((level, title)) = heading(line) {
+            let title = transform.headings.get(title).map_or(title, String::as_str);
+            let level = (level as i16 + i16::from(offset)).clamp(1, 6) as usize;
+            let ending = &line[line.trim_end_matches(['\r', '\n']).len()..];
+            reshaped.push_str(&format!("{} {title}{ending}", "#".repeat(level)));
+        } else {
+            reshaped.push_str(line);
+        }
+    }
+    reshaped
+}
+
+/// `(level, title)` of a `# Title` line.
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let line = line.trim_end_matches(['\r', '\n']);
+    let level = line.bytes().take_while(|byte| *byte == b'#').count();
+    let rest = &line[level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with([' ', '\t'])) {
+        return None;
+    }
+    Some((level, rest.trim()))
+}
+
+/// Appends the `keys` the frontmatter does not set yet, opening a block when
+/// there is none. The master's own lines are kept as written.
+fn add_frontmatter(text: &str, keys: &Map<String, Value>) -> Result<String> {
+    let (yaml, body) = match split_frontmatter(text) {
+        Some((yaml, body, _)) => (yaml, body),
+        None => ("", text),
+    };
+    let existing = match yaml::from_str(yaml)? {
+        Value::Object(existing) => existing,
+        _ => return Err(anyhow!("frontmatter is not a mapping")),
+    };
+    let missing = keys
+        .iter()
+        .filter(|(key, _)| !existing.contains_key(*key))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect::<Map<_, _>>();
+    if missing.is_empty() {
+        return Ok(text.to_owned());
+    }
+    Ok(format!(
+        "---\n{yaml}{}---\n{body}",
+        yaml::to_string(&Value::Object(missing))
+    ))
+}
+
+/// Cuts `text` so that it fits in `max_bytes` with the managed header, at
+/// the last line break that fits.
+fn cap_size(text: String, max_bytes: usize, target: &Path) -> String {
+    if text.len() + HEADER_LEN <= max_bytes {
+        return text;
+    }
+    let mut end = max_bytes.saturating_sub(HEADER_LEN);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let end = text[..end].rfind('\n').map_or(end, |newline| newline + 1);
+    info!(
+        "warn: {} is {} bytes, over max_bytes = {max_bytes}; cut to {}",
+        target.display(),
+        text.len() + HEADER_LEN,
+        end + HEADER_LEN
+    );
+    text[..end].to_owned()
+}
+
+/// The content tokens for targets resolved in `ctx`: `<repo>`,
+/// `<repo_name>`, `<home>` and `<hostname>`, then the config's `[vars]`,
+/// which win over the built-in names.
+pub(crate) fn content_vars(
+    config_vars: &BTreeMap<String, String>,
+    ctx: &ResolveContext,
+) -> Arc<BTreeMap<String, String>> {
+    let mut vars = BTreeMap::new();
+    let repo = Path::new(&ctx.repo_root_text);
+    vars.insert("repo".to_owned(), ctx.repo_root_text.clone());
+    if let Some(name) = repo.file_name() {
+        vars.insert("repo_name".to_owned(), name.to_string_lossy().into_owned());
+    }
+    if let Some(home) = &ctx.home_dir_text {
+        vars.insert("home".to_owned(), home.clone());
+    }
+    if let Some(hostname) = hostname() {
+        vars.insert("hostname".to_owned(), hostname.to_owned());
+    }
+    vars.extend(config_vars.clone());
+    Arc::new(vars)
+}
+
+/// Replaces each `<name>` whose name is in `vars`; other angle-bracket text
+/// such as HTML tags is left alone.
+fn substitute(text: &str, vars: &BTreeMap<String, String>) -> String {
+    let mut substituted = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        substituted.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let value = after
+            .find('>')
+            .and_then(|end| Some((vars.get(&after[..end])?, end)));
+        match value {
+            Some((value, end)) => {
+                substituted.push_str(value);
+                rest = &after[end + 1..];
+            }
+            None => {
+                substituted.push('<');
+                rest = after;
+            }
+        }
+    }
+    substituted.push_str(rest);
+    substituted
+}
+
+/// The machine's name, or `PROMPT_SYNC_HOSTNAME` when set, for `<hostname>`
+/// and `[hosts]` sections.
+pub(crate) fn hostname() -> Option<&'static str> {
+    static HOSTNAME: OnceLock<Option<String>> = OnceLock::new();
+    HOSTNAME
+        .get_or_init(|| {
+            std::env::var("PROMPT_SYNC_HOSTNAME")
+                .ok()
+                .filter(|name| !name.is_empty())
+                .or_else(read_hostname)
+        })
+        .as_deref()
+}
+
+#[cfg(unix)]
+fn read_hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    // SAFETY: the buffer is writable for its full length, and the name is
+    // only read up to the NUL gethostname leaves when it succeeds.
+    if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } != 0 {
+        return None;
+    }
+    let name = std::ffi::CStr::from_bytes_until_nul(&buf).ok()?;
+    Some(name.to_string_lossy().into_owned())
+}
+
+#[cfg(not(unix))]
+fn read_hostname() -> Option<String> {
+    std::env::var("COMPUTERNAME").ok()
+}
+
+/// Drops the marker lines, and the sections that are not for `profile`: an
+/// `<!-- only: a, b -->` block names its tools, a `<!-- section: name -->`
+/// block takes them from the frontmatter `sections`. Both end at
+/// `<!-- end -->` and do not nest. Without a profile only the unmarked text
+/// is kept.
+pub(crate) fn render(text: &str, profile: Option<&Profile>) -> Result<String> {
+    let master = Master::parse(text)?;
+    let mut rendered = master.kept.clone();
+    let mut section: Option<(usize, bool)> = None;
+    for (index, line) in master.body.split_inclusive('\n').enumerate() {
+        let line_number = master.body_line + index;
+        let marker = line.trim();
+        let profiles = if let Some(list) = marker_value(marker, "only:") {
+            Some(parse_profiles(list, line_number)?)
+        } else if let Some(name) = marker_value(marker, "section:") {
+            let profiles = master.meta.sections.get(name.trim()).ok_or_else(|| {
+                anyhow!(
+                    "line {line_number}: section `{}` is not in the frontmatter",
+                    name.trim()
+                )
+            })?;
+            Some(profiles.clone())
+        } else {
+            None
+        };
+        if let Some(profiles) = profiles {
+            if let Some((start, _)) = section {
+                return Err(anyhow!(
+                    "line {line_number}: section opened inside the one at line {start}"
+                ));
+            }
+            let keep = profiles.iter().any(|only| Some(only) == profile);
+            section = Some((line_number, keep));
+        } else if marker == END_MARKER {
+            if section.take().is_none() {
+                return Err(anyhow!(
+                    "line {line_number}: {END_MARKER} without a section"
+                ));
+            }
+        } else if section.is_none_or(|(_, keep)| keep) {
+            rendered.push_str(line);
+        }
+    }
+    if let Some((start, _)) = section {
+        return Err(anyhow!(
+            "line {start}: section is never closed with {END_MARKER}"
+        ));
+    }
+    Ok(rendered)
+}
+
+/// The text after `key` in an `<!-- key ... -->` line.
+fn marker_value<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    line.strip_prefix("<!--")?
+        .strip_suffix("-->")?
+        .trim()
+        .strip_prefix(key)
+}
+
+fn parse_profiles(list: &str, line_number: usize) -> Result<Vec<Profile>> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            name.parse::<Profile>()
+                .map_err(|_| anyhow!("line {line_number}: unknown profile in section: {name}"))
+        })
+        .collect()
+}
@@ -1,15 +1,20 @@
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Once;
 
 use anyhow::{Context, Result, anyhow};
 use chrono::Utc;
 use sha2::{Digest, Sha256};
 
-#[cfg(unix)]
-use std::os::unix::fs::MetadataExt;
+use crate::backup_manifest::{BackupEntry, BackupManifest};
+use crate::logging::Rotation;
+use crate::model::{Tally, run_id, tally};
+use crate::stat_cache::{self, Stat};
 
 pub(crate) fn ensure_parent_dir(path: &Path) -> Result<()> {
-    if let Some(parent) = path.parent() {
+    if let Some(parent) = path.parent()
+        && !stat_cache::stat(parent).is_ok_and(|stat| stat.is_dir)
+    {
         fs::create_dir_all(parent).with_context(|| {
             format!(
                 "failed to create parent directories {}",
@@ -20,18 +25,20 @@ pub(crate) fn ensure_parent_dir(path: &Path) -> Result<()> {
     Ok(())
 }
 
+/// The source and the target's parent are looked up through `stat_cache`,
+/// since a skills set hardlinks many files from and into the same places.
 pub(crate) fn create_hard_link_checked(source: &Path, target: &Path) -> Result<()> {
-    let source_meta = fs::symlink_metadata(source)
+    let source_stat = stat_cache::stat(source)
         .with_context(|| format!("failed to inspect source {}", source.display()))?;
 
-    if !source_meta.file_type().is_file() {
+    if !source_stat.is_file {
         return Err(anyhow!(
             "source is not a regular file: {}",
             source.display()
         ));
     }
 
-    check_same_filesystem(&source_meta, target)?;
+    check_same_filesystem(&source_stat, target)?;
 
     fs::hard_link(source, target).with_context(|| {
         format!(
@@ -44,7 +51,214 @@ pub(crate) fn create_hard_link_checked(source: &Path, target: &Path) -> Result<(
     Ok(())
 }
 
-use std::time::{SystemTime, UNIX_EPOCH};
+pub(crate) fn create_symlink_checked(source: &Path, target: &Path) -> Result<()> {
+    let source_meta = fs::symlink_metadata(source)
+        .with_context(|| format!("failed to inspect source {}", source.display()))?;
+
+    if !source_meta.file_type().is_file() {
+        return Err(anyhow!(
+            "source is not a regular file: {}",
+            source.display()
+        ));
+    }
+
+    create_symlink(source, target).with_context(|| {
+        format!(
+            "failed to create symlink {} -> {}",
+            target.display(),
+            source.display()
+        )
+    })?;
+
+    Ok(())
+}
+
+pub(crate) fn copy_file_checked(source: &Path, target: &Path) -> Result<()> {
+    let source_meta = fs::symlink_metadata(source)
+        .with_context(|| format!("failed to inspect source {}", source.display()))?;
+
+    if !source_meta.file_type().is_file() {
+        return Err(anyhow!(
+            "source is not a regular file: {}",
+            source.display()
+        ));
+    }
+
+    fs::copy(source, target).with_context(|| {
+        format!(
+            "failed to copy {} -> {}",
+            source.display(),
+            target.display()
+        )
+    })?;
+
+    Ok(())
+}
+
+/// Creates `target` as a copy-on-write clone of `source` (`FICLONE` on
+/// Linux, `clonefile(2)` on macOS). Filesystems without reflinks, or a
+/// target on another filesystem, get a plain copy instead.
+pub(crate) fn clone_file_checked(source: &Path, target: &Path) -> Result<()> {
+    let source_meta = fs::symlink_metadata(source)
+        .with_context(|| format!("failed to inspect source {}", source.display()))?;
+
+    if !source_meta.file_type().is_file() {
+        return Err(anyhow!(
+            "source is not a regular file: {}",
+            source.display()
+        ));
+    }
+
+    if let Err(err) = clone_file(source, target) {
+        // A filesystem without reflinks, or a target on another one, stays
+        // that way for every file of the run: said once, not per file.
+        if matches!(
+            err.kind(),
+            std::io::ErrorKind::Unsupported | std::io::ErrorKind::CrossesDevices
+        ) {
+            static WARNED: Once = Once::new();
+            WARNED.call_once(|| {
+                eprintln!("warn: cannot reflink ({err}); copying instead");
+            });
+        } else {
+            eprintln!(
+                "warn: cannot reflink {} ({}); copying instead",
+                target.display(),
+                err
+            );
+        }
+        fs::copy(source, target).with_context(|| {
+            format!(
+                "failed to copy {} -> {}",
+                source.display(),
+                target.display()
+            )
+        })?;
+    }
+
+    Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn clone_file(source: &Path, target: &Path) -> std::io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let source_file = fs::File::open(source)?;
+    let target_file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(target)?;
+    // SAFETY: both descriptors stay open for the duration of the call.
+    let result = unsafe {
+        libc::ioctl(
+            target_file.as_raw_fd(),
+            libc::FICLONE,
+            source_file.as_raw_fd(),
+        )
+    };
+    if result == 0 {
+        return Ok(());
+    }
+    let err = std::io::Error::last_os_error();
+    drop(target_file);
+    let _ = fs::remove_file(target);
+    Err(err)
+}
+
+#[cfg(target_os = "macos")]
+fn clone_file(source: &Path, target: &Path) -> std::io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let invalid = |_| std::io::Error::from(std::io::ErrorKind::InvalidInput);
+    let source_cstr = CString::new(source.as_os_str().as_bytes()).map_err(invalid)?;
+    let target_cstr = CString::new(target.as_os_str().as_bytes()).map_err(invalid)?;
+    // SAFETY: both paths are NUL-terminated and outlive the call.
+    if unsafe { libc::clonefile(source_cstr.as_ptr(), target_cstr.as_ptr(), 0) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+fn clone_file(_source: &Path, _target: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "reflinks are not supported on this platform",
+    ))
+}
+
+/// Whether this process may create symlinks. Windows only allows it with
+/// Developer Mode or elevation, so it is probed once with a scratch link.
+#[cfg(windows)]
+pub(crate) fn symlinks_allowed() -> bool {
+    static ALLOWED: std::sync::OnceLock<bool> = std::sync::OnceLock::new();
+    *ALLOWED.get_or_init(|| {
+        let probe_dir = std::env::temp_dir();
+        let id = format!("prompt-sync-probe-{}", std::process::id());
+        let source = probe_dir.join(format!("{id}.src"));
+        let link = probe_dir.join(format!("{id}.lnk"));
+        let allowed = fs::write(&source, b"").is_ok() && create_symlink(&source, &link).is_ok();
+        let _ = fs::remove_file(&link);
+        let _ = fs::remove_file(&source);
+        allowed
+    })
+}
+
+#[cfg(not(windows))]
+pub(crate) fn symlinks_allowed() -> bool {
+    cfg!(unix)
+}
+
+#[cfg(unix)]
+fn create_symlink(source: &Path, target: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(source, target)
+}
+
+#[cfg(windows)]
+fn create_symlink(source: &Path, target: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(source, target)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn create_symlink(_source: &Path, _target: &Path) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "symlinks are not supported on this platform",
+    ))
+}
+
+/// Clears every write bit of the file behind `path`, or gives its owner
+/// write access back. Returns whether the permissions changed.
+pub(crate) fn set_read_only(path: &Path, read_only: bool) -> Result<bool> {
+    let meta =
+        fs::metadata(path).with_context(|| format!("failed to inspect {}", path.display()))?;
+    if meta.permissions().readonly() == read_only {
+        return Ok(false);
+    }
+    let permissions = writable_permissions(meta.permissions(), !read_only);
+    fs::set_permissions(path, permissions)
+        .with_context(|| format!("failed to change permissions of {}", path.display()))?;
+    Ok(true)
+}
+
+#[cfg(unix)]
+fn writable_permissions(permissions: fs::Permissions, writable: bool) -> fs::Permissions {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = permissions.mode();
+    fs::Permissions::from_mode(if writable {
+        mode | 0o200
+    } else {
+        mode & !0o222
+    })
+}
+
+#[cfg(not(unix))]
+fn writable_permissions(mut permissions: fs::Permissions, writable: bool) -> fs::Permissions {
+    permissions.set_readonly(!writable);
+    permissions
+}
 
 #[derive(Debug, Clone)]
 pub(crate) struct BackupOutcome {
@@ -57,9 +271,24 @@ impl BackupOutcome {
     }
 }
 
-pub(crate) fn remove_existing_target_file(
+/// Free space kept on the backup volume unless `--backup-min-free` is given.
+pub(crate) const DEFAULT_BACKUP_MIN_FREE: u64 = 64 << 20;
+
+/// Where `--force` moves replaced targets, and how much free space must
+/// remain there afterwards.
+#[derive(Debug, Clone)]
+pub(crate) struct BackupDir {
+    pub(crate) path: PathBuf,
+    pub(crate) min_free: u64,
+    /// How its `.operations.log` is rotated.
+    pub(crate) rotation: Rotation,
+}
+
+/// Backs up an existing target before it is replaced. The target itself
+/// stays in place until the replacement is renamed over it.
+pub(crate) fn backup_existing_target(
     target: &Path,
-    backup_dir: Option<&Path>,
+    backup_dir: Option<&BackupDir>,
 ) -> Result<BackupOutcome> {
     match fs::symlink_metadata(target) {
         Ok(meta) => {
@@ -70,14 +299,10 @@ pub(crate) fn remove_existing_target_file(
                 ));
             }
 
-            if let Some(backup_root) = backup_dir {
-                return backup_target_file(target, backup_root, meta.len());
+            match backup_dir {
+                Some(backup) => backup_target_file(target, backup, &meta),
+                None => Ok(BackupOutcome::none()),
             }
-
-            fs::remove_file(target).with_context(|| {
-                format!("failed to remove existing target {}", target.display())
-            })?;
-            Ok(BackupOutcome::none())
         }
         Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(BackupOutcome::none()),
         Err(err) => Err(anyhow!(
@@ -88,8 +313,13 @@ pub(crate) fn remove_existing_target_file(
     }
 }
 
-fn backup_target_file(target: &Path, backup_root: &Path, file_size: u64) -> Result<BackupOutcome> {
-    check_disk_space(backup_root, file_size)?;
+fn backup_target_file(
+    target: &Path,
+    backup: &BackupDir,
+    meta: &fs::Metadata,
+) -> Result<BackupOutcome> {
+    let backup_root = backup.path.as_path();
+    check_disk_space(backup_root, meta.len(), backup.min_free)?;
 
     fs::create_dir_all(backup_root).with_context(|| {
         format!(
@@ -99,41 +329,145 @@ fn backup_target_file(target: &Path, backup_root: &Path, file_size: u64) -> Resu
     })?;
 
     let backup_path = build_backup_path(backup_root, target);
+    ensure_parent_dir(&backup_path)?;
 
-    match fs::rename(target, &backup_path) {
-        Ok(_) => finalize_backup(backup_root, backup_path, file_size),
-        Err(_) => {
-            fs::copy(target, &backup_path).with_context(|| {
-                format!("failed to copy target to backup {}", backup_path.display())
-            })?;
-            fs::remove_file(target).with_context(|| {
-                format!("failed to remove existing target {}", target.display())
-            })?;
-            finalize_backup(backup_root, backup_path, file_size)
+    copy_target(target, &backup_path, meta)
+        .with_context(|| format!("failed to copy target to backup {}", backup_path.display()))?;
+    finalize_backup(backup_root, backup_path, target, meta.len())
+}
+
+/// Copies a target file, or recreates it when it is a symlink.
+fn copy_target(target: &Path, copy_path: &Path, meta: &fs::Metadata) -> std::io::Result<()> {
+    if meta.file_type().is_symlink() {
+        create_symlink(&fs::read_link(target)?, copy_path)
+    } else {
+        fs::copy(target, copy_path).map(|_| ())
+    }
+}
+
+/// Keeps the current content of `target` under a sibling name so an
+/// `--atomic` run can put it back. A hardlink is tried first, which costs
+/// nothing and survives the target being renamed over.
+pub(crate) fn stash_target(target: &Path) -> Result<PathBuf> {
+    let stash_path = stash_path(target);
+    let _ = fs::remove_file(&stash_path);
+    let meta = fs::symlink_metadata(target)
+        .with_context(|| format!("failed to inspect existing target {}", target.display()))?;
+    if meta.file_type().is_symlink() || fs::hard_link(target, &stash_path).is_err() {
+        copy_target(target, &stash_path, &meta).with_context(|| {
+            format!("failed to keep a copy of {} for rollback", target.display())
+        })?;
+    }
+    Ok(stash_path)
+}
+
+/// Puts a backup's content back at its original path after checking it
+/// against the recorded hash. The backup itself is kept.
+pub(crate) fn restore_backup(entry: &BackupEntry) -> Result<()> {
+    if let Some(expected) = &entry.hash {
+        let actual = calculate_sha256(&entry.backup)?;
+        if &actual != expected {
+            return Err(anyhow!(
+                "backup {} does not match its recorded hash; refusing to restore",
+                entry.backup.display()
+            ));
         }
     }
+    if fs::symlink_metadata(&entry.original).is_ok_and(|meta| meta.is_dir()) {
+        return Err(anyhow!(
+            "target is a directory; refusing to replace: {}",
+            entry.original.display()
+        ));
+    }
+    ensure_parent_dir(&entry.original)?;
+
+    // Same rename-over as link replacement, so the target is never missing.
+    let temp = temp_sibling_path(&entry.original);
+    let _ = fs::remove_file(&temp);
+    let result = fs::copy(&entry.backup, &temp)
+        .with_context(|| format!("failed to copy backup {}", entry.backup.display()))
+        .and_then(|_| {
+            fs::rename(&temp, &entry.original)
+                .with_context(|| format!("failed to restore target {}", entry.original.display()))
+        });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
 }
 
-fn build_backup_path(backup_root: &Path, target: &Path) -> PathBuf {
-    let ts = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map(|d| d.as_secs())
-        .unwrap_or(0);
+/// Scratch name next to `target`, so renaming it over the target stays on
+/// one filesystem and is atomic.
+pub(crate) fn temp_sibling_path(target: &Path) -> PathBuf {
+    sibling_path(target, "tmp")
+}
+
+/// Where `--atomic` keeps a replaced or removed target until the run ends.
+pub(crate) fn stash_path(target: &Path) -> PathBuf {
+    sibling_path(target, "orig")
+}
+
+fn sibling_path(target: &Path, extension: &str) -> PathBuf {
     let file_name = target
         .file_name()
         .map(|n| n.to_string_lossy().into_owned())
         .unwrap_or_else(|| "target".to_owned());
-    backup_root.join(format!("{}-{}", ts, file_name))
+    target.with_file_name(format!(
+        ".{}.prompt-sync-{}.{}",
+        file_name,
+        std::process::id(),
+        extension
+    ))
+}
+
+/// `<backup_root>/<run-id>/<target path without its root>`, so targets
+/// with the same file name never collide. A target backed up twice in one
+/// run (e.g. by `watch`) gets a numbered suffix.
+fn build_backup_path(backup_root: &Path, target: &Path) -> PathBuf {
+    let mut path = backup_root.join(run_id());
+    for component in target.components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::Prefix(prefix) => {
+                let drive = prefix
+                    .as_os_str()
+                    .to_string_lossy()
+                    .replace([':', '\\', '/', '?'], "");
+                path.push(drive);
+            }
+            Component::RootDir | Component::CurDir | Component::ParentDir => {}
+        }
+    }
+
+    let mut candidate = path.clone();
+    let mut counter = 1;
+    while fs::symlink_metadata(&candidate).is_ok() {
+        let mut name = path.as_os_str().to_owned();
+        name.push(format!(".{counter}"));
+        candidate = PathBuf::from(name);
+        counter += 1;
+    }
+    candidate
 }
 
 fn finalize_backup(
     backup_root: &Path,
     backup_path: PathBuf,
+    target: &Path,
     file_size: u64,
 ) -> Result<BackupOutcome> {
-    if let Ok(hash) = calculate_sha256(&backup_path) {
-        let _ = save_hash_metadata(&backup_path, &hash, file_size);
+    let hash = calculate_sha256(&backup_path).ok();
+    if let Some(hash) = &hash {
+        let _ = save_hash_metadata(&backup_path, hash, file_size);
     }
+    let _ = BackupManifest::new(backup_root).add(BackupEntry {
+        backup: backup_path.clone(),
+        original: target.to_path_buf(),
+        hash,
+        size: file_size,
+        run_id: run_id().to_owned(),
+        created_at: Utc::now(),
+    });
     let _ = cleanup_old_backups(backup_root, 100);
 
     Ok(BackupOutcome {
@@ -142,20 +476,20 @@ fn finalize_backup(
 }
 
 #[cfg(unix)]
-fn check_same_filesystem(source_meta: &fs::Metadata, target: &Path) -> Result<()> {
+fn check_same_filesystem(source_stat: &Stat, target: &Path) -> Result<()> {
     let target_parent = target.parent().unwrap_or_else(|| Path::new("."));
-    let parent_meta = fs::metadata(target_parent).with_context(|| {
+    let parent_stat = stat_cache::stat(target_parent).with_context(|| {
         format!(
             "failed to inspect target parent directory {}",
             target_parent.display()
         )
     })?;
 
-    if source_meta.dev() != parent_meta.dev() {
+    if let (Some(source_dev), Some(parent_dev)) = (source_stat.device, parent_stat.device)
+        && source_dev != parent_dev
+    {
         return Err(anyhow!(
-            "hardlink across filesystems is not supported: source={} target_parent={}",
-            source_meta.dev(),
-            parent_meta.dev()
+            "hardlink across filesystems is not supported: source={source_dev} target_parent={parent_dev} (set on_cross_device to symlink or copy)"
         ));
     }
 
@@ -163,12 +497,13 @@ fn check_same_filesystem(source_meta: &fs::Metadata, target: &Path) -> Result<()
 }
 
 #[cfg(not(unix))]
-fn check_same_filesystem(_source_meta: &fs::Metadata, _target: &Path) -> Result<()> {
+fn check_same_filesystem(_source_stat: &Stat, _target: &Path) -> Result<()> {
     Ok(())
 }
 
 // Phase 1: SHA256 Hash calculation
 pub(crate) fn calculate_sha256(path: &Path) -> Result<String> {
+    tally(Tally::HashedFiles, 1);
     let file = fs::File::open(path)
         .with_context(|| format!("failed to open file for hashing {}", path.display()))?;
     let mut hasher = Sha256::new();
@@ -180,14 +515,13 @@ pub(crate) fn calculate_sha256(path: &Path) -> Result<String> {
     Ok(format!("{:x}", hasher.finalize()))
 }
 
+/// The hash `calculate_sha256` would give a file holding `bytes`.
+pub(crate) fn sha256_hex(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
 pub(crate) fn save_hash_metadata(backup_path: &Path, hash: &str, file_size: u64) -> Result<()> {
-    let hash_path = backup_path.with_extension(format!(
-        "{}.sha256",
-        backup_path
-            .extension()
-            .map(|e| e.to_string_lossy())
-            .unwrap_or_default()
-    ));
+    let hash_path = hash_metadata_path(backup_path);
 
     let metadata = format!(
         "algorithm=sha256\nhash={}\nsize={}\ntimestamp={}\n",
@@ -202,43 +536,72 @@ pub(crate) fn save_hash_metadata(backup_path: &Path, hash: &str, file_size: u64)
     Ok(())
 }
 
+pub(crate) fn hash_metadata_path(backup_path: &Path) -> PathBuf {
+    backup_path.with_extension(format!(
+        "{}.sha256",
+        backup_path
+            .extension()
+            .map(|e| e.to_string_lossy())
+            .unwrap_or_default()
+    ))
+}
+
 // Phase 2: Disk space check
+/// Fails when writing `required_bytes` under `path` would leave less than
+/// `min_free` bytes available to the current user.
+pub(crate) fn check_disk_space(path: &Path, required_bytes: u64, min_free: u64) -> Result<()> {
+    // The backup directory may not exist yet; measure the volume it will be on.
+    let Some(existing) = path.ancestors().find(|dir| dir.exists()) else {
+        return Ok(());
+    };
+    let Some(available_bytes) = available_space(existing)? else {
+        return Ok(());
+    };
+
+    if available_bytes < required_bytes.saturating_add(min_free) {
+        return Err(anyhow!(
+            "insufficient disk space: required={} bytes plus {} bytes reserve, available={} bytes",
+            required_bytes,
+            min_free,
+            available_bytes
+        ));
+    }
+
+    Ok(())
+}
+
 #[cfg(unix)]
-pub(crate) fn check_disk_space(path: &Path, required_bytes: u64) -> Result<()> {
+fn available_space(dir: &Path) -> Result<Option<u64>> {
     use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
 
-    let target_parent = path.parent().unwrap_or_else(|| Path::new("."));
-    let path_cstr = CString::new(target_parent.to_string_lossy().as_bytes())
+    let path_cstr = CString::new(dir.as_os_str().as_bytes())
         .map_err(|_| anyhow!("invalid path for disk space check"))?;
 
+    // SAFETY: `path_cstr` is NUL-terminated and `stat_buf` is only read after
+    // statvfs reports success.
     let stat = unsafe {
-        let mut stat_buf = std::mem::MaybeUninit::uninit();
-        if libc::statfs(path_cstr.as_ptr(), stat_buf.as_mut_ptr()) != 0 {
-            return Err(anyhow!(
-                "failed to check disk space for {}",
-                target_parent.display()
-            ));
+        let mut stat_buf = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+        if libc::statvfs(path_cstr.as_ptr(), stat_buf.as_mut_ptr()) != 0 {
+            return Err(anyhow!("failed to check disk space for {}", dir.display()));
         }
         stat_buf.assume_init()
     };
 
-    let available_bytes = (stat.f_bavail as u64) * (stat.f_bsize as u64);
-
-    if available_bytes < required_bytes {
-        return Err(anyhow!(
-            "insufficient disk space: required={} bytes, available={} bytes",
-            required_bytes,
-            available_bytes
-        ));
-    }
+    let available = (stat.f_bavail as u64).saturating_mul(stat.f_frsize as u64);
+    Ok(Some(available))
+}
 
-    Ok(())
+#[cfg(windows)]
+fn available_space(dir: &Path) -> Result<Option<u64>> {
+    crate::win32::available_space(dir)
+        .map(Some)
+        .with_context(|| format!("failed to check disk space for {}", dir.display()))
 }
 
-#[cfg(not(unix))]
-pub(crate) fn check_disk_space(_path: &Path, _required_bytes: u64) -> Result<()> {
-    // Simplified check on non-Unix platforms
-    Ok(())
+#[cfg(not(any(unix, windows)))]
+fn available_space(_dir: &Path) -> Result<Option<u64>> {
+    Ok(None)
 }
 
 // Phase 3: Version limit management
@@ -266,7 +629,7 @@ pub(crate) fn cleanup_old_backups(backup_dir: &Path, max_versions: usize) -> Res
 
     if backup_files.len() > max_versions {
         // Sort by modification time (oldest first)
-        backup_files.sort_by(|a, b| a.1.cmp(&b.1));
+        backup_files.sort_by_key(|a| a.1);
 
         let to_remove = backup_files.len() - max_versions;
         for (path, _) in backup_files.iter().take(to_remove) {
@@ -0,0 +1,470 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::{Value, json};
+
+use crate::cli::LinkType;
+use crate::config::{CONFIG_VERSION, CrossDevice, LogSink};
+use crate::model::{MappingKind, MasterAction, REPORT_SCHEMA_VERSION, Status, Summary};
+use crate::vendors::vendors;
+
+/// JSON Schema (draft 2020-12) for the config file described by
+/// `config::ConfigFile`. Enum values are taken from the serde names of the
+/// CLI enums so new variants show up without editing this file.
+pub(crate) fn config_schema() -> Value {
+    let link_type = json!({
+        "description": "How targets are created: hardlink (default), symlink, copy, auto (hardlink, or on Windows a symlink when allowed and otherwise a copy), or reflink (a copy-on-write clone where the filesystem supports it).",
+        "enum": serde_names(LinkType::value_variants()),
+    });
+    let on_cross_device = json!({
+        "description": "What a hardlink rule does when source and target are on different filesystems.",
+        "enum": serde_names(CrossDevice::value_variants()),
+    });
+    let git_exclude = json!({
+        "description": "List targets inside a git repo in its .git/info/exclude so they do not show as untracked.",
+        "type": "boolean",
+    });
+    let protect = json!({
+        "description": "Keep the source and targets read-only; link and repair restore it and verify reports writable files.",
+        "type": "boolean",
+    });
+    let substitute = json!({
+        "description": "Substitute <repo_name>, <hostname> and [vars] tokens into copy targets as generate does.",
+        "type": "boolean",
+    });
+    let os_list = |description: &str| {
+        json!({
+            "description": description,
+            "type": "array",
+            "items": { "type": "string" },
+        })
+    };
+    let profiles = json!({
+        "description": "Apply the rule only when one of these profiles is selected with --profile.",
+        "type": "array",
+        "items": { "enum": profile_names() },
+    });
+
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "prompt-sync config",
+        "type": "object",
+        "additionalProperties": false,
+        "properties": {
+            "version": {
+                "description": "Config layout version.",
+                "type": "integer",
+                "minimum": 0,
+                "maximum": CONFIG_VERSION,
+            },
+            "on_cross_device": on_cross_device,
+            "git_exclude": git_exclude,
+            "protect": protect,
+            "substitute": substitute,
+            "vars": {
+                "description": "Values for <name> tokens in generated content.",
+                "type": "object",
+                "additionalProperties": { "type": "string" },
+            },
+            "transforms": {
+                "description": "Per-profile reshaping of generated targets, keyed by profile.",
+                "type": "object",
+                "propertyNames": { "enum": profile_names() },
+                "additionalProperties": {
+                    "type": "object",
+                    "additionalProperties": false,
+                    "properties": {
+                        "frontmatter": {
+                            "description": "Keys added to the output's frontmatter unless the master sets them.",
+                            "type": "object",
+                        },
+                        "headings": {
+                            "description": "Heading texts to rename, old = new.",
+                            "type": "object",
+                            "additionalProperties": { "type": "string" },
+                        },
+                        "heading_offset": {
+                            "description": "Levels added to every heading, clamped to 1..6.",
+                            "type": "integer",
+                            "minimum": -5,
+                            "maximum": 5,
+                        },
+                        "max_bytes": {
+                            "description": "Cut longer output at the last line break that fits.",
+                            "type": "integer",
+                            "minimum": 1,
+                        },
+                    },
+                },
+            },
+            "logging": {
+                "description": "Append-only NDJSON journal of every change, independent of --backup-dir.",
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "enabled": {
+                        "description": "Write the journal (default true once the section is present).",
+                        "type": "boolean",
+                    },
+                    "path": {
+                        "description": "Journal file (default operations.ndjson in the state directory).",
+                        "type": "string",
+                    },
+                    "sink": {
+                        "description": "Where journal lines go: the file, syslog or journald (the latter two need the syslog feature).",
+                        "enum": serde_names(LogSink::value_variants()),
+                    },
+                    "max_size": {
+                        "description": "Size the journal and the backup .operations.log grow to before they are rotated: bytes, or a number with K, M or G (default 1M).",
+                        "type": "string",
+                        "pattern": "^[0-9]+[KMGkmg]?$",
+                    },
+                    "max_files": {
+                        "description": "Rotated generations kept as <log>.1.gz (newest) to <log>.N.gz (default 5).",
+                        "type": "integer",
+                        "minimum": 0,
+                    },
+                },
+            },
+            "hooks": {
+                "description": "Shell commands run around link, sync and repair, in the directory of the config that lists them.",
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "pre_link": {
+                        "description": "Run before link and sync; a failing command stops the run.",
+                        "type": "array",
+                        "items": { "type": "string" },
+                    },
+                    "post_link": {
+                        "description": "Run after link and sync, with PROMPT_SYNC_REPORT naming the JSON report.",
+                        "type": "array",
+                        "items": { "type": "string" },
+                    },
+                    "post_repair": {
+                        "description": "Run after repair, with PROMPT_SYNC_REPORT naming the JSON report.",
+                        "type": "array",
+                        "items": { "type": "string" },
+                    },
+                },
+            },
+            "watch": {
+                "description": "Settings for watch and daemon.",
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "notify": {
+                        "description": "Send a desktop notification when a pass finds drifted targets (notify-send on Linux, osascript on macOS).",
+                        "type": "boolean",
+                    },
+                },
+            },
+            "repos": {
+                "description": "Repositories that rules with <repo> paths are expanded across.",
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "roots": {
+                        "description": "Repository roots; each <repo> rule maps once per root.",
+                        "type": "array",
+                        "items": { "type": "string" },
+                    },
+                    "scan": {
+                        "description": "Directories searched for git, jj and hg repositories.",
+                        "type": "array",
+                        "items": { "type": "string" },
+                    },
+                    "scan_depth": {
+                        "description": "How many directories deep scan looks (default 3).",
+                        "type": "integer",
+                        "minimum": 0,
+                    },
+                    "exclude": {
+                        "description": "Globs for directories scan does not enter, matched by name and by path relative to the scan directory.",
+                        "type": "array",
+                        "items": { "type": "string" },
+                    },
+                },
+            },
+            "master": {
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "root": {
+                        "description": "Directory holding the master instruction files.",
+                        "type": "string",
+                    },
+                    "repo": {
+                        "description": "Git remote the root is a clone of; `sync` clones it when the root is missing and pulls it before linking.",
+                        "type": "string",
+                    },
+                    "branch": {
+                        "description": "Branch `sync` clones; defaults to the remote's HEAD.",
+                        "type": "string",
+                    },
+                },
+            },
+            "links": {
+                "type": "array",
+                "items": { "$ref": "#/$defs/link_rule" },
+            },
+            "skills_sets": {
+                "type": "array",
+                "items": { "$ref": "#/$defs/skills_set" },
+            },
+            "hosts": {
+                "description": "Per-machine changes keyed by hostname (the full name or its first label), applied when the config is loaded.",
+                "type": "object",
+                "additionalProperties": {
+                    "type": "object",
+                    "additionalProperties": false,
+                    "properties": {
+                        "master": {
+                            "description": "[master] fields to override; link sources and source roots under the old root move to the new one.",
+                            "$ref": "#/properties/master",
+                        },
+                        "remove_targets": {
+                            "description": "Targets and skills target roots dropped from this file's rules on the machine.",
+                            "type": "array",
+                            "items": { "type": "string" },
+                        },
+                        "links": {
+                            "description": "Rules added on the machine.",
+                            "$ref": "#/properties/links",
+                        },
+                        "skills_sets": {
+                            "description": "Skills sets added on the machine.",
+                            "$ref": "#/properties/skills_sets",
+                        },
+                    },
+                },
+            },
+        },
+        "$defs": {
+            "link_target": {
+                "oneOf": [
+                    { "type": "string" },
+                    {
+                        "type": "object",
+                        "additionalProperties": false,
+                        "required": ["path"],
+                        "properties": {
+                            "path": {
+                                "description": "Target file, or the directory the source is linked into when `name` is set.",
+                                "type": "string",
+                            },
+                            "name": {
+                                "description": "File name of the target inside `path`.",
+                                "type": "string",
+                            },
+                            "profile": {
+                                "description": "Profile whose <!-- only: ... --> sections a generate rule keeps (default inferred from the path).",
+                                "enum": profile_names(),
+                            },
+                        },
+                    },
+                ],
+            },
+            "link_rule": {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["source"],
+                "properties": {
+                    "source": {
+                        "description": "Source file path or glob pattern.",
+                        "type": "string",
+                    },
+                    "targets": {
+                        "type": "array",
+                        "items": { "$ref": "#/$defs/link_target" },
+                    },
+                    "link_type": link_type,
+                    "mode": { "$ref": "#/$defs/link_rule/properties/link_type" },
+                    "on_cross_device": { "$ref": "#/properties/on_cross_device" },
+                    "git_exclude": { "$ref": "#/properties/git_exclude" },
+                    "protect": { "$ref": "#/properties/protect" },
+                    "substitute": { "$ref": "#/properties/substitute" },
+                    "only_on": os_list("Apply the rule only on these OS names or families."),
+                    "skip_on": os_list("Skip the rule on these OS names or families."),
+                    "when": {
+                        "description": "Apply the rule only while the environment variable `env` equals `equals`, or without `equals` while it is set and not empty.",
+                        "type": "object",
+                        "additionalProperties": false,
+                        "required": ["env"],
+                        "properties": {
+                            "env": { "type": "string" },
+                            "equals": { "type": "string" },
+                        },
+                    },
+                    "profiles": profiles,
+                },
+            },
+            "skills_set": {
+                "type": "object",
+                "additionalProperties": false,
+                "required": ["source_root"],
+                "properties": {
+                    "source_root": {
+                        "description": "Directory whose files are mirrored into every target root.",
+                        "type": "string",
+                    },
+                    "target_roots": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                    },
+                    "exclude": {
+                        "description": "Glob patterns, relative to source_root, to leave out.",
+                        "type": "array",
+                        "items": { "type": "string" },
+                    },
+                    "only_skills": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                    },
+                    "exclude_skills": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                    },
+                    "follow_symlinks": {
+                        "description": "Walk into symlinked directories under source_root.",
+                        "type": "boolean",
+                    },
+                    "max_depth": {
+                        "description": "Deepest level walked below source_root (1 = files directly in it).",
+                        "type": "integer",
+                        "minimum": 0,
+                    },
+                    "include_dirs": {
+                        "description": "Globs of directories to walk, matched by relative path or name.",
+                        "type": "array",
+                        "items": { "type": "string" },
+                    },
+                    "exclude_dirs": {
+                        "description": "Globs of directories never entered, matched by relative path or name.",
+                        "type": "array",
+                        "items": { "type": "string" },
+                    },
+                    "link_type": { "$ref": "#/$defs/link_rule/properties/link_type" },
+                    "mode": { "$ref": "#/$defs/link_rule/properties/link_type" },
+                    "on_cross_device": { "$ref": "#/properties/on_cross_device" },
+                    "git_exclude": { "$ref": "#/properties/git_exclude" },
+                    "protect": { "$ref": "#/properties/protect" },
+                    "substitute": { "$ref": "#/properties/substitute" },
+                    "only_on": { "$ref": "#/$defs/link_rule/properties/only_on" },
+                    "skip_on": { "$ref": "#/$defs/link_rule/properties/skip_on" },
+                    "when": { "$ref": "#/$defs/link_rule/properties/when" },
+                    "profiles": { "$ref": "#/$defs/link_rule/properties/profiles" },
+                },
+            },
+        },
+    })
+}
+
+/// JSON Schema (draft 2020-12) for the `--json` report of `model::Report`,
+/// for `schema_version` `REPORT_SCHEMA_VERSION`. Tools should accept
+/// properties and enum values they do not know; only a new
+/// `schema_version` removes, renames or retypes one.
+pub(crate) fn report_schema() -> Value {
+    let counts = serde_json::to_value(Summary::default())
+        .ok()
+        .and_then(|summary| summary.as_object().cloned())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(name, _)| (name, json!({ "type": "integer", "minimum": 0 })))
+        .collect::<serde_json::Map<_, _>>();
+    let count_names = counts.keys().cloned().collect::<Vec<_>>();
+    let path = json!({ "type": "string" });
+
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "prompt-sync report",
+        "type": "object",
+        "required": ["schema_version", "command", "run_id", "started_at", "elapsed_ms", "summary", "records"],
+        "properties": {
+            "schema_version": {
+                "description": "Report layout version; bumped only for incompatible changes.",
+                "const": REPORT_SCHEMA_VERSION,
+            },
+            "command": { "type": "string" },
+            "run_id": {
+                "description": "UUID shared with the journal, logs and backups of the same run.",
+                "type": "string",
+            },
+            "master": {
+                "description": "What sync did to the [master] clone before linking.",
+                "type": "object",
+                "required": ["repo", "root", "action"],
+                "properties": {
+                    "repo": { "type": "string" },
+                    "root": path,
+                    "action": { "enum": serde_names(&MasterAction::ALL) },
+                    "message": { "type": "string" },
+                },
+            },
+            "started_at": { "type": "string", "format": "date-time" },
+            "elapsed_ms": { "type": "number", "minimum": 0 },
+            "timing": {
+                "description": "Where elapsed_ms went: milliseconds per phase and counts of the filesystem work.",
+                "type": "object",
+                "properties": {
+                    "config_ms": { "type": "number", "minimum": 0 },
+                    "mappings_ms": { "type": "number", "minimum": 0 },
+                    "records_ms": { "type": "number", "minimum": 0 },
+                    "walked_entries": { "type": "integer", "minimum": 0 },
+                    "hashed_files": { "type": "integer", "minimum": 0 },
+                    "stat_cache_hits": { "type": "integer", "minimum": 0 },
+                },
+            },
+            "summary": {
+                "type": "object",
+                "required": count_names,
+                "properties": counts,
+            },
+            "records": {
+                "type": "array",
+                "items": { "$ref": "#/$defs/record" },
+            },
+        },
+        "$defs": {
+            "record": {
+                "type": "object",
+                "required": ["kind", "link_type", "source", "target", "status"],
+                "properties": {
+                    "kind": { "enum": serde_names(&MappingKind::ALL) },
+                    "link_type": { "enum": serde_names(LinkType::value_variants()) },
+                    "source": path,
+                    "target": path,
+                    "status": { "enum": serde_names(&Status::ALL) },
+                    "message": { "type": "string" },
+                    "content_match": {
+                        "description": "Whether source and target hold the same bytes (verify --hash).",
+                        "type": "boolean",
+                    },
+                    "duration_ms": { "type": "number", "minimum": 0 },
+                    "config": {
+                        "description": "Config file of the rule; absent for pruned orphans.",
+                        "type": "string",
+                    },
+                    "rule_kind": { "enum": ["links", "skills_sets"] },
+                    "rule_index": { "type": "integer", "minimum": 0 },
+                    "source_template": { "type": "string" },
+                    "target_template": { "type": "string" },
+                },
+            },
+        },
+    })
+}
+
+/// The registry's vendors, built-in and the user's.
+fn profile_names() -> Vec<Value> {
+    vendors()
+        .iter()
+        .map(|vendor| Value::from(vendor.name.as_str()))
+        .collect()
+}
+
+fn serde_names<T: Serialize>(variants: &[T]) -> Vec<Value> {
+    variants
+        .iter()
+        .filter_map(|variant| serde_json::to_value(variant).ok())
+        .collect()
+}
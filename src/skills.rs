@@ -0,0 +1,221 @@
+//! `skills diff`: what each target root holds of every skill, compared
+//! with its `source_root`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use walkdir::WalkDir;
+
+use crate::cli::MappingArgs;
+use crate::config::LoadedConfig;
+use crate::engine::{build_mappings, inspect_mapping, skills_set_roots};
+use crate::model::{MappingKind, Status};
+use crate::progress::Progress;
+use crate::safe_fs::calculate_sha256;
+
+/// One cell of the matrix: a skill in one target root.
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct SkillDiff {
+    pub(crate) skills_set: String,
+    pub(crate) skill: String,
+    pub(crate) target_root: PathBuf,
+    pub(crate) ok: usize,
+    /// Source files with no target.
+    pub(crate) missing: Vec<PathBuf>,
+    /// Targets whose content differs from the source.
+    pub(crate) drifted: Vec<PathBuf>,
+    /// Targets with the source's content that are not linked to it.
+    pub(crate) unlinked: Vec<PathBuf>,
+    /// Files in the target skill directory that no source file maps to.
+    pub(crate) extra: Vec<PathBuf>,
+}
+
+impl SkillDiff {
+    fn is_clean(&self) -> bool {
+        self.missing.is_empty()
+            && self.drifted.is_empty()
+            && self.unlinked.is_empty()
+            && self.extra.is_empty()
+    }
+
+    fn cell(&self) -> String {
+        if self.is_clean() {
+            return "ok".to_owned();
+        }
+        [
+            (self.missing.len(), "missing"),
+            (self.drifted.len(), "drifted"),
+            (self.unlinked.len(), "unlinked"),
+            (self.extra.len(), "extra"),
+        ]
+        .into_iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, label)| format!("{count} {label}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+    }
+}
+
+/// Diffs every skill of every skills set against each of its target roots.
+pub(crate) fn diff_skills(
+    configs: &[LoadedConfig],
+    options: &MappingArgs,
+) -> Result<Vec<SkillDiff>> {
+    let mut diffs = Vec::new();
+    for loaded in configs {
+        let mappings = build_mappings(&loaded.config, &loaded.ctx, options, &Progress::disabled())?;
+        for (set_index, source_root, target_roots) in
+            skills_set_roots(&loaded.config, &loaded.ctx, options)?
+        {
+            let skills_set = if configs.len() > 1 {
+                format!("{}: skills_sets[{set_index}]", loaded.path.display())
+            } else {
+                format!("skills_sets[{set_index}]")
+            };
+            let mut cells: BTreeMap<(String, usize), SkillDiff> = BTreeMap::new();
+            let mut expected = BTreeSet::new();
+            for mapping in mappings.iter().filter(|mapping| {
+                matches!(mapping.kind, MappingKind::SkillFile) && mapping.rule_index == set_index
+            }) {
+                let Ok(rel) = mapping.source.strip_prefix(&source_root) else {
+                    continue;
+                };
+                let Some(root_index) = target_roots.iter().position(|root| {
+                    mapping
+                        .target
+                        .strip_prefix(root)
+                        .is_ok_and(|path| path == rel)
+                }) else {
+                    continue;
+                };
+                let Some(skill) = rel.components().next() else {
+                    continue;
+                };
+                let skill = skill.as_os_str().to_string_lossy().into_owned();
+                let cell = cells
+                    .entry((skill.clone(), root_index))
+                    .or_insert_with(|| SkillDiff {
+                        skills_set: skills_set.clone(),
+                        skill,
+                        target_root: target_roots[root_index].clone(),
+                        ..SkillDiff::default()
+                    });
+                expected.insert(mapping.target.clone());
+                match inspect_mapping(mapping).status {
+                    Status::Ok => cell.ok += 1,
+                    Status::Missing => cell.missing.push(mapping.target.clone()),
+                    _ if same_content(&mapping.source, &mapping.target) => {
+                        cell.unlinked.push(mapping.target.clone());
+                    }
+                    _ => cell.drifted.push(mapping.target.clone()),
+                }
+            }
+            for cell in cells.values_mut() {
+                cell.extra = extra_files(&cell.target_root.join(&cell.skill), &expected)?;
+            }
+            diffs.extend(cells.into_values());
+        }
+    }
+    Ok(diffs)
+}
+
+fn same_content(source: &Path, target: &Path) -> bool {
+    match (calculate_sha256(source), calculate_sha256(target)) {
+        (Ok(source), Ok(target)) => source == target,
+        _ => false,
+    }
+}
+
+/// Files under a target skill directory that no mapping produces.
+fn extra_files(skill_dir: &Path, expected: &BTreeSet<PathBuf>) -> Result<Vec<PathBuf>> {
+    if !skill_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut extra = Vec::new();
+    for entry in WalkDir::new(skill_dir).sort_by_file_name() {
+        let entry = entry.with_context(|| format!("failed to walk {}", skill_dir.display()))?;
+        if !entry.file_type().is_dir() && !expected.contains(entry.path()) {
+            extra.push(entry.into_path());
+        }
+    }
+    Ok(extra)
+}
+
+/// One matrix per skills set, with a skill per row and a target root per
+/// column, then the files behind every cell that is not ok.
+pub(crate) fn print_skill_diffs(diffs: &[SkillDiff], json: bool) -> Result<()> {
+    if json {
+        let json_text = serde_json::to_string_pretty(diffs).context("failed to serialize JSON")?;
+        println!("{json_text}");
+        return Ok(());
+    }
+
+    let mut sets: BTreeMap<&str, Vec<&SkillDiff>> = BTreeMap::new();
+    for diff in diffs {
+        sets.entry(&diff.skills_set).or_default().push(diff);
+    }
+    for (skills_set, diffs) in sets {
+        let mut roots = Vec::<&Path>::new();
+        let mut rows: BTreeMap<&str, BTreeMap<&Path, String>> = BTreeMap::new();
+        for diff in &diffs {
+            if !roots.contains(&diff.target_root.as_path()) {
+                roots.push(&diff.target_root);
+            }
+            rows.entry(&diff.skill)
+                .or_default()
+                .insert(&diff.target_root, diff.cell());
+        }
+        let mut table = vec![
+            std::iter::once("SKILL".to_owned())
+                .chain(roots.iter().map(|root| root.display().to_string()))
+                .collect::<Vec<_>>(),
+        ];
+        for (skill, cells) in &rows {
+            table.push(
+                std::iter::once((*skill).to_owned())
+                    .chain(
+                        roots
+                            .iter()
+                            .map(|root| cells.get(root).cloned().unwrap_or_else(|| "-".to_owned())),
+                    )
+                    .collect(),
+            );
+        }
+        let widths = (0..=roots.len())
+            .map(|column| table.iter().map(|row| row[column].len()).max().unwrap_or(0))
+            .collect::<Vec<_>>();
+
+        println!("{skills_set}");
+        for row in &table {
+            let line = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{cell:<width$}"))
+                .collect::<Vec<_>>()
+                .join("  ");
+            println!("{}", line.trim_end());
+        }
+        for diff in diffs {
+            for (label, paths) in [
+                ("missing", &diff.missing),
+                ("drifted", &diff.drifted),
+                ("unlinked", &diff.unlinked),
+                ("extra", &diff.extra),
+            ] {
+                for path in paths {
+                    println!("  {label}: {}", path.display());
+                }
+            }
+        }
+    }
+    let dirty = diffs.iter().filter(|diff| !diff.is_clean()).count();
+    println!("skills diff: {} cell(s), {dirty} differ", diffs.len());
+    Ok(())
+}
+
+/// Whether any skill differs in any target root.
+pub(crate) fn has_differences(diffs: &[SkillDiff]) -> bool {
+    diffs.iter().any(|diff| !diff.is_clean())
+}
@@ -0,0 +1,88 @@
+//! A per-run cache of the metadata that linking asks for again and again:
+//! whether a source is a regular file, whether a target's parent directory
+//! exists, and which device each is on. A skills set links every file of a
+//! tree into each target root, so without it the same sources and parent
+//! directories are inspected once per mapping.
+//!
+//! Only facts a run does not change are kept, never sizes, times or link
+//! counts, and lookups that fail are not cached, because the parent
+//! directories they ask about are created as the run goes. `watch` and
+//! `daemon` start every pass with an empty cache.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, OnceLock, PoisonError};
+
+use crate::model::{Tally, tally};
+
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+
+#[cfg(windows)]
+use crate::win32;
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Stat {
+    /// A regular file itself, not a symlink to one.
+    pub(crate) is_file: bool,
+    /// A directory, or a symlink to one.
+    pub(crate) is_dir: bool,
+    /// The device of the file, or of what a symlink points to. `None` for
+    /// a dangling symlink or where the platform does not say.
+    pub(crate) device: Option<u64>,
+}
+
+fn cache() -> MutexGuard<'static, HashMap<PathBuf, Stat>> {
+    static CACHE: OnceLock<Mutex<HashMap<PathBuf, Stat>>> = OnceLock::new();
+    // Nothing panics while holding the lock, and the map stays valid if it did.
+    CACHE
+        .get_or_init(Mutex::default)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+/// `Stat` for `path`, inspected on the first lookup of the run.
+pub(crate) fn stat(path: &Path) -> io::Result<Stat> {
+    if let Some(stat) = cache().get(path) {
+        tally(Tally::StatCacheHits, 1);
+        return Ok(*stat);
+    }
+
+    let link_meta = fs::symlink_metadata(path)?;
+    let is_file = link_meta.file_type().is_file();
+    let meta = if link_meta.file_type().is_symlink() {
+        fs::metadata(path).ok()
+    } else {
+        Some(link_meta)
+    };
+    let stat = Stat {
+        is_file,
+        is_dir: meta.as_ref().is_some_and(fs::Metadata::is_dir),
+        device: meta.as_ref().and_then(|meta| device(path, meta)),
+    };
+    cache().insert(path.to_path_buf(), stat);
+    Ok(stat)
+}
+
+/// Forgets everything, so the next pass of a long-running command sees
+/// sources and directories replaced since the last one.
+pub(crate) fn clear() {
+    cache().clear();
+}
+
+#[cfg(unix)]
+fn device(_path: &Path, meta: &fs::Metadata) -> Option<u64> {
+    Some(meta.dev())
+}
+
+#[cfg(windows)]
+fn device(path: &Path, _meta: &fs::Metadata) -> Option<u64> {
+    win32::file_info(path, false).map(|info| info.volume_serial)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn device(_path: &Path, _meta: &fs::Metadata) -> Option<u64> {
+    None
+}
@@ -0,0 +1,212 @@
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::cli::LinkType;
+use crate::model::{Mapping, MappingKind};
+use crate::pathing::{file_identity, home_dir};
+use crate::safe_fs::calculate_sha256;
+
+const STATE_FILE_NAME: &str = "state.json";
+const LOCK_FILE_NAME: &str = "lock";
+
+/// Targets created by prompt-sync, keyed by absolute target path.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct State {
+    #[serde(default)]
+    pub(crate) targets: BTreeMap<PathBuf, ManagedTarget>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct ManagedTarget {
+    pub(crate) config: PathBuf,
+    pub(crate) kind: MappingKind,
+    pub(crate) link_type: LinkType,
+    pub(crate) source: PathBuf,
+    /// `(dev, ino)` of the target right after it was linked (volume serial
+    /// and file index on Windows).
+    #[serde(default)]
+    pub(crate) identity: Option<(u64, u64)>,
+    /// SHA-256 of the target content right after it was linked.
+    #[serde(default)]
+    pub(crate) hash: Option<String>,
+    #[serde(default)]
+    pub(crate) linked_at: Option<DateTime<Utc>>,
+}
+
+pub(crate) struct StateStore {
+    state_path: PathBuf,
+}
+
+/// Advisory lock on the state directory held by a run that changes
+/// targets; released when dropped.
+#[derive(Debug)]
+pub(crate) struct RunLock {
+    _file: File,
+}
+
+impl StateStore {
+    pub(crate) fn new(state_dir: &Path) -> Self {
+        StateStore {
+            state_path: state_dir.join(STATE_FILE_NAME),
+        }
+    }
+
+    pub(crate) fn dir(&self) -> Option<&Path> {
+        self.state_path.parent()
+    }
+
+    pub(crate) fn load(&self) -> Result<State> {
+        if !self.state_path.exists() {
+            return Ok(State::default());
+        }
+        let text = fs::read_to_string(&self.state_path)
+            .with_context(|| format!("failed to read state: {}", self.state_path.display()))?;
+        serde_json::from_str(&text)
+            .with_context(|| format!("invalid state file: {}", self.state_path.display()))
+    }
+
+    /// Takes the run lock. Without `wait`, a lock held by another process
+    /// is an error naming that process.
+    pub(crate) fn lock(&self, wait: bool) -> Result<RunLock> {
+        let lock_path = self.state_path.with_file_name(LOCK_FILE_NAME);
+        if let Some(parent) = lock_path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!("failed to create state directory: {}", parent.display())
+            })?;
+        }
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&lock_path)
+            .with_context(|| format!("failed to open lock file: {}", lock_path.display()))?;
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let holder = fs::read_to_string(&lock_path)
+                    .ok()
+                    .map(|pid| pid.trim().to_owned())
+                    .filter(|pid| !pid.is_empty())
+                    .map(|pid| format!(" (pid {pid})"))
+                    .unwrap_or_default();
+                if !wait {
+                    return Err(anyhow!(
+                        "another prompt-sync run{holder} holds {}; use --wait to queue behind it",
+                        lock_path.display()
+                    ));
+                }
+                eprintln!("waiting for another prompt-sync run{holder} to finish");
+                file.lock()
+                    .with_context(|| format!("failed to lock {}", lock_path.display()))?;
+            }
+            Err(TryLockError::Error(err)) => {
+                return Err(err).with_context(|| format!("failed to lock {}", lock_path.display()));
+            }
+        }
+
+        // The pid is informational only; the lock is what excludes others.
+        let _ = file
+            .set_len(0)
+            .and_then(|()| writeln!(file, "{}", std::process::id()));
+        Ok(RunLock { _file: file })
+    }
+
+    pub(crate) fn save(&self, state: &State) -> Result<()> {
+        if let Some(parent) = self.state_path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!("failed to create state directory: {}", parent.display())
+            })?;
+        }
+        let json_str = serde_json::to_string_pretty(state).context("failed to serialize state")?;
+
+        // Write to a sibling file first so a crash never leaves a truncated state.
+        let tmp_path = self.state_path.with_extension("json.tmp");
+        fs::write(&tmp_path, json_str)
+            .with_context(|| format!("failed to write state to {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, &self.state_path)
+            .with_context(|| format!("failed to write state to {}", self.state_path.display()))?;
+
+        Ok(())
+    }
+}
+
+impl State {
+    pub(crate) fn track(&mut self, mapping: &Mapping) {
+        let identity = fs::symlink_metadata(&mapping.target)
+            .ok()
+            .and_then(|meta| file_identity(&mapping.target, &meta));
+        self.targets.insert(
+            mapping.target.clone(),
+            ManagedTarget {
+                config: mapping.config.clone(),
+                kind: mapping.kind.clone(),
+                link_type: mapping.link_type,
+                source: mapping.source.clone(),
+                identity,
+                hash: calculate_sha256(&mapping.target).ok(),
+                linked_at: Some(Utc::now()),
+            },
+        );
+    }
+
+    pub(crate) fn owns(&self, target: &Path) -> bool {
+        self.targets.contains_key(target)
+    }
+
+    pub(crate) fn linked_since(&self, target: &Path, since: DateTime<Utc>) -> bool {
+        self.targets
+            .get(target)
+            .and_then(|entry| entry.linked_at)
+            .is_some_and(|linked_at| linked_at >= since)
+    }
+
+    pub(crate) fn forget(&mut self, target: &Path) {
+        self.targets.remove(target);
+    }
+
+    /// Targets recorded for any of `config_paths` that no current mapping
+    /// produces.
+    pub(crate) fn orphans(
+        &self,
+        config_paths: &[PathBuf],
+        mappings: &[Mapping],
+    ) -> Vec<(PathBuf, ManagedTarget)> {
+        let current = mappings
+            .iter()
+            .map(|mapping| mapping.target.as_path())
+            .collect::<HashSet<_>>();
+
+        self.targets
+            .iter()
+            .filter(|(target, entry)| {
+                config_paths.contains(&entry.config) && !current.contains(target.as_path())
+            })
+            .map(|(target, entry)| (target.clone(), entry.clone()))
+            .collect()
+    }
+}
+
+/// `$XDG_CACHE_HOME/prompt-sync`, falling back to `~/.cache/prompt-sync`.
+pub(crate) fn default_cache_dir() -> Option<PathBuf> {
+    if let Some(xdg) = env::var_os("XDG_CACHE_HOME").filter(|dir| !dir.is_empty()) {
+        return Some(PathBuf::from(xdg).join("prompt-sync"));
+    }
+    home_dir().map(|home| home.join(".cache").join("prompt-sync"))
+}
+
+/// `$XDG_STATE_HOME/prompt-sync`, falling back to `~/.local/state/prompt-sync`.
+pub(crate) fn default_state_dir() -> Option<PathBuf> {
+    if let Some(xdg) = env::var_os("XDG_STATE_HOME").filter(|dir| !dir.is_empty()) {
+        return Some(PathBuf::from(xdg).join("prompt-sync"));
+    }
+    home_dir().map(|home| home.join(".local").join("state").join("prompt-sync"))
+}
@@ -0,0 +1,91 @@
+//! `[logging] sink = "syslog"` and `"journald"`: journal lines sent to the
+//! system log so fleet tooling can audit replaced instruction files.
+
+use std::ffi::CString;
+use std::os::unix::net::UnixDatagram;
+use std::sync::Once;
+
+use anyhow::{Context, Result};
+
+use crate::journal::JournalEntry;
+
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Failures are warnings, the rest notices.
+fn priority(entry: &JournalEntry) -> libc::c_int {
+    if entry.action == "ERROR" {
+        libc::LOG_WARNING
+    } else {
+        libc::LOG_NOTICE
+    }
+}
+
+/// Logs each entry as its JSON line under the `prompt-sync` ident.
+pub(crate) fn send_syslog(lines: &[JournalEntry]) -> Result<()> {
+    static OPEN: Once = Once::new();
+    // SAFETY: the ident is a static C string, as openlog keeps the pointer.
+    OPEN.call_once(|| unsafe {
+        libc::openlog(c"prompt-sync".as_ptr(), libc::LOG_PID, libc::LOG_USER)
+    });
+    for line in lines {
+        // JSON escapes NUL, so the message never holds an interior one.
+        let message =
+            CString::new(serde_json::to_string(line)?).context("journal line holds a NUL byte")?;
+        // SAFETY: both pointers are NUL-terminated strings and "%s" consumes
+        // exactly the one argument.
+        unsafe { libc::syslog(priority(line), c"%s".as_ptr(), message.as_ptr()) };
+    }
+    Ok(())
+}
+
+/// Sends each entry to journald's native protocol with the fields as
+/// `PROMPT_SYNC_*` so they can be matched with `journalctl`.
+pub(crate) fn send_journald(lines: &[JournalEntry]) -> Result<()> {
+    let socket = UnixDatagram::unbound().context("failed to open a journald socket")?;
+    for line in lines {
+        let mut payload = Vec::new();
+        let message = format!("{} {}", line.action, line.target.display());
+        push_field(&mut payload, "MESSAGE", &message);
+        push_field(&mut payload, "PRIORITY", &priority(line).to_string());
+        push_field(&mut payload, "SYSLOG_IDENTIFIER", "prompt-sync");
+        push_field(&mut payload, "PROMPT_SYNC_RUN_ID", &line.run_id);
+        push_field(&mut payload, "PROMPT_SYNC_COMMAND", &line.command);
+        push_field(&mut payload, "PROMPT_SYNC_ACTION", &line.action);
+        push_field(
+            &mut payload,
+            "PROMPT_SYNC_SOURCE",
+            &line.source.to_string_lossy(),
+        );
+        push_field(
+            &mut payload,
+            "PROMPT_SYNC_TARGET",
+            &line.target.to_string_lossy(),
+        );
+        if let Some(link_type) = line.link_type {
+            push_field(&mut payload, "PROMPT_SYNC_LINK_TYPE", link_type.as_str());
+        }
+        if let Some(text) = &line.message {
+            push_field(&mut payload, "PROMPT_SYNC_MESSAGE", text);
+        }
+        if let Some(hash) = &line.hash {
+            push_field(&mut payload, "PROMPT_SYNC_HASH", hash);
+        }
+        socket.send_to(&payload, JOURNALD_SOCKET).with_context(|| {
+            format!("failed to send to {JOURNALD_SOCKET} (is journald running?)")
+        })?;
+    }
+    Ok(())
+}
+
+/// `NAME=value\n`, or the length-prefixed form when the value spans lines.
+fn push_field(payload: &mut Vec<u8>, name: &str, value: &str) {
+    payload.extend_from_slice(name.as_bytes());
+    if value.contains('\n') {
+        payload.push(b'\n');
+        payload.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        payload.push(b'=');
+    }
+    payload.extend_from_slice(value.as_bytes());
+    payload.push(b'\n');
+}
@@ -0,0 +1,110 @@
+//! Leveled diagnostics on stderr, chosen with `-v`/`-vv`/`-vvv` or
+//! `RUST_LOG`, plus timing spans around the slow parts of a run.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::Instant;
+
+/// Verbosity levels; each `-v` enables the next one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Level {
+    /// Skipped rules, overrides and fallbacks (`-v`).
+    Info = 1,
+    /// Span timings for config load and mapping build (`-vv`).
+    Debug = 2,
+    /// Per-record spans and other chatter (`-vvv`).
+    Trace = 3,
+}
+
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(0);
+
+/// Sets the level from the `-v` count, raised by `RUST_LOG` when that asks
+/// for more.
+pub(crate) fn init(verbose: u8) {
+    let from_env = std::env::var("RUST_LOG")
+        .map(|raw| parse_rust_log(&raw))
+        .unwrap_or(0);
+    MAX_LEVEL.store(verbose.min(3).max(from_env), Ordering::Relaxed);
+}
+
+pub(crate) fn enabled(level: Level) -> bool {
+    level as u8 <= MAX_LEVEL.load(Ordering::Relaxed)
+}
+
+/// Prints `args` when `level` is enabled. Info lines are printed as is, so
+/// `-v` output reads the same as before; deeper levels are prefixed.
+pub(crate) fn emit(level: Level, args: fmt::Arguments<'_>) {
+    if !enabled(level) {
+        return;
+    }
+    match level {
+        Level::Info => eprintln!("{args}"),
+        Level::Debug => eprintln!("debug: {args}"),
+        Level::Trace => eprintln!("trace: {args}"),
+    }
+}
+
+/// Reads the highest level `RUST_LOG` enables for this crate. Directives
+/// are `level` or `target=level`; only targets naming prompt-sync count.
+fn parse_rust_log(raw: &str) -> u8 {
+    raw.split(',')
+        .filter_map(|directive| {
+            let (target, level) = match directive.trim().split_once('=') {
+                Some((target, level)) => (Some(target.trim()), level),
+                None => (None, directive),
+            };
+            if target.is_some_and(|target| !target.replace('-', "_").starts_with("prompt_sync")) {
+                return None;
+            }
+            match level.trim().to_ascii_lowercase().as_str() {
+                "off" | "error" | "warn" => Some(0),
+                "info" => Some(Level::Info as u8),
+                "debug" => Some(Level::Debug as u8),
+                "trace" => Some(Level::Trace as u8),
+                _ => None,
+            }
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Logs how long it lived when dropped, at its level.
+pub(crate) struct Span {
+    level: Level,
+    label: Option<String>,
+    start: Instant,
+}
+
+/// Starts a span; the label is only formatted when `level` is enabled.
+pub(crate) fn span(level: Level, label: fmt::Arguments<'_>) -> Span {
+    Span {
+        level,
+        label: enabled(level).then(|| label.to_string()),
+        start: Instant::now(),
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        if let Some(label) = &self.label {
+            emit(
+                self.level,
+                format_args!("{label} took {:.2?}", self.start.elapsed()),
+            );
+        }
+    }
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::trace::emit($crate::trace::Level::Info, format_args!($($arg)*))
+    };
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        $crate::trace::emit($crate::trace::Level::Debug, format_args!($($arg)*))
+    };
+}
+
+pub(crate) use {debug, info};
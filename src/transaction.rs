@@ -0,0 +1,94 @@
+use std::cell::RefCell;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::backup_manifest::BackupManifest;
+use crate::model::{Record, Status};
+use crate::safe_fs::hash_metadata_path;
+
+/// A filesystem change made during an `--atomic` run, with what is needed
+/// to undo it.
+#[derive(Debug)]
+pub(crate) enum Mutation {
+    /// A link created where nothing existed.
+    Created { target: PathBuf },
+    /// A target replaced; `original` holds its previous content.
+    Replaced { target: PathBuf, original: PathBuf },
+    /// A target removed; `original` is where it was moved to.
+    Removed { target: PathBuf, original: PathBuf },
+    /// A backup written under `root` before a replacement.
+    BackedUp { root: PathBuf, backup: PathBuf },
+}
+
+/// Every mutation of the current run, so `--atomic` can undo all of them
+/// when any target ends in an error.
+#[derive(Debug, Default)]
+pub(crate) struct Transaction {
+    mutations: RefCell<Vec<Mutation>>,
+}
+
+impl Transaction {
+    pub(crate) fn record(&self, mutation: Mutation) {
+        self.mutations.borrow_mut().push(mutation);
+    }
+
+    /// Commits the run when no record is an error, otherwise rolls every
+    /// mutation back and marks the records that had applied a change.
+    pub(crate) fn finish<'a>(self, records: impl IntoIterator<Item = &'a mut Record>) {
+        let records = records.into_iter().collect::<Vec<_>>();
+        if records.iter().all(|record| record.status != Status::Error) {
+            self.commit();
+            return;
+        }
+
+        self.rollback();
+        for record in records {
+            if matches!(
+                record.status,
+                Status::Created | Status::Replaced | Status::Pruned
+            ) {
+                record.status = Status::RolledBack;
+                record.message = Some("rolled back after another target failed".to_owned());
+            }
+        }
+    }
+
+    fn commit(self) {
+        for mutation in self.mutations.into_inner() {
+            if let Mutation::Replaced { original, .. } | Mutation::Removed { original, .. } =
+                mutation
+            {
+                let _ = fs::remove_file(original);
+            }
+        }
+    }
+
+    /// Undoes mutations newest first. A step that fails is reported and the
+    /// rest are still attempted.
+    fn rollback(self) {
+        for mutation in self.mutations.into_inner().into_iter().rev() {
+            let (result, path) = match mutation {
+                Mutation::Created { target } => (fs::remove_file(&target), target),
+                Mutation::Replaced { target, original }
+                | Mutation::Removed { target, original } => {
+                    (fs::rename(&original, &target), target)
+                }
+                Mutation::BackedUp { root, backup } => {
+                    let _ = fs::remove_file(hash_metadata_path(&backup));
+                    let _ = BackupManifest::new(&root).remove(&backup);
+                    let result = fs::remove_file(&backup);
+                    // Drop the run's directories too once they are empty.
+                    for dir in backup.ancestors().skip(1) {
+                        if dir == root || fs::remove_dir(dir).is_err() {
+                            break;
+                        }
+                    }
+                    (result, backup)
+                }
+            };
+            if let Err(err) = result {
+                eprintln!("warn: failed to roll back {}: {}", path.display(), err);
+            }
+        }
+    }
+}
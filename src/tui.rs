@@ -0,0 +1,513 @@
+//! `status --tui`: a full-screen dashboard of every mapping, grouped by
+//! target root and refreshed while it is open, from which single records
+//! can be repaired, forced or adopted.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow};
+
+use crate::engine::{adopt_target, apply_repair, inspect_mapping, serde_label, status_color};
+use crate::model::{Mapping, Record, Summary};
+use crate::render::{is_rendered, render_source};
+
+/// How long the dashboard waits for a key before it re-inspects.
+const REFRESH_MS: libc::c_int = 2000;
+/// Larger files are not diffed line by line.
+const MAX_DIFF_LINES: usize = 2000;
+const HELP: &str =
+    "j/k move  enter diff  J/K scroll diff  r repair  f force  a adopt  g refresh  q quit";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Detail,
+    DetailUp,
+    DetailDown,
+    Repair,
+    Force,
+    Adopt,
+    Refresh,
+    Quit,
+    Other,
+}
+
+enum Row {
+    Group(PathBuf),
+    Item(usize),
+}
+
+/// Shows the dashboard until the user quits. `on_change` is told about
+/// every record an action produced, so state and journal stay current.
+pub(crate) fn run_dashboard(
+    mappings: &[Mapping],
+    roots: &[PathBuf],
+    on_change: &mut dyn FnMut(&Mapping, &Record),
+) -> Result<()> {
+    // SAFETY: isatty only inspects the descriptors.
+    let interactive =
+        unsafe { libc::isatty(libc::STDIN_FILENO) == 1 && libc::isatty(libc::STDOUT_FILENO) == 1 };
+    if !interactive {
+        return Err(anyhow!("status --tui needs an interactive terminal"));
+    }
+    let mut dashboard = Dashboard::new(mappings, roots);
+    let _terminal = RawTerminal::enter()?;
+    let mut out = io::stdout();
+    loop {
+        out.write_all(dashboard.draw(terminal_size()).as_bytes())?;
+        out.flush()?;
+        let Some(key) = read_key(REFRESH_MS)? else {
+            dashboard.refresh();
+            continue;
+        };
+        let pending = dashboard.pending.take();
+        match key {
+            Key::Quit => return Ok(()),
+            Key::Up => dashboard.step(-1),
+            Key::Down => dashboard.step(1),
+            Key::PageUp => dashboard.step(-10),
+            Key::PageDown => dashboard.step(10),
+            Key::Detail => {
+                dashboard.detail = !dashboard.detail;
+                dashboard.detail_scroll = 0;
+            }
+            Key::DetailUp => dashboard.detail_scroll = dashboard.detail_scroll.saturating_sub(1),
+            Key::DetailDown => dashboard.detail_scroll += 1,
+            Key::Refresh => dashboard.refresh(),
+            Key::Repair => dashboard.act("repair", on_change, |mapping| {
+                apply_repair(mapping, false, false, None, None)
+            }),
+            // Forcing replaces the target and adopting the source, so both
+            // ask for the key a second time.
+            Key::Force | Key::Adopt if pending != Some(key) => {
+                let what = if key == Key::Force {
+                    "replace the target"
+                } else {
+                    "overwrite the source with the target"
+                };
+                dashboard.message = format!("press the key again to {what}");
+                dashboard.pending = Some(key);
+            }
+            Key::Force => dashboard.act("force", on_change, |mapping| {
+                apply_repair(mapping, true, false, None, None)
+            }),
+            Key::Adopt => dashboard.act("adopt", on_change, adopt_target),
+            Key::Other => {}
+        }
+    }
+}
+
+struct Dashboard<'a> {
+    mappings: &'a [Mapping],
+    records: Vec<Record>,
+    rows: Vec<Row>,
+    /// Index into `rows`; always an item unless there are none.
+    selected: usize,
+    scroll: usize,
+    detail: bool,
+    detail_scroll: usize,
+    /// The diff of the selected row, until it or the statuses change.
+    diff: Option<(usize, Vec<String>)>,
+    message: String,
+    /// A force or adopt waiting for its confirming key.
+    pending: Option<Key>,
+}
+
+impl<'a> Dashboard<'a> {
+    fn new(mappings: &'a [Mapping], roots: &[PathBuf]) -> Self {
+        let mut groups: BTreeMap<PathBuf, Vec<usize>> = BTreeMap::new();
+        for (index, mapping) in mappings.iter().enumerate() {
+            groups
+                .entry(group_root(&mapping.target, roots))
+                .or_default()
+                .push(index);
+        }
+        let mut rows = Vec::new();
+        for (root, mut items) in groups {
+            items.sort_by(|a, b| mappings[*a].target.cmp(&mappings[*b].target));
+            rows.push(Row::Group(root));
+            rows.extend(items.into_iter().map(Row::Item));
+        }
+        let selected = rows
+            .iter()
+            .position(|row| matches!(row, Row::Item(_)))
+            .unwrap_or(0);
+        Self {
+            mappings,
+            records: mappings.iter().map(inspect_mapping).collect(),
+            rows,
+            selected,
+            scroll: 0,
+            detail: false,
+            detail_scroll: 0,
+            diff: None,
+            message: String::new(),
+            pending: None,
+        }
+    }
+
+    fn selected_item(&self) -> Option<usize> {
+        match self.rows.get(self.selected) {
+            Some(Row::Item(index)) => Some(*index),
+            _ => None,
+        }
+    }
+
+    /// Moves the selection by `delta` items, skipping group headers.
+    fn step(&mut self, delta: isize) {
+        let items = self
+            .rows
+            .iter()
+            .enumerate()
+            .filter(|(_, row)| matches!(row, Row::Item(_)))
+            .map(|(position, _)| position)
+            .collect::<Vec<_>>();
+        let Some(current) = items.iter().position(|position| *position == self.selected) else {
+            return;
+        };
+        let next = current
+            .saturating_add_signed(delta)
+            .min(items.len().saturating_sub(1));
+        if items[next] != self.selected {
+            self.selected = items[next];
+            self.detail_scroll = 0;
+        }
+    }
+
+    fn refresh(&mut self) {
+        self.records = self.mappings.iter().map(inspect_mapping).collect();
+        self.diff = None;
+    }
+
+    fn act(
+        &mut self,
+        label: &str,
+        on_change: &mut dyn FnMut(&Mapping, &Record),
+        action: impl Fn(&Mapping) -> Record,
+    ) {
+        let Some(index) = self.selected_item() else {
+            return;
+        };
+        let mapping = &self.mappings[index];
+        let record = action(mapping);
+        on_change(mapping, &record);
+        self.message = format!(
+            "{label}: {} {}{}",
+            serde_label(&record.status),
+            mapping.target.display(),
+            record
+                .message
+                .as_deref()
+                .map(|message| format!(" ({message})"))
+                .unwrap_or_default()
+        );
+        self.records[index] = inspect_mapping(mapping);
+        self.diff = None;
+    }
+
+    /// The whole screen, redrawn from the top-left corner.
+    fn draw(&mut self, (height, width): (usize, usize)) -> String {
+        let summary = Summary::from_records(&self.records);
+        let mut lines = vec![format!(
+            "\x1b[1mprompt-sync status\x1b[0m  {} targets  ok={} missing={} broken={} conflict={} errors={}",
+            self.records.len(),
+            summary.ok,
+            summary.missing,
+            summary.broken,
+            summary.conflict,
+            summary.errors
+        )];
+
+        let body = height.saturating_sub(2).max(1);
+        let list_height = if self.detail { (body / 2).max(1) } else { body };
+        if self.selected < self.scroll {
+            self.scroll = self.selected.saturating_sub(1);
+        } else if self.selected >= self.scroll + list_height {
+            self.scroll = self.selected + 1 - list_height;
+        }
+        for position in self.scroll..(self.scroll + list_height).min(self.rows.len()) {
+            lines.push(self.row_line(position, width));
+        }
+        while lines.len() < list_height + 1 {
+            lines.push(String::new());
+        }
+
+        if self.detail {
+            let diff = self.selected_diff();
+            self.detail_scroll = self.detail_scroll.min(diff.len().saturating_sub(1));
+            let detail_height = body.saturating_sub(list_height + 1);
+            let mut detail = vec![format!(
+                "\x1b[7m{}\x1b[0m",
+                fit(" diff: - source  + target ", width)
+            )];
+            detail.extend(
+                diff.iter()
+                    .skip(self.detail_scroll)
+                    .take(detail_height)
+                    .map(|line| diff_line(line, width)),
+            );
+            lines.extend(detail);
+        }
+        while lines.len() < height.saturating_sub(1) {
+            lines.push(String::new());
+        }
+        let footer = if self.message.is_empty() {
+            HELP
+        } else {
+            &self.message
+        };
+        lines.push(fit(footer, width));
+
+        let mut screen = String::from("\x1b[H");
+        for (index, line) in lines.iter().enumerate() {
+            screen.push_str(line);
+            screen.push_str("\x1b[K");
+            if index + 1 < lines.len() {
+                screen.push_str("\r\n");
+            }
+        }
+        screen.push_str("\x1b[J");
+        screen
+    }
+
+    fn row_line(&self, position: usize, width: usize) -> String {
+        match &self.rows[position] {
+            Row::Group(root) => {
+                format!("\x1b[1m{}\x1b[0m", fit(&root.display().to_string(), width))
+            }
+            Row::Item(index) => {
+                let record = &self.records[*index];
+                let mapping = &self.mappings[*index];
+                let status = format!("{:<9}", serde_label(&record.status).to_uppercase());
+                let target = self
+                    .rows
+                    .iter()
+                    .take(position)
+                    .rev()
+                    .find_map(|row| match row {
+                        Row::Group(root) => mapping.target.strip_prefix(root).ok(),
+                        Row::Item(_) => None,
+                    })
+                    .unwrap_or(&mapping.target);
+                let message = record
+                    .message
+                    .as_deref()
+                    .map(|message| format!("  {message}"))
+                    .unwrap_or_default();
+                let text = fit(&format!("  {status} {}{message}", target.display()), width);
+                let (status_text, rest) = text.split_at(text.len().min(2 + status.len()));
+                let colored = match status_color(record.status) {
+                    Some(code) => format!("\x1b[{code}m{status_text}\x1b[39m{rest}"),
+                    None => text.clone(),
+                };
+                if position == self.selected {
+                    format!("\x1b[7m{colored}\x1b[0m")
+                } else {
+                    colored
+                }
+            }
+        }
+    }
+
+    fn selected_diff(&mut self) -> Vec<String> {
+        let Some(index) = self.selected_item() else {
+            return Vec::new();
+        };
+        match &self.diff {
+            Some((cached, lines)) if *cached == index => lines.clone(),
+            _ => {
+                let lines = diff_mapping(&self.mappings[index]);
+                self.diff = Some((index, lines.clone()));
+                lines
+            }
+        }
+    }
+}
+
+/// The longest configured root holding `target`, or its directory.
+fn group_root(target: &Path, roots: &[PathBuf]) -> PathBuf {
+    roots
+        .iter()
+        .filter(|root| target.starts_with(root))
+        .max_by_key(|root| root.components().count())
+        .cloned()
+        .unwrap_or_else(|| target.parent().map(Path::to_path_buf).unwrap_or_default())
+}
+
+/// What the target should hold against what it holds, as `-`, `+` and
+/// ` ` prefixed lines.
+fn diff_mapping(mapping: &Mapping) -> Vec<String> {
+    if fs::symlink_metadata(&mapping.target).is_err() {
+        return vec!["target does not exist".to_owned()];
+    }
+    let expected = if is_rendered(mapping) {
+        render_source(mapping)
+    } else {
+        fs::read_to_string(&mapping.source)
+            .with_context(|| format!("failed to read {}", mapping.source.display()))
+    };
+    let actual = fs::read_to_string(&mapping.target)
+        .with_context(|| format!("failed to read {}", mapping.target.display()));
+    match (expected, actual) {
+        (Err(err), _) | (_, Err(err)) => vec![format!("{err:#}")],
+        (Ok(expected), Ok(actual)) if expected == actual => {
+            vec!["contents are identical".to_owned()]
+        }
+        (Ok(expected), Ok(actual)) => line_diff(&expected, &actual),
+    }
+}
+
+/// A longest-common-subsequence diff of the two texts' lines.
+fn line_diff(expected: &str, actual: &str) -> Vec<String> {
+    let old = expected.lines().collect::<Vec<_>>();
+    let new = actual.lines().collect::<Vec<_>>();
+    if old.len() > MAX_DIFF_LINES || new.len() > MAX_DIFF_LINES {
+        return vec![format!(
+            "files differ; too long to diff (over {MAX_DIFF_LINES} lines)"
+        )];
+    }
+    // common[i][j]: LCS length of old[i..] and new[j..].
+    let mut common = vec![vec![0u32; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::new();
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push(format!("  {}", old[i]));
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || common[i][j + 1] >= common[i + 1][j]) {
+            lines.push(format!("+ {}", new[j]));
+            j += 1;
+        } else {
+            lines.push(format!("- {}", old[i]));
+            i += 1;
+        }
+    }
+    lines
+}
+
+fn diff_line(line: &str, width: usize) -> String {
+    let text = fit(line, width);
+    match line.as_bytes().first() {
+        Some(b'-') => format!("\x1b[31m{text}\x1b[39m"),
+        Some(b'+') => format!("\x1b[32m{text}\x1b[39m"),
+        _ => text,
+    }
+}
+
+/// `text` cut to `width` characters, with tabs and control characters
+/// replaced so they cannot move the cursor.
+fn fit(text: &str, width: usize) -> String {
+    text.chars()
+        .map(|ch| if ch.is_control() { ' ' } else { ch })
+        .take(width)
+        .collect()
+}
+
+fn terminal_size() -> (usize, usize) {
+    // SAFETY: winsize is plain data that TIOCGWINSZ fills in.
+    let mut size = unsafe { std::mem::zeroed::<libc::winsize>() };
+    // SAFETY: the pointer is to a live winsize, as TIOCGWINSZ expects.
+    let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+    if ok && size.ws_row > 0 && size.ws_col > 0 {
+        (usize::from(size.ws_row), usize::from(size.ws_col))
+    } else {
+        (24, 80)
+    }
+}
+
+/// Waits up to `timeout_ms` for a key; `None` when none came.
+fn read_key(timeout_ms: libc::c_int) -> Result<Option<Key>> {
+    let mut poll = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // SAFETY: one live pollfd is passed with a count of one.
+    let ready = unsafe { libc::poll(&mut poll, 1, timeout_ms) };
+    if ready < 0 {
+        let err = io::Error::last_os_error();
+        if err.kind() == io::ErrorKind::Interrupted {
+            return Ok(None);
+        }
+        return Err(err).context("failed to wait for input");
+    }
+    if ready == 0 {
+        return Ok(None);
+    }
+    let mut buf = [0u8; 8];
+    // SAFETY: the buffer is writable for its full length.
+    let read = unsafe { libc::read(libc::STDIN_FILENO, buf.as_mut_ptr().cast(), buf.len()) };
+    if read <= 0 {
+        return Ok(Some(Key::Quit));
+    }
+    Ok(Some(match &buf[..read as usize] {
+        b"q" | b"\x03" | b"\x1b" => Key::Quit,
+        b"k" | b"\x1b[A" | b"\x1bOA" => Key::Up,
+        b"j" | b"\x1b[B" | b"\x1bOB" => Key::Down,
+        b"\x1b[5~" => Key::PageUp,
+        b"\x1b[6~" | b" " => Key::PageDown,
+        b"\r" | b"\n" | b"d" => Key::Detail,
+        b"K" => Key::DetailUp,
+        b"J" => Key::DetailDown,
+        b"r" => Key::Repair,
+        b"f" => Key::Force,
+        b"a" => Key::Adopt,
+        b"g" => Key::Refresh,
+        _ => Key::Other,
+    }))
+}
+
+/// Raw input on the alternate screen while alive; the terminal is restored
+/// on drop, also when the dashboard fails.
+struct RawTerminal {
+    original: libc::termios,
+}
+
+impl RawTerminal {
+    fn enter() -> Result<Self> {
+        // SAFETY: termios is plain data that tcgetattr fills in.
+        let mut original = unsafe { std::mem::zeroed::<libc::termios>() };
+        // SAFETY: the pointer is to a live termios.
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return Err(io::Error::last_os_error()).context("failed to read terminal settings");
+        }
+        let mut raw = original;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG | libc::IEXTEN);
+        raw.c_iflag &= !(libc::IXON | libc::ICRNL);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        // SAFETY: the pointer is to a live termios.
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &raw) } != 0 {
+            return Err(io::Error::last_os_error()).context("failed to enter raw mode");
+        }
+        let mut out = io::stdout();
+        out.write_all(b"\x1b[?1049h\x1b[?25l")?;
+        out.flush()?;
+        Ok(Self { original })
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let mut out = io::stdout();
+        let _ = out.write_all(b"\x1b[?25h\x1b[?1049l");
+        let _ = out.flush();
+        // SAFETY: the settings were read from this terminal in `enter`.
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &self.original) };
+    }
+}
@@ -0,0 +1,175 @@
+//! `self-update`: replaces the running executable with the matching build
+//! from the project's GitHub releases.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+
+use crate::pathing::canonical_path;
+use crate::remote::curl;
+use crate::safe_fs::{calculate_sha256, temp_sibling_path};
+
+/// Overrides the releases API base, for mirrors and tests.
+const RELEASES_URL_ENV: &str = "PROMPT_SYNC_RELEASES_URL";
+/// `sha256sum` output covering every binary of a release.
+const CHECKSUMS_ASSET: &str = "SHA256SUMS";
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    #[serde(default)]
+    assets: Vec<Asset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    fn asset(&self, name: &str) -> Result<&Asset> {
+        self.assets
+            .iter()
+            .find(|asset| asset.name == name)
+            .ok_or_else(|| anyhow!("release {} has no {name} asset", self.tag_name))
+    }
+}
+
+/// Exit code 1 with `check` when a newer release exists. Without `tag`
+/// only a newer release is installed, unless `force` is set.
+pub(crate) fn self_update(check: bool, tag: Option<&str>, force: bool) -> Result<i32> {
+    let current = env!("CARGO_PKG_VERSION");
+    let url = release_url(tag);
+    let release: Release = serde_json::from_slice(&fetch(&url)?)
+        .with_context(|| format!("failed to parse release metadata from {url}"))?;
+    let version = release.tag_name.trim_start_matches('v');
+    let newer = version_key(version) > version_key(current);
+    if check {
+        if newer {
+            println!("update available: {current} -> {version}");
+            return Ok(1);
+        }
+        println!("prompt-sync {current} is up to date");
+        return Ok(0);
+    }
+    if !newer && tag.is_none() && !force {
+        println!("prompt-sync {current} is up to date");
+        return Ok(0);
+    }
+
+    let name = asset_name();
+    let binary = release.asset(&name)?;
+    let sums = fetch(&release.asset(CHECKSUMS_ASSET)?.browser_download_url)?;
+    let expected = expected_hash(&String::from_utf8_lossy(&sums), &name)?;
+    let exe = env::current_exe()
+        .and_then(|exe| canonical_path(&exe))
+        .context("failed to locate the running executable")?;
+
+    // Download next to the executable so the swap is a same-directory rename.
+    let temp = temp_sibling_path(&exe);
+    let _ = fs::remove_file(&temp);
+    let result = download(&binary.browser_download_url, &temp)
+        .and_then(|()| {
+            let actual = calculate_sha256(&temp)?;
+            if actual != expected {
+                return Err(anyhow!(
+                    "checksum mismatch for {name}: expected {expected}, got {actual}; keeping {}",
+                    exe.display()
+                ));
+            }
+            let permissions = fs::metadata(&exe)
+                .with_context(|| format!("failed to inspect {}", exe.display()))?
+                .permissions();
+            fs::set_permissions(&temp, permissions)
+                .with_context(|| format!("failed to set permissions on {}", temp.display()))
+        })
+        .and_then(|()| swap_executable(&temp, &exe));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result?;
+    println!(
+        "updated prompt-sync {current} -> {version}: {}",
+        exe.display()
+    );
+    Ok(0)
+}
+
+fn release_url(tag: Option<&str>) -> String {
+    let base = env::var(RELEASES_URL_ENV)
+        .ok()
+        .filter(|url| !url.is_empty())
+        .unwrap_or_else(|| {
+            let repo = env!("CARGO_PKG_REPOSITORY").trim_start_matches("https://github.com/");
+            format!("https://api.github.com/repos/{repo}/releases")
+        });
+    let base = base.trim_end_matches('/');
+    match tag {
+        Some(tag) => format!("{base}/tags/{tag}"),
+        None => format!("{base}/latest"),
+    }
+}
+
+/// `prompt-sync-<arch>-<os>`, e.g. `prompt-sync-aarch64-macos`.
+fn asset_name() -> String {
+    format!(
+        "prompt-sync-{}-{}{}",
+        env::consts::ARCH,
+        env::consts::OS,
+        env::consts::EXE_SUFFIX
+    )
+}
+
+/// The hash listed for `name`; `*name` (binary mode) matches too.
+fn expected_hash(sums: &str, name: &str) -> Result<String> {
+    sums.lines()
+        .filter_map(|line| line.split_once(char::is_whitespace))
+        .find(|(_, file)| file.trim().trim_start_matches('*') == name)
+        .map(|(hash, _)| hash.to_ascii_lowercase())
+        .filter(|hash| hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit()))
+        .ok_or_else(|| anyhow!("{CHECKSUMS_ASSET} has no valid entry for {name}"))
+}
+
+/// Numeric components of `1.2.3`; a pre-release suffix is ignored.
+fn version_key(version: &str) -> Vec<u64> {
+    version
+        .split(['-', '+'])
+        .next()
+        .unwrap_or_default()
+        .split('.')
+        .map(|part| part.parse().unwrap_or(0))
+        .collect()
+}
+
+fn fetch(url: &str) -> Result<Vec<u8>> {
+    curl(&["--header", "Accept: application/vnd.github+json", url])
+}
+
+fn download(url: &str, path: &Path) -> Result<()> {
+    let path = path.to_string_lossy();
+    curl(&["--output", &path, url]).map(drop)
+}
+
+/// A rename over the running executable is atomic, and the old inode stays
+/// valid for the process still using it.
+#[cfg(not(windows))]
+fn swap_executable(new: &Path, exe: &Path) -> Result<()> {
+    fs::rename(new, exe).with_context(|| format!("failed to replace {}", exe.display()))
+}
+
+/// Windows cannot replace a running executable but can rename it, so the
+/// old one is moved aside (and put back if the new one fails to move in).
+#[cfg(windows)]
+fn swap_executable(new: &Path, exe: &Path) -> Result<()> {
+    let old = exe.with_extension("exe.old");
+    let _ = fs::remove_file(&old);
+    fs::rename(exe, &old).with_context(|| format!("failed to move aside {}", exe.display()))?;
+    fs::rename(new, exe).map_err(|err| {
+        let _ = fs::rename(&old, exe);
+        anyhow!(err).context(format!("failed to replace {}", exe.display()))
+    })
+}
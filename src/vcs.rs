@@ -1,23 +1,73 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ffi::OsStr;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 
 use anyhow::{Context, Result, anyhow};
+use clap::ValueEnum;
+use globset::GlobSet;
+use walkdir::WalkDir;
+
+use crate::cli::GuardMode;
+use crate::model::{MasterAction, MasterSync};
 
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
 
-const COMMIT_GUARD_HOOK: &str = r#"#!/bin/sh
-set -eu
+/// Marks hooks prompt-sync wrote; only those are upgraded or uninstalled.
+const COMMIT_GUARD_MARKER: &str = "# prompt-sync commit-guard v";
+
+/// Bumped whenever the hook text changes, so `--status` can tell an old
+/// install from a local edit.
+pub(crate) const COMMIT_GUARD_VERSION: u32 = 2;
+
+/// Runs the hook `--chain` moved aside first; with `set -e` its failure
+/// aborts the commit as it did before.
+const CHAIN_PRELUDE: &str = r#"
+# Chained by prompt-sync install-commit-guard --chain.
+chained="$(dirname "$0")/commit-msg.prompt-sync-chained"
+if [ -x "$chained" ]; then
+  "$chained" "$@"
+fi
+"#;
 
+const GIT_MESSAGE: &str = r#"
 msg_file="$1"
 if [ ! -f "$msg_file" ]; then
   exit 0
 fi
+"#;
+
+/// Mercurial's `pretxncommit` sees the commit, not a message file.
+const HG_MESSAGE: &str = r#"
+msg_file="$(mktemp)"
+trap 'rm -f "$msg_file"' EXIT
+hg log -r "$HG_NODE" --template '{desc}\n' > "$msg_file"
+"#;
+
+/// Shared by both backends; reject mode exits here.
+const REJECT_CHECK: &str = r#"
+ai='(chatgpt|claude|codex|gemini|copilot|kiro|openai|anthropic)'
+
+if [ "$mode" = reject ]; then
+  # Reject commits carrying AI attribution lines, ignoring git's comments.
+  offending="$(grep -v '^#' "$msg_file" \
+    | grep -Ei -e "^Co-authored-by:.*$ai" -e "generated with.*$ai" || true)"
+  if [ -n "$offending" ]; then
+    echo "prompt-sync commit guard: remove these AI attribution lines and commit again:" >&2
+    printf '%s\n' "$offending" | sed 's/^/  /' >&2
+    exit 1
+  fi
+  exit 0
+fi
+"#;
 
+const STRIP_BODY: &str = r#"
 # Remove AI attribution lines automatically.
 tmp_file="$(mktemp)"
-grep -Eiv '^Co-authored-by:.*(chatgpt|claude|codex|gemini|copilot|kiro|openai|anthropic)' "$msg_file" \
-  | grep -Eiv 'generated with.*(chatgpt|claude|codex|gemini|copilot|kiro|openai|anthropic)' \
+grep -Eiv "^Co-authored-by:.*$ai" "$msg_file" \
+  | grep -Eiv "generated with.*$ai" \
   > "$tmp_file" || true
 cat "$tmp_file" > "$msg_file"
 rm -f "$tmp_file"
@@ -25,25 +75,434 @@ rm -f "$tmp_file"
 exit 0
 "#;
 
+/// `.hg/hgrc` key running the Mercurial guard; hgrc allows any number of
+/// hooks per event, so no chaining is needed.
+const HG_HOOK_KEY: &str = "pretxncommit.prompt-sync";
+const HG_GUARD_FILE: &str = "prompt-sync-commit-guard";
+
+/// The version control system of a repo, detected from its metadata dir.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Vcs {
+    Git,
+    /// `colocated` when a git repo shares the work tree, whose hooks then
+    /// cover `git commit` (jj itself runs no commit hooks).
+    Jujutsu {
+        colocated: bool,
+    },
+    Mercurial,
+}
+
+/// Jujutsu first, as a colocated jj repo also has `.git`.
+pub(crate) fn detect_vcs(repo_root: &Path) -> Result<Vcs> {
+    if repo_root.join(".jj").is_dir() {
+        return Ok(Vcs::Jujutsu {
+            colocated: repo_root.join(".git").exists(),
+        });
+    }
+    if repo_root.join(".hg").is_dir() {
+        return Ok(Vcs::Mercurial);
+    }
+    if repo_root.join(".git").exists() {
+        return Ok(Vcs::Git);
+    }
+    Err(anyhow!(
+        "no .git, .jj or .hg in repo: {}",
+        repo_root.display()
+    ))
+}
+
+/// Repositories under `root`, at most `max_depth` directories down. The
+/// walk does not enter a repository it found, nor directories matching
+/// `exclude` by name or by path relative to `root`.
+pub(crate) fn scan_repositories(root: &Path, max_depth: usize, exclude: &GlobSet) -> Vec<PathBuf> {
+    let mut repos = Vec::new();
+    let mut walker = WalkDir::new(root)
+        .max_depth(max_depth)
+        .into_iter()
+        .filter_entry(|entry| {
+            entry.file_type().is_dir()
+                && !exclude.is_match(entry.file_name())
+                && entry
+                    .path()
+                    .strip_prefix(root)
+                    .is_ok_and(|rel| rel.as_os_str().is_empty() || !exclude.is_match(rel))
+        });
+    while let Some(entry) = walker.next() {
+        // Unreadable directories just hold no repositories.
+        let Ok(entry) = entry else {
+            continue;
+        };
+        if detect_vcs(entry.path()).is_ok() {
+            repos.push(entry.into_path());
+            walker.skip_current_dir();
+        }
+    }
+    repos
+}
+
+/// The backend the commit guard is installed with, failing for repos it
+/// cannot hook.
+fn guard_backend(repo_root: &Path) -> Result<Vcs> {
+    let vcs = detect_vcs(repo_root)?;
+    if vcs == (Vcs::Jujutsu { colocated: false }) {
+        return Err(anyhow!(
+            "{} is a Jujutsu repo without a colocated git repo; jj runs no commit hooks, so colocate it (jj git init --colocate) to use the guard",
+            repo_root.display()
+        ));
+    }
+    Ok(vcs)
+}
+
+/// Hooks git runs after it rewrites files in the work tree.
+const REPAIR_HOOK_NAMES: [&str; 2] = ["post-merge", "post-checkout"];
+
+/// Relinks the repo's targets after `git pull` or a checkout replaced them.
+/// `--owned-only` keeps `--force` to targets prompt-sync linked, and the hook
+/// never fails the git command.
+fn repair_hook(repo_root: &Path) -> String {
+    let prefix = format!("{}{}", repo_root.display(), std::path::MAIN_SEPARATOR);
+    format!(
+        r#"#!/bin/sh
+# Installed by prompt-sync install-repair-hooks.
+command -v prompt-sync >/dev/null 2>&1 || exit 0
+prompt-sync repair --force --owned-only --quiet --target-contains {} || true
+exit 0
+"#,
+        shell_quote(&prefix)
+    )
+}
+
+fn shell_quote(raw: &str) -> String {
+    format!("'{}'", raw.replace('\'', r"'\''"))
+}
+
+/// Where `--chain` moves a `commit-msg` hook it did not write.
+const CHAINED_SUFFIX: &str = "prompt-sync-chained";
+
+/// The installed `commit-msg` hook and, when it chains one, the hook that
+/// was there before.
+pub(crate) struct GuardHook {
+    pub(crate) hook: PathBuf,
+    pub(crate) chained: Option<PathBuf>,
+    pub(crate) vcs: Vcs,
+}
+
+/// What `install-commit-guard --status` found.
+pub(crate) struct GuardStatus {
+    pub(crate) hook: PathBuf,
+    /// Marker version of an installed guard; `None` when the hook is
+    /// missing or was not written by prompt-sync.
+    pub(crate) version: Option<u32>,
+    pub(crate) mode: Option<GuardMode>,
+    pub(crate) present: bool,
+    /// The hook differs from what this version would install.
+    pub(crate) modified: bool,
+    pub(crate) chained: Option<PathBuf>,
+}
+
+impl GuardStatus {
+    pub(crate) fn is_current(&self) -> bool {
+        self.version == Some(COMMIT_GUARD_VERSION) && !self.modified
+    }
+}
+
+fn commit_guard_hook(chain: bool, mode: GuardMode) -> String {
+    let prelude = if chain { CHAIN_PRELUDE } else { "" };
+    format!(
+        "{}{prelude}{GIT_MESSAGE}{REJECT_CHECK}{STRIP_BODY}",
+        guard_header(mode)
+    )
+}
+
+fn hg_guard_hook() -> String {
+    format!(
+        "{}{HG_MESSAGE}{REJECT_CHECK}",
+        guard_header(GuardMode::Reject)
+    )
+}
+
+fn guard_header(mode: GuardMode) -> String {
+    format!(
+        "#!/bin/sh\n{COMMIT_GUARD_MARKER}{COMMIT_GUARD_VERSION}\nset -eu\nmode={}\n",
+        mode.as_str()
+    )
+}
+
+fn guard_version(text: &str) -> Option<u32> {
+    text.lines()
+        .find_map(|line| line.strip_prefix(COMMIT_GUARD_MARKER)?.trim().parse().ok())
+}
+
+/// The `mode=` line of a guard; hooks from before modes existed strip.
+fn guard_mode(text: &str) -> Option<GuardMode> {
+    let raw = text.lines().find_map(|line| line.strip_prefix("mode="))?;
+    GuardMode::from_str(raw.trim(), false).ok()
+}
+
+fn is_commit_guard(hook_path: &Path) -> bool {
+    fs::read_to_string(hook_path).is_ok_and(|text| guard_version(&text).is_some())
+}
+
+pub(crate) fn commit_guard_status(repo_root: &Path) -> Result<GuardStatus> {
+    if guard_backend(repo_root)? == Vcs::Mercurial {
+        return hg_guard_status(repo_root);
+    }
+    let git_dir = resolve_git_dir(repo_root)?;
+    let hook_path = git_dir.join("hooks").join("commit-msg");
+    let chained_path = hook_path.with_extension(CHAINED_SUFFIX);
+    let chained = chained_path.exists().then_some(chained_path);
+    let text = read_optional(&hook_path)?;
+    let version = text.as_deref().and_then(guard_version);
+    let mode = version.map(|_| {
+        text.as_deref()
+            .and_then(guard_mode)
+            .unwrap_or(GuardMode::Strip)
+    });
+    // Older versions cannot be regenerated, so only the current one is
+    // compared byte for byte.
+    let modified = version == Some(COMMIT_GUARD_VERSION)
+        && text.as_deref()
+            != Some(commit_guard_hook(chained.is_some(), mode.unwrap_or_default()).as_str());
+    Ok(GuardStatus {
+        hook: hook_path,
+        version,
+        mode,
+        present: text.is_some(),
+        modified,
+        chained,
+    })
+}
+
+/// Installs the commit guard. With `chain`, a `commit-msg` hook it did not
+/// write is moved aside and run first instead of being refused.
 pub(crate) fn install_commit_guard(
     repo_root: &Path,
     force: bool,
+    chain: bool,
+    mode: GuardMode,
     dry_run: bool,
-) -> Result<PathBuf> {
+) -> Result<GuardHook> {
+    let vcs = guard_backend(repo_root)?;
+    if vcs == Vcs::Mercurial {
+        return install_hg_guard(repo_root, force, mode, dry_run);
+    }
     let git_dir = resolve_git_dir(repo_root)?;
     let hook_path = git_dir.join("hooks").join("commit-msg");
+    let chained_path = hook_path.with_extension(CHAINED_SUFFIX);
+    if !chain {
+        check_hook_free(&hook_path, force)?;
+        if !dry_run {
+            write_hook(&hook_path, &commit_guard_hook(false, mode))?;
+        }
+        return Ok(GuardHook {
+            hook: hook_path,
+            chained: None,
+            vcs,
+        });
+    }
 
-    if hook_path.exists() && !force {
+    let foreign = hook_path.exists() && !is_commit_guard(&hook_path);
+    if foreign && chained_path.exists() {
         return Err(anyhow!(
-            "hook already exists: {} (use --force to overwrite)",
+            "{} already holds a chained hook; run install-commit-guard --uninstall first",
+            chained_path.display()
+        ));
+    }
+    // Re-running --chain keeps chaining the hook moved aside earlier.
+    let chained = (foreign || chained_path.exists()).then_some(chained_path);
+    if !dry_run {
+        if let Some(chained_path) = chained.as_ref().filter(|_| foreign) {
+            fs::rename(&hook_path, chained_path)
+                .with_context(|| format!("failed to move hook aside: {}", hook_path.display()))?;
+        }
+        write_hook(&hook_path, &commit_guard_hook(chained.is_some(), mode))?;
+    }
+    Ok(GuardHook {
+        hook: hook_path,
+        chained,
+        vcs,
+    })
+}
+
+/// Removes the commit guard, putting back a hook `--chain` moved aside.
+pub(crate) fn uninstall_commit_guard(repo_root: &Path, dry_run: bool) -> Result<GuardHook> {
+    let vcs = guard_backend(repo_root)?;
+    if vcs == Vcs::Mercurial {
+        return uninstall_hg_guard(repo_root, dry_run);
+    }
+    let git_dir = resolve_git_dir(repo_root)?;
+    let hook_path = git_dir.join("hooks").join("commit-msg");
+    if !is_commit_guard(&hook_path) {
+        return Err(anyhow!(
+            "no commit guard installed by prompt-sync at {}",
             hook_path.display()
         ));
     }
+    let chained_path = hook_path.with_extension(CHAINED_SUFFIX);
+    let chained = chained_path.exists().then_some(chained_path);
+    if !dry_run {
+        match &chained {
+            Some(chained_path) => fs::rename(chained_path, &hook_path),
+            None => fs::remove_file(&hook_path),
+        }
+        .with_context(|| format!("failed to restore hook: {}", hook_path.display()))?;
+    }
+    Ok(GuardHook {
+        hook: hook_path,
+        chained,
+        vcs,
+    })
+}
 
-    if dry_run {
-        return Ok(hook_path);
+/// Mercurial hooks cannot rewrite a message, so its guard always rejects:
+/// a `pretxncommit` hook in `.hg/hgrc` running a script under `.hg`.
+fn install_hg_guard(
+    repo_root: &Path,
+    force: bool,
+    mode: GuardMode,
+    dry_run: bool,
+) -> Result<GuardHook> {
+    if mode != GuardMode::Reject {
+        return Err(anyhow!(
+            "Mercurial hooks cannot rewrite commit messages; use --mode reject"
+        ));
     }
+    let hg_dir = repo_root.join(".hg");
+    let script = hg_dir.join(HG_GUARD_FILE);
+    check_hook_free(&script, force)?;
+    if !dry_run {
+        write_hook(&script, &hg_guard_hook())?;
+        let hgrc = hg_dir.join("hgrc");
+        let text = read_optional(&hgrc)?.unwrap_or_default();
+        let entry = format!(
+            "{HG_HOOK_KEY} = {}",
+            shell_quote(&script.display().to_string())
+        );
+        fs::write(&hgrc, add_hgrc_hook(&remove_hgrc_hook(&text), &entry))
+            .with_context(|| format!("failed to write {}", hgrc.display()))?;
+    }
+    Ok(GuardHook {
+        hook: script,
+        chained: None,
+        vcs: Vcs::Mercurial,
+    })
+}
+
+fn uninstall_hg_guard(repo_root: &Path, dry_run: bool) -> Result<GuardHook> {
+    let hg_dir = repo_root.join(".hg");
+    let script = hg_dir.join(HG_GUARD_FILE);
+    if !is_commit_guard(&script) {
+        return Err(anyhow!(
+            "no commit guard installed by prompt-sync at {}",
+            script.display()
+        ));
+    }
+    if !dry_run {
+        let hgrc = hg_dir.join("hgrc");
+        if let Some(text) = read_optional(&hgrc)? {
+            fs::write(&hgrc, remove_hgrc_hook(&text))
+                .with_context(|| format!("failed to write {}", hgrc.display()))?;
+        }
+        fs::remove_file(&script)
+            .with_context(|| format!("failed to remove hook: {}", script.display()))?;
+    }
+    Ok(GuardHook {
+        hook: script,
+        chained: None,
+        vcs: Vcs::Mercurial,
+    })
+}
+
+/// A guard script missing from `.hg/hgrc` never runs, so that counts as
+/// modified.
+fn hg_guard_status(repo_root: &Path) -> Result<GuardStatus> {
+    let hg_dir = repo_root.join(".hg");
+    let script = hg_dir.join(HG_GUARD_FILE);
+    let text = read_optional(&script)?;
+    let version = text.as_deref().and_then(guard_version);
+    let registered = read_optional(&hg_dir.join("hgrc"))?
+        .is_some_and(|hgrc| hgrc.lines().any(is_hgrc_guard_line));
+    let modified = version == Some(COMMIT_GUARD_VERSION)
+        && (!registered || text.as_deref() != Some(hg_guard_hook().as_str()));
+    Ok(GuardStatus {
+        hook: script,
+        version,
+        mode: version.map(|_| GuardMode::Reject),
+        present: text.is_some(),
+        modified,
+        chained: None,
+    })
+}
+
+/// Inserts `entry` at the top of the `[hooks]` section, adding the section
+/// when there is none.
+fn add_hgrc_hook(text: &str, entry: &str) -> String {
+    let mut lines = text.lines().collect::<Vec<_>>();
+    match lines.iter().position(|line| line.trim() == "[hooks]") {
+        Some(index) => lines.insert(index + 1, entry),
+        None => {
+            if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+                lines.push("");
+            }
+            lines.extend(["[hooks]", entry]);
+        }
+    }
+    format!("{}\n", lines.join("\n"))
+}
+
+fn remove_hgrc_hook(text: &str) -> String {
+    text.lines()
+        .filter(|line| !is_hgrc_guard_line(line))
+        .map(|line| format!("{line}\n"))
+        .collect()
+}
+
+fn is_hgrc_guard_line(line: &str) -> bool {
+    line.split_once('=')
+        .is_some_and(|(key, _)| key.trim() == HG_HOOK_KEY)
+}
+
+fn read_optional(path: &Path) -> Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(Some(text)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).with_context(|| format!("failed to read {}", path.display())),
+    }
+}
 
+/// Installs `post-merge` and `post-checkout` hooks that repair the targets
+/// inside `repo_root`. Nothing is written when either hook already exists
+/// and `force` is off.
+pub(crate) fn install_repair_hooks(
+    repo_root: &Path,
+    force: bool,
+    dry_run: bool,
+) -> Result<Vec<PathBuf>> {
+    let git_dir = resolve_git_dir(repo_root)?;
+    let hook_paths = REPAIR_HOOK_NAMES.map(|name| git_dir.join("hooks").join(name));
+    for hook_path in &hook_paths {
+        check_hook_free(hook_path, force)?;
+    }
+    if !dry_run {
+        let hook = repair_hook(repo_root);
+        for hook_path in &hook_paths {
+            write_hook(hook_path, &hook)?;
+        }
+    }
+    Ok(hook_paths.to_vec())
+}
+
+fn check_hook_free(hook_path: &Path, force: bool) -> Result<()> {
+    if hook_path.exists() && !force {
+        return Err(anyhow!(
+            "hook already exists: {} (use --force to overwrite)",
+            hook_path.display()
+        ));
+    }
+    Ok(())
+}
+
+fn write_hook(hook_path: &Path, contents: &str) -> Result<()> {
     if let Some(parent) = hook_path.parent() {
         fs::create_dir_all(parent).with_context(|| {
             format!(
@@ -53,20 +512,139 @@ pub(crate) fn install_commit_guard(
         })?;
     }
 
-    fs::write(&hook_path, COMMIT_GUARD_HOOK)
+    fs::write(hook_path, contents)
         .with_context(|| format!("failed to write hook: {}", hook_path.display()))?;
 
     #[cfg(unix)]
     {
-        let mut permissions = fs::metadata(&hook_path)
+        let mut permissions = fs::metadata(hook_path)
             .with_context(|| format!("failed to stat hook: {}", hook_path.display()))?
             .permissions();
         permissions.set_mode(0o755);
-        fs::set_permissions(&hook_path, permissions)
+        fs::set_permissions(hook_path, permissions)
             .with_context(|| format!("failed to set executable bit: {}", hook_path.display()))?;
     }
 
-    Ok(hook_path)
+    Ok(())
+}
+
+/// Brackets the `.git/info/exclude` lines prompt-sync owns; lines outside
+/// the block are the user's and are never touched.
+const EXCLUDE_BEGIN: &str = "# >>> prompt-sync managed targets";
+const EXCLUDE_END: &str = "# <<< prompt-sync managed targets";
+
+/// Adds (`true`) or removes (`false`) each target's entry in the
+/// `.git/info/exclude` of the git repo holding it. Targets outside a git
+/// repo are ignored.
+pub(crate) fn update_git_excludes<'a>(
+    changes: impl IntoIterator<Item = (&'a Path, bool)>,
+) -> Result<()> {
+    let mut edits: BTreeMap<PathBuf, Vec<(String, bool)>> = BTreeMap::new();
+    // Skill trees put many targets in one directory; look each up once.
+    let mut repos: HashMap<PathBuf, Option<(PathBuf, PathBuf)>> = HashMap::new();
+    for (target, exclude) in changes {
+        let Some(parent) = target.parent() else {
+            continue;
+        };
+        let repo = repos
+            .entry(parent.to_path_buf())
+            .or_insert_with(|| find_git_exclude(parent));
+        let Some((repo_root, exclude_file)) = repo else {
+            continue;
+        };
+        if let Ok(rel) = target.strip_prefix(&*repo_root) {
+            edits
+                .entry(exclude_file.clone())
+                .or_default()
+                .push((exclude_pattern(rel), exclude));
+        }
+    }
+    for (exclude_file, edits) in edits {
+        edit_exclude_block(&exclude_file, &edits)?;
+    }
+    Ok(())
+}
+
+/// The nearest git repo holding `dir` and its `info/exclude`, which lives
+/// in the common dir for linked worktrees.
+fn find_git_exclude(dir: &Path) -> Option<(PathBuf, PathBuf)> {
+    let repo_root = dir.ancestors().find(|dir| dir.join(".git").exists())?;
+    let git_dir = resolve_git_dir(repo_root).ok()?;
+    let common_dir = match fs::read_to_string(git_dir.join("commondir")) {
+        Ok(raw) => git_dir.join(raw.trim()),
+        Err(_) => git_dir,
+    };
+    Some((
+        repo_root.to_path_buf(),
+        common_dir.join("info").join("exclude"),
+    ))
+}
+
+/// An anchored pattern matching exactly `rel`, with glob characters escaped.
+fn exclude_pattern(rel: &Path) -> String {
+    let mut pattern = String::from("/");
+    for (index, component) in rel.components().enumerate() {
+        if index > 0 {
+            pattern.push('/');
+        }
+        for c in component.as_os_str().to_string_lossy().chars() {
+            if matches!(c, '\\' | '*' | '?' | '[') {
+                pattern.push('\\');
+            }
+            pattern.push(c);
+        }
+    }
+    pattern
+}
+
+fn edit_exclude_block(exclude_file: &Path, edits: &[(String, bool)]) -> Result<()> {
+    let text = read_optional(exclude_file)?.unwrap_or_default();
+    let mut outside = Vec::new();
+    let mut managed = BTreeSet::new();
+    let mut in_block = false;
+    for line in text.lines() {
+        match line {
+            EXCLUDE_BEGIN => in_block = true,
+            EXCLUDE_END => in_block = false,
+            _ if in_block => {
+                managed.insert(line.to_owned());
+            }
+            _ => outside.push(line),
+        }
+    }
+    let before = managed.clone();
+    for (pattern, exclude) in edits {
+        if *exclude {
+            managed.insert(pattern.clone());
+        } else {
+            managed.remove(pattern);
+        }
+    }
+
+    if managed == before {
+        return Ok(());
+    }
+
+    let mut updated = outside.join("\n");
+    if !updated.is_empty() {
+        updated.push('\n');
+    }
+    if !managed.is_empty() {
+        updated.push_str(EXCLUDE_BEGIN);
+        updated.push('\n');
+        for pattern in &managed {
+            updated.push_str(pattern);
+            updated.push('\n');
+        }
+        updated.push_str(EXCLUDE_END);
+        updated.push('\n');
+    }
+    if let Some(parent) = exclude_file.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create directory: {}", parent.display()))?;
+    }
+    fs::write(exclude_file, updated)
+        .with_context(|| format!("failed to write {}", exclude_file.display()))
 }
 
 fn resolve_git_dir(repo_root: &Path) -> Result<PathBuf> {
@@ -101,3 +679,104 @@ fn resolve_git_dir(repo_root: &Path) -> Result<PathBuf> {
         dot_git.display()
     ))
 }
+
+/// `sync`: clones `[master] repo` into the root when it is missing or an
+/// empty directory, otherwise fast-forwards the clone. A failed pull is
+/// only a warning, so an offline machine still links the checkout it has.
+pub(crate) fn sync_master_repo(
+    repo: &str,
+    root: &Path,
+    branch: Option<&str>,
+    dry_run: bool,
+) -> Result<MasterSync> {
+    let done = |action, message| MasterSync {
+        repo: repo.to_owned(),
+        root: root.to_path_buf(),
+        action,
+        message,
+    };
+    let is_empty = match fs::read_dir(root) {
+        Ok(mut entries) => entries.next().is_none(),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => true,
+        Err(err) => {
+            return Err(err)
+                .with_context(|| format!("failed to read master root: {}", root.display()));
+        }
+    };
+    if is_empty {
+        if dry_run {
+            return Ok(done(MasterAction::WouldClone, None));
+        }
+        if let Some(parent) = root.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create directory: {}", parent.display()))?;
+        }
+        let mut args = vec![OsStr::new("clone")];
+        if let Some(branch) = branch {
+            args.extend([OsStr::new("--branch"), OsStr::new(branch)]);
+        }
+        args.extend([OsStr::new(repo), root.as_os_str()]);
+        run_git(&args)?;
+        return Ok(done(MasterAction::Cloned, None));
+    }
+    if !root.join(".git").exists() {
+        return Err(anyhow!(
+            "master root {} exists but is not a git clone; move it aside to let sync clone {repo}",
+            root.display()
+        ));
+    }
+    if dry_run {
+        return Ok(done(MasterAction::WouldPull, None));
+    }
+    let head = || {
+        run_git(&[
+            OsStr::new("-C"),
+            root.as_os_str(),
+            OsStr::new("rev-parse"),
+            OsStr::new("--short"),
+            OsStr::new("HEAD"),
+        ])
+    };
+    let before = head()?;
+    if let Err(err) = run_git(&[
+        OsStr::new("-C"),
+        root.as_os_str(),
+        OsStr::new("pull"),
+        OsStr::new("--ff-only"),
+    ]) {
+        eprintln!("warn: {err:#}; linking the current checkout");
+        return Ok(done(MasterAction::PullFailed, Some(format!("{err:#}"))));
+    }
+    let after = head()?;
+    Ok(if before == after {
+        done(MasterAction::UpToDate, Some(after))
+    } else {
+        done(MasterAction::Pulled, Some(format!("{before}..{after}")))
+    })
+}
+
+/// Runs git without a terminal prompt and returns its trimmed stdout; its
+/// stderr becomes the error.
+fn run_git(args: &[&OsStr]) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|err| match err.kind() {
+            std::io::ErrorKind::NotFound => anyhow!("sync needs git on PATH"),
+            _ => anyhow!(err).context("failed to run git"),
+        })?;
+    if !output.status.success() {
+        let command = args
+            .iter()
+            .map(|arg| arg.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join(" ");
+        return Err(anyhow!(
+            "`git {command}` failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
@@ -0,0 +1,195 @@
+//! The vendor registry behind `--profile`: each tool's instruction files,
+//! skills directories and the path names that identify it. The built-in
+//! `vendors.toml` is extended, or overridden field by field, by a
+//! `vendors.toml` next to the user config.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::config::user_config_path;
+use crate::model::ResolveContext;
+use crate::pathing::resolve_path;
+
+const BUILT_IN: &str = include_str!("vendors.toml");
+
+/// One `[[vendor]]` table; fields left out keep the built-in value.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VendorEntry {
+    name: String,
+    default: Option<bool>,
+    instructions: Option<Vec<String>>,
+    legacy: Option<Vec<String>>,
+    skills: Option<Vec<String>>,
+    names: Option<Vec<String>>,
+    frontmatter: Option<serde_json::Map<String, serde_json::Value>>,
+    detect_paths: Option<Vec<String>>,
+    detect_commands: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VendorFile {
+    #[serde(default)]
+    vendor: Vec<VendorEntry>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Vendor {
+    pub(crate) name: String,
+    /// Part of `init`'s selection when no `--profile` is given.
+    pub(crate) default: bool,
+    /// Instruction files the tool reads; `init` links the first and
+    /// `bootstrap` all of them.
+    pub(crate) instructions: Vec<String>,
+    /// Older locations `init --from-existing` still picks up.
+    pub(crate) legacy: Vec<String>,
+    /// Skills directories; `init` links the first and `bootstrap` all.
+    pub(crate) skills: Vec<String>,
+    /// Path components that tell a target is for the tool.
+    pub(crate) names: Vec<String>,
+    /// Frontmatter keys generated targets get unless set otherwise.
+    pub(crate) frontmatter: serde_json::Map<String, serde_json::Value>,
+    /// Paths, one of which exists when the tool is installed.
+    pub(crate) detect_paths: Vec<String>,
+    /// Commands, one of which is on `PATH` when the tool is installed.
+    pub(crate) detect_commands: Vec<String>,
+}
+
+impl Vendor {
+    /// Whether one of the tool's `detect_paths` exists or `detect_commands`
+    /// is on `PATH`. A vendor that lists neither cannot be told apart and
+    /// counts as installed.
+    pub(crate) fn is_installed(&self, ctx: &ResolveContext) -> bool {
+        if self.detect_paths.is_empty() && self.detect_commands.is_empty() {
+            return true;
+        }
+        self.detect_paths
+            .iter()
+            .any(|raw| resolve_path(raw, ctx).exists())
+            || self
+                .detect_commands
+                .iter()
+                .any(|command| is_on_path(command))
+    }
+}
+
+/// The built-in vendors with the user's `vendors.toml` applied. A user file
+/// that cannot be read or parsed is skipped with a warning.
+pub(crate) fn vendors() -> &'static [Vendor] {
+    static VENDORS: OnceLock<Vec<Vendor>> = OnceLock::new();
+    VENDORS.get_or_init(|| {
+        let mut vendors = Vec::new();
+        apply(
+            &mut vendors,
+            parse(BUILT_IN).expect("built-in vendors.toml is valid"),
+        );
+        if let Some(path) = user_vendors_path() {
+            match fs::read_to_string(&path) {
+                Ok(text) => match parse(&text)
+                    .with_context(|| format!("invalid vendors file: {}", path.display()))
+                {
+                    Ok(entries) => apply(&mut vendors, entries),
+                    Err(err) => eprintln!("warn: {err:#}"),
+                },
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => eprintln!("warn: failed to read {}: {err}", path.display()),
+            }
+        }
+        vendors
+    })
+}
+
+pub(crate) fn vendor(name: &str) -> Option<&'static Vendor> {
+    vendors().iter().find(|vendor| vendor.name == name)
+}
+
+/// `vendors.toml` beside the user config.
+pub(crate) fn user_vendors_path() -> Option<PathBuf> {
+    Some(user_config_path()?.with_file_name("vendors.toml"))
+}
+
+fn parse(text: &str) -> Result<Vec<VendorEntry>> {
+    Ok(toml::from_str::<VendorFile>(text)?.vendor)
+}
+
+/// Adds new vendors after the known ones; an entry for a known vendor
+/// replaces just the fields it sets.
+fn apply(vendors: &mut Vec<Vendor>, entries: Vec<VendorEntry>) {
+    for entry in entries {
+        let index = match vendors.iter().position(|vendor| vendor.name == entry.name) {
+            Some(index) => index,
+            None => {
+                vendors.push(Vendor {
+                    name: entry.name.clone(),
+                    ..Vendor::default()
+                });
+                vendors.len() - 1
+            }
+        };
+        let vendor = &mut vendors[index];
+        if let Some(default) = entry.default {
+            vendor.default = default;
+        }
+        if let Some(instructions) = entry.instructions {
+            vendor.instructions = instructions;
+        }
+        if let Some(legacy) = entry.legacy {
+            vendor.legacy = legacy;
+        }
+        if let Some(skills) = entry.skills {
+            vendor.skills = skills;
+        }
+        if let Some(names) = entry.names {
+            vendor.names = names;
+        }
+        if let Some(frontmatter) = entry.frontmatter {
+            vendor.frontmatter = frontmatter;
+        }
+        if let Some(detect_paths) = entry.detect_paths {
+            vendor.detect_paths = detect_paths;
+        }
+        if let Some(detect_commands) = entry.detect_commands {
+            vendor.detect_commands = detect_commands;
+        }
+    }
+}
+
+/// Whether an executable named `command` is in a `PATH` directory, with one
+/// of the `PATHEXT` extensions on Windows.
+fn is_on_path(command: &str) -> bool {
+    let Some(path) = env::var_os("PATH") else {
+        return false;
+    };
+    let extensions = if cfg!(windows) {
+        env::var("PATHEXT")
+            .unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_owned())
+            .split(';')
+            .filter(|extension| !extension.is_empty())
+            .map(str::to_owned)
+            .collect()
+    } else {
+        vec![String::new()]
+    };
+    env::split_paths(&path).any(|dir| {
+        extensions
+            .iter()
+            .any(|extension| is_executable(&dir.join(format!("{command}{extension}"))))
+    })
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
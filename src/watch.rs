@@ -0,0 +1,200 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::SystemTime;
+
+use anyhow::{Context, Result, anyhow};
+use walkdir::WalkDir;
+
+use crate::config::{ConfigFile, LoadedConfig};
+use crate::engine::{expand_source_glob, is_glob_pattern};
+use crate::model::{Record, ResolveContext, Status};
+use crate::pathing::{home_dir, resolve_path};
+use crate::remote::is_url;
+use crate::render::fragments;
+
+/// Cheap change marker for a watched path. Editors that save via rename
+/// produce a new mtime even when the size is unchanged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Stamp {
+    len: u64,
+    modified: Option<SystemTime>,
+    is_dir: bool,
+}
+
+pub(crate) type Snapshot = BTreeMap<PathBuf, Stamp>;
+
+pub(crate) fn snapshot(config_path: &Path, config: &ConfigFile, ctx: &ResolveContext) -> Snapshot {
+    let mut snapshot = Snapshot::new();
+    record_path(&mut snapshot, config_path);
+
+    for rule in &config.links {
+        // A URL source is refetched by each pass anyway.
+        if is_url(&rule.source) {
+            continue;
+        }
+        let source = resolve_path(&rule.source, ctx);
+        if is_glob_pattern(&rule.source) {
+            for (matched, _) in expand_source_glob(&source).unwrap_or_default() {
+                record_path(&mut snapshot, &matched);
+            }
+        } else {
+            record_path(&mut snapshot, &source);
+            // Includes count too, so editing a fragment regenerates.
+            for fragment in fragments(&source) {
+                record_path(&mut snapshot, &fragment);
+            }
+        }
+    }
+
+    for set in &config.skills_sets {
+        let source_root = resolve_path(&set.source_root, ctx);
+        for entry in WalkDir::new(&source_root).into_iter().flatten() {
+            record_path(&mut snapshot, entry.path());
+        }
+    }
+
+    snapshot
+}
+
+fn record_path(snapshot: &mut Snapshot, path: &Path) {
+    if let Ok(meta) = fs::symlink_metadata(path) {
+        snapshot.insert(
+            path.to_path_buf(),
+            Stamp {
+                len: meta.len(),
+                modified: meta.modified().ok(),
+                is_dir: meta.is_dir(),
+            },
+        );
+    }
+}
+
+/// Targets a notification names before it says how many more there are.
+const NOTIFY_LIST_LIMIT: usize = 3;
+
+/// Desktop notifications for `[watch] notify`: each `watch` or `daemon`
+/// pass that repaired a target, or found one it could not repair, sends
+/// one. A target that stays unrepaired is announced only once.
+#[derive(Debug, Default)]
+pub(crate) struct Notifier {
+    unrepaired: HashSet<PathBuf>,
+    warned: bool,
+}
+
+impl Notifier {
+    pub(crate) fn pass(&mut self, configs: &[LoadedConfig], records: &[Record]) {
+        let enabled = configs
+            .iter()
+            .rev()
+            .find_map(|loaded| loaded.config.watch.as_ref()?.notify)
+            .unwrap_or(false);
+        let repaired = records
+            .iter()
+            .filter(|record| matches!(record.status, Status::Created | Status::Replaced))
+            .collect::<Vec<_>>();
+        let unrepaired = records
+            .iter()
+            .filter(|record| {
+                matches!(
+                    record.status,
+                    Status::Missing | Status::Broken | Status::Conflict | Status::Error
+                )
+            })
+            .collect::<Vec<_>>();
+        let new = unrepaired
+            .iter()
+            .filter(|record| !self.unrepaired.contains(&record.target))
+            .copied()
+            .collect::<Vec<_>>();
+        self.unrepaired = unrepaired
+            .iter()
+            .map(|record| record.target.clone())
+            .collect();
+        if !enabled || (repaired.is_empty() && new.is_empty()) {
+            return;
+        }
+
+        let title = format!(
+            "prompt-sync: {} target(s) drifted",
+            repaired.len() + new.len()
+        );
+        let mut lines = Vec::new();
+        if !repaired.is_empty() {
+            lines.push(format!("repaired: {}", list_targets(&repaired)));
+        }
+        if !new.is_empty() {
+            lines.push(format!("not repaired: {}", list_targets(&new)));
+        }
+        // Warned about once; a missing notifier would otherwise repeat it
+        // on every pass.
+        if let Err(err) = send_notification(&title, &lines.join("\n"))
+            && !self.warned
+        {
+            self.warned = true;
+            eprintln!("warn: failed to send notification: {err:#}");
+        }
+    }
+}
+
+fn list_targets(records: &[&Record]) -> String {
+    let home = home_dir();
+    let mut names = records
+        .iter()
+        .take(NOTIFY_LIST_LIMIT)
+        .map(|record| {
+            match home
+                .as_ref()
+                .and_then(|home| record.target.strip_prefix(home).ok())
+            {
+                Some(rest) => format!("~/{}", rest.display()),
+                None => record.target.display().to_string(),
+            }
+        })
+        .collect::<Vec<_>>();
+    if records.len() > NOTIFY_LIST_LIMIT {
+        names.push(format!("{} more", records.len() - NOTIFY_LIST_LIMIT));
+    }
+    names.join(", ")
+}
+
+#[cfg(target_os = "macos")]
+fn notification_command(title: &str, body: &str) -> Result<Command> {
+    let quote = |text: &str| format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""));
+    let mut command = Command::new("osascript");
+    command.arg("-e").arg(format!(
+        "display notification {} with title {}",
+        quote(body),
+        quote(title)
+    ));
+    Ok(command)
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn notification_command(title: &str, body: &str) -> Result<Command> {
+    let mut command = Command::new("notify-send");
+    command.args(["--app-name", "prompt-sync", title, body]);
+    Ok(command)
+}
+
+#[cfg(not(unix))]
+fn notification_command(_title: &str, _body: &str) -> Result<Command> {
+    Err(anyhow!("desktop notifications need Linux or macOS"))
+}
+
+fn send_notification(title: &str, body: &str) -> Result<()> {
+    let mut command = notification_command(title, body)?;
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command
+        .output()
+        .with_context(|| format!("failed to run {program}"))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{program} exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
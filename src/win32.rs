@@ -0,0 +1,165 @@
+//! Minimal Win32 bindings for what `std` does not expose on stable.
+
+use std::ffi::{OsString, c_void};
+use std::fs::OpenOptions;
+use std::io;
+use std::os::windows::ffi::{OsStrExt, OsStringExt};
+use std::os::windows::fs::OpenOptionsExt;
+use std::os::windows::io::AsRawHandle;
+use std::path::{Path, PathBuf};
+
+const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x0200_0000;
+const FILE_FLAG_OPEN_REPARSE_POINT: u32 = 0x0020_0000;
+
+#[repr(C)]
+#[allow(dead_code)]
+struct FileTime {
+    low: u32,
+    high: u32,
+}
+
+/// `BY_HANDLE_FILE_INFORMATION`; only some fields are read.
+#[repr(C)]
+#[allow(dead_code)]
+struct ByHandleFileInformation {
+    file_attributes: u32,
+    creation_time: FileTime,
+    last_access_time: FileTime,
+    last_write_time: FileTime,
+    volume_serial_number: u32,
+    file_size_high: u32,
+    file_size_low: u32,
+    number_of_links: u32,
+    file_index_high: u32,
+    file_index_low: u32,
+}
+
+#[link(name = "kernel32")]
+unsafe extern "system" {
+    fn GetFileInformationByHandle(file: *mut c_void, info: *mut ByHandleFileInformation) -> i32;
+    fn GetDiskFreeSpaceExW(
+        directory: *const u16,
+        free_to_caller: *mut u64,
+        total: *mut u64,
+        total_free: *mut u64,
+    ) -> i32;
+}
+
+#[repr(C)]
+struct Guid {
+    data1: u32,
+    data2: u16,
+    data3: u16,
+    data4: [u8; 8],
+}
+
+/// `FOLDERID_Profile`, the user's profile directory.
+const FOLDERID_PROFILE: Guid = Guid {
+    data1: 0x5E6C_858F,
+    data2: 0x0E22,
+    data3: 0x4760,
+    data4: [0x9A, 0xFE, 0xEA, 0x33, 0x17, 0xB6, 0x71, 0x73],
+};
+
+#[link(name = "shell32")]
+unsafe extern "system" {
+    fn SHGetKnownFolderPath(
+        id: *const Guid,
+        flags: u32,
+        token: *mut c_void,
+        path: *mut *mut u16,
+    ) -> i32;
+}
+
+#[link(name = "ole32")]
+unsafe extern "system" {
+    fn CoTaskMemFree(memory: *mut c_void);
+}
+
+/// Volume serial, file index and link count of a file, the Windows
+/// equivalent of `(dev, ino)` and `nlink`.
+pub(crate) struct FileInfo {
+    pub(crate) volume_serial: u64,
+    pub(crate) file_index: u64,
+    pub(crate) links: u64,
+}
+
+/// Reads `FileInfo` for `path`. With `no_follow`, a symlink itself is
+/// inspected rather than the file it points to.
+pub(crate) fn file_info(path: &Path, no_follow: bool) -> Option<FileInfo> {
+    let mut flags = FILE_FLAG_BACKUP_SEMANTICS;
+    if no_follow {
+        flags |= FILE_FLAG_OPEN_REPARSE_POINT;
+    }
+    // Access mode 0 only queries metadata, so files opened elsewhere still work.
+    let file = OpenOptions::new()
+        .access_mode(0)
+        .custom_flags(flags)
+        .open(path)
+        .ok()?;
+
+    let mut info = std::mem::MaybeUninit::<ByHandleFileInformation>::zeroed();
+    // SAFETY: the handle is valid for the lifetime of `file` and `info`
+    // points to a writable buffer of the expected layout.
+    let info = unsafe {
+        if GetFileInformationByHandle(file.as_raw_handle(), info.as_mut_ptr()) == 0 {
+            return None;
+        }
+        info.assume_init()
+    };
+
+    Some(FileInfo {
+        volume_serial: u64::from(info.volume_serial_number),
+        file_index: (u64::from(info.file_index_high) << 32) | u64::from(info.file_index_low),
+        links: u64::from(info.number_of_links),
+    })
+}
+
+/// Bytes available to the current user on the volume holding `dir`.
+pub(crate) fn available_space(dir: &Path) -> io::Result<u64> {
+    let wide = dir
+        .as_os_str()
+        .encode_wide()
+        .chain(std::iter::once(0))
+        .collect::<Vec<_>>();
+    let mut free_to_caller = 0u64;
+    // SAFETY: `wide` is NUL-terminated and the out pointers are valid; the
+    // totals are optional and may be null.
+    let ok = unsafe {
+        GetDiskFreeSpaceExW(
+            wide.as_ptr(),
+            &mut free_to_caller,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    };
+    if ok == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(free_to_caller)
+}
+
+/// The profile directory from the shell's known folders, for when neither
+/// `HOME` nor `USERPROFILE` is set.
+pub(crate) fn profile_dir() -> Option<PathBuf> {
+    let mut raw: *mut u16 = std::ptr::null_mut();
+    // SAFETY: the GUID and out pointer are valid; a null token means the
+    // current user.
+    let result =
+        unsafe { SHGetKnownFolderPath(&FOLDERID_PROFILE, 0, std::ptr::null_mut(), &mut raw) };
+    let path = (result == 0 && !raw.is_null()).then(|| {
+        let mut len = 0;
+        // SAFETY: on success `raw` is a NUL-terminated wide string.
+        let wide = unsafe {
+            while *raw.add(len) != 0 {
+                len += 1;
+            }
+            std::slice::from_raw_parts(raw, len)
+        };
+        PathBuf::from(OsString::from_wide(wide))
+    });
+    // SAFETY: the buffer must be freed whether or not the call succeeded;
+    // freeing null is a no-op.
+    unsafe { CoTaskMemFree(raw.cast()) };
+    path
+}
@@ -0,0 +1,513 @@
+//! Minimal YAML reader/writer for config files.
+//!
+//! Supports the subset config files need: block mappings and sequences,
+//! flow `[...]` / `{...}` collections on a single line, plain, single- and
+//! double-quoted scalars, and `#` comments. Anchors, tags, multi-document
+//! streams and block scalars (`|`, `>`) are rejected.
+
+use anyhow::{Result, anyhow, bail};
+use serde_json::{Map, Number, Value};
+
+pub(crate) fn from_str(text: &str) -> Result<Value> {
+    let mut lines = Vec::new();
+    for (index, raw) in text.lines().enumerate() {
+        let content = strip_comment(raw).trim_end();
+        if content.trim().is_empty() || (lines.is_empty() && content == "---") {
+            continue;
+        }
+        if raw.starts_with('\t') {
+            bail!("line {}: tabs are not allowed for indentation", index + 1);
+        }
+        let indent = content.len() - content.trim_start().len();
+        lines.push(Line {
+            number: index + 1,
+            indent,
+            text: content.trim_start().to_owned(),
+        });
+    }
+
+    if lines.is_empty() {
+        return Ok(Value::Object(Map::new()));
+    }
+    let mut parser = Parser { lines, pos: 0 };
+    let indent = parser.lines[0].indent;
+    let value = parser.parse_block(indent)?;
+    if let Some(line) = parser.lines.get(parser.pos) {
+        bail!("line {}: unexpected indentation", line.number);
+    }
+    Ok(value)
+}
+
+pub(crate) fn to_string(value: &Value) -> String {
+    let mut out = String::new();
+    match value {
+        Value::Object(map) if !map.is_empty() => write_mapping(&mut out, map, 0),
+        Value::Array(items) if !items.is_empty() => write_sequence(&mut out, items, 0),
+        other => {
+            out.push_str(&scalar(other));
+            out.push('\n');
+        }
+    }
+    out
+}
+
+struct Line {
+    number: usize,
+    indent: usize,
+    text: String,
+}
+
+struct Parser {
+    lines: Vec<Line>,
+    pos: usize,
+}
+
+impl Parser {
+    fn parse_block(&mut self, indent: usize) -> Result<Value> {
+        let line = &self.lines[self.pos];
+        if is_sequence_item(&line.text) {
+            self.parse_sequence(indent)
+        } else {
+            self.parse_mapping(indent)
+        }
+    }
+
+    fn parse_sequence(&mut self, indent: usize) -> Result<Value> {
+        let mut items = Vec::new();
+        while let Some(line) = self.lines.get(self.pos) {
+            if line.indent != indent || !is_sequence_item(&line.text) {
+                break;
+            }
+            let number = line.number;
+            let rest = line.text[1..].trim_start().to_owned();
+            if rest.is_empty() {
+                self.pos += 1;
+                items.push(self.parse_nested(indent)?);
+                continue;
+            }
+
+            // `- key: value` opens a mapping whose keys sit after the dash.
+            let offset = line.text.len() - rest.len();
+            let line = &mut self.lines[self.pos];
+            line.indent += offset;
+            line.text = rest;
+            if is_sequence_item(&line.text) || split_key(&line.text).is_some() {
+                let inner = line.indent;
+                items.push(self.parse_block(inner)?);
+            } else {
+                let value = parse_inline(&line.text, number)?;
+                self.pos += 1;
+                items.push(value);
+            }
+        }
+        Ok(Value::Array(items))
+    }
+
+    fn parse_mapping(&mut self, indent: usize) -> Result<Value> {
+        let mut map = Map::new();
+        while let Some(line) = self.lines.get(self.pos) {
+            if line.indent != indent || is_sequence_item(&line.text) {
+                break;
+            }
+            let number = line.number;
+            let (key, rest) = split_key(&line.text)
+                .ok_or_else(|| anyhow!("line {number}: expected `key: value`"))?;
+            let key = match parse_inline(&key, number)? {
+                Value::String(key) => key,
+                other => other.to_string(),
+            };
+            self.pos += 1;
+
+            let value = if rest.is_empty() {
+                match self.lines.get(self.pos) {
+                    // A sequence may sit at the same indentation as its key.
+                    Some(next) if next.indent == indent && is_sequence_item(&next.text) => {
+                        self.parse_sequence(indent)?
+                    }
+                    _ => self.parse_nested(indent)?,
+                }
+            } else {
+                parse_inline(&rest, number)?
+            };
+            if map.insert(key.clone(), value).is_some() {
+                bail!("line {number}: duplicate key `{key}`");
+            }
+        }
+        Ok(Value::Object(map))
+    }
+
+    fn parse_nested(&mut self, parent_indent: usize) -> Result<Value> {
+        match self.lines.get(self.pos) {
+            Some(next) if next.indent > parent_indent => {
+                let indent = next.indent;
+                self.parse_block(indent)
+            }
+            _ => Ok(Value::Null),
+        }
+    }
+}
+
+fn is_sequence_item(text: &str) -> bool {
+    text == "-" || text.starts_with("- ")
+}
+
+/// Splits `key: rest` at the first `:` that is followed by whitespace or the
+/// end of the line and is not inside quotes or brackets.
+fn split_key(text: &str) -> Option<(String, String)> {
+    if text.starts_with(['[', '{']) {
+        return None;
+    }
+    let quoted = quoted_mask(text);
+    let chars = text.char_indices().collect::<Vec<_>>();
+    for (i, &(at, ch)) in chars.iter().enumerate() {
+        if ch == ':' && !quoted[i] {
+            let next = chars.get(i + 1).map(|&(_, next)| next);
+            if next.is_none_or(char::is_whitespace) {
+                let key = text[..at].trim_end().to_owned();
+                let rest = text[at + 1..].trim().to_owned();
+                return (!key.is_empty()).then_some((key, rest));
+            }
+        }
+    }
+    None
+}
+
+fn strip_comment(raw: &str) -> &str {
+    let quoted = quoted_mask(raw);
+    let mut previous = ' ';
+    for (i, (at, ch)) in raw.char_indices().enumerate() {
+        if ch == '#' && !quoted[i] && previous.is_whitespace() {
+            return &raw[..at];
+        }
+        previous = ch;
+    }
+    raw
+}
+
+/// For each character of `text`, whether it is part of a quoted scalar,
+/// quotes included. A quote opens one only where a scalar can begin: at
+/// the start, after `[`, `{` or `,`, or after `: ` or `- `; elsewhere, as
+/// in `it's.md`, it is a plain character. Inside, `\` escapes in double
+/// quotes and `''` in single quotes are followed as `Flow` reads them.
+fn quoted_mask(text: &str) -> Vec<bool> {
+    let chars = text.chars().collect::<Vec<_>>();
+    let mut quoted = vec![false; chars.len()];
+    let mut quote = None;
+    // The last character outside quotes that is not whitespace, and
+    // whether whitespace followed it.
+    let mut last = None;
+    let mut spaced = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let ch = chars[i];
+        match quote {
+            Some(q) => {
+                quoted[i] = true;
+                if q == '"' && ch == '\\' {
+                    if let Some(escaped) = quoted.get_mut(i + 1) {
+                        *escaped = true;
+                    }
+                    i += 1;
+                } else if q == '\'' && ch == q && chars.get(i + 1) == Some(&q) {
+                    quoted[i + 1] = true;
+                    i += 1;
+                } else if ch == q {
+                    quote = None;
+                    last = Some(ch);
+                    spaced = false;
+                }
+            }
+            None if ch.is_whitespace() => spaced = true,
+            None => {
+                let opens = match last {
+                    None | Some('[' | '{' | ',') => true,
+                    Some(':' | '-') => spaced,
+                    Some(_) => false,
+                };
+                if opens && (ch == '"' || ch == '\'') {
+                    quote = Some(ch);
+                    quoted[i] = true;
+                }
+                last = Some(ch);
+                spaced = false;
+            }
+        }
+        i += 1;
+    }
+    quoted
+}
+
+fn parse_inline(text: &str, number: usize) -> Result<Value> {
+    let mut flow = Flow {
+        chars: text.chars().collect(),
+        pos: 0,
+        number,
+    };
+    let value = flow.value(false)?;
+    flow.skip_spaces();
+    if flow.pos != flow.chars.len() {
+        bail!("line {number}: unexpected trailing characters");
+    }
+    Ok(value)
+}
+
+struct Flow {
+    chars: Vec<char>,
+    pos: usize,
+    number: usize,
+}
+
+impl Flow {
+    fn value(&mut self, in_flow: bool) -> Result<Value> {
+        self.skip_spaces();
+        match self.chars.get(self.pos) {
+            Some('[') => self.sequence(),
+            Some('{') => self.mapping(),
+            Some('"') => self.double_quoted().map(Value::String),
+            Some('\'') => self.single_quoted().map(Value::String),
+            Some('&' | '*' | '!' | '|' | '>') => Err(anyhow!(
+                "line {}: anchors, tags and block scalars are not supported",
+                self.number
+            )),
+            _ => {
+                let start = self.pos;
+                while let Some(&ch) = self.chars.get(self.pos) {
+                    let ends_key = ch == ':'
+                        && self
+                            .chars
+                            .get(self.pos + 1)
+                            .is_none_or(|next| next.is_whitespace() || ",]}".contains(*next));
+                    if in_flow && (matches!(ch, ',' | ']' | '}') || ends_key) {
+                        break;
+                    }
+                    self.pos += 1;
+                }
+                let plain = self.chars[start..self.pos].iter().collect::<String>();
+                Ok(plain_scalar(plain.trim()))
+            }
+        }
+    }
+
+    fn sequence(&mut self) -> Result<Value> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        loop {
+            self.skip_spaces();
+            if self.eat(']') {
+                return Ok(Value::Array(items));
+            }
+            items.push(self.value(true)?);
+            self.skip_spaces();
+            if !self.eat(',') && self.chars.get(self.pos) != Some(&']') {
+                bail!("line {}: expected `,` or `]`", self.number);
+            }
+        }
+    }
+
+    fn mapping(&mut self) -> Result<Value> {
+        self.pos += 1;
+        let mut map = Map::new();
+        loop {
+            self.skip_spaces();
+            if self.eat('}') {
+                return Ok(Value::Object(map));
+            }
+            let key = match self.value(true)? {
+                Value::String(key) => key,
+                other => other.to_string(),
+            };
+            self.skip_spaces();
+            if !self.eat(':') {
+                bail!("line {}: expected `:` after `{key}`", self.number);
+            }
+            let value = self.value(true)?;
+            map.insert(key, value);
+            self.skip_spaces();
+            if !self.eat(',') && self.chars.get(self.pos) != Some(&'}') {
+                bail!("line {}: expected `,` or `}}`", self.number);
+            }
+        }
+    }
+
+    fn double_quoted(&mut self) -> Result<String> {
+        self.pos += 1;
+        let mut out = String::new();
+        while let Some(&ch) = self.chars.get(self.pos) {
+            self.pos += 1;
+            match ch {
+                '"' => return Ok(out),
+                '\\' => {
+                    let escaped = self.chars.get(self.pos).copied();
+                    self.pos += 1;
+                    out.push(match escaped {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('0') => '\0',
+                        Some(other @ ('"' | '\\' | '/')) => other,
+                        Some('u') => self.unicode_escape()?,
+                        _ => bail!("line {}: unsupported escape sequence", self.number),
+                    });
+                }
+                other => out.push(other),
+            }
+        }
+        Err(anyhow!(
+            "line {}: unterminated double-quoted string",
+            self.number
+        ))
+    }
+
+    fn unicode_escape(&mut self) -> Result<char> {
+        let digits = self
+            .chars
+            .get(self.pos..self.pos + 4)
+            .map(|digits| digits.iter().collect::<String>());
+        self.pos += 4;
+        digits
+            .and_then(|digits| u32::from_str_radix(&digits, 16).ok())
+            .and_then(char::from_u32)
+            .ok_or_else(|| anyhow!("line {}: invalid \\u escape", self.number))
+    }
+
+    fn single_quoted(&mut self) -> Result<String> {
+        self.pos += 1;
+        let mut out = String::new();
+        while let Some(&ch) = self.chars.get(self.pos) {
+            self.pos += 1;
+            if ch == '\'' {
+                if self.eat('\'') {
+                    out.push('\'');
+                    continue;
+                }
+                return Ok(out);
+            }
+            out.push(ch);
+        }
+        Err(anyhow!(
+            "line {}: unterminated single-quoted string",
+            self.number
+        ))
+    }
+
+    fn skip_spaces(&mut self) {
+        while self
+            .chars
+            .get(self.pos)
+            .is_some_and(|ch| ch.is_whitespace())
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        let matched = self.chars.get(self.pos) == Some(&expected);
+        if matched {
+            self.pos += 1;
+        }
+        matched
+    }
+}
+
+fn plain_scalar(text: &str) -> Value {
+    match text {
+        "" | "~" | "null" | "Null" | "NULL" => Value::Null,
+        "true" | "True" | "TRUE" => Value::Bool(true),
+        "false" | "False" | "FALSE" => Value::Bool(false),
+        _ => {
+            if let Ok(int) = text.parse::<i64>() {
+                return Value::Number(int.into());
+            }
+            if text.contains(['.', 'e', 'E'])
+                && let Some(number) = text.parse::<f64>().ok().and_then(Number::from_f64)
+            {
+                return Value::Number(number);
+            }
+            Value::String(text.to_owned())
+        }
+    }
+}
+
+fn write_mapping(out: &mut String, map: &Map<String, Value>, indent: usize) {
+    for (key, value) in map {
+        push_indent(out, indent);
+        out.push_str(&quote_if_needed(key));
+        out.push(':');
+        write_child(out, value, indent);
+    }
+}
+
+fn write_sequence(out: &mut String, items: &[Value], indent: usize) {
+    for item in items {
+        push_indent(out, indent);
+        out.push('-');
+        match item {
+            Value::Object(map) if !map.is_empty() => {
+                // First key shares the dash line; the rest align under it.
+                let mut entries = map.iter();
+                if let Some((key, value)) = entries.next() {
+                    out.push(' ');
+                    out.push_str(&quote_if_needed(key));
+                    out.push(':');
+                    write_child(out, value, indent + 2);
+                }
+                for (key, value) in entries {
+                    push_indent(out, indent + 2);
+                    out.push_str(&quote_if_needed(key));
+                    out.push(':');
+                    write_child(out, value, indent + 2);
+                }
+            }
+            other => write_child(out, other, indent),
+        }
+    }
+}
+
+/// Writes the value following a `key:` or `-`, nesting collections below.
+fn write_child(out: &mut String, value: &Value, indent: usize) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            out.push('\n');
+            write_mapping(out, map, indent + 2);
+        }
+        Value::Array(items) if !items.is_empty() => {
+            out.push('\n');
+            write_sequence(out, items, indent + 2);
+        }
+        other => {
+            out.push(' ');
+            out.push_str(&scalar(other));
+            out.push('\n');
+        }
+    }
+}
+
+fn scalar(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_owned(),
+        Value::Bool(flag) => flag.to_string(),
+        Value::Number(number) => number.to_string(),
+        Value::String(text) => quote_if_needed(text),
+        Value::Array(_) => "[]".to_owned(),
+        Value::Object(_) => "{}".to_owned(),
+    }
+}
+
+fn quote_if_needed(text: &str) -> String {
+    let plain_safe = !text.is_empty()
+        && text.trim() == text
+        && !text.starts_with(|ch: char| !(ch.is_alphanumeric() || matches!(ch, '.' | '/' | '_')))
+        && !text.contains(": ")
+        && !text.contains(" #")
+        && !text.ends_with(':')
+        && !text.chars().any(char::is_control)
+        && plain_scalar(text) == Value::String(text.to_owned());
+    if plain_safe {
+        return text.to_owned();
+    }
+    serde_json::to_string(text).unwrap_or_else(|_| format!("\"{text}\""))
+}
+
+fn push_indent(out: &mut String, indent: usize) {
+    out.extend(std::iter::repeat_n(' ', indent));
+}
@@ -1,9 +1,13 @@
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
+use clap::Parser;
 use tempfile::TempDir;
 
-use prompt_sync::{Cli, Command, run};
+use prompt_sync::{
+    Cli, Command, ConfigCommand, ConfigFormat, GuardMode, KindFilter, LinkType, MappingArgs,
+    OutputArgs, Profile, run,
+};
 
 #[cfg(unix)]
 use std::os::unix::fs::MetadataExt;
@@ -22,22 +26,45 @@ fn link_then_verify_success() -> anyhow::Result<()> {
     write_config(temp.path(), &source, &target)?;
 
     let link_code = run(Cli {
-        config: temp.path().join("prompt-sync.toml"),
-        verbose: false,
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
         command: Command::Link {
             only_missing: false,
             force: false,
+            owned_only: false,
             dry_run: false,
-            json: false,
+            atomic: false,
+            output: OutputArgs::default(),
             backup_dir: None,
+            mapping: MappingArgs::default(),
+            prune: false,
         },
     })?;
     assert_eq!(link_code, 0);
 
     let verify_code = run(Cli {
-        config: temp.path().join("prompt-sync.toml"),
-        verbose: false,
-        command: Command::Verify { json: false },
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
+        command: Command::Verify {
+            hash: false,
+            fix: false,
+            force: false,
+            backup_dir: None,
+            baseline: None,
+            update_baseline: false,
+            output: OutputArgs::default(),
+            mapping: MappingArgs::default(),
+        },
     })?;
     assert_eq!(verify_code, 0);
 
@@ -62,9 +89,23 @@ fn verify_missing_returns_one() -> anyhow::Result<()> {
     write_config(temp.path(), &source, &target)?;
 
     let verify_code = run(Cli {
-        config: temp.path().join("prompt-sync.toml"),
-        verbose: false,
-        command: Command::Verify { json: false },
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
+        command: Command::Verify {
+            hash: false,
+            fix: false,
+            force: false,
+            backup_dir: None,
+            baseline: None,
+            update_baseline: false,
+            output: OutputArgs::default(),
+            mapping: MappingArgs::default(),
+        },
     })?;
     assert_eq!(verify_code, 1);
 
@@ -86,14 +127,23 @@ fn link_conflict_without_force_returns_two() -> anyhow::Result<()> {
     write_config(temp.path(), &source, &target)?;
 
     let link_code = run(Cli {
-        config: temp.path().join("prompt-sync.toml"),
-        verbose: false,
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
         command: Command::Link {
             only_missing: false,
             force: false,
+            owned_only: false,
             dry_run: false,
-            json: false,
+            atomic: false,
+            output: OutputArgs::default(),
             backup_dir: None,
+            mapping: MappingArgs::default(),
+            prune: false,
         },
     })?;
     assert_eq!(link_code, 2);
@@ -103,378 +153,6186 @@ fn link_conflict_without_force_returns_two() -> anyhow::Result<()> {
 }
 
 #[test]
-fn repair_conflict_with_force_replaces_target() -> anyhow::Result<()> {
+fn atomic_link_rolls_back_every_change_when_a_target_fails() -> anyhow::Result<()> {
     let temp = TempDir::new()?;
     let source = temp.path().join("master.md");
-    let target = temp.path().join("out").join("AGENTS.md");
-
     fs::write(&source, "master instruction")?;
-    let parent = target
-        .parent()
-        .ok_or_else(|| anyhow::anyhow!("missing parent path"))?;
-    fs::create_dir_all(parent)?;
-    fs::write(&target, "local override")?;
-    write_config(temp.path(), &source, &target)?;
+    fs::create_dir_all(temp.path().join("out"))?;
+    fs::write(temp.path().join("out").join("CLAUDE.md"), "local override")?;
+    // A regular file where a target directory should be makes the last
+    // target fail after the first two were applied.
+    fs::write(temp.path().join("blocked"), "")?;
+    let root = temp.path().display().to_string().replace('\\', "/");
+    fs::write(
+        temp.path().join("prompt-sync.toml"),
+        format!(
+            r#"[[links]]
+source = "{root}/master.md"
+targets = ["{root}/out/AGENTS.md", "{root}/out/CLAUDE.md", "{root}/blocked/GEMINI.md"]
+"#
+        ),
+    )?;
 
-    let repair_code = run(Cli {
-        config: temp.path().join("prompt-sync.toml"),
-        verbose: false,
-        command: Command::Repair {
-            force: true,
-            dry_run: false,
-            json: false,
-            backup_dir: None,
-        },
-    })?;
-    assert_eq!(repair_code, 0);
+    let link = |atomic: bool| {
+        run(Cli {
+            config: vec![temp.path().join("prompt-sync.toml")],
+            verbose: 0,
+            no_user_config: false,
+            state_dir: Some(temp.path().join("state")),
+            backup_min_free: Some(0),
+            wait: false,
+            no_lock: false,
+            command: Command::Link {
+                only_missing: false,
+                force: true,
+                owned_only: false,
+                dry_run: false,
+                atomic,
+                output: OutputArgs::default(),
+                backup_dir: Some(temp.path().join("backups")),
+                mapping: MappingArgs::default(),
+                prune: false,
+            },
+        })
+    };
 
-    #[cfg(unix)]
-    {
-        let source_meta = fs::metadata(&source)?;
-        let target_meta = fs::metadata(&target)?;
-        assert_eq!(source_meta.ino(), target_meta.ino());
-        assert_eq!(source_meta.dev(), target_meta.dev());
-    }
+    assert_eq!(link(true)?, 2);
+    assert!(!temp.path().join("out").join("AGENTS.md").exists());
+    assert_eq!(
+        fs::read_to_string(temp.path().join("out").join("CLAUDE.md"))?,
+        "local override"
+    );
+    let out_entries = fs::read_dir(temp.path().join("out"))?.count();
+    assert_eq!(out_entries, 1);
+    // Only the operations log, its lock and the manifest remain; the backup
+    // was discarded.
+    assert_eq!(fs::read_dir(temp.path().join("backups"))?.count(), 3);
+
+    assert_eq!(link(false)?, 2);
+    assert_eq!(
+        fs::read_to_string(temp.path().join("out").join("AGENTS.md"))?,
+        "master instruction"
+    );
+    assert_eq!(
+        fs::read_to_string(temp.path().join("out").join("CLAUDE.md"))?,
+        "master instruction"
+    );
 
     Ok(())
 }
 
 #[test]
-fn link_dry_run_does_not_create_target() -> anyhow::Result<()> {
+fn mutating_commands_take_the_state_dir_lock() -> anyhow::Result<()> {
     let temp = TempDir::new()?;
     let source = temp.path().join("master.md");
     let target = temp.path().join("out").join("AGENTS.md");
-
     fs::write(&source, "master instruction")?;
     write_config(temp.path(), &source, &target)?;
+    fs::create_dir_all(temp.path().join("state"))?;
 
-    let link_code = run(Cli {
-        config: temp.path().join("prompt-sync.toml"),
-        verbose: false,
-        command: Command::Link {
-            only_missing: false,
+    let command = |wait: bool, no_lock: bool, dry_run: bool| Cli {
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait,
+        no_lock,
+        command: Command::Repair {
             force: false,
-            dry_run: true,
-            json: false,
+            owned_only: false,
+            dry_run,
+            atomic: false,
+            output: OutputArgs::default(),
             backup_dir: None,
+            mapping: MappingArgs::default(),
         },
-    })?;
-    assert_eq!(link_code, 0);
+    };
+
+    let held = fs::File::create(temp.path().join("state").join("lock"))?;
+    held.lock()?;
+
+    let err = run(command(false, false, false)).expect_err("lock is held");
+    assert!(err.to_string().contains("--wait"), "{err}");
     assert!(!target.exists());
+    assert_eq!(run(command(false, false, true))?, 0);
+    assert_eq!(run(command(false, true, false))?, 0);
+    assert!(target.exists());
+
+    fs::remove_file(&target)?;
+    let release = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        drop(held);
+    });
+    assert_eq!(run(command(true, false, false))?, 0);
+    release.join().expect("release thread");
+    assert!(target.exists());
+
+    assert!(Cli::try_parse_from(["prompt-sync", "--wait", "--no-lock", "repair"]).is_err());
 
     Ok(())
 }
 
-#[cfg(unix)]
 #[test]
-fn verify_symlink_target_is_conflict() -> anyhow::Result<()> {
+fn logging_section_appends_every_change_to_the_journal() -> anyhow::Result<()> {
     let temp = TempDir::new()?;
     let source = temp.path().join("master.md");
-    let symlink_src = temp.path().join("other.md");
-    let target = temp.path().join("out").join("AGENTS.md");
-
     fs::write(&source, "master instruction")?;
-    fs::write(&symlink_src, "other instruction")?;
-    let parent = target
-        .parent()
-        .ok_or_else(|| anyhow::anyhow!("missing parent path"))?;
-    fs::create_dir_all(parent)?;
-    symlink(&symlink_src, &target)?;
-    write_config(temp.path(), &source, &target)?;
+    fs::create_dir_all(temp.path().join("out"))?;
+    fs::write(temp.path().join("out").join("CLAUDE.md"), "local override")?;
+    let root = temp.path().display().to_string().replace('\\', "/");
+    let rule = format!(
+        r#"[[links]]
+source = "{root}/master.md"
+targets = ["{root}/out/AGENTS.md", "{root}/out/CLAUDE.md"]
+"#
+    );
+    fs::write(temp.path().join("prompt-sync.toml"), &rule)?;
+    let journal = temp.path().join("state").join("operations.ndjson");
 
-    let verify_code = run(Cli {
-        config: temp.path().join("prompt-sync.toml"),
-        verbose: false,
-        command: Command::Verify { json: false },
-    })?;
-    assert_eq!(verify_code, 1);
+    let link = |force: bool| {
+        run(Cli {
+            config: vec![temp.path().join("prompt-sync.toml")],
+            verbose: 0,
+            no_user_config: false,
+            state_dir: Some(temp.path().join("state")),
+            backup_min_free: None,
+            wait: false,
+            no_lock: false,
+            command: Command::Link {
+                only_missing: false,
+                force,
+                owned_only: false,
+                dry_run: false,
+                atomic: false,
+                output: OutputArgs::default(),
+                backup_dir: None,
+                mapping: MappingArgs::default(),
+                prune: false,
+            },
+        })
+    };
+
+    // Without a [logging] section nothing is journaled.
+    assert_eq!(link(false)?, 2);
+    assert!(!journal.exists());
+
+    fs::write(
+        temp.path().join("prompt-sync.toml"),
+        format!("[logging]\n\n{rule}"),
+    )?;
+    assert_eq!(link(false)?, 2);
+    assert_eq!(link(true)?, 0);
+
+    let lines = fs::read_to_string(&journal)?
+        .lines()
+        .map(serde_json::from_str::<serde_json::Value>)
+        .collect::<Result<Vec<_>, _>>()?;
+    let actions = lines
+        .iter()
+        .map(|line| line["action"].as_str().unwrap_or_default())
+        .collect::<Vec<_>>();
+    assert_eq!(actions, ["ERROR", "REPLACED"]);
+    assert!(lines.iter().all(|line| line["command"] == "link"));
+    assert_eq!(
+        lines[1]["target"],
+        temp.path()
+            .join("out")
+            .join("CLAUDE.md")
+            .to_string_lossy()
+            .as_ref()
+    );
 
     Ok(())
 }
 
 #[test]
-fn bootstrap_write_config_refuses_overwrite_without_force() -> anyhow::Result<()> {
+fn repo_rules_expand_across_configured_repositories() -> anyhow::Result<()> {
     let temp = TempDir::new()?;
-    let config_path = temp.path().join("prompt-sync.toml");
-    fs::write(&config_path, "# existing\n")?;
+    let source = temp.path().join("master.md");
+    fs::write(&source, "master instruction")?;
+    let (project_a, project_b, project_c) = (
+        temp.path().join("project-a"),
+        temp.path().join("project-b"),
+        temp.path().join("project-c"),
+    );
+    for project in [&project_a, &project_b, &project_c] {
+        fs::create_dir_all(project)?;
+    }
+    let config = temp.path().join("prompt-sync.toml");
+    let toml_path = |path: &Path| path.display().to_string().replace('\\', "/");
+    fs::write(
+        &config,
+        format!(
+            "[repos]\nroots = [\"{}\", \"{}\", \"{}\"]\n\n[[links]]\nsource = \"{}\"\ntargets = [\"<repo>/AGENTS.md\"]\n",
+            toml_path(&project_a),
+            toml_path(&project_b),
+            toml_path(&temp.path().join("missing")),
+            toml_path(&source)
+        ),
+    )?;
 
-    let result = run(Cli {
-        config: config_path.clone(),
-        verbose: false,
-        command: Command::Bootstrap {
-            force: false,
-            dry_run: false,
-            json: false,
-            backup_dir: None,
-            write_config: true,
-        },
-    });
+    let link = |args: &[&str]| -> anyhow::Result<()> {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+            .current_dir(temp.path())
+            .args(["--config", &config.to_string_lossy()])
+            .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+            .arg("link")
+            .args(args)
+            .output()?;
+        assert!(output.status.success(), "{output:?}");
+        Ok(())
+    };
+
+    link(&[])?;
+    assert!(project_a.join("AGENTS.md").exists());
+    assert!(project_b.join("AGENTS.md").exists());
+    assert!(!project_c.join("AGENTS.md").exists());
+    assert!(!temp.path().join("AGENTS.md").exists());
+
+    // `--repo` replaces the configured roots.
+    link(&["--repo", "project-c"])?;
+    assert_eq!(
+        fs::read_to_string(project_c.join("AGENTS.md"))?,
+        "master instruction"
+    );
 
-    assert!(result.is_err());
-    assert_eq!(fs::read_to_string(&config_path)?, "# existing\n");
     Ok(())
 }
 
 #[test]
-fn install_commit_guard_creates_hook() -> anyhow::Result<()> {
+fn repos_scan_finds_clones_for_links_and_commit_guards() -> anyhow::Result<()> {
     let temp = TempDir::new()?;
-    let repo = temp.path().join("repo");
-    fs::create_dir_all(repo.join(".git").join("hooks"))?;
+    let source = temp.path().join("master.md");
+    fs::write(&source, "master instruction")?;
+    let workspace = temp.path().join("src");
+    let (project_a, project_b, excluded, nested) = (
+        workspace.join("project-a"),
+        workspace.join("group").join("project-b"),
+        workspace.join("node_modules").join("dep"),
+        workspace.join("project-a").join("vendor").join("lib"),
+    );
+    for repo in [&project_a, &project_b, &excluded, &nested] {
+        fs::create_dir_all(repo.join(".git"))?;
+    }
+    let config = temp.path().join("prompt-sync.toml");
+    let toml_path = |path: &Path| path.display().to_string().replace('\\', "/");
+    fs::write(
+        &config,
+        format!(
+            "[repos]\nscan = [\"{}\"]\nexclude = [\"node_modules\"]\n\n[[links]]\nsource = \"{}\"\ntargets = [\"<repo>/AGENTS.md\"]\n",
+            toml_path(&workspace),
+            toml_path(&source)
+        ),
+    )?;
 
-    let code = run(Cli {
-        config: temp.path().join("prompt-sync.toml"),
-        verbose: false,
-        command: Command::InstallCommitGuard {
-            repo: repo.clone(),
-            force: false,
-            dry_run: false,
-        },
-    })?;
-    assert_eq!(code, 0);
+    let run = |args: &[&str]| -> anyhow::Result<std::process::Output> {
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .current_dir(temp.path())
+                .args(["--config", &config.to_string_lossy()])
+                .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+                .args(args)
+                .output()?,
+        )
+    };
 
-    let hook_path = repo.join(".git").join("hooks").join("commit-msg");
-    let hook_body = fs::read_to_string(&hook_path)?;
-    assert!(hook_body.contains("Co-authored-by"));
-    assert!(hook_body.contains("chatgpt|claude|codex|gemini|copilot|kiro|openai|anthropic"));
+    let output = run(&["link"])?;
+    assert!(output.status.success(), "{output:?}");
+    assert!(project_a.join("AGENTS.md").exists());
+    assert!(project_b.join("AGENTS.md").exists());
+    assert!(!excluded.join("AGENTS.md").exists());
+    assert!(!nested.join("AGENTS.md").exists());
 
-    #[cfg(unix)]
-    {
-        let mode = fs::metadata(&hook_path)?.permissions().mode();
-        assert_ne!(mode & 0o111, 0);
-    }
+    let output = run(&["install-commit-guard", "--all-repos"])?;
+    assert!(output.status.success(), "{output:?}");
+    assert!(project_a.join(".git/hooks/commit-msg").exists());
+    assert!(project_b.join(".git/hooks/commit-msg").exists());
+    assert!(!excluded.join(".git/hooks/commit-msg").exists());
+
+    let output = run(&["install-commit-guard", "--all-repos", "--status"])?;
+    assert_eq!(output.status.code(), Some(0), "{output:?}");
+    let stdout = String::from_utf8(output.stdout)?;
+    assert_eq!(
+        stdout.matches("commit guard: installed").count(),
+        2,
+        "{stdout}"
+    );
 
     Ok(())
 }
 
 #[test]
-fn install_commit_guard_refuses_overwrite_without_force() -> anyhow::Result<()> {
+fn git_exclude_lists_repo_targets_until_unlinked() -> anyhow::Result<()> {
     let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    fs::write(&source, "master instruction")?;
     let repo = temp.path().join("repo");
-    let hooks = repo.join(".git").join("hooks");
-    fs::create_dir_all(&hooks)?;
-    let hook_path = hooks.join("commit-msg");
-    fs::write(&hook_path, "# existing hook\n")?;
+    fs::create_dir_all(repo.join(".git").join("info"))?;
+    let exclude = repo.join(".git").join("info").join("exclude");
+    fs::write(&exclude, "*.log\n")?;
+    fs::write(repo.join(".gitignore"), "target/\n")?;
+    let config = temp.path().join("prompt-sync.toml");
+    let toml_path = |path: &Path| path.display().to_string().replace('\\', "/");
+    fs::write(
+        &config,
+        format!(
+            "git_exclude = true\n\n[[links]]\nsource = \"{}\"\ntargets = [\"{}\", \"{}\"]\n",
+            toml_path(&source),
+            toml_path(&repo.join("AGENTS.md")),
+            toml_path(&repo.join("docs").join("CLAUDE.md"))
+        ),
+    )?;
 
-    let result = run(Cli {
-        config: temp.path().join("prompt-sync.toml"),
-        verbose: false,
-        command: Command::InstallCommitGuard {
-            repo: repo.clone(),
-            force: false,
-            dry_run: false,
-        },
-    });
-    assert!(result.is_err());
-    assert_eq!(fs::read_to_string(&hook_path)?, "# existing hook\n");
+    let run_command = |command: &str| -> anyhow::Result<()> {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+            .args(["--config", &config.to_string_lossy()])
+            .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+            .arg(command)
+            .output()?;
+        assert!(output.status.success(), "{output:?}");
+        Ok(())
+    };
+
+    run_command("link")?;
+    assert_eq!(
+        fs::read_to_string(&exclude)?,
+        "*.log\n# >>> prompt-sync managed targets\n/AGENTS.md\n/docs/CLAUDE.md\n# <<< prompt-sync managed targets\n"
+    );
+    // A second run leaves the block as it is.
+    run_command("link")?;
+    assert_eq!(
+        fs::read_to_string(&exclude)?.matches("/AGENTS.md").count(),
+        1
+    );
+
+    run_command("unlink")?;
+    assert_eq!(fs::read_to_string(&exclude)?, "*.log\n");
+    assert_eq!(fs::read_to_string(repo.join(".gitignore"))?, "target/\n");
 
     Ok(())
 }
 
+/// A generated target without its managed header line.
+fn read_generated(path: &Path) -> anyhow::Result<String> {
+    let text = fs::read_to_string(path)?;
+    let header = text
+        .lines()
+        .find(|line| line.starts_with("<!-- generated by prompt-sync"))
+        .ok_or_else(|| anyhow::anyhow!("no managed header in {}", path.display()))?;
+    Ok(text.replacen(&format!("{header}\n"), "", 1))
+}
+
 #[test]
-fn link_skills_sets_creates_hardlinks() -> anyhow::Result<()> {
+fn generate_renders_vendor_sections_per_target() -> anyhow::Result<()> {
     let temp = TempDir::new()?;
-    let source_root = temp.path().join("skills");
-    let skill_dir = source_root.join("my-skill");
-    fs::create_dir_all(&skill_dir)?;
-    let source_file = skill_dir.join("SKILL.md");
-    fs::write(&source_file, "skill content")?;
-
-    let target_root = temp.path().join("target");
+    let source = temp.path().join("master.md");
+    fs::write(
+        &source,
+        "# Rules\n<!-- only: claude -->\nUse CLAUDE tools.\n<!-- end -->\n<!-- only: gemini, codex -->\nUse the shell.\n<!-- end -->\nBe brief.\n",
+    )?;
+    let out = temp.path().join("out");
+    let config = temp.path().join("prompt-sync.toml");
+    let toml_path = |path: &Path| path.display().to_string().replace('\\', "/");
+    fs::write(
+        &config,
+        format!(
+            "[[links]]\nsource = \"{}\"\nlink_type = \"generate\"\ntargets = [\"{}\", \"{}\", {{ path = \"{}\", profile = \"codex\" }}]\n",
+            toml_path(&source),
+            toml_path(&out.join("CLAUDE.md")),
+            toml_path(&out.join(".gemini").join("rules.md")),
+            toml_path(&out.join("notes.md"))
+        ),
+    )?;
 
-    let source_str = source_root.display().to_string().replace('\\', "/");
-    let target_str = target_root.display().to_string().replace('\\', "/");
+    let run_command = |command: &str| -> anyhow::Result<std::process::Output> {
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .args(["--config", &config.to_string_lossy()])
+                .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+                .arg(command)
+                .output()?,
+        )
+    };
 
-    let config = format!(
-        r#"[[skills_sets]]
-source_root = "{}"
-target_roots = ["{}"]
-"#,
-        source_str, target_str
+    let output = run_command("link")?;
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        read_generated(&out.join("CLAUDE.md"))?,
+        "# Rules\nUse CLAUDE tools.\nBe brief.\n"
     );
-    fs::write(temp.path().join("prompt-sync.toml"), config)?;
-
-    let link_code = run(Cli {
-        config: temp.path().join("prompt-sync.toml"),
-        verbose: false,
-        command: Command::Link {
-            only_missing: false,
-            force: false,
-            dry_run: false,
-            json: false,
-            backup_dir: None,
-        },
-    })?;
-    assert_eq!(link_code, 0);
+    assert_eq!(
+        read_generated(&out.join(".gemini").join("rules.md"))?,
+        "# Rules\nUse the shell.\nBe brief.\n"
+    );
+    assert_eq!(
+        read_generated(&out.join("notes.md"))?,
+        "# Rules\nUse the shell.\nBe brief.\n"
+    );
+    assert_eq!(run_command("verify")?.status.code(), Some(0));
 
-    let target_file = target_root.join("my-skill").join("SKILL.md");
-    assert!(target_file.exists(), "target skill file should exist");
-    assert_eq!(fs::read_to_string(&target_file)?, "skill content");
+    // An edit to one vendor's section only drifts that vendor's target.
+    fs::write(
+        &source,
+        fs::read_to_string(&source)?.replace("Use CLAUDE tools.", "Use Claude tools."),
+    )?;
+    let output = run_command("verify")?;
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("broken=1"), "{stdout}");
 
-    #[cfg(unix)]
-    {
-        let source_meta = fs::metadata(&source_file)?;
-        let target_meta = fs::metadata(&target_file)?;
-        assert_eq!(source_meta.ino(), target_meta.ino());
-        assert_eq!(source_meta.dev(), target_meta.dev());
-    }
+    fs::write(&source, "<!-- only: claude -->\nnever closed\n")?;
+    let output = run_command("verify")?;
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(
+        stdout.contains("line 1: section is never closed"),
+        "{stdout}"
+    );
 
     Ok(())
 }
 
 #[test]
-fn link_skills_sets_exclude_filters_files() -> anyhow::Result<()> {
+fn master_frontmatter_picks_profiles_and_named_sections() -> anyhow::Result<()> {
     let temp = TempDir::new()?;
-    let source_root = temp.path().join("skills");
+    let source = temp.path().join("master.md");
+    fs::write(
+        &source,
+        "---\napplyTo: \"**\"\nprofiles: [claude, copilot]\nsections:\n  testing: [claude]\n---\n# Rules\n<!-- section: testing -->\nRun cargo test.\n<!-- end -->\nDone.\n",
+    )?;
+    let out = temp.path().join("out");
+    let config = temp.path().join("prompt-sync.toml");
+    let toml_path = |path: &Path| path.display().to_string().replace('\\', "/");
+    fs::write(
+        &config,
+        format!(
+            "[[links]]\nsource = \"{}\"\nlink_type = \"generate\"\ntargets = [\"{}\", \"{}\", \"{}\"]\n",
+            toml_path(&source),
+            toml_path(&out.join("CLAUDE.md")),
+            toml_path(&out.join(".github").join("copilot-instructions.md")),
+            toml_path(&out.join("GEMINI.md"))
+        ),
+    )?;
 
-    // Create skill with references/ subdir that should be excluded
-    let skill_dir = source_root.join("my-skill");
-    fs::create_dir_all(skill_dir.join("references"))?;
-    fs::write(skill_dir.join("SKILL.md"), "skill content")?;
-    fs::write(skill_dir.join("references").join("ref.md"), "ref content")?;
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+        .args(["--config", &config.to_string_lossy()])
+        .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+        .args(["link", "--json"])
+        .output()?;
+    assert!(output.status.success(), "{output:?}");
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(report["summary"]["total"], 2);
 
-    // Create another skill without references
-    let skill2_dir = source_root.join("other-skill");
-    fs::create_dir_all(&skill2_dir)?;
-    fs::write(skill2_dir.join("SKILL.md"), "other content")?;
+    assert_eq!(
+        read_generated(&out.join("CLAUDE.md"))?,
+        "---\napplyTo: \"**\"\n---\n# Rules\nRun cargo test.\nDone.\n"
+    );
+    assert_eq!(
+        read_generated(&out.join(".github").join("copilot-instructions.md"))?,
+        "---\napplyTo: \"**\"\n---\n# Rules\nDone.\n"
+    );
+    assert!(!out.join("GEMINI.md").exists());
 
-    let target_root = temp.path().join("target");
-    let source_str = source_root.display().to_string().replace('\\', "/");
-    let target_str = target_root.display().to_string().replace('\\', "/");
+    Ok(())
+}
 
-    let config = format!(
-        r#"[[skills_sets]]
-source_root = "{}"
-target_roots = ["{}"]
-exclude = ["*/references/**"]
-"#,
-        source_str, target_str
-    );
-    fs::write(temp.path().join("prompt-sync.toml"), config)?;
+#[test]
+fn generate_expands_include_fragments_and_verify_flags_stale_output() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    let fragments = temp.path().join("fragments");
+    fs::create_dir_all(&fragments)?;
+    fs::write(
+        &source,
+        "# Rules\n<!-- include: ./fragments/rust.md -->\nDone.\n",
+    )?;
+    fs::write(
+        fragments.join("rust.md"),
+        "Use cargo.\n<!-- include: common.md -->",
+    )?;
+    fs::write(fragments.join("common.md"), "Be brief.\n")?;
+    let target = temp.path().join("out").join("CLAUDE.md");
+    let config = temp.path().join("prompt-sync.toml");
+    let toml_path = |path: &Path| path.display().to_string().replace('\\', "/");
+    fs::write(
+        &config,
+        format!(
+            "[[links]]\nsource = \"{}\"\nlink_type = \"generate\"\ntargets = [\"{}\"]\n",
+            toml_path(&source),
+            toml_path(&target)
+        ),
+    )?;
 
-    let link_code = run(Cli {
-        config: temp.path().join("prompt-sync.toml"),
-        verbose: false,
-        command: Command::Link {
-            only_missing: false,
-            force: false,
-            dry_run: false,
-            json: false,
-            backup_dir: None,
-        },
-    })?;
-    assert_eq!(link_code, 0);
+    let run_command = |command: &str| -> anyhow::Result<std::process::Output> {
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .args(["--config", &config.to_string_lossy()])
+                .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+                .arg(command)
+                .output()?,
+        )
+    };
 
-    // SKILL.md files should be linked
-    assert!(target_root.join("my-skill").join("SKILL.md").exists());
-    assert!(target_root.join("other-skill").join("SKILL.md").exists());
+    assert!(run_command("link")?.status.success());
+    assert_eq!(
+        read_generated(&target)?,
+        "# Rules\nUse cargo.\nBe brief.\nDone.\n"
+    );
+    assert_eq!(run_command("verify")?.status.code(), Some(0));
 
-    // references/ should be excluded
+    let common = fragments.join("common.md");
+    fs::write(&common, "Be very brief.\n")?;
+    fs::File::options()
+        .write(true)
+        .open(&common)?
+        .set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(60))?;
+    let output = run_command("verify")?;
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let stdout = String::from_utf8(output.stdout)?;
     assert!(
-        !target_root
-            .join("my-skill")
-            .join("references")
-            .join("ref.md")
-            .exists(),
-        "references/ref.md should be excluded"
+        stdout.contains("fragment changed since the target was generated"),
+        "{stdout}"
     );
+    assert!(run_command("repair")?.status.success());
+    assert!(read_generated(&target)?.contains("Be very brief."));
+
+    fs::write(&common, "<!-- include: rust.md -->\n")?;
+    let stdout = String::from_utf8(run_command("verify")?.stdout)?;
+    assert!(stdout.contains("include cycle"), "{stdout}");
 
     Ok(())
 }
 
 #[test]
-fn link_skills_sets_only_skills_filters_dirs() -> anyhow::Result<()> {
+fn generated_and_substitute_copies_fill_content_tokens() -> anyhow::Result<()> {
     let temp = TempDir::new()?;
-    let source_root = temp.path().join("skills");
+    let source = temp.path().join("master.md");
+    fs::write(
+        &source,
+        "Project <repo_name> for <team> on <hostname>.<br> <unknown>\n",
+    )?;
+    let project = temp.path().join("project-x");
+    fs::create_dir_all(&project)?;
+    let config = temp.path().join("prompt-sync.toml");
+    let toml_path = |path: &Path| path.display().to_string().replace('\\', "/");
+    fs::write(
+        &config,
+        format!(
+            r#"[vars]
+team = "platform"
 
-    // Create three skills
-    for name in &["alpha", "beta", "gamma"] {
-        let dir = source_root.join(name);
-        fs::create_dir_all(&dir)?;
-        fs::write(dir.join("SKILL.md"), format!("{name} content"))?;
-    }
+[repos]
+roots = ["{project}"]
 
-    let target_root = temp.path().join("target");
-    let source_str = source_root.display().to_string().replace('\\', "/");
-    let target_str = target_root.display().to_string().replace('\\', "/");
+[[links]]
+source = "{source}"
+link_type = "generate"
+targets = ["<repo>/AGENTS.md"]
 
-    let config = format!(
-        r#"[[skills_sets]]
-source_root = "{}"
-target_roots = ["{}"]
-only_skills = ["alpha", "gamma"]
+[[links]]
+source = "{source}"
+link_type = "copy"
+substitute = true
+targets = ["<repo>/CLAUDE.md"]
+
+[[links]]
+source = "{source}"
+link_type = "copy"
+targets = ["<repo>/GEMINI.md"]
+"#,
+            project = toml_path(&project),
+            source = toml_path(&source)
+        ),
+    )?;
+
+    let run_command = |command: &str| -> anyhow::Result<std::process::Output> {
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .args(["--config", &config.to_string_lossy()])
+                .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+                .arg(command)
+                .output()?,
+        )
+    };
+
+    assert!(run_command("link")?.status.success());
+    let generated = read_generated(&project.join("AGENTS.md"))?;
+    assert!(
+        generated.starts_with("Project project-x for platform on "),
+        "{generated}"
+    );
+    assert!(generated.ends_with(".<br> <unknown>\n"), "{generated}");
+    assert!(!generated.contains("<hostname>"), "{generated}");
+    assert_eq!(fs::read_to_string(project.join("CLAUDE.md"))?, generated);
+    assert_eq!(
+        fs::read_to_string(project.join("GEMINI.md"))?,
+        fs::read_to_string(&source)?
+    );
+    assert_eq!(run_command("verify")?.status.code(), Some(0));
+
+    Ok(())
+}
+
+#[test]
+fn transforms_reshape_generated_output_per_profile() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    fs::write(
+        &source,
+        "# Rules\nKeep it short.\n```sh\n# Rules\n```\n## Testing\nRun the tests.\n",
+    )?;
+    let project = temp.path().join("project");
+    let config = temp.path().join("prompt-sync.toml");
+    let toml_path = |path: &Path| path.display().to_string().replace('\\', "/");
+    fs::write(
+        &config,
+        format!(
+            r#"[transforms.cursor.frontmatter]
+description = "Team rules"
+
+[transforms.copilot]
+max_bytes = 148
+
+[transforms.gemini]
+headings = {{ "Rules" = "Instructions" }}
+heading_offset = 1
+
+[[links]]
+source = "{source}"
+link_type = "generate"
+targets = [
+  "{project}/.cursor/rules/master.mdc",
+  "{project}/.github/copilot-instructions.md",
+  "{project}/GEMINI.md",
+  "{project}/AGENTS.md",
+]
+"#,
+            project = toml_path(&project),
+            source = toml_path(&source)
+        ),
+    )?;
+
+    let run_command = |command: &str| -> anyhow::Result<std::process::Output> {
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .args(["--config", &config.to_string_lossy()])
+                .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+                .arg(command)
+                .output()?,
+        )
+    };
+
+    let output = run_command("link")?;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let master = fs::read_to_string(&source)?;
+    assert_eq!(
+        read_generated(&project.join(".cursor/rules/master.mdc"))?,
+        format!("---\ndescription: Team rules\nalwaysApply: true\n---\n{master}")
+    );
+    assert_eq!(
+        read_generated(&project.join(".github/copilot-instructions.md"))?,
+        "# Rules\nKeep it short.\n"
+    );
+    assert_eq!(
+        read_generated(&project.join("GEMINI.md"))?,
+        "## Instructions\nKeep it short.\n```sh\n# Rules\n```\n### Testing\nRun the tests.\n"
+    );
+    assert_eq!(read_generated(&project.join("AGENTS.md"))?, master);
+    assert_eq!(run_command("verify")?.status.code(), Some(0));
+
+    Ok(())
+}
+
+#[test]
+fn generate_command_writes_headed_targets_and_verify_flags_hand_edits() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    fs::write(&source, "---\napplyTo: \"**\"\n---\n# Rules\n")?;
+    let out = temp.path().join("out");
+    let config = temp.path().join("prompt-sync.toml");
+    let toml_path = |path: &Path| path.display().to_string().replace('\\', "/");
+    fs::write(
+        &config,
+        format!(
+            "[[links]]\nsource = \"{source}\"\nlink_type = \"generate\"\ntargets = [\"{claude}\"]\n\n[[links]]\nsource = \"{source}\"\ntargets = [\"{agents}\"]\n",
+            source = toml_path(&source),
+            claude = toml_path(&out.join("CLAUDE.md")),
+            agents = toml_path(&out.join("AGENTS.md"))
+        ),
+    )?;
+
+    let run_command = |args: &[&str]| -> anyhow::Result<std::process::Output> {
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .args(["--config", &config.to_string_lossy()])
+                .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+                .args(args)
+                .output()?,
+        )
+    };
+
+    let output = run_command(&["generate", "--json"])?;
+    assert!(output.status.success(), "{output:?}");
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(report["summary"]["total"], 1);
+    assert!(!out.join("AGENTS.md").exists());
+    let generated = fs::read_to_string(out.join("CLAUDE.md"))?;
+    assert!(
+        generated.starts_with(
+            "---\napplyTo: \"**\"\n---\n<!-- generated by prompt-sync — do not edit (sha256 "
+        ),
+        "{generated}"
+    );
+    assert!(generated.ends_with(" -->\n# Rules\n"), "{generated}");
+
+    // A source edit leaves the target stale but untouched: BROKEN.
+    fs::write(&source, "# Rules\nBe brief.\n")?;
+    let stdout = String::from_utf8(run_command(&["verify"])?.stdout)?;
+    assert!(stdout.contains("broken=1"), "{stdout}");
+    assert!(run_command(&["generate"])?.status.success());
+
+    // An edit by hand is a CONFLICT that generate keeps until --force.
+    let target = out.join("CLAUDE.md");
+    fs::write(
+        &target,
+        fs::read_to_string(&target)?.replace("brief", "terse"),
+    )?;
+    let output = run_command(&["verify"])?;
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(
+        stdout.contains("generated target was edited by hand"),
+        "{stdout}"
+    );
+    let stdout = String::from_utf8(run_command(&["generate"])?.stdout)?;
+    assert!(stdout.contains("skipped=1"), "{stdout}");
+    assert!(fs::read_to_string(&target)?.contains("terse"));
+    assert!(run_command(&["generate", "--force"])?.status.success());
+    assert_eq!(read_generated(&target)?, "# Rules\nBe brief.\n");
+
+    Ok(())
+}
+
+#[test]
+fn skills_lint_flags_missing_and_malformed_manifests() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let skills = temp.path().join("skills");
+    for (skill, manifest) in [
+        (
+            "review",
+            Some("---\nname: review\ndescription: Review a diff.\n---\nSteps.\n"),
+        ),
+        ("deploy", None),
+        ("notes", Some("# Notes\n")),
+        ("triage", Some("---\nname: Triage Bugs\n---\n")),
+    ] {
+        fs::create_dir_all(skills.join(skill))?;
+        if let Some(manifest) = manifest {
+            fs::write(skills.join(skill).join("SKILL.md"), manifest)?;
+        }
+    }
+    let config = temp.path().join("prompt-sync.toml");
+    fs::write(
+        &config,
+        format!(
+            "[[skills_sets]]\nsource_root = \"{}\"\ntarget_roots = [\"{}\"]\n",
+            skills.display().to_string().replace('\\', "/"),
+            temp.path()
+                .join("out")
+                .display()
+                .to_string()
+                .replace('\\', "/")
+        ),
+    )?;
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+        .args(["--config", &config.to_string_lossy()])
+        .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+        .args(["skills", "lint", "--json"])
+        .output()?;
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let issues: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout)?;
+    let messages = issues
+        .iter()
+        .map(|issue| {
+            format!(
+                "{} {}",
+                issue["rule"].as_str().unwrap_or_default(),
+                issue["message"].as_str().unwrap_or_default()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    assert!(
+        messages.contains("deploy skill has no SKILL.md"),
+        "{messages}"
+    );
+    assert!(
+        messages.contains("notes SKILL.md does not open with a --- frontmatter block"),
+        "{messages}"
+    );
+    assert!(
+        messages.contains("triage name `Triage Bugs` must be lowercase"),
+        "{messages}"
+    );
+    assert!(
+        messages.contains("triage name `Triage Bugs` does not match"),
+        "{messages}"
+    );
+    assert!(
+        messages.contains("triage SKILL.md frontmatter has no description"),
+        "{messages}"
+    );
+    assert!(!messages.contains("review"), "{messages}");
+    assert!(!temp.path().join("out").exists());
+
+    Ok(())
+}
+
+#[test]
+fn skills_diff_prints_a_matrix_per_target_root() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let skills = temp.path().join("skills");
+    fs::create_dir_all(skills.join("review"))?;
+    fs::create_dir_all(skills.join("deploy"))?;
+    fs::write(skills.join("review").join("SKILL.md"), "review")?;
+    fs::write(skills.join("review").join("notes.md"), "notes")?;
+    fs::write(skills.join("deploy").join("SKILL.md"), "deploy")?;
+    let claude = temp.path().join("claude");
+    let gemini = temp.path().join("gemini");
+    let config = temp.path().join("prompt-sync.toml");
+    let toml_path = |path: &Path| path.display().to_string().replace('\\', "/");
+    fs::write(
+        &config,
+        format!(
+            "[[skills_sets]]\nsource_root = \"{}\"\ntarget_roots = [\"{}\", \"{}\"]\n",
+            toml_path(&skills),
+            toml_path(&claude),
+            toml_path(&gemini)
+        ),
+    )?;
+
+    let run_command = |args: &[&str]| -> anyhow::Result<std::process::Output> {
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .args(["--config", &config.to_string_lossy()])
+                .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+                .args(args)
+                .output()?,
+        )
+    };
+
+    assert!(run_command(&["link"])?.status.success());
+    let output = run_command(&["skills", "diff"])?;
+    assert_eq!(output.status.code(), Some(0), "{output:?}");
+
+    fs::remove_file(claude.join("review").join("notes.md"))?;
+    fs::remove_file(gemini.join("review").join("SKILL.md"))?;
+    fs::write(gemini.join("review").join("SKILL.md"), "edited")?;
+    fs::remove_file(gemini.join("deploy").join("SKILL.md"))?;
+    fs::write(gemini.join("deploy").join("SKILL.md"), "deploy")?;
+    fs::write(gemini.join("deploy").join("stale.md"), "old")?;
+
+    let output = run_command(&["skills", "diff"])?;
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let stdout = String::from_utf8(output.stdout)?;
+    let row = |skill: &str| {
+        stdout
+            .lines()
+            .find(|line| line.starts_with(skill))
+            .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+    };
+    assert_eq!(
+        row("deploy").as_deref(),
+        Some("deploy ok 1 unlinked, 1 extra"),
+        "{stdout}"
+    );
+    assert_eq!(
+        row("review").as_deref(),
+        Some("review 1 missing 1 drifted"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("extra: "), "{stdout}");
+
+    let output = run_command(&["skills", "diff", "--json"])?;
+    let diffs: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(diffs.len(), 4);
+    assert!(
+        diffs
+            .iter()
+            .all(|diff| diff["skills_set"] == "skills_sets[0]")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn status_tui_refuses_to_run_without_a_terminal() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    let target = temp.path().join("out").join("AGENTS.md");
+    fs::write(&source, "master instruction")?;
+    write_config(temp.path(), &source, &target)?;
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+        .args([
+            "--config",
+            &temp.path().join("prompt-sync.toml").to_string_lossy(),
+        ])
+        .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+        .args(["status", "--tui"])
+        .stdin(std::process::Stdio::null())
+        .output()?;
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("status --tui needs"), "{stderr}");
+    assert!(!target.exists());
+
+    Ok(())
+}
+
+#[test]
+fn daemon_once_repairs_missing_targets_and_journals_the_pass() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    fs::write(&source, "master instruction")?;
+    let out = temp.path().join("out");
+    fs::create_dir_all(&out)?;
+    fs::write(out.join("CLAUDE.md"), "local override")?;
+    let root = temp.path().display().to_string().replace('\\', "/");
+    let config = temp.path().join("prompt-sync.toml");
+    fs::write(
+        &config,
+        format!(
+            "[logging]\n\n[[links]]\nsource = \"{root}/master.md\"\ntargets = [\"{root}/out/AGENTS.md\", \"{root}/out/CLAUDE.md\"]\n"
+        ),
+    )?;
+    let daemon = || -> anyhow::Result<std::process::Output> {
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .args(["--config", &config.to_string_lossy()])
+                .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+                .args(["daemon", "--once"])
+                .output()?,
+        )
+    };
+
+    let output = daemon()?;
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(
+        stdout.contains("daemon: 2 checked, 1 repaired, 1 conflict(s), 0 error(s)"),
+        "{stdout}"
+    );
+    assert_eq!(
+        fs::read_to_string(out.join("AGENTS.md"))?,
+        "master instruction"
+    );
+    assert_eq!(fs::read_to_string(out.join("CLAUDE.md"))?, "local override");
+
+    // A vendor app deleting its file is repaired on the next pass.
+    fs::remove_file(out.join("AGENTS.md"))?;
+    let stdout = String::from_utf8(daemon()?.stdout)?;
+    assert!(stdout.contains("1 repaired"), "{stdout}");
+    assert!(out.join("AGENTS.md").exists());
+
+    let journal = fs::read_to_string(temp.path().join("state").join("operations.ndjson"))?;
+    let created = journal
+        .lines()
+        .map(serde_json::from_str::<serde_json::Value>)
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .filter(|entry| entry["command"] == "daemon" && entry["action"] == "CREATED")
+        .count();
+    assert_eq!(created, 2);
+
+    Ok(())
+}
+
+#[test]
+fn watch_relinks_after_source_fragment_and_rule_edits() -> anyhow::Result<()> {
+    use std::io::BufRead;
+
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    let fragments = temp.path().join("fragments");
+    fs::create_dir_all(&fragments)?;
+    fs::write(
+        &source,
+        "# Rules\n<!-- include: ./fragments/common.md -->\n",
+    )?;
+    fs::write(fragments.join("common.md"), "Be brief.\n")?;
+    let out = temp.path().join("out");
+    let config = temp.path().join("prompt-sync.toml");
+    let rule = |targets: &str| {
+        format!(
+            "[[links]]\nsource = \"master.md\"\nlink_type = \"generate\"\ntargets = [{targets}]\n"
+        )
+    };
+    fs::write(&config, rule("\"out/CLAUDE.md\""))?;
+    let watch = |max_passes: &str| {
+        let mut command = std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"));
+        command
+            .args(["--config", &config.to_string_lossy()])
+            .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+            .args(["watch", "--interval", "1", "--max-passes", max_passes])
+            .current_dir(temp.path());
+        command
+    };
+    let wait_for = |what: &str, done: &dyn Fn() -> bool| -> anyhow::Result<()> {
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        while !done() {
+            anyhow::ensure!(std::time::Instant::now() < deadline, "watch never {what}");
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+        Ok(())
+    };
+    let claude = out.join("CLAUDE.md");
+    let generated = |text: &str| read_generated(&claude).is_ok_and(|body| body.contains(text));
+
+    // The first poll links everything, and the pass limit ends the run.
+    let output = watch("1").output()?;
+    assert!(output.status.success(), "{output:?}");
+    assert!(generated("Be brief."));
+
+    // Edits are made only after the first pass's report, so each is seen
+    // as a change; the pass limit stops a watch the test failed to kill.
+    let mut child = watch("30").stdout(std::process::Stdio::piped()).spawn()?;
+    let stdout = child.stdout.take().expect("piped stdout");
+    let mut lines = std::io::BufReader::new(stdout).lines();
+    let result = (|| -> anyhow::Result<()> {
+        loop {
+            match lines.next().transpose()? {
+                Some(line) if line.contains("command: watch") => break,
+                Some(_) => {}
+                None => anyhow::bail!("watch exited before its first report"),
+            }
+        }
+        fs::write(fragments.join("common.md"), "Be very brief.\n")?;
+        wait_for("regenerated after the fragment edit", &|| {
+            generated("Be very brief.")
+        })?;
+        fs::write(
+            &source,
+            "# Rules v2\n<!-- include: ./fragments/common.md -->\n",
+        )?;
+        wait_for("regenerated after the source edit", &|| {
+            generated("# Rules v2")
+        })?;
+        fs::write(&config, rule("\"out/CLAUDE.md\", \"out/AGENTS.md\""))?;
+        wait_for("picked up the new target", &|| {
+            out.join("AGENTS.md").exists()
+        })
+    })();
+    child.kill()?;
+    child.wait()?;
+    result
+}
+
+#[test]
+fn skills_links_reuse_source_and_directory_lookups() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let skills = temp.path().join("skills").join("review");
+    fs::create_dir_all(&skills)?;
+    for name in ["SKILL.md", "checklist.md", "examples.md"] {
+        fs::write(skills.join(name), name)?;
+    }
+    let config_path = temp.path().join("prompt-sync.toml");
+    fs::write(
+        &config_path,
+        "[[skills_sets]]\nsource_root = \"skills\"\ntarget_roots = [\"a\", \"b\"]\n",
+    )?;
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+        .args(["--config", &config_path.to_string_lossy()])
+        .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+        .args(["link", "--json"])
+        .current_dir(temp.path())
+        .output()?;
+    assert!(output.status.success(), "{output:?}");
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    // In each root the first file creates review/ and caches it; the other
+    // two find it cached for the parent check and the device check. The
+    // second root finds all three sources cached: 2 * 2 * 2 + 3.
+    assert_eq!(report["timing"]["stat_cache_hits"], 11, "{report}");
+    for root in ["a", "b"] {
+        for name in ["SKILL.md", "checklist.md", "examples.md"] {
+            let target = temp.path().join(root).join("review").join(name);
+            assert_eq!(fs::read_to_string(&target)?, name);
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn reports_break_the_run_time_down_by_phase() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let skills = temp.path().join("skills").join("review");
+    fs::create_dir_all(&skills)?;
+    fs::write(skills.join("SKILL.md"), "review")?;
+    fs::write(skills.join("checklist.md"), "checklist")?;
+    let config_path = temp.path().join("prompt-sync.toml");
+    fs::write(
+        &config_path,
+        "[[skills_sets]]\nsource_root = \"skills\"\ntarget_roots = [\"out\"]\n",
+    )?;
+    let prompt_sync = |args: &[&str]| -> anyhow::Result<std::process::Output> {
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .args(["--config", &config_path.to_string_lossy()])
+                .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+                .args(args)
+                .current_dir(temp.path())
+                .output()?,
+        )
+    };
+
+    let output = prompt_sync(&["link", "--json"])?;
+    assert!(output.status.success(), "{output:?}");
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    let timing = &report["timing"];
+    for phase in ["config_ms", "mappings_ms", "records_ms"] {
+        assert!(
+            timing[phase].as_f64().is_some_and(|ms| ms >= 0.0),
+            "{timing}"
+        );
+    }
+    // The source root, the skill directory and its two files.
+    assert_eq!(timing["walked_entries"], 4, "{timing}");
+    assert!(timing["hashed_files"].is_u64(), "{timing}");
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn logs_rotate_into_gzip_generations() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    fs::write(temp.path().join("master.md"), "master instruction")?;
+    let config_path = temp.path().join("prompt-sync.toml");
+    fs::write(
+        &config_path,
+        "[logging]\nmax_size = \"1\"\nmax_files = 2\n\n\
+         [[links]]\nsource = \"master.md\"\ntargets = [\"AGENTS.md\"]\n",
+    )?;
+    let state = temp.path().join("state");
+    let backups = temp.path().join("backups");
+    let prompt_sync = |args: &[&str]| -> anyhow::Result<std::process::Output> {
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .args(["--config", &config_path.to_string_lossy()])
+                .args(["--state-dir", &state.to_string_lossy()])
+                .args(args)
+                .current_dir(temp.path())
+                .output()?,
+        )
+    };
+    let gunzip = |path: &Path| -> anyhow::Result<String> {
+        let output = std::process::Command::new("gzip")
+            .arg("-dc")
+            .arg(path)
+            .output()?;
+        assert!(output.status.success(), "{output:?}");
+        Ok(String::from_utf8(output.stdout)?)
+    };
+
+    // One journal line per run: the oldest falls off after two generations.
+    for command in ["link", "unlink", "link", "unlink"] {
+        let output = prompt_sync(&[command])?;
+        assert!(output.status.success(), "{output:?}");
+    }
+    let journal = state.join("operations.ndjson");
+    assert!(fs::read_to_string(&journal)?.contains("\"REMOVED\""));
+    assert!(gunzip(&state.join("operations.ndjson.1.gz"))?.contains("\"CREATED\""));
+    assert!(gunzip(&state.join("operations.ndjson.2.gz"))?.contains("\"REMOVED\""));
+    assert!(!state.join("operations.ndjson.3.gz").exists());
+
+    // An uncompressed generation from older versions is kept as the second.
+    fs::create_dir_all(&backups)?;
+    fs::write(
+        backups.join(".operations.log"),
+        "{\"status\":\"current\"}\n",
+    )?;
+    fs::write(
+        backups.join(".operations.log.1"),
+        "{\"status\":\"legacy\"}\n",
+    )?;
+    fs::write(temp.path().join("AGENTS.md"), "local")?;
+    let output = prompt_sync(&[
+        "link",
+        "--force",
+        "--backup-dir",
+        &backups.to_string_lossy(),
+    ])?;
+    assert!(output.status.success(), "{output:?}");
+    assert!(!backups.join(".operations.log.1").exists());
+    assert_eq!(
+        gunzip(&backups.join(".operations.log.1.gz"))?,
+        "{\"status\":\"current\"}\n"
+    );
+    assert_eq!(
+        gunzip(&backups.join(".operations.log.2.gz"))?,
+        "{\"status\":\"legacy\"}\n"
+    );
+    assert!(fs::read_to_string(backups.join(".operations.log"))?.contains("\"replace\""));
+    Ok(())
+}
+
+#[test]
+fn operations_log_is_appended_as_ndjson_and_converts_an_array_log() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    fs::write(temp.path().join("master.md"), "master instruction")?;
+    fs::write(temp.path().join("AGENTS.md"), "old")?;
+    fs::write(temp.path().join("CLAUDE.md"), "old")?;
+    let backups = temp.path().join("backups");
+    fs::create_dir_all(&backups)?;
+    // The layout older versions rewrote on every entry.
+    fs::write(
+        backups.join(".operations.log"),
+        "[\n  {\n    \"action\": \"replace\",\n    \"status\": \"success\"\n  }\n]",
+    )?;
+    let config_path = temp.path().join("prompt-sync.toml");
+    fs::write(
+        &config_path,
+        "[[links]]\nsource = \"master.md\"\ntargets = [\"AGENTS.md\", \"CLAUDE.md\"]\n",
+    )?;
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+        .args(["--config", &config_path.to_string_lossy()])
+        .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+        .args(["link", "--force", "--backup-dir"])
+        .arg(&backups)
+        .current_dir(temp.path())
+        .output()?;
+    assert!(output.status.success(), "{output:?}");
+    let log = fs::read_to_string(backups.join(".operations.log"))?;
+    let entries = log
+        .lines()
+        .map(serde_json::from_str::<serde_json::Value>)
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(entries.len(), 3, "{log}");
+    assert_eq!(
+        entries[0],
+        serde_json::json!({"action": "replace", "status": "success"})
+    );
+    for (entry, target) in entries[1..].iter().zip(["AGENTS.md", "CLAUDE.md"]) {
+        assert_eq!(
+            entry["target"],
+            temp.path().join(target).to_string_lossy().as_ref()
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn porcelain_output_prints_one_tab_separated_line_per_record() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    fs::write(temp.path().join("master.md"), "master instruction")?;
+    let config_path = temp.path().join("prompt-sync.toml");
+    fs::write(
+        &config_path,
+        "[[links]]\nsource = \"master.md\"\ntargets = [\"AGENTS.md\", \"CLAUDE.md\"]\n",
+    )?;
+    let prompt_sync = |args: &[&str]| -> anyhow::Result<std::process::Output> {
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .args(["--config", &config_path.to_string_lossy()])
+                .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+                .args(args)
+                .current_dir(temp.path())
+                .output()?,
+        )
+    };
+    let line = |status: &str, target: &str| {
+        format!(
+            "{status}\tconfig_file\t{}\t{}\n",
+            temp.path().join("master.md").display(),
+            temp.path().join(target).display()
+        )
+    };
+
+    fs::write(temp.path().join("CLAUDE.md"), "edited by hand")?;
+    let output = prompt_sync(&["link", "--output", "porcelain"])?;
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        line("CREATED", "AGENTS.md") + &line("ERROR", "CLAUDE.md")
+    );
+
+    let output = prompt_sync(&["status", "--output", "porcelain"])?;
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        line("OK", "AGENTS.md") + &line("CONFLICT", "CLAUDE.md")
+    );
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn daemon_notifies_about_drifted_targets() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    fs::write(temp.path().join("master.md"), "master instruction")?;
+    // Stands in for notify-send and records what it was asked to show.
+    let bin = temp.path().join("bin");
+    fs::create_dir_all(&bin)?;
+    fs::write(
+        bin.join("notify-send"),
+        "#!/bin/sh\nshift 2\nprintf '%s|%s\\n' \"$1\" \"$2\" >> \"$NOTIFY_LOG\"\n",
+    )?;
+    fs::set_permissions(bin.join("notify-send"), fs::Permissions::from_mode(0o755))?;
+    let config_path = temp.path().join("prompt-sync.toml");
+    fs::write(
+        &config_path,
+        "[watch]\nnotify = true\n\n\
+         [[links]]\nsource = \"master.md\"\ntargets = [\"AGENTS.md\", \"CLAUDE.md\"]\n",
+    )?;
+    let log = temp.path().join("notify.log");
+    let prompt_sync = |args: &[&str]| -> anyhow::Result<std::process::Output> {
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .args(["--config", &config_path.to_string_lossy()])
+                .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+                .args(args)
+                .env("PATH", &bin)
+                .env("HOME", temp.path())
+                .env("NOTIFY_LOG", &log)
+                .current_dir(temp.path())
+                .output()?,
+        )
+    };
+
+    let output = prompt_sync(&["link"])?;
+    assert!(output.status.success(), "{output:?}");
+    let output = prompt_sync(&["daemon", "--once"])?;
+    assert!(output.status.success(), "{output:?}");
+    assert!(!log.exists(), "nothing drifted");
+
+    fs::remove_file(temp.path().join("AGENTS.md"))?;
+    fs::remove_file(temp.path().join("CLAUDE.md"))?;
+    fs::write(temp.path().join("CLAUDE.md"), "edited by hand")?;
+    let output = prompt_sync(&["daemon", "--once"])?;
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    assert_eq!(
+        fs::read_to_string(&log)?,
+        "prompt-sync: 2 target(s) drifted|repaired: ~/AGENTS.md\nnot repaired: ~/CLAUDE.md\n"
+    );
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn hooks_run_around_link_and_repair_with_the_report() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    fs::write(temp.path().join("master.md"), "master instruction")?;
+    let config_path = temp.path().join("prompt-sync.toml");
+    fs::write(
+        &config_path,
+        "[hooks]\n\
+         pre_link = [\"test ! -e gate\", \"echo pre >> hooks.log\"]\n\
+         post_link = [\"echo \\\"post $PROMPT_SYNC_COMMAND\\\" >> hooks.log\", \"cp \\\"$PROMPT_SYNC_REPORT\\\" report.json\"]\n\
+         post_repair = [\"echo repaired >> hooks.log\", \"false\"]\n\n\
+         [[links]]\nsource = \"master.md\"\ntargets = [\"AGENTS.md\"]\n",
+    )?;
+    let prompt_sync = |args: &[&str]| -> anyhow::Result<std::process::Output> {
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .args(["--config", &config_path.to_string_lossy()])
+                .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+                .args(args)
+                // Hooks run in the config's directory, not the caller's.
+                .current_dir(env!("CARGO_MANIFEST_DIR"))
+                .output()?,
+        )
+    };
+
+    let output = prompt_sync(&["link", "--json"])?;
+    assert!(output.status.success(), "{output:?}");
+    serde_json::from_slice::<serde_json::Value>(&output.stdout)?;
+    assert_eq!(
+        fs::read_to_string(temp.path().join("hooks.log"))?,
+        "pre\npost link\n"
+    );
+    let report: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(temp.path().join("report.json"))?)?;
+    assert_eq!(report["command"], "link");
+    assert_eq!(report["summary"]["created"], 1);
+
+    let output = prompt_sync(&["link", "--dry-run"])?;
+    assert!(output.status.success(), "{output:?}");
+    fs::remove_file(temp.path().join("AGENTS.md"))?;
+    let output = prompt_sync(&["repair"])?;
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(
+        stderr.contains("warn: post_repair hook `false` exited"),
+        "{stderr}"
+    );
+    assert_eq!(
+        fs::read_to_string(temp.path().join("hooks.log"))?,
+        "pre\npost link\nrepaired\n"
+    );
+
+    fs::write(temp.path().join("gate"), "")?;
+    fs::remove_file(temp.path().join("AGENTS.md"))?;
+    let output = prompt_sync(&["link"])?;
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    assert!(!temp.path().join("AGENTS.md").exists());
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn edit_relinks_targets_an_editor_severed_by_renaming() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let root = temp.path().join("ai");
+    fs::create_dir_all(&root)?;
+    fs::write(root.join("master.md"), "v1")?;
+    fs::write(temp.path().join("other.md"), "other")?;
+    // Saves like many editors do: a new file renamed over the old one.
+    let editor = temp.path().join("editor.sh");
+    fs::write(
+        &editor,
+        "#!/bin/sh\nprintf v2 > \"$1.new\" && mv \"$1.new\" \"$1\"\n",
+    )?;
+    fs::set_permissions(&editor, fs::Permissions::from_mode(0o755))?;
+    let config_path = temp.path().join("prompt-sync.toml");
+    fs::write(
+        &config_path,
+        "[master]\nroot = \"ai\"\n\n\
+         [[links]]\nsource = \"ai/master.md\"\ntargets = [\"AGENTS.md\"]\n\n\
+         [[links]]\nsource = \"other.md\"\ntargets = [\"OTHER.md\"]\n",
+    )?;
+    let prompt_sync = |args: &[&str]| -> anyhow::Result<std::process::Output> {
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .args(["--config", &config_path.to_string_lossy()])
+                .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+                .args(args)
+                .env("EDITOR", &editor)
+                .env_remove("VISUAL")
+                .current_dir(temp.path())
+                .output()?,
+        )
+    };
+
+    let output = prompt_sync(&["link"])?;
+    assert!(output.status.success(), "{output:?}");
+    let output = prompt_sync(&["edit"])?;
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("replaced=1"), "{stdout}");
+    let target = temp.path().join("AGENTS.md");
+    assert_eq!(fs::read_to_string(&target)?, "v2");
+    assert_eq!(
+        fs::metadata(&target)?.ino(),
+        fs::metadata(root.join("master.md"))?.ino()
+    );
+
+    let output = prompt_sync(&["edit", "other.md"])?;
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(fs::read_to_string(temp.path().join("OTHER.md"))?, "v2");
+
+    let output = prompt_sync(&["edit", "missing.md"])?;
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    Ok(())
+}
+
+#[test]
+fn protect_keeps_sources_and_targets_read_only() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    fs::write(&source, "master instruction")?;
+    let config_path = temp.path().join("prompt-sync.toml");
+    fs::write(
+        &config_path,
+        "protect = true\n\n[[links]]\nsource = \"master.md\"\ntargets = [\"a.md\"]\n\n\
+         [[links]]\nsource = \"master.md\"\ntargets = [\"b.md\"]\nlink_type = \"copy\"\n",
+    )?;
+    let prompt_sync = |args: &[&str]| -> anyhow::Result<std::process::Output> {
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .args(["--config", &config_path.to_string_lossy()])
+                .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+                .args(args)
+                .current_dir(temp.path())
+                .output()?,
+        )
+    };
+    let read_only = |name: &str| -> anyhow::Result<bool> {
+        Ok(fs::metadata(temp.path().join(name))?
+            .permissions()
+            .readonly())
+    };
+
+    let output = prompt_sync(&["link"])?;
+    assert!(output.status.success(), "{output:?}");
+    assert!(read_only("master.md")? && read_only("a.md")? && read_only("b.md")?);
+    let output = prompt_sync(&["verify"])?;
+    assert!(output.status.success(), "{output:?}");
+
+    // An editing session: writable until the next link puts protection back.
+    let output = prompt_sync(&["protect", "--unprotect"])?;
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("writable: "), "{stdout}");
+    assert!(stdout.contains("b.md"), "{stdout}");
+    assert!(!read_only("master.md")? && !read_only("b.md")?);
+
+    let output = prompt_sync(&["verify", "--json"])?;
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(report["records"][0]["status"], "BROKEN", "{report}");
+    assert!(
+        report["records"][0]["message"]
+            .as_str()
+            .is_some_and(|message| message.contains("is writable")),
+        "{report}"
+    );
+
+    let output = prompt_sync(&["verify", "--fix"])?;
+    assert!(output.status.success(), "{output:?}");
+    assert!(read_only("master.md")? && read_only("a.md")? && read_only("b.md")?);
+
+    let output = prompt_sync(&["protect", "--unprotect"])?;
+    assert!(output.status.success(), "{output:?}");
+    let output = prompt_sync(&["link"])?;
+    assert!(output.status.success(), "{output:?}");
+    assert!(read_only("master.md")? && read_only("b.md")?);
+    Ok(())
+}
+
+#[test]
+fn status_porcelain_prints_one_line_for_shell_prompts() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    fs::write(temp.path().join("master.md"), "master instruction")?;
+    let config_path = temp.path().join("prompt-sync.toml");
+    fs::write(
+        &config_path,
+        "[[links]]\nsource = \"master.md\"\ntargets = [\"a.md\", \"b.md\"]\n\n\
+         [[links]]\nsource = \"master.md\"\ntargets = [\"c.md\"]\nlink_type = \"copy\"\n",
+    )?;
+    let prompt_sync = |args: &[&str]| -> anyhow::Result<std::process::Output> {
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .args(["--config", &config_path.to_string_lossy()])
+                .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+                .args(args)
+                .current_dir(temp.path())
+                .output()?,
+        )
+    };
+
+    let output = prompt_sync(&["link"])?;
+    assert!(output.status.success(), "{output:?}");
+    let output = prompt_sync(&["status", "--porcelain"])?;
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(String::from_utf8(output.stdout)?, "prompt-sync: ok\n");
+
+    // A missing target and a local copy that replaced the hard link.
+    fs::remove_file(temp.path().join("a.md"))?;
+    fs::remove_file(temp.path().join("b.md"))?;
+    fs::write(temp.path().join("b.md"), "master instruction")?;
+    let output = prompt_sync(&["status", "--porcelain"])?;
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        "prompt-sync: 2 drifted\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn apply_carries_out_a_plan_and_refuses_a_stale_one() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    fs::write(&source, "master instruction")?;
+    fs::write(temp.path().join("b.md"), "local edits")?;
+    let config_path = temp.path().join("prompt-sync.toml");
+    fs::write(
+        &config_path,
+        "[[links]]\nsource = \"master.md\"\ntargets = [\"a.md\", \"b.md\"]\n",
+    )?;
+    let plan_path = temp.path().join("plan.json");
+    let prompt_sync = |args: &[&str]| -> anyhow::Result<std::process::Output> {
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .args(["--config", &config_path.to_string_lossy()])
+                .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+                .args(args)
+                .current_dir(temp.path())
+                .output()?,
+        )
+    };
+
+    let output = prompt_sync(&["plan", "--out", &plan_path.to_string_lossy(), "--force"])?;
+    assert!(output.status.success(), "{output:?}");
+    assert!(!temp.path().join("a.md").exists());
+    let plan: serde_json::Value = serde_json::from_str(&fs::read_to_string(&plan_path)?)?;
+    let actions = plan["actions"].as_array().expect("actions");
+    assert_eq!(actions.len(), 2, "{plan}");
+    assert_eq!(actions[0]["action"], "create");
+    assert_eq!(actions[0]["target_state"], "missing");
+    assert_eq!(actions[1]["action"], "replace");
+    assert!(
+        actions[1]["target_state"]
+            .as_str()
+            .is_some_and(|state| state.starts_with("sha256:")),
+        "{plan}"
+    );
+
+    let output = prompt_sync(&["apply", "--plan", &plan_path.to_string_lossy()])?;
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read_to_string(temp.path().join("a.md"))?,
+        "master instruction"
+    );
+    assert_eq!(
+        fs::read_to_string(temp.path().join("b.md"))?,
+        "master instruction"
+    );
+
+    // A target that changed after planning stops the whole plan.
+    fs::remove_file(temp.path().join("a.md"))?;
+    fs::remove_file(temp.path().join("b.md"))?;
+    let output = prompt_sync(&["plan", "--out", &plan_path.to_string_lossy()])?;
+    assert!(output.status.success(), "{output:?}");
+    fs::write(temp.path().join("b.md"), "written meanwhile")?;
+    let output = prompt_sync(&["apply", "--plan", &plan_path.to_string_lossy()])?;
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(
+        stderr.contains("changed since planning: target"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("out of date"), "{stderr}");
+    assert!(!temp.path().join("a.md").exists());
+    assert_eq!(
+        fs::read_to_string(temp.path().join("b.md"))?,
+        "written meanwhile"
+    );
+
+    // A plan cannot leave the link type to be decided later.
+    let text = fs::read_to_string(&plan_path)?.replace("\"hardlink\"", "\"auto\"");
+    fs::write(&plan_path, text)?;
+    let output = prompt_sync(&["apply", "--plan", &plan_path.to_string_lossy()])?;
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("link_type auto"), "{stderr}");
+    assert!(!temp.path().join("a.md").exists());
+    Ok(())
+}
+
+#[test]
+fn bootstrap_links_only_detected_vendors() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let home = temp.path().join("home");
+    fs::create_dir_all(home.join(".claude"))?;
+    fs::create_dir_all(home.join(".ai_settings"))?;
+    fs::write(home.join(".ai_settings").join("master.md"), "master")?;
+    let repo = temp.path().join("repo");
+    fs::create_dir_all(repo.join(".git"))?;
+    let bootstrap = |args: &[&str]| -> anyhow::Result<String> {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+            .args(["--config", &temp.path().join("c.toml").to_string_lossy()])
+            .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+            .args(["bootstrap", "--dry-run", "--json"])
+            .args(args)
+            .env("HOME", &home)
+            .env("XDG_CONFIG_HOME", temp.path().join("xdg"))
+            .env("PATH", "")
+            .current_dir(&repo)
+            .output()?;
+        assert!(output.status.success(), "{output:?}");
+        Ok(String::from_utf8(output.stdout)?)
+    };
+
+    let stdout = bootstrap(&[])?;
+    assert!(stdout.contains(".claude/CLAUDE.md"), "{stdout}");
+    assert!(!stdout.contains(".cursor"), "{stdout}");
+    assert!(!stdout.contains(".gemini"), "{stdout}");
+
+    let stdout = bootstrap(&["--include", "cursor"])?;
+    assert!(stdout.contains(".cursor/rules/master.mdc"), "{stdout}");
+
+    let stdout = bootstrap(&["--include", "cursor", "--exclude", "claude"])?;
+    assert!(!stdout.contains("CLAUDE.md"), "{stdout}");
+    assert!(stdout.contains(".cursor/rules/master.mdc"), "{stdout}");
+
+    // --profile names the vendors outright, installed or not.
+    let stdout = bootstrap(&["--profile", "codex", "--profile", "gemini"])?;
+    assert!(stdout.contains(".codex/AGENTS.md"), "{stdout}");
+    assert!(stdout.contains(".gemini/GEMINI.md"), "{stdout}");
+    assert!(!stdout.contains("CLAUDE.md"), "{stdout}");
+
+    let stdout = bootstrap(&["--home-only"])?;
+    assert!(stdout.contains(".claude/CLAUDE.md"), "{stdout}");
+    assert!(!stdout.contains("repo/CLAUDE.md"), "{stdout}");
+    let stdout = bootstrap(&["--repo-only"])?;
+    assert!(stdout.contains("repo/CLAUDE.md"), "{stdout}");
+    assert!(!stdout.contains(".claude/CLAUDE.md"), "{stdout}");
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+        .args(["magic", "--profile", "nope"])
+        .output()?;
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+        .args(["--config", &temp.path().join("c.toml").to_string_lossy()])
+        .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+        .args(["bootstrap", "--yes", "--profile", "claude"])
+        .env("HOME", &home)
+        .env("XDG_CONFIG_HOME", temp.path().join("xdg"))
+        .current_dir(&repo)
+        .output()?;
+    assert!(output.status.success(), "{output:?}");
+    assert!(home.join(".claude").join("CLAUDE.md").exists());
+    assert!(repo.join("CLAUDE.md").exists());
+    Ok(())
+}
+
+#[test]
+fn user_vendors_file_adds_and_overrides_profiles() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let xdg = temp.path().join("xdg");
+    fs::create_dir_all(xdg.join("prompt-sync"))?;
+    fs::write(
+        xdg.join("prompt-sync").join("vendors.toml"),
+        r#"[[vendor]]
+name = "acme"
+instructions = ["<repo>/ACME.md"]
+names = ["ACME.md"]
+
+[[vendor]]
+name = "claude"
+instructions = ["<repo>/docs/CLAUDE.md"]
+"#,
+    )?;
+    let config_path = temp.path().join("prompt-sync.toml");
+    let prompt_sync = |args: &[&str]| -> anyhow::Result<std::process::Output> {
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .args(["--config", &config_path.to_string_lossy()])
+                .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+                .args(args)
+                .env("XDG_CONFIG_HOME", &xdg)
+                .current_dir(temp.path())
+                .output()?,
+        )
+    };
+
+    let output = prompt_sync(&["init", "--profile", "acme", "--profile", "claude"])?;
+    assert!(output.status.success(), "{output:?}");
+    let text = fs::read_to_string(&config_path)?;
+    // The override keeps claude's skills directory.
+    assert!(
+        text.contains("\n    \"<repo>/docs/CLAUDE.md\",\n    \"<repo>/ACME.md\",\n]"),
+        "{text}"
+    );
+    assert!(text.contains("\"~/.claude/skills\""), "{text}");
+
+    // A registered vendor tags rules like a built-in one.
+    fs::write(temp.path().join("master.md"), "master instruction")?;
+    fs::write(
+        &config_path,
+        r#"version = 1
+
+[[links]]
+source = "master.md"
+targets = ["ACME.md"]
+profiles = ["acme"]
+"#,
+    )?;
+    let output = prompt_sync(&["link", "--profile", "acme"])?;
+    assert!(output.status.success(), "{output:?}");
+    assert!(temp.path().join("ACME.md").exists());
+
+    let output = prompt_sync(&["link", "--profile", "nope"])?;
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("unknown profile `nope`"), "{stderr}");
+    assert!(stderr.contains("amazon-q, acme"), "{stderr}");
+
+    Ok(())
+}
+
+#[test]
+fn init_profiles_cover_repo_rule_files_of_newer_tools() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let config_path = temp.path().join("prompt-sync.toml");
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+        .args(["--config", &config_path.to_string_lossy()])
+        .args(["init", "--profile", "windsurf", "--profile", "amazon-q"])
+        .output()?;
+    assert!(output.status.success(), "{output:?}");
+    let text = fs::read_to_string(&config_path)?;
+    assert!(
+        text.contains(
+            "\n    \"<repo>/.windsurfrules\",\n    \"<repo>/.amazonq/rules/master.md\",\n]"
+        ),
+        "{text}"
+    );
+    // Neither tool has a skills directory.
+    assert!(text.contains("skills_sets = []"), "{text}");
+
+    Ok(())
+}
+
+#[test]
+fn target_add_appends_to_the_existing_rule_after_validating() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    fs::write(temp.path().join("master.md"), "master instruction")?;
+    fs::write(temp.path().join("other.md"), "other instruction")?;
+    let config = temp.path().join("prompt-sync.toml");
+    let original = r#"version = 1
+
+# Shared by every tool.
+[[links]]
+source = "master.md"
+targets = ["AGENTS.md"]
+
+[[links]]
+source = "other.md"
+targets = ["OTHER.md"]
+"#;
+    fs::write(&config, original)?;
+    let target_add = |args: &[&str]| -> anyhow::Result<std::process::Output> {
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .args(["--config", &config.to_string_lossy()])
+                .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+                .args(["target", "add"])
+                .args(args)
+                .current_dir(temp.path())
+                .output()?,
+        )
+    };
+
+    // A target another rule already maps would fail `config check`.
+    let output = target_add(&["--source", "master.md", "CLAUDE.md", "OTHER.md"])?;
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    assert!(String::from_utf8(output.stderr)?.contains("is mapped from multiple sources"));
+    assert_eq!(fs::read_to_string(&config)?, original);
+
+    // So would the rule's own source, and a source no rule has.
+    for args in [
+        ["--source", "master.md", "master.md"],
+        ["--source", "missing.md", "CLAUDE.md"],
+    ] {
+        let output = target_add(&args)?;
+        assert_eq!(output.status.code(), Some(2), "{output:?}");
+    }
+
+    let output = target_add(&[
+        "--source",
+        "master.md",
+        "CLAUDE.md",
+        "AGENTS.md",
+        "CLAUDE.md",
+        "--link",
+    ])?;
+    assert!(output.status.success(), "{output:?}");
+    let text = fs::read_to_string(&config)?;
+    assert!(
+        text.contains("# Shared by every tool.\n[[links]]\nsource = \"master.md\"\ntargets = [\"AGENTS.md\", \"CLAUDE.md\"]\n"),
+        "{text}"
+    );
+    assert_eq!(
+        fs::read_to_string(temp.path().join("CLAUDE.md"))?,
+        "master instruction"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn add_and_remove_edit_link_rules_keeping_comments() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    fs::write(temp.path().join("master.md"), "master instruction")?;
+    let config = temp.path().join("prompt-sync.toml");
+    fs::write(
+        &config,
+        r#"version = 1
+
+[[links]]
+source = "master.md" # the shared master
+targets = [
+  "AGENTS.md",
+]
+"#,
+    )?;
+    let prompt_sync = |args: &[&str]| -> anyhow::Result<std::process::Output> {
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .args(["--config", &config.to_string_lossy()])
+                .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+                .args(args)
+                .current_dir(temp.path())
+                .output()?,
+        )
+    };
+
+    // A new target joins the rule for the same source and can be linked.
+    let output = prompt_sync(&[
+        "add",
+        "--source",
+        "master.md",
+        "--target",
+        "CLAUDE.md",
+        "--target",
+        "AGENTS.md",
+        "--link",
+    ])?;
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("unchanged: AGENTS.md is already a target of links[0]"));
+    let text = fs::read_to_string(&config)?;
+    assert!(
+        text.contains("source = \"master.md\" # the shared master"),
+        "{text}"
+    );
+    assert!(
+        text.contains("  \"AGENTS.md\",\n  \"CLAUDE.md\",\n]"),
+        "{text}"
+    );
+    assert_eq!(
+        fs::read_to_string(temp.path().join("CLAUDE.md"))?,
+        "master instruction"
+    );
+    assert!(!temp.path().join("AGENTS.md").exists());
+
+    // Another link type gets a rule of its own.
+    let output = prompt_sync(&[
+        "add",
+        "--source",
+        "master.md",
+        "--target",
+        "GEMINI.md",
+        "--type",
+        "copy",
+    ])?;
+    assert!(output.status.success(), "{output:?}");
+    assert!(
+        String::from_utf8(output.stdout)?.contains("added rule links[1]: master.md -> GEMINI.md")
+    );
+    assert!(fs::read_to_string(&config)?.contains("link_type = \"copy\""));
+
+    let output = prompt_sync(&["remove", "--source", "master.md", "--target", "nope.md"])?;
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+
+    // Without --target every rule for the source goes; --unlink removes
+    // the link made above.
+    let output = prompt_sync(&["remove", "--source", "master.md", "--unlink"])?;
+    assert!(output.status.success(), "{output:?}");
+    let text = fs::read_to_string(&config)?;
+    assert!(!text.contains("[[links]]"), "{text}");
+    assert!(text.starts_with("version = 1\n"), "{text}");
+    assert!(!temp.path().join("CLAUDE.md").exists());
+    assert!(temp.path().join("master.md").exists());
+
+    Ok(())
+}
+
+#[test]
+fn explain_shows_the_rule_tokens_state_and_planned_actions() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let repo = temp.path().join("repo");
+    fs::create_dir_all(&repo)?;
+    fs::write(temp.path().join("master.md"), "master instruction")?;
+    fs::write(repo.join("AGENTS.md"), "local edit")?;
+    let config = temp.path().join("prompt-sync.toml");
+    fs::write(
+        &config,
+        r#"version = 1
+
+[vars]
+ai = "<config_dir>"
+
+[[links]]
+source = "<ai>/master.md"
+targets = ["<repo>/AGENTS.md"]
+"#,
+    )?;
+    let explain = |path: &str| -> anyhow::Result<std::process::Output> {
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .args(["--config", &config.to_string_lossy()])
+                .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+                .args(["explain", path])
+                .current_dir(&repo)
+                .output()?,
+        )
+    };
+
+    let output = explain("AGENTS.md")?;
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8(output.stdout)?;
+    let field = |label: &str| {
+        stdout
+            .lines()
+            .filter(|line| line.trim_start().starts_with(label))
+            .map(|line| line.trim_start()[label.len()..].trim().to_owned())
+            .collect::<Vec<_>>()
+    };
+    assert!(field("rule:")[0].starts_with("links[0] in "), "{stdout}");
+    assert!(
+        field("token:")
+            .iter()
+            .any(|token| token.starts_with("<repo> = ")),
+        "{stdout}"
+    );
+    assert!(field("status:")[0].starts_with("CONFLICT"), "{stdout}");
+    assert!(field("target file:")[0].contains("1 link(s), 10 bytes, sha256 "));
+    assert!(field("link:")[0].contains("use --force"), "{stdout}");
+    assert!(
+        field("link --force:")[0].starts_with("WOULD_REPLACE"),
+        "{stdout}"
+    );
+    assert_eq!(fs::read_to_string(repo.join("AGENTS.md"))?, "local edit");
+
+    // The source explains the same mapping.
+    let output = explain("../master.md")?;
+    assert!(output.status.success(), "{output:?}");
+    assert!(String::from_utf8(output.stdout)?.contains("is the source of:"));
+
+    let output = explain("CLAUDE.md")?;
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+
+    Ok(())
+}
+
+#[test]
+fn reports_carry_a_schema_version_and_print_schema_describes_them() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    fs::write(temp.path().join("master.md"), "master instruction")?;
+    let config = temp.path().join("prompt-sync.toml");
+    fs::write(
+        &config,
+        "version = 1\n\n[[links]]\nsource = \"master.md\"\ntargets = [\"out/AGENTS.md\"]\n",
+    )?;
+    let run = |args: &[&str]| -> anyhow::Result<serde_json::Value> {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+            .args(["--config", &config.to_string_lossy()])
+            .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+            .args(args)
+            .current_dir(temp.path())
+            .output()?;
+        assert!(output.status.success(), "{output:?}");
+        Ok(serde_json::from_slice(&output.stdout)?)
+    };
+
+    let schema = run(&["verify", "--print-schema"])?;
+    assert_eq!(schema["title"], "prompt-sync report");
+    assert!(!temp.path().join("state").exists());
+
+    let report = run(&["link", "--json"])?;
+    assert_eq!(
+        report["schema_version"],
+        schema["properties"]["schema_version"]["const"]
+    );
+    let top = schema["properties"].as_object().expect("properties");
+    for key in report.as_object().expect("report").keys() {
+        assert!(
+            top.contains_key(key),
+            "{key} missing from the report schema"
+        );
+    }
+    let fields = schema["$defs"]["record"]["properties"]
+        .as_object()
+        .expect("record properties");
+    for record in report["records"].as_array().expect("records") {
+        for key in record.as_object().expect("record").keys() {
+            assert!(
+                fields.contains_key(key),
+                "{key} missing from the record schema"
+            );
+        }
+        let statuses = fields["status"]["enum"].as_array().expect("statuses");
+        assert!(statuses.contains(&record["status"]));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn records_name_the_rule_and_templates_they_came_from() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    fs::write(temp.path().join("master.md"), "master instruction")?;
+    fs::write(temp.path().join("blocker"), "a file, not a directory")?;
+    let config = temp.path().join("prompt-sync.toml");
+    fs::write(
+        &config,
+        r#"version = 1
+
+[[links]]
+source = "master.md"
+targets = ["<config_dir>/out/AGENTS.md"]
+
+[[links]]
+source = "<config_dir>/master.md"
+targets = ["blocker/CLAUDE.md"]
+"#,
+    )?;
+    let run = |args: &[&str]| -> anyhow::Result<std::process::Output> {
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .args(["--config", &config.to_string_lossy()])
+                .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+                .args(args)
+                .current_dir(temp.path())
+                .output()?,
+        )
+    };
+
+    let output = run(&["link", "--json"])?;
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    let failed = report["records"]
+        .as_array()
+        .and_then(|records| records.iter().find(|record| record["status"] == "ERROR"))
+        .expect("failed record");
+    assert_eq!(failed["rule_kind"], "links");
+    assert_eq!(failed["rule_index"], 1);
+    assert_eq!(failed["source_template"], "<config_dir>/master.md");
+    assert_eq!(failed["target_template"], "blocker/CLAUDE.md");
+    assert!(
+        failed["config"]
+            .as_str()
+            .is_some_and(|path| path.ends_with("prompt-sync.toml"))
+    );
+
+    let output = run(&["link"])?;
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(
+        stdout.lines().any(|line| line.starts_with("[Error] ")
+            && line.contains("[links[1] in ")
+            && line.ends_with("prompt-sync.toml]")),
+        "{stdout}"
+    );
+
+    let output = run(&["list", "--json"])?;
+    let plan: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(plan[0]["target_template"], "<config_dir>/out/AGENTS.md");
+
+    Ok(())
+}
+
+#[test]
+fn reports_carry_timing_and_timing_lists_the_slowest_records() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    fs::write(temp.path().join("master.md"), "master instruction")?;
+    let config = temp.path().join("prompt-sync.toml");
+    fs::write(
+        &config,
+        r#"version = 1
+
+[[links]]
+source = "master.md"
+targets = ["out/AGENTS.md", "out/CLAUDE.md"]
+"#,
+    )?;
+    let run = |args: &[&str]| -> anyhow::Result<std::process::Output> {
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .args(["--config", &config.to_string_lossy()])
+                .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+                .args(args)
+                .current_dir(temp.path())
+                .output()?,
+        )
+    };
+
+    let output = run(&["link", "--json"])?;
+    assert!(output.status.success(), "{output:?}");
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert!(report["started_at"].is_string(), "{report}");
+    assert!(report["elapsed_ms"].as_f64().is_some_and(|ms| ms >= 0.0));
+    let records = report["records"].as_array().expect("records");
+    assert_eq!(records.len(), 2);
+    assert!(records.iter().all(|record| record["duration_ms"].is_f64()));
+
+    let output = run(&["verify", "--timing"])?;
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8(output.stdout)?;
+    let timing = stdout
+        .lines()
+        .position(|line| line.starts_with("timing: ") && line.contains("ms for 2 record(s)"))
+        .expect("timing line");
+    let phases = stdout.lines().nth(timing + 1).unwrap_or_default();
+    assert!(phases.starts_with("phases: config "), "{stdout}");
+    let slowest = stdout.lines().skip(timing + 2).collect::<Vec<_>>();
+    assert_eq!(slowest.len(), 2, "{stdout}");
+    assert!(
+        slowest.iter().all(|line| line.contains("ms [Ok] ")),
+        "{stdout}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn verify_baseline_accepts_listed_target_status_pairs() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let repo = temp.path().join("repo");
+    fs::create_dir_all(&repo)?;
+    fs::write(temp.path().join("master.md"), "master instruction")?;
+    fs::write(repo.join("AGENTS.md"), "local edit")?;
+    let config = temp.path().join("prompt-sync.toml");
+    fs::write(
+        &config,
+        r#"version = 1
+
+[[links]]
+source = "master.md"
+targets = ["<repo>/AGENTS.md", "<repo>/docs/CLAUDE.md"]
+"#,
+    )?;
+    let baseline = temp.path().join("baseline.json");
+    let run = |args: &[&str]| -> anyhow::Result<std::process::Output> {
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .args(["--config", &config.to_string_lossy()])
+                .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+                .args(["verify", "--baseline", &baseline.to_string_lossy()])
+                .args(args)
+                .current_dir(&repo)
+                .output()?,
+        )
+    };
+
+    let output = run(&[])?;
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+
+    let output = run(&["--update-baseline"])?;
+    assert_eq!(output.status.code(), Some(0), "{output:?}");
+    assert!(String::from_utf8(output.stdout)?.contains("baseline: accepted 2 target(s)"));
+    let written: serde_json::Value = serde_json::from_str(&fs::read_to_string(&baseline)?)?;
+    assert_eq!(
+        written["accepted"],
+        serde_json::json!([
+            {"target": "repo/AGENTS.md", "status": "CONFLICT"},
+            {"target": "repo/docs/CLAUDE.md", "status": "MISSING"},
+        ])
+    );
+
+    let output = run(&["--json"])?;
+    assert_eq!(output.status.code(), Some(0), "{output:?}");
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(report["summary"]["accepted"], 2);
+    assert_eq!(report["records"][0]["status"], "ACCEPTED");
+    assert!(
+        report["records"][0]["message"]
+            .as_str()
+            .is_some_and(|message| message.starts_with("baseline CONFLICT"))
+    );
+
+    // A different status on an accepted target fails again.
+    fs::remove_file(repo.join("AGENTS.md"))?;
+    let output = run(&[])?;
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+
+    Ok(())
+}
+
+#[test]
+fn verify_output_github_annotates_problem_targets() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let repo = temp.path().join("repo");
+    fs::create_dir_all(&repo)?;
+    fs::write(temp.path().join("master.md"), "master instruction")?;
+    fs::write(repo.join("AGENTS.md"), "local edit")?;
+    let config = temp.path().join("prompt-sync.toml");
+    fs::write(
+        &config,
+        r#"version = 1
+
+[[links]]
+source = "master.md"
+targets = ["<repo>/AGENTS.md", "<repo>/docs/CLAUDE.md"]
+"#,
+    )?;
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+        .args(["--config", &config.to_string_lossy()])
+        .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+        .args(["verify", "--output", "github"])
+        .current_dir(&repo)
+        .env("GITHUB_WORKSPACE", &repo)
+        .output()?;
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let stdout = String::from_utf8(output.stdout)?;
+    let lines = stdout
+        .lines()
+        .filter(|line| line.starts_with("::"))
+        .collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2, "{stdout}");
+    assert!(
+        lines[0].starts_with("::error file=AGENTS.md,title=prompt-sync%3A conflict::"),
+        "{stdout}"
+    );
+    assert!(
+        lines[1].starts_with("::warning file=docs/CLAUDE.md,title=prompt-sync%3A missing::"),
+        "{stdout}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn empty_home_counts_as_unset_when_resolving_tilde() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    fs::write(temp.path().join("master.md"), "master instruction")?;
+    let config = temp.path().join("prompt-sync.toml");
+    fs::write(
+        &config,
+        "version = 1\n\n[[links]]\nsource = \"master.md\"\ntargets = [\"~/.claude/CLAUDE.md\"]\n",
+    )?;
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+        .args(["--config", &config.to_string_lossy()])
+        .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+        .args(["config", "check"])
+        .env("HOME", "")
+        .env_remove("USERPROFILE")
+        .output()?;
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(
+        stdout.contains("cannot resolve ~ in path: ~/.claude/CLAUDE.md"),
+        "{stdout}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn vars_become_path_tokens_and_reject_cycles_and_unknown_tokens() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let home = temp.path().join("home");
+    fs::create_dir_all(home.join("src").join("dotfiles").join("ai"))?;
+    fs::write(
+        home.join("src")
+            .join("dotfiles")
+            .join("ai")
+            .join("master.md"),
+        "master instruction",
+    )?;
+    let config = temp.path().join("prompt-sync.toml");
+    let run = |text: &str| -> anyhow::Result<std::process::Output> {
+        fs::write(&config, text)?;
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .args(["--config", &config.to_string_lossy()])
+                .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+                .arg("list")
+                .env("HOME", &home)
+                .output()?,
+        )
+    };
+    let rule = "[[links]]\nsource = \"<ai>/master.md\"\ntargets = [\"~/.claude/CLAUDE.md\"]\n";
+
+    let output = run(&format!(
+        "[vars]\ndotfiles = \"~/src/dotfiles\"\nai = \"<dotfiles>/ai\"\n\n{rule}"
+    ))?;
+    assert!(output.status.success(), "{output:?}");
+    let listed = String::from_utf8(output.stdout)?.replace('\\', "/");
+    assert!(
+        listed.contains("home/src/dotfiles/ai/master.md -> "),
+        "{listed}"
+    );
+
+    let output = run(&format!(
+        "[vars]\nai = \"<dotfiles>/ai\"\ndotfiles = \"<ai>/..\"\n\n{rule}"
+    ))?;
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    assert!(String::from_utf8(output.stderr)?.contains("cycle: ai -> dotfiles -> ai"));
+
+    let output = run(&format!("[vars]\nai = \"<dotfile>/ai\"\n\n{rule}"))?;
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    assert!(String::from_utf8(output.stderr)?.contains("links[0]: unknown token <dotfile>"));
+
+    Ok(())
+}
+
+#[test]
+fn path_tokens_expand_user_host_config_dir_and_xdg_dirs() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    fs::write(temp.path().join("master.md"), "master instruction")?;
+    let config = temp.path().join("prompt-sync.toml");
+    fs::write(
+        &config,
+        r#"[[links]]
+source = "<config_dir>/master.md"
+targets = [
+    "<xdg_config>/tool/AGENTS.md",
+    "<xdg_data>/tool/AGENTS.md",
+    "<home>/<user>@<hostname>.md",
+]
+"#,
+    )?;
+    let home = temp.path().join("home");
+    let run = |args: &[&str], user: Option<&str>| -> anyhow::Result<std::process::Output> {
+        let mut command = std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"));
+        command
+            .args(["--config", &config.to_string_lossy()])
+            .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+            .args(args)
+            .env("HOME", &home)
+            .env("XDG_CONFIG_HOME", temp.path().join("xdg-config"))
+            .env_remove("XDG_DATA_HOME")
+            .env_remove("USER")
+            .env_remove("USERNAME")
+            .env("PROMPT_SYNC_HOSTNAME", "desk");
+        if let Some(user) = user {
+            command.env("USER", user);
+        }
+        Ok(command.output()?)
+    };
+
+    let output = run(&["list"], Some("me"))?;
+    assert!(output.status.success(), "{output:?}");
+    let listed = String::from_utf8(output.stdout)?.replace('\\', "/");
+    let root = temp.path().display().to_string().replace('\\', "/");
+    assert!(
+        listed.contains(&format!("{root}/master.md -> ")),
+        "{listed}"
+    );
+    assert!(
+        listed.contains(&format!("{root}/xdg-config/tool/AGENTS.md")),
+        "{listed}"
+    );
+    assert!(
+        listed.contains(&format!("{root}/home/.local/share/tool/AGENTS.md")),
+        "{listed}"
+    );
+    assert!(
+        listed.contains(&format!("{root}/home/me@desk.md")),
+        "{listed}"
+    );
+
+    let output = run(&["config", "check"], None)?;
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    assert!(String::from_utf8(output.stdout)?.contains("<user>"));
+
+    Ok(())
+}
+
+#[test]
+fn when_conditions_toggle_rules_on_environment_variables() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    fs::write(temp.path().join("master.md"), "master instruction")?;
+    fs::create_dir_all(temp.path().join("skills").join("review"))?;
+    fs::write(
+        temp.path().join("skills").join("review").join("SKILL.md"),
+        "skill",
+    )?;
+    let root = temp.path().display().to_string().replace('\\', "/");
+    let config = temp.path().join("prompt-sync.toml");
+    fs::write(
+        &config,
+        format!(
+            r#"[[links]]
+source = "{root}/master.md"
+targets = ["{root}/corp/AGENTS.md"]
+when = {{ env = "PROMPT_SYNC_TEST_WORK", equals = "1" }}
+
+[[links]]
+source = "{root}/master.md"
+targets = ["{root}/beta/AGENTS.md"]
+when = {{ env = "PROMPT_SYNC_TEST_BETA" }}
+
+[[skills_sets]]
+source_root = "{root}/skills"
+target_roots = ["{root}/corp/skills"]
+when = {{ env = "PROMPT_SYNC_TEST_WORK", equals = "1" }}
+"#
+        ),
+    )?;
+    let list = |work: Option<&str>, beta: Option<&str>| -> anyhow::Result<String> {
+        let mut command = std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"));
+        command
+            .args(["--config", &config.to_string_lossy()])
+            .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+            .arg("list")
+            .env_remove("PROMPT_SYNC_TEST_WORK")
+            .env_remove("PROMPT_SYNC_TEST_BETA");
+        if let Some(work) = work {
+            command.env("PROMPT_SYNC_TEST_WORK", work);
+        }
+        if let Some(beta) = beta {
+            command.env("PROMPT_SYNC_TEST_BETA", beta);
+        }
+        let output = command.output()?;
+        assert!(output.status.success(), "{output:?}");
+        Ok(String::from_utf8(output.stdout)?.replace('\\', "/"))
+    };
+
+    assert_eq!(list(None, None)?, "");
+    let work = list(Some("1"), Some(""))?;
+    assert_eq!(work.lines().count(), 2, "{work}");
+    assert!(work.contains("corp/AGENTS.md"), "{work}");
+    assert!(work.contains("corp/skills/review/SKILL.md"), "{work}");
+    let beta = list(Some("0"), Some("yes"))?;
+    assert_eq!(beta.lines().count(), 1, "{beta}");
+    assert!(beta.contains("beta/AGENTS.md"), "{beta}");
+
+    Ok(())
+}
+
+#[test]
+fn host_sections_override_the_master_root_and_targets_per_machine() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let home = temp.path().join("home");
+    for dir in ["shared", "work"] {
+        fs::create_dir_all(home.join(dir))?;
+        fs::write(home.join(dir).join("master.md"), dir)?;
+    }
+    fs::write(home.join("work").join("extra.md"), "extra")?;
+    let config = temp.path().join("prompt-sync.toml");
+    fs::write(
+        &config,
+        r#"[master]
+root = "~/shared"
+
+[[links]]
+source = "~/shared/master.md"
+targets = ["~/.claude/CLAUDE.md", "~/.codex/AGENTS.md"]
+
+[hosts."work-laptop"]
+master = { root = "~/work" }
+remove_targets = ["~/.codex/AGENTS.md"]
+
+[[hosts."work-laptop".links]]
+source = "~/work/extra.md"
+targets = ["~/.gemini/GEMINI.md"]
+"#,
+    )?;
+    let list = |hostname: &str| -> anyhow::Result<String> {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+            .args(["--config", &config.to_string_lossy()])
+            .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+            .arg("list")
+            .env("HOME", &home)
+            .env("PROMPT_SYNC_HOSTNAME", hostname)
+            .output()?;
+        assert!(output.status.success(), "{output:?}");
+        Ok(String::from_utf8(output.stdout)?.replace('\\', "/"))
+    };
+
+    let desktop = list("desktop")?;
+    assert_eq!(desktop.lines().count(), 2, "{desktop}");
+    assert!(desktop.contains("shared/master.md -> "), "{desktop}");
+    assert!(desktop.contains(".codex/AGENTS.md"), "{desktop}");
+
+    // The first label matches, case-insensitively.
+    let laptop = list("Work-Laptop.corp.example")?;
+    assert_eq!(laptop.lines().count(), 2, "{laptop}");
+    assert!(laptop.contains("work/master.md -> "), "{laptop}");
+    assert!(!laptop.contains("shared/"), "{laptop}");
+    assert!(!laptop.contains(".codex/AGENTS.md"), "{laptop}");
+    assert!(laptop.contains("work/extra.md -> "), "{laptop}");
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn import_convert_symlinks_replaces_and_adopts_symlinked_targets() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let home = temp.path().join("home");
+    let dotfiles = home.join("dotfiles");
+    fs::create_dir_all(&dotfiles)?;
+    fs::write(dotfiles.join("master.md"), "master")?;
+    fs::write(dotfiles.join("gemini.md"), "gemini")?;
+    for (dir, name, source) in [
+        (".claude", "CLAUDE.md", "master.md"),
+        (".codex", "AGENTS.md", "master.md"),
+        (".gemini", "GEMINI.md", "gemini.md"),
+    ] {
+        fs::create_dir_all(home.join(dir))?;
+        symlink(dotfiles.join(source), home.join(dir).join(name))?;
+    }
+    let config = temp.path().join("prompt-sync.toml");
+    let original =
+        "[[links]]\nsource = \"~/dotfiles/master.md\"\ntargets = [\"~/.claude/CLAUDE.md\"]\n";
+    fs::write(&config, original)?;
+    let convert = |extra: &[&str]| -> anyhow::Result<std::process::Output> {
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .args(["--config", &config.to_string_lossy()])
+                .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+                .args(["import", "--convert-symlinks"])
+                .args(extra)
+                .env("HOME", &home)
+                .output()?,
+        )
+    };
+    let is_symlink = |path: PathBuf| -> anyhow::Result<bool> {
+        Ok(fs::symlink_metadata(path)?.file_type().is_symlink())
+    };
+
+    let output = convert(&["--dry-run"])?;
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8(output.stdout)?;
+    assert_eq!(stdout.matches("adopted: ").count(), 2, "{stdout}");
+    assert_eq!(fs::read_to_string(&config)?, original);
+    assert!(is_symlink(home.join(".claude").join("CLAUDE.md"))?);
+
+    let output = convert(&[])?;
+    assert!(output.status.success(), "{output:?}");
+    let text = fs::read_to_string(&config)?;
+    assert_eq!(text.matches("[[links]]").count(), 2, "{text}");
+    assert!(text.contains("\"~/.codex/AGENTS.md\""), "{text}");
+    assert!(text.contains("source = \"~/dotfiles/gemini.md\""), "{text}");
+    let master_inode = fs::metadata(dotfiles.join("master.md"))?.ino();
+    for target in [
+        home.join(".claude").join("CLAUDE.md"),
+        home.join(".codex").join("AGENTS.md"),
+    ] {
+        assert!(!is_symlink(target.clone())?, "{}", target.display());
+        assert_eq!(fs::metadata(&target)?.ino(), master_inode);
+    }
+    assert!(!is_symlink(home.join(".gemini").join("GEMINI.md"))?);
+    assert_eq!(fs::read_to_string(dotfiles.join("gemini.md"))?, "gemini");
+
+    let output = convert(&[])?;
+    assert!(output.status.success(), "{output:?}");
+    assert!(!String::from_utf8(output.stdout)?.contains("adopted: "));
+
+    Ok(())
+}
+
+#[test]
+fn import_turns_managed_instruction_files_and_skills_into_rules() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let write = |path: PathBuf, content: &str| -> anyhow::Result<()> {
+        fs::create_dir_all(path.parent().expect("parent"))?;
+        fs::write(path, content)?;
+        Ok(())
+    };
+    let stow = temp.path().join("stow");
+    write(
+        stow.join("ai").join("dot-claude").join("CLAUDE.md"),
+        "shared",
+    )?;
+    write(stow.join("ai").join(".codex").join("AGENTS.md"), "shared")?;
+    write(stow.join("ai").join(".gemini").join("GEMINI.md"), "gemini")?;
+    write(
+        stow.join("ai")
+            .join("dot-claude")
+            .join("skills")
+            .join("review")
+            .join("SKILL.md"),
+        "skill",
+    )?;
+    write(stow.join("shell").join(".bashrc"), "unrelated")?;
+    let chezmoi = temp.path().join("chezmoi");
+    write(
+        chezmoi.join("dot_claude").join("private_CLAUDE.md"),
+        "shared",
+    )?;
+    write(
+        chezmoi.join("dot_codex").join("AGENTS.md.tmpl"),
+        "{{ .name }}",
+    )?;
+    write(chezmoi.join(".chezmoiignore"), "")?;
+
+    let import = |from: &str, dir: &Path, config: &Path| -> anyhow::Result<std::process::Output> {
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .args(["--config", &config.to_string_lossy()])
+                .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+                .args(["import", "--from", from])
+                .arg(dir)
+                .env("HOME", temp.path().join("home"))
+                .output()?,
+        )
+    };
+
+    let config = temp.path().join("stow.toml");
+    let output = import("stow", &stow, &config)?;
+    assert!(output.status.success(), "{output:?}");
+    assert!(String::from_utf8(output.stdout)?.contains("`stow -D`"));
+    let text = fs::read_to_string(&config)?;
+    assert_eq!(text.matches("[[links]]").count(), 2, "{text}");
+    assert!(
+        text.contains("\"~/.claude/CLAUDE.md\",\n    \"~/.codex/AGENTS.md\","),
+        "identical files share a rule: {text}"
+    );
+    assert!(
+        text.contains("dot-claude/skills\"\ntarget_roots = [\"~/.claude/skills\"]"),
+        "{text}"
+    );
+    assert!(!text.contains("bashrc"));
+    assert_eq!(import("stow", &stow, &config)?.status.code(), Some(2));
+
+    let config = temp.path().join("chezmoi.yaml");
+    let output = import("chezmoi", &chezmoi, &config)?;
+    assert!(output.status.success(), "{output:?}");
+    assert!(String::from_utf8(output.stderr)?.contains("AGENTS.md.tmpl: a template"));
+    let text = fs::read_to_string(&config)?;
+    assert!(text.contains("private_CLAUDE.md"), "{text}");
+    assert!(text.contains("~/.claude/CLAUDE.md"), "{text}");
+    assert!(!text.contains("AGENTS"), "templates are not linked: {text}");
+
+    let output = import("chezmoi", &temp.path().join("home"), &config)?;
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+
+    Ok(())
+}
+
+#[test]
+fn export_stow_mirrors_targets_below_the_target_dir() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    fs::write(temp.path().join("master.md"), "master instruction")?;
+    let root = temp.path().display().to_string().replace('\\', "/");
+    let config = temp.path().join("prompt-sync.toml");
+    fs::write(
+        &config,
+        format!(
+            "[[links]]\nsource = \"{root}/master.md\"\ntargets = [\"{root}/home/.claude/CLAUDE.md\", \"{root}/repo/AGENTS.md\"]\n"
+        ),
+    )?;
+    let export = |extra: &[&str]| -> anyhow::Result<std::process::Output> {
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .args(["--config", &config.to_string_lossy()])
+                .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+                .args(["export", "--format", "stow"])
+                .arg(temp.path().join("stow"))
+                .arg("--target-dir")
+                .arg(temp.path().join("home"))
+                .args(extra)
+                .output()?,
+        )
+    };
+    let exported = temp
+        .path()
+        .join("stow")
+        .join("prompt-sync")
+        .join(".claude")
+        .join("CLAUDE.md");
+
+    let output = export(&[])?;
+    assert!(output.status.success(), "{output:?}");
+    assert!(
+        String::from_utf8(output.stdout)?.contains("1 written, 0 unchanged, 1 skipped"),
+        "export summary"
+    );
+    assert!(String::from_utf8(output.stderr)?.contains("AGENTS.md: not under"));
+    assert_eq!(fs::read_to_string(&exported)?, "master instruction");
+    assert!(!temp.path().join("home").exists());
+
+    fs::write(&exported, "edited through stow")?;
+    let output = export(&[])?;
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    assert_eq!(fs::read_to_string(&exported)?, "edited through stow");
+    assert!(export(&["--force"])?.status.success());
+    assert_eq!(fs::read_to_string(&exported)?, "master instruction");
+
+    Ok(())
+}
+
+#[test]
+fn url_sources_are_cached_refreshed_in_place_and_used_offline() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let server = temp.path().join("server");
+    fs::create_dir_all(&server)?;
+    fs::write(server.join("master.md"), "v1")?;
+    let root = temp.path().display().to_string().replace('\\', "/");
+    let url = format!(
+        "file://{}{root}/server/master.md",
+        if root.starts_with('/') { "" } else { "/" }
+    );
+    let config = temp.path().join("prompt-sync.toml");
+    fs::write(
+        &config,
+        format!("[[links]]\nsource = \"{url}\"\ntargets = [\"{root}/out/AGENTS.md\"]\n"),
+    )?;
+    let run = |command: &str| -> anyhow::Result<std::process::Output> {
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .args(["--config", &config.to_string_lossy()])
+                .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+                .arg(command)
+                .env("XDG_CACHE_HOME", temp.path().join("cache"))
+                .output()?,
+        )
+    };
+    let target = temp.path().join("out").join("AGENTS.md");
+
+    // Read-only commands never download; an unfetched source is reported.
+    let output = run("verify")?;
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    assert!(String::from_utf8(output.stdout)?.contains("has not been fetched yet"));
+    assert!(!temp.path().join("cache").exists());
+
+    let output = run("link")?;
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(fs::read_to_string(&target)?, "v1");
+    let cache = temp
+        .path()
+        .join("cache")
+        .join("prompt-sync")
+        .join("sources");
+    let cached = fs::read_dir(&cache)?
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("nothing cached"))??
+        .path()
+        .join("master.md");
+    assert_eq!(fs::read_to_string(&cached)?, "v1");
+
+    // The cached copy is rewritten in place, so the hardlink follows it.
+    // If-Modified-Since has one-second resolution.
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    fs::write(server.join("master.md"), "v2")?;
+    let output = run("verify")?;
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(fs::read_to_string(&target)?, "v1");
+    let output = run("link")?;
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(fs::read_to_string(&target)?, "v2");
+
+    fs::remove_file(server.join("master.md"))?;
+    let output = run("link")?;
+    assert!(output.status.success(), "{output:?}");
+    assert!(String::from_utf8(output.stderr)?.contains("using the cached copy"));
+    assert_eq!(fs::read_to_string(&cached)?, "v2");
+
+    Ok(())
+}
+
+#[test]
+fn sync_clones_the_master_repo_and_relinks_after_each_pull() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let git = |dir: &Path, args: &[&str]| -> anyhow::Result<()> {
+        let output = std::process::Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .output()?;
+        anyhow::ensure!(output.status.success(), "{output:?}");
+        Ok(())
+    };
+    let seed = temp.path().join("seed");
+    fs::create_dir_all(&seed)?;
+    git(&seed, &["init", "-q", "-b", "main"])?;
+    fs::write(seed.join("master.md"), "v1")?;
+    git(&seed, &["add", "master.md"])?;
+    git(&seed, &["commit", "-q", "-m", "v1"])?;
+    let remote = temp.path().join("remote.git");
+    git(
+        temp.path(),
+        &["clone", "-q", "--bare", "seed", "remote.git"],
+    )?;
+
+    let root = temp.path().display().to_string().replace('\\', "/");
+    let config = temp.path().join("prompt-sync.toml");
+    fs::write(
+        &config,
+        format!(
+            "[master]\nroot = \"{root}/ai_settings\"\nrepo = \"{root}/remote.git\"\n\n[[links]]\nsource = \"{root}/ai_settings/master.md\"\ntargets = [\"{root}/out/AGENTS.md\"]\n"
+        ),
+    )?;
+    let sync = || -> anyhow::Result<std::process::Output> {
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .args(["--config", &config.to_string_lossy()])
+                .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+                .arg("sync")
+                .output()?,
+        )
+    };
+
+    let output = sync()?;
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("command: sync"), "{stdout}");
+    assert!(stdout.contains("master: cloned"), "{stdout}");
+    let target = temp.path().join("out").join("AGENTS.md");
+    assert_eq!(fs::read_to_string(&target)?, "v1");
+
+    // The pull writes a new master file, so the old link is stale.
+    fs::write(seed.join("master.md"), "v2")?;
+    git(&seed, &["commit", "-q", "-am", "v2"])?;
+    git(&seed, &["push", "-q", &remote.to_string_lossy(), "main"])?;
+    let output = sync()?;
+    assert!(output.status.success(), "{output:?}");
+    assert!(String::from_utf8(output.stdout)?.contains("master: pulled"));
+    assert_eq!(fs::read_to_string(&target)?, "v2");
+    #[cfg(unix)]
+    assert_eq!(
+        fs::metadata(&target)?.ino(),
+        fs::metadata(temp.path().join("ai_settings").join("master.md"))?.ino()
+    );
+
+    // A target dropped from the config is pruned in the same run.
+    let text = fs::read_to_string(&config)?.replace("AGENTS.md", "CLAUDE.md");
+    fs::write(&config, text)?;
+    let output = sync()?;
+    assert!(output.status.success(), "{output:?}");
+    assert!(String::from_utf8(output.stdout)?.contains("master: up to date"));
+    assert!(temp.path().join("out").join("CLAUDE.md").exists());
+    assert!(!target.exists());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn self_update_swaps_in_a_release_build_only_when_its_checksum_matches() -> anyhow::Result<()> {
+    use sha2::{Digest, Sha256};
+
+    let temp = TempDir::new()?;
+    let bin = temp.path().join("bin");
+    fs::create_dir_all(&bin)?;
+    let exe = bin.join("prompt-sync");
+    fs::copy(env!("CARGO_BIN_EXE_prompt-sync"), &exe)?;
+    let releases = temp.path().join("releases");
+    fs::create_dir_all(&releases)?;
+    let asset = format!(
+        "prompt-sync-{}-{}",
+        std::env::consts::ARCH,
+        std::env::consts::OS
+    );
+    let new_build = b"#!/bin/sh\necho new build\n";
+    fs::write(releases.join(&asset), new_build)?;
+    let url = |name: &str| format!("file://{}", releases.join(name).display());
+    fs::write(
+        releases.join("latest"),
+        serde_json::json!({
+            "tag_name": "v99.0.0",
+            "assets": [
+                {"name": asset, "browser_download_url": url(&asset)},
+                {"name": "SHA256SUMS", "browser_download_url": url("SHA256SUMS")},
+            ],
+        })
+        .to_string(),
+    )?;
+    let self_update = |args: &[&str]| -> anyhow::Result<std::process::Output> {
+        Ok(std::process::Command::new(&exe)
+            .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+            .arg("self-update")
+            .args(args)
+            .env(
+                "PROMPT_SYNC_RELEASES_URL",
+                format!("file://{}", releases.display()),
+            )
+            .output()?)
+    };
+
+    let output = self_update(&["--check"])?;
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    assert!(String::from_utf8(output.stdout)?.contains("-> 99.0.0"));
+
+    fs::write(
+        releases.join("SHA256SUMS"),
+        format!("{:x}  {asset}\n", Sha256::digest(b"something else")),
+    )?;
+    let output = self_update(&[])?;
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    assert!(String::from_utf8(output.stderr)?.contains("checksum mismatch"));
+    assert_ne!(fs::read(&exe)?, new_build);
+    assert_eq!(fs::read_dir(&bin)?.count(), 1);
+
+    fs::write(
+        releases.join("SHA256SUMS"),
+        format!("{:x}  {asset}\n", Sha256::digest(new_build)),
+    )?;
+    let output = self_update(&[])?;
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(fs::read(&exe)?, new_build);
+    assert_eq!(fs::metadata(&exe)?.permissions().mode() & 0o111, 0o111);
+
+    Ok(())
+}
+
+#[cfg(not(feature = "syslog"))]
+#[test]
+fn system_log_sink_falls_back_to_the_file_without_the_feature() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    let target = temp.path().join("out").join("AGENTS.md");
+    fs::write(&source, "master instruction")?;
+    write_config(temp.path(), &source, &target)?;
+    let config = temp.path().join("prompt-sync.toml");
+    fs::write(
+        &config,
+        format!(
+            "[logging]\nsink = \"journald\"\n\n{}",
+            fs::read_to_string(&config)?
+        ),
+    )?;
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+        .args(["--config", &config.to_string_lossy()])
+        .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+        .arg("link")
+        .output()?;
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(
+        stderr.contains("needs a Unix build with the `syslog` feature"),
+        "{stderr}"
+    );
+    let journal = fs::read_to_string(temp.path().join("state").join("operations.ndjson"))?;
+    assert!(journal.contains("\"CREATED\""), "{journal}");
+
+    Ok(())
+}
+
+#[test]
+fn log_show_filters_journal_entries() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let state = temp.path().join("state");
+    fs::create_dir_all(&state)?;
+    let target = temp.path().join("out").join("CLAUDE.md");
+    let other = temp.path().join("out").join("AGENTS.md");
+    let entry = |timestamp: &str, action: &str, target: &Path| {
+        format!(
+            "{}\n",
+            serde_json::json!({
+                "timestamp": timestamp,
+                "run_id": "run",
+                "command": "link",
+                "action": action,
+                "link_type": "hardlink",
+                "source": temp.path().join("master.md"),
+                "target": target,
+            })
+        )
+    };
+    fs::write(
+        state.join("operations.ndjson"),
+        [
+            entry("2020-01-01T00:00:00Z", "REPLACED", &target),
+            entry("2099-01-01T00:00:00Z", "ERROR", &target),
+            entry("2099-01-01T00:00:00Z", "REPLACED", &target),
+            entry("2099-01-01T00:00:00Z", "CREATED", &other),
+        ]
+        .concat(),
+    )?;
+
+    let log_show = |args: &[&str]| -> anyhow::Result<Vec<serde_json::Value>> {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+            .args(["--config", &temp.path().join("none.toml").to_string_lossy()])
+            .args(["--state-dir", &state.to_string_lossy()])
+            .args(["log", "show", "--json"])
+            .args(args)
+            .output()?;
+        assert!(output.status.success(), "{output:?}");
+        Ok(serde_json::from_slice(&output.stdout)?)
+    };
+    let target_arg = target.to_string_lossy().into_owned();
+
+    assert_eq!(log_show(&[])?.len(), 4);
+    assert_eq!(log_show(&["--target", &target_arg])?.len(), 3);
+    assert_eq!(
+        log_show(&["--since", "7d", "--target", &target_arg])?.len(),
+        2
+    );
+    let failed = log_show(&["--status", "failed"])?;
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0]["action"], "ERROR");
+    let replaced = log_show(&["--action", "replace", "--status", "ok"])?;
+    assert_eq!(replaced.len(), 2);
+    assert!(
+        replaced
+            .iter()
+            .all(|entry| entry["target"] == target_arg.as_str())
+    );
+
+    Ok(())
+}
+
+#[test]
+fn repair_conflict_with_force_replaces_target() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    let target = temp.path().join("out").join("AGENTS.md");
+
+    fs::write(&source, "master instruction")?;
+    let parent = target
+        .parent()
+        .ok_or_else(|| anyhow::anyhow!("missing parent path"))?;
+    fs::create_dir_all(parent)?;
+    fs::write(&target, "local override")?;
+    write_config(temp.path(), &source, &target)?;
+
+    let repair_code = run(Cli {
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
+        command: Command::Repair {
+            force: true,
+            owned_only: false,
+            dry_run: false,
+            atomic: false,
+            output: OutputArgs::default(),
+            backup_dir: None,
+            mapping: MappingArgs::default(),
+        },
+    })?;
+    assert_eq!(repair_code, 0);
+
+    #[cfg(unix)]
+    {
+        let source_meta = fs::metadata(&source)?;
+        let target_meta = fs::metadata(&target)?;
+        assert_eq!(source_meta.ino(), target_meta.ino());
+        assert_eq!(source_meta.dev(), target_meta.dev());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn repair_replaces_target_in_place_and_backs_up_a_copy() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    let target = temp.path().join("out").join("AGENTS.md");
+
+    fs::write(&source, "master instruction")?;
+    fs::create_dir_all(temp.path().join("out"))?;
+    fs::write(&target, "local override")?;
+    write_config(temp.path(), &source, &target)?;
+
+    let repair_code = run(Cli {
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: Some(0),
+        wait: false,
+        no_lock: false,
+        command: Command::Repair {
+            force: true,
+            owned_only: false,
+            dry_run: false,
+            atomic: false,
+            output: OutputArgs::default(),
+            backup_dir: Some(temp.path().join("backups")),
+            mapping: MappingArgs::default(),
+        },
+    })?;
+    assert_eq!(repair_code, 0);
+    assert_eq!(fs::read_to_string(&target)?, "master instruction");
+
+    // The new link was renamed over the target, so no scratch file is left.
+    let out_entries = fs::read_dir(temp.path().join("out"))?
+        .map(|entry| entry.map(|entry| entry.file_name()))
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(out_entries, vec![std::ffi::OsString::from("AGENTS.md")]);
+
+    #[cfg(unix)]
+    {
+        // Backups land under <root>/<run-id>/ with the target's full path.
+        let run_dirs = fs::read_dir(temp.path().join("backups"))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .filter(|path| path.as_ref().is_ok_and(|path| path.is_dir()))
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(run_dirs.len(), 1, "{run_dirs:?}");
+        let relative_target = target
+            .components()
+            .filter(|component| matches!(component, std::path::Component::Normal(_)))
+            .collect::<PathBuf>();
+        assert_eq!(
+            fs::read_to_string(run_dirs[0].join(relative_target))?,
+            "local override"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn same_named_targets_get_separate_backups() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    fs::write(temp.path().join("master.md"), "master instruction")?;
+    for vendor in ["codex", "claude"] {
+        fs::create_dir_all(temp.path().join(vendor))?;
+        fs::write(temp.path().join(vendor).join("AGENTS.md"), vendor)?;
+    }
+    let root = temp.path().display().to_string().replace('\\', "/");
+    fs::write(
+        temp.path().join("prompt-sync.toml"),
+        format!(
+            r#"[[links]]
+source = "{root}/master.md"
+targets = ["{root}/codex/AGENTS.md", "{root}/claude/AGENTS.md"]
+"#
+        ),
+    )?;
+
+    let repair_code = run(Cli {
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: Some(0),
+        wait: false,
+        no_lock: false,
+        command: Command::Repair {
+            force: true,
+            owned_only: false,
+            dry_run: false,
+            atomic: false,
+            output: OutputArgs::default(),
+            backup_dir: Some(temp.path().join("backups")),
+            mapping: MappingArgs::default(),
+        },
+    })?;
+    assert_eq!(repair_code, 0);
+
+    let manifest: serde_json::Value = serde_json::from_str(&fs::read_to_string(
+        temp.path().join("backups/manifest.json"),
+    )?)?;
+    let mut contents = manifest
+        .as_array()
+        .expect("manifest entries")
+        .iter()
+        .map(|entry| fs::read_to_string(entry["backup"].as_str().unwrap_or_default()))
+        .collect::<Result<Vec<_>, _>>()?;
+    contents.sort();
+    assert_eq!(contents, ["claude", "codex"]);
+
+    Ok(())
+}
+
+#[test]
+fn verbosity_levels_add_span_timings() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    let target = temp.path().join("out").join("AGENTS.md");
+    fs::write(&source, "master instruction")?;
+    write_config(temp.path(), &source, &target)?;
+
+    let stderr = |args: &[&str], rust_log: Option<&str>| -> anyhow::Result<String> {
+        let mut command = std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"));
+        command
+            .args([
+                "--config",
+                &temp.path().join("prompt-sync.toml").to_string_lossy(),
+            ])
+            .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+            .args(args)
+            .env_remove("RUST_LOG");
+        if let Some(rust_log) = rust_log {
+            command.env("RUST_LOG", rust_log);
+        }
+        let output = command.output()?;
+        assert!(output.status.success(), "{output:?}");
+        Ok(String::from_utf8(output.stderr)?)
+    };
+
+    let trace = stderr(&["-vvv", "link"], None)?;
+    assert!(
+        trace.contains(&format!("trace: link {} took", target.display())),
+        "{trace}"
+    );
+
+    assert_eq!(stderr(&["verify"], None)?, "");
+    let debug = stderr(&["-vv", "verify"], None)?;
+    assert!(debug.contains("debug: config load took"), "{debug}");
+    assert!(debug.contains("debug: mapping build took"), "{debug}");
+    assert!(!debug.contains("trace:"), "{debug}");
+
+    let from_env = stderr(&["verify"], Some("warn,prompt_sync=debug"))?;
+    assert!(from_env.contains("debug: mapping build took"), "{from_env}");
+    assert_eq!(stderr(&["verify"], Some("other_crate=trace"))?, "");
+
+    Ok(())
+}
+
+#[test]
+fn run_id_correlates_report_journal_and_backups() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    let target = temp.path().join("out").join("AGENTS.md");
+    fs::write(&source, "master instruction")?;
+    fs::create_dir_all(temp.path().join("out"))?;
+    fs::write(&target, "local override")?;
+    write_config(temp.path(), &source, &target)?;
+    let config = temp.path().join("prompt-sync.toml");
+    fs::write(
+        &config,
+        format!("[logging]\n\n{}", fs::read_to_string(&config)?),
+    )?;
+    let backups = temp.path().join("backups");
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+        .args(["--config", &config.to_string_lossy()])
+        .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+        .args(["--backup-min-free", "0"])
+        .args(["link", "--force", "--json", "--backup-dir"])
+        .arg(&backups)
+        .output()?;
+    assert!(output.status.success(), "{output:?}");
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    let run_id = report["run_id"].as_str().expect("report run id");
+    // A version 4 UUID.
+    assert_eq!(run_id.len(), 36);
+    assert_eq!(run_id.chars().nth(14), Some('4'));
+
+    let manifest: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(backups.join("manifest.json"))?)?;
+    assert_eq!(manifest[0]["run_id"], run_id);
+    assert!(backups.join(run_id).is_dir());
+    let log = fs::read_to_string(backups.join(".operations.log"))?;
+    let entry: serde_json::Value = serde_json::from_str(log.lines().next().unwrap_or_default())?;
+    assert_eq!(entry["run_id"], run_id);
+    let journal = fs::read_to_string(temp.path().join("state").join("operations.ndjson"))?;
+    let line: serde_json::Value = serde_json::from_str(journal.lines().next().unwrap_or_default())?;
+    assert_eq!(line["run_id"], run_id);
+
+    Ok(())
+}
+
+#[test]
+fn backup_list_reads_manifest_of_replaced_targets() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    let target = temp.path().join("out").join("AGENTS.md");
+    fs::write(&source, "master instruction")?;
+    fs::create_dir_all(temp.path().join("out"))?;
+    fs::write(&target, "local override")?;
+    write_config(temp.path(), &source, &target)?;
+    let backups = temp.path().join("backups");
+
+    let prompt_sync = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+            .args([
+                "--config",
+                &temp.path().join("prompt-sync.toml").to_string_lossy(),
+            ])
+            .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+            .args(["--backup-min-free", "0"])
+            .args(args)
+            .output()
+    };
+
+    let output = prompt_sync(&["backup", "list", "--backup-dir", &backups.to_string_lossy()])?;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)?.starts_with("no backups in"));
+
+    let output = prompt_sync(&[
+        "repair",
+        "--force",
+        "--backup-dir",
+        &backups.to_string_lossy(),
+    ])?;
+    assert!(output.status.success(), "{output:?}");
+
+    let output = prompt_sync(&[
+        "backup",
+        "list",
+        "--backup-dir",
+        &backups.to_string_lossy(),
+        "--json",
+    ])?;
+    assert!(output.status.success());
+    let entries: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    let entries = entries.as_array().expect("manifest entries");
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0]["original"], target.to_string_lossy().as_ref());
+    assert_eq!(entries[0]["size"], "local override".len());
+    let backup = PathBuf::from(entries[0]["backup"].as_str().expect("backup path"));
+    assert_eq!(fs::read_to_string(backup)?, "local override");
+    assert!(
+        entries[0]["run_id"]
+            .as_str()
+            .is_some_and(|id| !id.is_empty())
+    );
+
+    let output = prompt_sync(&["backup", "list", "--backup-dir", &backups.to_string_lossy()])?;
+    let text = String::from_utf8(output.stdout)?;
+    assert!(
+        text.contains(&format!("{} -> ", target.display())),
+        "{text}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn backup_restore_puts_original_content_back() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    let target = temp.path().join("out").join("AGENTS.md");
+    fs::write(&source, "master instruction")?;
+    fs::create_dir_all(temp.path().join("out"))?;
+    fs::write(&target, "local override")?;
+    write_config(temp.path(), &source, &target)?;
+    let backups = temp.path().join("backups").to_string_lossy().into_owned();
+
+    let prompt_sync = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+            .args([
+                "--config",
+                &temp.path().join("prompt-sync.toml").to_string_lossy(),
+            ])
+            .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+            .args(["--backup-min-free", "0"])
+            .args(args)
+            .output()
+    };
+    let target_arg = target.to_string_lossy().into_owned();
+
+    let output = prompt_sync(&["backup", "restore", &target_arg, "--backup-dir", &backups])?;
+    assert_eq!(output.status.code(), Some(2));
+
+    assert!(
+        prompt_sync(&["repair", "--force", "--backup-dir", &backups])?
+            .status
+            .success()
+    );
+    assert_eq!(fs::read_to_string(&target)?, "master instruction");
+
+    let output = prompt_sync(&[
+        "backup",
+        "restore",
+        &target_arg,
+        "--backup-dir",
+        &backups,
+        "--at",
+        "1970-01-01T00:00:00Z",
+    ])?;
+    assert_eq!(output.status.code(), Some(2));
+
+    let output = prompt_sync(&["backup", "restore", &target_arg, "--backup-dir", &backups])?;
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(fs::read_to_string(&target)?, "local override");
+    assert_eq!(fs::read_to_string(&source)?, "master instruction");
+
+    // A backup that no longer matches its recorded hash is refused.
+    assert!(
+        prompt_sync(&["repair", "--force", "--backup-dir", &backups])?
+            .status
+            .success()
+    );
+    let list = prompt_sync(&["backup", "list", "--backup-dir", &backups, "--json"])?;
+    let entries: serde_json::Value = serde_json::from_slice(&list.stdout)?;
+    let newest = entries
+        .as_array()
+        .and_then(|entries| entries.last())
+        .and_then(|entry| entry["backup"].as_str())
+        .map(PathBuf::from)
+        .expect("newest backup");
+    fs::write(&newest, "tampered")?;
+    let backup_id = newest
+        .strip_prefix(&backups)?
+        .to_string_lossy()
+        .into_owned();
+    let output = prompt_sync(&["backup", "restore", &backup_id, "--backup-dir", &backups])?;
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr)?.contains("hash"));
+    assert_eq!(fs::read_to_string(&target)?, "master instruction");
+
+    Ok(())
+}
+
+#[test]
+fn repair_backup_respects_min_free_reserve() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    let target = temp.path().join("out").join("AGENTS.md");
+
+    fs::write(&source, "master instruction")?;
+    fs::create_dir_all(temp.path().join("out"))?;
+    fs::write(&target, "local override")?;
+    write_config(temp.path(), &source, &target)?;
+
+    let repair = |backup_min_free: Option<u64>| {
+        run(Cli {
+            config: vec![temp.path().join("prompt-sync.toml")],
+            verbose: 0,
+            no_user_config: false,
+            state_dir: Some(temp.path().join("state")),
+            backup_min_free,
+            wait: false,
+            no_lock: false,
+            command: Command::Repair {
+                force: true,
+                owned_only: false,
+                dry_run: false,
+                atomic: false,
+                output: OutputArgs::default(),
+                backup_dir: Some(temp.path().join("backups")),
+                mapping: MappingArgs::default(),
+            },
+        })
+    };
+
+    // No volume has an exabyte to spare, so the backup is refused.
+    assert_eq!(repair(Some(1 << 60))?, 2);
+    assert_eq!(fs::read_to_string(&target)?, "local override");
+
+    assert_eq!(repair(Some(0))?, 0);
+    assert_eq!(fs::read_to_string(&target)?, "master instruction");
+
+    let parsed = Cli::try_parse_from(["prompt-sync", "--backup-min-free", "512K", "verify"])?;
+    assert_eq!(parsed.backup_min_free, Some(512 * 1024));
+    assert!(Cli::try_parse_from(["prompt-sync", "--backup-min-free", "5X", "verify"]).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn link_dry_run_does_not_create_target() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    let target = temp.path().join("out").join("AGENTS.md");
+
+    fs::write(&source, "master instruction")?;
+    write_config(temp.path(), &source, &target)?;
+
+    let link_code = run(Cli {
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
+        command: Command::Link {
+            only_missing: false,
+            force: false,
+            owned_only: false,
+            dry_run: true,
+            atomic: false,
+            output: OutputArgs::default(),
+            backup_dir: None,
+            mapping: MappingArgs::default(),
+            prune: false,
+        },
+    })?;
+    assert_eq!(link_code, 0);
+    assert!(!target.exists());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn verify_symlink_target_is_conflict() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    let symlink_src = temp.path().join("other.md");
+    let target = temp.path().join("out").join("AGENTS.md");
+
+    fs::write(&source, "master instruction")?;
+    fs::write(&symlink_src, "other instruction")?;
+    let parent = target
+        .parent()
+        .ok_or_else(|| anyhow::anyhow!("missing parent path"))?;
+    fs::create_dir_all(parent)?;
+    symlink(&symlink_src, &target)?;
+    write_config(temp.path(), &source, &target)?;
+
+    let verify_code = run(Cli {
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
+        command: Command::Verify {
+            hash: false,
+            fix: false,
+            force: false,
+            backup_dir: None,
+            baseline: None,
+            update_baseline: false,
+            output: OutputArgs::default(),
+            mapping: MappingArgs::default(),
+        },
+    })?;
+    assert_eq!(verify_code, 1);
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn link_symlink_rule_creates_symlink_and_verifies() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    let target = temp.path().join("out").join("AGENTS.md");
+
+    fs::write(&source, "master instruction")?;
+    let source_str = source.display().to_string().replace('\\', "/");
+    let target_str = target.display().to_string().replace('\\', "/");
+    let config = format!(
+        r#"[[links]]
+source = "{}"
+targets = ["{}"]
+link_type = "symlink"
+"#,
+        source_str, target_str
+    );
+    fs::write(temp.path().join("prompt-sync.toml"), config)?;
+
+    let link_code = run(Cli {
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
+        command: Command::Link {
+            only_missing: false,
+            force: false,
+            owned_only: false,
+            dry_run: false,
+            atomic: false,
+            output: OutputArgs::default(),
+            backup_dir: None,
+            mapping: MappingArgs::default(),
+            prune: false,
+        },
+    })?;
+    assert_eq!(link_code, 0);
+    assert!(fs::symlink_metadata(&target)?.file_type().is_symlink());
+    assert_eq!(fs::read_link(&target)?, source);
+
+    let verify_code = run(Cli {
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
+        command: Command::Verify {
+            hash: false,
+            fix: false,
+            force: false,
+            backup_dir: None,
+            baseline: None,
+            update_baseline: false,
+            output: OutputArgs::default(),
+            mapping: MappingArgs::default(),
+        },
+    })?;
+    assert_eq!(verify_code, 0);
+
+    // The same target checked as a hardlink is a conflict.
+    let verify_code = run(Cli {
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
+        command: Command::Verify {
+            hash: false,
+            fix: false,
+            force: false,
+            backup_dir: None,
+            baseline: None,
+            update_baseline: false,
+            output: OutputArgs::default(),
+            mapping: MappingArgs {
+                link_type: Some(LinkType::Hardlink),
+                ..MappingArgs::default()
+            },
+        },
+    })?;
+    assert_eq!(verify_code, 1);
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn repair_symlink_override_fixes_dangling_symlink() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    let target = temp.path().join("out").join("AGENTS.md");
+
+    fs::write(&source, "master instruction")?;
+    let parent = target
+        .parent()
+        .ok_or_else(|| anyhow::anyhow!("missing parent path"))?;
+    fs::create_dir_all(parent)?;
+    symlink(temp.path().join("gone.md"), &target)?;
+    write_config(temp.path(), &source, &target)?;
+
+    let verify_code = run(Cli {
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
+        command: Command::Verify {
+            hash: false,
+            fix: false,
+            force: false,
+            backup_dir: None,
+            baseline: None,
+            update_baseline: false,
+            output: OutputArgs::default(),
+            mapping: MappingArgs {
+                link_type: Some(LinkType::Symlink),
+                ..MappingArgs::default()
+            },
+        },
+    })?;
+    assert_eq!(verify_code, 1);
+
+    let repair_code = run(Cli {
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
+        command: Command::Repair {
+            force: false,
+            owned_only: false,
+            dry_run: false,
+            atomic: false,
+            output: OutputArgs::default(),
+            backup_dir: None,
+            mapping: MappingArgs {
+                link_type: Some(LinkType::Symlink),
+                ..MappingArgs::default()
+            },
+        },
+    })?;
+    assert_eq!(repair_code, 0);
+    assert_eq!(fs::read_link(&target)?, source);
+    assert_eq!(fs::read_to_string(&target)?, "master instruction");
+
+    Ok(())
+}
+
+#[test]
+fn copy_mode_detects_drift_and_repair_recopies() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    let target = temp.path().join("out").join("AGENTS.md");
+
+    fs::write(&source, "master instruction")?;
+    let source_str = source.display().to_string().replace('\\', "/");
+    let target_str = target.display().to_string().replace('\\', "/");
+    let config = format!(
+        r#"[[links]]
+source = "{}"
+targets = ["{}"]
+mode = "copy"
+"#,
+        source_str, target_str
+    );
+    fs::write(temp.path().join("prompt-sync.toml"), config)?;
+
+    let link_code = run(Cli {
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
+        command: Command::Link {
+            only_missing: false,
+            force: false,
+            owned_only: false,
+            dry_run: false,
+            atomic: false,
+            output: OutputArgs::default(),
+            backup_dir: None,
+            mapping: MappingArgs::default(),
+            prune: false,
+        },
+    })?;
+    assert_eq!(link_code, 0);
+    assert_eq!(fs::read_to_string(&target)?, "master instruction");
+
+    #[cfg(unix)]
+    {
+        let source_meta = fs::metadata(&source)?;
+        let target_meta = fs::metadata(&target)?;
+        assert_ne!(source_meta.ino(), target_meta.ino());
+    }
+
+    let verify = || {
+        run(Cli {
+            config: vec![temp.path().join("prompt-sync.toml")],
+            verbose: 0,
+            no_user_config: false,
+            state_dir: Some(temp.path().join("state")),
+            backup_min_free: None,
+            wait: false,
+            no_lock: false,
+            command: Command::Verify {
+                hash: false,
+                fix: false,
+                force: false,
+                backup_dir: None,
+                baseline: None,
+                update_baseline: false,
+                output: OutputArgs::default(),
+                mapping: MappingArgs::default(),
+            },
+        })
+    };
+    assert_eq!(verify()?, 0);
+
+    fs::write(&target, "edited by vendor tool")?;
+    assert_eq!(verify()?, 1);
+
+    let repair_code = run(Cli {
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
+        command: Command::Repair {
+            force: false,
+            owned_only: false,
+            dry_run: false,
+            atomic: false,
+            output: OutputArgs::default(),
+            backup_dir: None,
+            mapping: MappingArgs::default(),
+        },
+    })?;
+    assert_eq!(repair_code, 0);
+    assert_eq!(fs::read_to_string(&target)?, "master instruction");
+    assert_eq!(verify()?, 0);
+
+    Ok(())
+}
+
+#[test]
+fn unlink_removes_linked_targets_and_keeps_conflicts() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    let linked = temp.path().join("out").join("AGENTS.md");
+    let conflict = temp.path().join("out").join("CLAUDE.md");
+
+    fs::write(&source, "master instruction")?;
+    fs::create_dir_all(temp.path().join("out"))?;
+    fs::hard_link(&source, &linked)?;
+    fs::write(&conflict, "local override")?;
+
+    let config = format!(
+        r#"[[links]]
+source = "{}"
+targets = ["{}", "{}"]
+"#,
+        source.display().to_string().replace('\\', "/"),
+        linked.display().to_string().replace('\\', "/"),
+        conflict.display().to_string().replace('\\', "/"),
+    );
+    fs::write(temp.path().join("prompt-sync.toml"), config)?;
+
+    let dry_run_code = run(Cli {
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
+        command: Command::Unlink {
+            dry_run: true,
+            output: OutputArgs::default(),
+            mapping: MappingArgs::default(),
+        },
+    })?;
+    assert_eq!(dry_run_code, 0);
+    assert!(linked.exists());
+
+    let unlink_code = run(Cli {
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
+        command: Command::Unlink {
+            dry_run: false,
+            output: OutputArgs::default(),
+            mapping: MappingArgs::default(),
+        },
+    })?;
+    assert_eq!(unlink_code, 0);
+    assert!(!linked.exists());
+    assert_eq!(fs::read_to_string(&conflict)?, "local override");
+    assert_eq!(fs::read_to_string(&source)?, "master instruction");
+
+    Ok(())
+}
+
+#[test]
+fn list_does_not_touch_targets() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    let target = temp.path().join("out").join("AGENTS.md");
+
+    fs::write(&source, "master instruction")?;
+    write_config(temp.path(), &source, &target)?;
+
+    let list_code = run(Cli {
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
+        command: Command::List {
+            json: true,
+            mapping: MappingArgs::default(),
+        },
+    })?;
+    assert_eq!(list_code, 0);
+    assert!(!target.exists());
+    assert!(!temp.path().join("out").exists());
+
+    Ok(())
+}
+
+#[test]
+fn bootstrap_write_config_refuses_overwrite_without_force() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let config_path = temp.path().join("prompt-sync.toml");
+    fs::write(&config_path, "# existing\n")?;
+
+    let result = run(Cli {
+        config: vec![config_path.clone()],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
+        command: Command::Bootstrap {
+            force: false,
+            dry_run: false,
+            output: OutputArgs::default(),
+            backup_dir: None,
+            write_config: true,
+            link_type: None,
+            profiles: vec![],
+            include: vec![],
+            exclude: vec![],
+            yes: false,
+            home_only: false,
+            repo_only: false,
+        },
+    });
+
+    assert!(result.is_err());
+    assert_eq!(fs::read_to_string(&config_path)?, "# existing\n");
+    Ok(())
+}
+
+#[test]
+fn install_commit_guard_creates_hook() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let repo = temp.path().join("repo");
+    fs::create_dir_all(repo.join(".git").join("hooks"))?;
+
+    let code = run(Cli {
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
+        command: Command::InstallCommitGuard {
+            repo: repo.clone(),
+            all_repos: false,
+            force: false,
+            chain: false,
+            mode: GuardMode::Strip,
+            uninstall: false,
+            status: false,
+            dry_run: false,
+        },
+    })?;
+    assert_eq!(code, 0);
+
+    let hook_path = repo.join(".git").join("hooks").join("commit-msg");
+    let hook_body = fs::read_to_string(&hook_path)?;
+    assert!(hook_body.contains("Co-authored-by"));
+    assert!(hook_body.contains("chatgpt|claude|codex|gemini|copilot|kiro|openai|anthropic"));
+
+    #[cfg(unix)]
+    {
+        let mode = fs::metadata(&hook_path)?.permissions().mode();
+        assert_ne!(mode & 0o111, 0);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn install_commit_guard_refuses_overwrite_without_force() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let repo = temp.path().join("repo");
+    let hooks = repo.join(".git").join("hooks");
+    fs::create_dir_all(&hooks)?;
+    let hook_path = hooks.join("commit-msg");
+    fs::write(&hook_path, "# existing hook\n")?;
+
+    let result = run(Cli {
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
+        command: Command::InstallCommitGuard {
+            repo: repo.clone(),
+            all_repos: false,
+            force: false,
+            chain: false,
+            mode: GuardMode::Strip,
+            uninstall: false,
+            status: false,
+            dry_run: false,
+        },
+    });
+    assert!(result.is_err());
+    assert_eq!(fs::read_to_string(&hook_path)?, "# existing hook\n");
+
+    Ok(())
+}
+
+#[test]
+fn install_commit_guard_chains_existing_hook_and_uninstalls_cleanly() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let repo = temp.path().join("repo");
+    let hooks = repo.join(".git").join("hooks");
+    fs::create_dir_all(&hooks)?;
+    let hook_path = hooks.join("commit-msg");
+    let chained_path = hooks.join("commit-msg.prompt-sync-chained");
+    fs::write(
+        &hook_path,
+        "#!/bin/sh\nnpx lefthook run commit-msg \"$@\"\n",
+    )?;
+
+    let guard = |chain: bool, uninstall: bool| {
+        run(Cli {
+            config: vec![temp.path().join("prompt-sync.toml")],
+            verbose: 0,
+            no_user_config: false,
+            state_dir: Some(temp.path().join("state")),
+            backup_min_free: None,
+            wait: false,
+            no_lock: false,
+            command: Command::InstallCommitGuard {
+                repo: repo.clone(),
+                all_repos: false,
+                force: false,
+                chain,
+                mode: GuardMode::Strip,
+                uninstall,
+                status: false,
+                dry_run: false,
+            },
+        })
+    };
+
+    assert_eq!(guard(true, false)?, 0);
+    assert!(fs::read_to_string(&chained_path)?.contains("lefthook"));
+    let dispatcher = fs::read_to_string(&hook_path)?;
+    assert!(dispatcher.contains("commit-msg.prompt-sync-chained"));
+    assert!(dispatcher.contains("Co-authored-by"));
+
+    // Chaining again keeps the original hook rather than chaining itself.
+    assert_eq!(guard(true, false)?, 0);
+    assert!(fs::read_to_string(&chained_path)?.contains("lefthook"));
+
+    assert_eq!(guard(false, true)?, 0);
+    assert!(!chained_path.exists());
+    assert!(fs::read_to_string(&hook_path)?.contains("lefthook"));
+    // The original hook is not ours to remove.
+    assert!(guard(false, true).is_err());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn commit_guard_reject_mode_lists_offending_lines() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let repo = temp.path().join("repo");
+    fs::create_dir_all(repo.join(".git").join("hooks"))?;
+    let install = |mode: GuardMode| {
+        run(Cli {
+            config: vec![temp.path().join("prompt-sync.toml")],
+            verbose: 0,
+            no_user_config: false,
+            state_dir: Some(temp.path().join("state")),
+            backup_min_free: None,
+            wait: false,
+            no_lock: false,
+            command: Command::InstallCommitGuard {
+                repo: repo.clone(),
+                all_repos: false,
+                force: true,
+                chain: false,
+                mode,
+                uninstall: false,
+                status: false,
+                dry_run: false,
+            },
+        })
+    };
+    let message = temp.path().join("COMMIT_EDITMSG");
+    let commit = |text: &str| -> anyhow::Result<std::process::Output> {
+        fs::write(&message, text)?;
+        Ok(
+            std::process::Command::new(repo.join(".git").join("hooks").join("commit-msg"))
+                .arg(&message)
+                .output()?,
+        )
+    };
+    let attributed = "Fix typo\n\nCo-authored-by: Claude <noreply@anthropic.com>\n";
+
+    assert_eq!(install(GuardMode::Reject)?, 0);
+    let output = commit(attributed)?;
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("  Co-authored-by: Claude"), "{stderr}");
+    assert_eq!(fs::read_to_string(&message)?, attributed);
+    assert!(commit("Fix typo\n")?.status.success());
+
+    assert_eq!(install(GuardMode::Strip)?, 0);
+    assert!(commit(attributed)?.status.success());
+    assert_eq!(fs::read_to_string(&message)?, "Fix typo\n\n");
+
+    Ok(())
+}
+
+#[test]
+fn install_commit_guard_detects_mercurial_and_jujutsu_repos() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let guard = |repo: &Path, args: &[&str]| -> anyhow::Result<std::process::Output> {
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .arg("install-commit-guard")
+                .args(["--repo", &repo.to_string_lossy()])
+                .args(args)
+                .output()?,
+        )
+    };
+
+    // Mercurial: a pretxncommit hook that can only reject.
+    let hg = temp.path().join("hg");
+    fs::create_dir_all(hg.join(".hg"))?;
+    fs::write(hg.join(".hg").join("hgrc"), "[ui]\nusername = dev\n")?;
+    assert_eq!(guard(&hg, &[])?.status.code(), Some(2));
+    assert!(guard(&hg, &["--mode", "reject"])?.status.success());
+    let hgrc = fs::read_to_string(hg.join(".hg").join("hgrc"))?;
+    assert!(hgrc.starts_with("[ui]\nusername = dev\n\n[hooks]\npretxncommit.prompt-sync = "));
+    assert!(
+        fs::read_to_string(hg.join(".hg").join("prompt-sync-commit-guard"))?
+            .contains("hg log -r \"$HG_NODE\"")
+    );
+    assert!(guard(&hg, &["--status"])?.status.success());
+    assert!(guard(&hg, &["--uninstall"])?.status.success());
+    assert_eq!(
+        fs::read_to_string(hg.join(".hg").join("hgrc"))?,
+        "[ui]\nusername = dev\n\n[hooks]\n"
+    );
+    assert!(!hg.join(".hg").join("prompt-sync-commit-guard").exists());
+
+    // Jujutsu: only a colocated git repo has hooks to install.
+    let jj = temp.path().join("jj");
+    fs::create_dir_all(jj.join(".jj"))?;
+    let output = guard(&jj, &[])?;
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr)?.contains("jj git init --colocate"));
+    fs::create_dir_all(jj.join(".git").join("hooks"))?;
+    let output = guard(&jj, &[])?;
+    assert!(output.status.success(), "{output:?}");
+    assert!(String::from_utf8(output.stderr)?.contains("covers `git commit`"));
+    assert!(jj.join(".git").join("hooks").join("commit-msg").exists());
+
+    Ok(())
+}
+
+#[test]
+fn install_commit_guard_status_reports_version_and_local_edits() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let repo = temp.path().join("repo");
+    let hook_path = repo.join(".git").join("hooks").join("commit-msg");
+    fs::create_dir_all(repo.join(".git").join("hooks"))?;
+
+    let guard = |args: &[&str]| -> anyhow::Result<(Option<i32>, String)> {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+            .arg("install-commit-guard")
+            .args(["--repo", &repo.to_string_lossy()])
+            .args(args)
+            .output()?;
+        Ok((output.status.code(), String::from_utf8(output.stdout)?))
+    };
+
+    let (code, text) = guard(&["--status"])?;
+    assert_eq!(code, Some(1));
+    assert!(text.starts_with("commit guard: not installed:"), "{text}");
+
+    assert_eq!(guard(&[])?.0, Some(0));
+    let (code, text) = guard(&["--status"])?;
+    assert_eq!(code, Some(0));
+    assert!(text.starts_with("commit guard: installed (v2):"), "{text}");
+
+    fs::write(
+        &hook_path,
+        fs::read_to_string(&hook_path)?.replace("exit 0\n", "echo edited\nexit 0\n"),
+    )?;
+    let (code, text) = guard(&["--status"])?;
+    assert_eq!(code, Some(1));
+    assert!(text.contains("modified locally"), "{text}");
+
+    // Uninstall goes by the marker comment, edits or not.
+    assert_eq!(guard(&["--uninstall"])?.0, Some(0));
+    assert!(!hook_path.exists());
+    fs::write(&hook_path, "#!/bin/sh\nexit 0\n")?;
+    let (code, text) = guard(&["--status"])?;
+    assert_eq!(code, Some(1));
+    assert!(text.contains("not written by prompt-sync"), "{text}");
+    assert_eq!(guard(&["--uninstall"])?.0, Some(2));
+    assert!(hook_path.exists());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn repair_hooks_relink_repo_targets_after_git_rewrites_them() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let repo = temp.path().join("repo");
+    fs::create_dir_all(repo.join(".git").join("hooks"))?;
+    let source = temp.path().join("master.md");
+    let target = repo.join("AGENTS.md");
+    fs::write(&source, "master instruction")?;
+    write_config(&repo, &source, &target)?;
+
+    let prompt_sync = |command: &mut std::process::Command| {
+        command
+            .current_dir(&repo)
+            .env("XDG_STATE_HOME", temp.path().join("state"))
+            .env("XDG_CONFIG_HOME", temp.path().join("xdg"))
+            .output()
+    };
+    let output = prompt_sync(
+        std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync")).arg("install-repair-hooks"),
+    )?;
+    assert!(output.status.success(), "{output:?}");
+    let output =
+        prompt_sync(std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync")).arg("link"))?;
+    assert!(output.status.success(), "{output:?}");
+
+    // What a pull that touched AGENTS.md leaves behind: a fresh file.
+    fs::remove_file(&target)?;
+    fs::write(&target, "upstream version")?;
+    let bin_dir = Path::new(env!("CARGO_BIN_EXE_prompt-sync"))
+        .parent()
+        .expect("binary directory");
+    let path = format!(
+        "{}:{}",
+        bin_dir.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+    let hook = repo.join(".git").join("hooks").join("post-merge");
+    let output = prompt_sync(std::process::Command::new(&hook).env("PATH", path))?;
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(fs::read_to_string(&target)?, "master instruction");
+    assert_eq!(fs::metadata(&target)?.ino(), fs::metadata(&source)?.ino());
+    assert_eq!(
+        fs::read_to_string(&hook)?,
+        fs::read_to_string(repo.join(".git").join("hooks").join("post-checkout"))?
+    );
+
+    // Both hooks are refused together when either exists.
+    let output = prompt_sync(
+        std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync")).arg("install-repair-hooks"),
+    )?;
+    assert_eq!(output.status.code(), Some(2));
+
+    Ok(())
+}
+
+#[test]
+fn link_skills_sets_creates_hardlinks() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source_root = temp.path().join("skills");
+    let skill_dir = source_root.join("my-skill");
+    fs::create_dir_all(&skill_dir)?;
+    let source_file = skill_dir.join("SKILL.md");
+    fs::write(&source_file, "skill content")?;
+
+    let target_root = temp.path().join("target");
+
+    let source_str = source_root.display().to_string().replace('\\', "/");
+    let target_str = target_root.display().to_string().replace('\\', "/");
+
+    let config = format!(
+        r#"[[skills_sets]]
+source_root = "{}"
+target_roots = ["{}"]
+"#,
+        source_str, target_str
+    );
+    fs::write(temp.path().join("prompt-sync.toml"), config)?;
+
+    let link_code = run(Cli {
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
+        command: Command::Link {
+            only_missing: false,
+            force: false,
+            owned_only: false,
+            dry_run: false,
+            atomic: false,
+            output: OutputArgs::default(),
+            backup_dir: None,
+            mapping: MappingArgs::default(),
+            prune: false,
+        },
+    })?;
+    assert_eq!(link_code, 0);
+
+    let target_file = target_root.join("my-skill").join("SKILL.md");
+    assert!(target_file.exists(), "target skill file should exist");
+    assert_eq!(fs::read_to_string(&target_file)?, "skill content");
+
+    #[cfg(unix)]
+    {
+        let source_meta = fs::metadata(&source_file)?;
+        let target_meta = fs::metadata(&target_file)?;
+        assert_eq!(source_meta.ino(), target_meta.ino());
+        assert_eq!(source_meta.dev(), target_meta.dev());
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn link_skills_sets_follow_symlinks_walks_linked_dirs() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let external = temp.path().join("other-repo").join("linked-skill");
+    fs::create_dir_all(&external)?;
+    fs::write(external.join("SKILL.md"), "linked content")?;
+    let source_root = temp.path().join("skills");
+    fs::create_dir_all(&source_root)?;
+    symlink(&external, source_root.join("linked-skill"))?;
+
+    let target_root = temp.path().join("target");
+    let write = |follow: bool| {
+        fs::write(
+            temp.path().join("prompt-sync.toml"),
+            format!(
+                r#"[[skills_sets]]
+source_root = "{}"
+target_roots = ["{}"]
+follow_symlinks = {follow}
+"#,
+                source_root.display(),
+                target_root.display()
+            ),
+        )
+    };
+    let link = || {
+        run(Cli {
+            config: vec![temp.path().join("prompt-sync.toml")],
+            verbose: 0,
+            no_user_config: false,
+            state_dir: Some(temp.path().join("state")),
+            backup_min_free: None,
+            wait: false,
+            no_lock: false,
+            command: Command::Link {
+                only_missing: false,
+                force: false,
+                owned_only: false,
+                dry_run: false,
+                atomic: false,
+                output: OutputArgs::default(),
+                backup_dir: None,
+                mapping: MappingArgs::default(),
+                prune: false,
+            },
+        })
+    };
+
+    let target = target_root.join("linked-skill").join("SKILL.md");
+    write(false)?;
+    assert_eq!(link()?, 0);
+    assert!(!target.exists());
+
+    write(true)?;
+    assert_eq!(link()?, 0);
+    assert_eq!(
+        fs::metadata(&target)?.ino(),
+        fs::metadata(external.join("SKILL.md"))?.ino()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn link_skills_sets_exclude_filters_files() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source_root = temp.path().join("skills");
+
+    // Create skill with references/ subdir that should be excluded
+    let skill_dir = source_root.join("my-skill");
+    fs::create_dir_all(skill_dir.join("references"))?;
+    fs::write(skill_dir.join("SKILL.md"), "skill content")?;
+    fs::write(skill_dir.join("references").join("ref.md"), "ref content")?;
+
+    // Create another skill without references
+    let skill2_dir = source_root.join("other-skill");
+    fs::create_dir_all(&skill2_dir)?;
+    fs::write(skill2_dir.join("SKILL.md"), "other content")?;
+
+    let target_root = temp.path().join("target");
+    let source_str = source_root.display().to_string().replace('\\', "/");
+    let target_str = target_root.display().to_string().replace('\\', "/");
+
+    let config = format!(
+        r#"[[skills_sets]]
+source_root = "{}"
+target_roots = ["{}"]
+exclude = ["*/references/**"]
+"#,
+        source_str, target_str
+    );
+    fs::write(temp.path().join("prompt-sync.toml"), config)?;
+
+    let link_code = run(Cli {
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
+        command: Command::Link {
+            only_missing: false,
+            force: false,
+            owned_only: false,
+            dry_run: false,
+            atomic: false,
+            output: OutputArgs::default(),
+            backup_dir: None,
+            mapping: MappingArgs::default(),
+            prune: false,
+        },
+    })?;
+    assert_eq!(link_code, 0);
+
+    // SKILL.md files should be linked
+    assert!(target_root.join("my-skill").join("SKILL.md").exists());
+    assert!(target_root.join("other-skill").join("SKILL.md").exists());
+
+    // references/ should be excluded
+    assert!(
+        !target_root
+            .join("my-skill")
+            .join("references")
+            .join("ref.md")
+            .exists(),
+        "references/ref.md should be excluded"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn link_skills_sets_depth_and_dir_filters_limit_walk() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source_root = temp.path().join("skills");
+    for rel in [
+        "alpha/SKILL.md",
+        "alpha/docs/usage.md",
+        "alpha/docs/deep/nested/notes.md",
+        "alpha/node_modules/pkg/index.js",
+        "beta/SKILL.md",
+    ] {
+        let file = source_root.join(rel);
+        fs::create_dir_all(file.parent().ok_or_else(|| anyhow::anyhow!("no parent"))?)?;
+        fs::write(&file, rel)?;
+    }
+
+    let target_root = temp.path().join("target");
+    let source_str = source_root.display().to_string().replace('\\', "/");
+    let target_str = target_root.display().to_string().replace('\\', "/");
+    let config = format!(
+        r#"[[skills_sets]]
+source_root = "{source_str}"
+target_roots = ["{target_str}"]
+max_depth = 3
+include_dirs = ["alpha"]
+exclude_dirs = ["node_modules"]
+"#
+    );
+    fs::write(temp.path().join("prompt-sync.toml"), config)?;
+
+    let link_code = run(Cli {
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
+        command: Command::Link {
+            only_missing: false,
+            force: false,
+            owned_only: false,
+            dry_run: false,
+            atomic: false,
+            output: OutputArgs::default(),
+            backup_dir: None,
+            mapping: MappingArgs::default(),
+            prune: false,
+        },
+    })?;
+    assert_eq!(link_code, 0);
+
+    assert!(target_root.join("alpha/SKILL.md").exists());
+    assert!(target_root.join("alpha/docs/usage.md").exists());
+    assert!(!target_root.join("alpha/docs/deep").exists());
+    assert!(!target_root.join("alpha/node_modules").exists());
+    assert!(!target_root.join("beta").exists());
+
+    Ok(())
+}
+
+#[test]
+fn promptsyncignore_excludes_files_from_config_dir_and_source_root() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source_root = temp.path().join("skills");
+    for rel in [
+        "alpha/SKILL.md",
+        "alpha/scratch.tmp",
+        "alpha/docs/drop.md",
+        "alpha/docs/keep.md",
+        "beta/SKILL.md",
+    ] {
+        let file = source_root.join(rel);
+        fs::create_dir_all(file.parent().ok_or_else(|| anyhow::anyhow!("no parent"))?)?;
+        fs::write(&file, rel)?;
+    }
+    fs::write(
+        temp.path().join(".promptsyncignore"),
+        "# editor leftovers\n*.tmp\n",
+    )?;
+    fs::write(
+        source_root.join(".promptsyncignore"),
+        "beta/\nalpha/docs/*.md\n!alpha/docs/keep.md\n",
+    )?;
+
+    let target_root = temp.path().join("target");
+    let source_str = source_root.display().to_string().replace('\\', "/");
+    let target_str = target_root.display().to_string().replace('\\', "/");
+    fs::write(
+        temp.path().join("prompt-sync.toml"),
+        format!(
+            r#"[[skills_sets]]
+source_root = "{source_str}"
+target_roots = ["{target_str}"]
+"#
+        ),
+    )?;
+
+    let link_code = run(Cli {
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
+        command: Command::Link {
+            only_missing: false,
+            force: false,
+            owned_only: false,
+            dry_run: false,
+            atomic: false,
+            output: OutputArgs::default(),
+            backup_dir: None,
+            mapping: MappingArgs::default(),
+            prune: false,
+        },
+    })?;
+    assert_eq!(link_code, 0);
+
+    assert!(target_root.join("alpha/SKILL.md").exists());
+    assert!(target_root.join("alpha/docs/keep.md").exists());
+    assert!(!target_root.join("alpha/docs/drop.md").exists());
+    assert!(!target_root.join("alpha/scratch.tmp").exists());
+    assert!(!target_root.join("beta").exists());
+    assert!(!target_root.join(".promptsyncignore").exists());
+
+    Ok(())
+}
+
+#[test]
+fn link_skills_sets_only_skills_filters_dirs() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source_root = temp.path().join("skills");
+
+    // Create three skills
+    for name in &["alpha", "beta", "gamma"] {
+        let dir = source_root.join(name);
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join("SKILL.md"), format!("{name} content"))?;
+    }
+
+    let target_root = temp.path().join("target");
+    let source_str = source_root.display().to_string().replace('\\', "/");
+    let target_str = target_root.display().to_string().replace('\\', "/");
+
+    let config = format!(
+        r#"[[skills_sets]]
+source_root = "{}"
+target_roots = ["{}"]
+only_skills = ["alpha", "gamma"]
+"#,
+        source_str, target_str
+    );
+    fs::write(temp.path().join("prompt-sync.toml"), config)?;
+
+    let link_code = run(Cli {
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
+        command: Command::Link {
+            only_missing: false,
+            force: false,
+            owned_only: false,
+            dry_run: false,
+            atomic: false,
+            output: OutputArgs::default(),
+            backup_dir: None,
+            mapping: MappingArgs::default(),
+            prune: false,
+        },
+    })?;
+    assert_eq!(link_code, 0);
+
+    assert!(target_root.join("alpha").join("SKILL.md").exists());
+    assert!(target_root.join("gamma").join("SKILL.md").exists());
+    assert!(
+        !target_root.join("beta").join("SKILL.md").exists(),
+        "beta should be excluded by only_skills"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn link_skills_sets_exclude_skills_filters_dirs() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source_root = temp.path().join("skills");
+
+    // Create three skills
+    for name in &["alpha", "beta", "gamma"] {
+        let dir = source_root.join(name);
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join("SKILL.md"), format!("{name} content"))?;
+    }
+
+    let target_root = temp.path().join("target");
+    let source_str = source_root.display().to_string().replace('\\', "/");
+    let target_str = target_root.display().to_string().replace('\\', "/");
+
+    let config = format!(
+        r#"[[skills_sets]]
+source_root = "{}"
+target_roots = ["{}"]
+exclude_skills = ["beta"]
+"#,
+        source_str, target_str
+    );
+    fs::write(temp.path().join("prompt-sync.toml"), config)?;
+
+    let link_code = run(Cli {
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
+        command: Command::Link {
+            only_missing: false,
+            force: false,
+            owned_only: false,
+            dry_run: false,
+            atomic: false,
+            output: OutputArgs::default(),
+            backup_dir: None,
+            mapping: MappingArgs::default(),
+            prune: false,
+        },
+    })?;
+    assert_eq!(link_code, 0);
+
+    assert!(target_root.join("alpha").join("SKILL.md").exists());
+    assert!(target_root.join("gamma").join("SKILL.md").exists());
+    assert!(
+        !target_root.join("beta").join("SKILL.md").exists(),
+        "beta should be excluded by exclude_skills"
+    );
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn link_prune_removes_orphaned_skill_targets() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source_root = temp.path().join("skills");
+    for name in &["alpha", "beta", "gamma"] {
+        let dir = source_root.join(name);
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join("SKILL.md"), format!("{name} content"))?;
+    }
+
+    let target_root = temp.path().join("target");
+    let source_str = source_root.display().to_string().replace('\\', "/");
+    let target_str = target_root.display().to_string().replace('\\', "/");
+    let config = format!(
+        r#"[[skills_sets]]
+source_root = "{}"
+target_roots = ["{}"]
 "#,
         source_str, target_str
     );
     fs::write(temp.path().join("prompt-sync.toml"), config)?;
 
+    let link = |prune: bool| {
+        run(Cli {
+            config: vec![temp.path().join("prompt-sync.toml")],
+            verbose: 0,
+            no_user_config: false,
+            state_dir: Some(temp.path().join("state")),
+            backup_min_free: None,
+            wait: false,
+            no_lock: false,
+            command: Command::Link {
+                only_missing: false,
+                force: false,
+                owned_only: false,
+                dry_run: false,
+                atomic: false,
+                output: OutputArgs::default(),
+                backup_dir: None,
+                mapping: MappingArgs::default(),
+                prune,
+            },
+        })
+    };
+    assert_eq!(link(false)?, 0);
+    assert!(temp.path().join("state").join("state.json").exists());
+
+    // beta is deleted from the source; gamma's target is replaced locally.
+    fs::remove_dir_all(source_root.join("beta"))?;
+    fs::remove_dir_all(source_root.join("gamma"))?;
+    let gamma_target = target_root.join("gamma").join("SKILL.md");
+    fs::remove_file(&gamma_target)?;
+    fs::write(&gamma_target, "hand-written gamma")?;
+
+    assert_eq!(link(false)?, 0);
+    assert!(target_root.join("beta").join("SKILL.md").exists());
+
+    assert_eq!(link(true)?, 0);
+    assert!(target_root.join("alpha").join("SKILL.md").exists());
+    assert!(!target_root.join("beta").join("SKILL.md").exists());
+    assert_eq!(fs::read_to_string(&gamma_target)?, "hand-written gamma");
+
+    Ok(())
+}
+
+#[test]
+fn prune_keeps_targets_of_rules_outside_the_selected_profiles() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    fs::write(temp.path().join("master.md"), "master instruction")?;
+    let config_path = temp.path().join("prompt-sync.toml");
+    let rules = [
+        "[[links]]\nsource = \"master.md\"\ntargets = [\"out/claude.md\"]\nprofiles = [\"claude\"]\n",
+        "[[links]]\nsource = \"master.md\"\ntargets = [\"out/gemini.md\"]\nprofiles = [\"gemini\"]\n",
+    ];
+    fs::write(&config_path, rules.join("\n"))?;
+    let prompt_sync = |args: &[&str]| -> anyhow::Result<std::process::Output> {
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .args(["--config", &config_path.to_string_lossy()])
+                .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+                .args(args)
+                .current_dir(temp.path())
+                .output()?,
+        )
+    };
+    let gemini = temp.path().join("out").join("gemini.md");
+
+    let output = prompt_sync(&["link"])?;
+    assert!(output.status.success(), "{output:?}");
+    assert!(gemini.exists());
+
+    for args in [
+        &["link", "--prune", "--profile", "claude"][..],
+        &["sync", "--profile", "claude"],
+        &[
+            "plan",
+            "--prune",
+            "--profile",
+            "claude",
+            "--out",
+            "plan.json",
+        ],
+    ] {
+        let output = prompt_sync(args)?;
+        assert!(output.status.success(), "{args:?}: {output:?}");
+        assert!(gemini.exists(), "{args:?}");
+    }
+    let plan = fs::read_to_string(temp.path().join("plan.json"))?;
+    assert!(!plan.contains("gemini.md"), "{plan}");
+
+    // Once its rule is gone, the same target is an orphan.
+    fs::write(&config_path, rules[0])?;
+    let output = prompt_sync(&["link", "--prune", "--profile", "claude"])?;
+    assert!(output.status.success(), "{output:?}");
+    assert!(!gemini.exists());
+    assert!(temp.path().join("out").join("claude.md").exists());
+
+    Ok(())
+}
+
+#[test]
+fn link_force_owned_only_keeps_unmanaged_targets() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    let target = temp.path().join("out").join("AGENTS.md");
+
+    fs::write(&source, "master instruction")?;
+    fs::create_dir_all(temp.path().join("out"))?;
+    fs::write(&target, "local override")?;
+    write_config(temp.path(), &source, &target)?;
+
+    let link_code = run(Cli {
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
+        command: Command::Link {
+            only_missing: false,
+            force: true,
+            owned_only: true,
+            dry_run: false,
+            atomic: false,
+            output: OutputArgs::default(),
+            backup_dir: None,
+            mapping: MappingArgs::default(),
+            prune: false,
+        },
+    })?;
+    assert_eq!(link_code, 0);
+    assert_eq!(fs::read_to_string(&target)?, "local override");
+
+    Ok(())
+}
+
+#[test]
+fn multiple_configs_merge_with_later_files_winning() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let home = temp.path().join("home");
+    let repo = temp.path().join("repo");
+    fs::create_dir_all(&home)?;
+    fs::create_dir_all(&repo)?;
+    fs::write(home.join("master.md"), "home instruction")?;
+    fs::write(repo.join("master.md"), "repo instruction")?;
+
+    // Relative paths resolve against each config's own directory.
+    let config = r#"[[links]]
+source = "master.md"
+targets = ["out/AGENTS.md", "../shared/AGENTS.md"]
+"#;
+    fs::write(home.join("config.toml"), config)?;
+    fs::write(repo.join("prompt-sync.toml"), config)?;
+
+    let code = run(Cli::try_parse_from([
+        "prompt-sync".as_ref(),
+        "--config".as_ref(),
+        home.join("config.toml").as_os_str(),
+        "--config".as_ref(),
+        repo.join("prompt-sync.toml").as_os_str(),
+        "--state-dir".as_ref(),
+        temp.path().join("state").as_os_str(),
+        "link".as_ref(),
+    ])?)?;
+    assert_eq!(code, 0);
+    assert_eq!(
+        fs::read_to_string(home.join("out").join("AGENTS.md"))?,
+        "home instruction"
+    );
+    assert_eq!(
+        fs::read_to_string(repo.join("out").join("AGENTS.md"))?,
+        "repo instruction"
+    );
+    assert_eq!(
+        fs::read_to_string(temp.path().join("shared").join("AGENTS.md"))?,
+        "repo instruction"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn default_config_layers_user_config_under_repo_config() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let user_dir = temp.path().join("xdg").join("prompt-sync");
+    let repo = temp.path().join("repo");
+    fs::create_dir_all(&user_dir)?;
+    fs::create_dir_all(&repo)?;
+    fs::write(user_dir.join("user.md"), "user instruction")?;
+    fs::write(repo.join("repo.md"), "repo instruction")?;
+    fs::write(
+        user_dir.join("config.toml"),
+        "[[links]]\nsource = \"user.md\"\ntargets = [\"out/USER.md\"]\n",
+    )?;
+    fs::write(
+        repo.join("prompt-sync.toml"),
+        "[[links]]\nsource = \"repo.md\"\ntargets = [\"out/REPO.md\"]\n",
+    )?;
+
+    let prompt_sync = |args: &[&str]| -> anyhow::Result<std::process::Output> {
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+                .args(args)
+                .current_dir(&repo)
+                .env("XDG_CONFIG_HOME", temp.path().join("xdg"))
+                .output()?,
+        )
+    };
+
+    let dump = prompt_sync(&["status", "--dump-config"])?;
+    assert!(dump.status.success());
+    let dump = String::from_utf8(dump.stdout)?;
+    assert_eq!(dump.matches("# layer: ").count(), 2, "{dump}");
+    let user_rule = dump.find("user.md").expect("user rule in dump");
+    let repo_rule = dump.find("repo.md").expect("repo rule in dump");
+    assert!(user_rule < repo_rule, "{dump}");
+
+    assert!(prompt_sync(&["--no-user-config", "link"])?.status.success());
+    assert!(repo.join("out").join("REPO.md").exists());
+    assert!(!user_dir.join("out").join("USER.md").exists());
+
+    assert!(prompt_sync(&["link"])?.status.success());
+    assert_eq!(
+        fs::read_to_string(user_dir.join("out").join("USER.md"))?,
+        "user instruction"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn verify_hash_reports_content_match_for_conflicts() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    fs::write(temp.path().join("master.md"), "master instruction")?;
+    fs::create_dir_all(temp.path().join("out"))?;
+    fs::write(temp.path().join("out/AGENTS.md"), "master instruction")?;
+    fs::write(temp.path().join("out/CLAUDE.md"), "local edit")?;
+    let root = temp.path().display().to_string().replace('\\', "/");
+    fs::write(
+        temp.path().join("prompt-sync.toml"),
+        format!(
+            r#"[[links]]
+source = "{root}/master.md"
+targets = ["{root}/out/AGENTS.md", "{root}/out/CLAUDE.md"]
+"#
+        ),
+    )?;
+
+    let verify = |extra: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+            .args([
+                "--config",
+                &temp.path().join("prompt-sync.toml").to_string_lossy(),
+            ])
+            .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+            .args(["verify", "--json"])
+            .args(extra)
+            .output()
+    };
+
+    let plain: serde_json::Value = serde_json::from_slice(&verify(&[])?.stdout)?;
+    assert!(plain["records"][0].get("content_match").is_none());
+
+    let output = verify(&["--hash"])?;
+    assert_eq!(output.status.code(), Some(1));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    let records = &report["records"];
+    assert_eq!(records[0]["status"], "CONFLICT");
+    assert_eq!(records[0]["content_match"], true);
+    assert_eq!(records[1]["status"], "CONFLICT");
+    assert_eq!(records[1]["content_match"], false);
+
+    Ok(())
+}
+
+#[test]
+fn verify_fix_repairs_inline_and_force_replaces_conflicts() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    fs::write(temp.path().join("master.md"), "master instruction")?;
+    fs::create_dir_all(temp.path().join("out"))?;
+    fs::write(temp.path().join("out/CLAUDE.md"), "local edit")?;
+    let root = temp.path().display().to_string().replace('\\', "/");
+    fs::write(
+        temp.path().join("prompt-sync.toml"),
+        format!(
+            r#"[[links]]
+source = "{root}/master.md"
+targets = ["{root}/out/AGENTS.md", "{root}/out/CLAUDE.md"]
+"#
+        ),
+    )?;
+
+    let verify = |fix: bool, force: bool| {
+        run(Cli {
+            config: vec![temp.path().join("prompt-sync.toml")],
+            verbose: 0,
+            no_user_config: false,
+            state_dir: Some(temp.path().join("state")),
+            backup_min_free: None,
+            wait: false,
+            no_lock: false,
+            command: Command::Verify {
+                hash: false,
+                fix,
+                force,
+                backup_dir: Some(temp.path().join("backup")),
+                baseline: None,
+                update_baseline: false,
+                output: OutputArgs::default(),
+                mapping: MappingArgs::default(),
+            },
+        })
+    };
+
+    // The missing target is created; the conflict is still reported.
+    assert_eq!(verify(true, false)?, 1);
+    assert_eq!(
+        fs::read_to_string(temp.path().join("out/AGENTS.md"))?,
+        "master instruction"
+    );
+    assert_eq!(
+        fs::read_to_string(temp.path().join("out/CLAUDE.md"))?,
+        "local edit"
+    );
+
+    assert_eq!(verify(true, true)?, 0);
+    assert_eq!(
+        fs::read_to_string(temp.path().join("out/CLAUDE.md"))?,
+        "master instruction"
+    );
+    assert_eq!(verify(false, false)?, 0);
+
+    let parsed = Cli::try_parse_from(["prompt-sync", "verify", "--force"]);
+    assert!(parsed.is_err());
+
+    Ok(())
+}
+
+#[test]
+fn json_lines_streams_records_then_summary() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    fs::write(&source, "master instruction")?;
+    let root = temp.path().display().to_string().replace('\\', "/");
+    fs::write(
+        temp.path().join("prompt-sync.toml"),
+        format!(
+            r#"[[links]]
+source = "{root}/master.md"
+targets = ["{root}/out/AGENTS.md", "{root}/out/CLAUDE.md"]
+"#
+        ),
+    )?;
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+        .args([
+            "--config",
+            &temp.path().join("prompt-sync.toml").to_string_lossy(),
+        ])
+        .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+        .args(["link", "--json-lines"])
+        .output()?;
+    assert!(output.status.success(), "{output:?}");
+
+    let lines = String::from_utf8(output.stdout)?
+        .lines()
+        .map(serde_json::from_str::<serde_json::Value>)
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(lines.len(), 3);
+    for record in &lines[..2] {
+        assert_eq!(record["type"], "record");
+        assert_eq!(record["status"], "CREATED");
+    }
+    assert_eq!(lines[2]["type"], "summary");
+    assert_eq!(lines[2]["command"], "link");
+    assert_eq!(lines[2]["summary"]["created"], 2);
+
+    Ok(())
+}
+
+#[test]
+fn output_table_and_yaml_render_verify_records() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    let target = temp.path().join("out").join("AGENTS.md");
+    fs::write(&source, "master instruction")?;
+    write_config(temp.path(), &source, &target)?;
+
+    let verify = |format: &str| -> anyhow::Result<String> {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+            .args([
+                "--config",
+                &temp.path().join("prompt-sync.toml").to_string_lossy(),
+            ])
+            .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+            .args(["verify", "--output", format])
+            .output()?;
+        assert_eq!(output.status.code(), Some(1));
+        Ok(String::from_utf8(output.stdout)?)
+    };
+
+    let table = verify("table")?;
+    let mut lines = table.lines();
+    let header = lines.next().unwrap_or_default();
+    let row = lines.next().unwrap_or_default();
+    assert!(
+        header.starts_with("STATUS   KIND         SOURCE"),
+        "{table}"
+    );
+    assert!(row.starts_with("MISSING  config_file  "), "{table}");
+    assert_eq!(
+        header.find("TARGET"),
+        row.find(&*target.display().to_string())
+    );
+
+    let yaml = verify("yaml")?;
+    assert!(
+        yaml.starts_with("schema_version: 1\ncommand: verify\n"),
+        "{yaml}"
+    );
+    assert!(yaml.contains("    status: MISSING\n"), "{yaml}");
+
+    Ok(())
+}
+
+#[test]
+fn color_and_compact_output_mark_each_status() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    let target = temp.path().join("out").join("AGENTS.md");
+    fs::write(&source, "master instruction")?;
+    write_config(temp.path(), &source, &target)?;
+
+    let verify = |args: &[&str]| -> anyhow::Result<String> {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+            .args([
+                "--config",
+                &temp.path().join("prompt-sync.toml").to_string_lossy(),
+            ])
+            .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+            .arg("verify")
+            .args(args)
+            .env("NO_COLOR", "1")
+            .output()?;
+        Ok(String::from_utf8(output.stdout)?)
+    };
+
+    assert!(verify(&["--color", "always"])?.contains("\x1b[33m[Missing]\x1b[0m"));
+    assert!(!verify(&["--color", "auto"])?.contains('\x1b'));
+
+    let compact = verify(&["--output", "compact", "--color", "never"])?;
+    assert_eq!(compact.lines().next(), Some("?"), "{compact}");
+
+    Ok(())
+}
+
+#[test]
+fn quiet_and_summary_only_trim_report_output() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    let target = temp.path().join("out").join("AGENTS.md");
+    fs::write(&source, "master instruction")?;
+    write_config(temp.path(), &source, &target)?;
+
+    let prompt_sync = |args: &[&str]| -> anyhow::Result<std::process::Output> {
+        Ok(
+            std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+                .args([
+                    "--config",
+                    &temp.path().join("prompt-sync.toml").to_string_lossy(),
+                ])
+                .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+                .args(args)
+                .output()?,
+        )
+    };
+
+    let quiet = prompt_sync(&["verify", "--quiet"])?;
+    assert_eq!(quiet.status.code(), Some(1));
+    assert!(quiet.stdout.is_empty());
+
+    let summary = prompt_sync(&["link", "--summary-only"])?;
+    assert!(summary.status.success());
+    let stdout = String::from_utf8(summary.stdout)?;
+    assert_eq!(stdout.lines().count(), 1, "{stdout}");
+    assert!(stdout.starts_with("ok=0 missing=0 broken=0 conflict=0 created=1 "));
+
+    assert!(
+        !prompt_sync(&["verify", "--quiet", "--json"])?
+            .status
+            .success()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn status_since_only_reports_recently_linked_targets() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    let target = temp.path().join("out").join("AGENTS.md");
+
+    fs::write(&source, "master instruction")?;
+    write_config(temp.path(), &source, &target)?;
+
+    let status = |since: &str| -> anyhow::Result<i32> {
+        run(Cli::try_parse_from([
+            "prompt-sync",
+            "--config",
+            &temp.path().join("prompt-sync.toml").to_string_lossy(),
+            "--state-dir",
+            &temp.path().join("state").to_string_lossy(),
+            "status",
+            "--since",
+            since,
+        ])?)
+    };
+
+    // Nothing has been linked yet, so the missing target is filtered out.
+    assert_eq!(status("1h")?, 0);
+
+    let verify_code = run(Cli {
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
+        command: Command::Verify {
+            hash: false,
+            fix: false,
+            force: false,
+            backup_dir: None,
+            baseline: None,
+            update_baseline: false,
+            output: OutputArgs::default(),
+            mapping: MappingArgs::default(),
+        },
+    })?;
+    assert_eq!(verify_code, 1);
+
+    let link_code = run(Cli {
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
+        command: Command::Link {
+            only_missing: false,
+            force: false,
+            owned_only: false,
+            dry_run: false,
+            atomic: false,
+            output: OutputArgs::default(),
+            backup_dir: None,
+            mapping: MappingArgs::default(),
+            prune: false,
+        },
+    })?;
+    assert_eq!(link_code, 0);
+    fs::remove_file(&target)?;
+    assert_eq!(status("1h")?, 1);
+
+    Ok(())
+}
+
+#[test]
+fn link_glob_source_fans_out_into_target_dirs() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let prompts = temp.path().join("prompts");
+    fs::create_dir_all(&prompts)?;
+    fs::write(prompts.join("review.md"), "review prompt")?;
+    fs::write(prompts.join("refactor.md"), "refactor prompt")?;
+    fs::write(prompts.join("notes.txt"), "not a prompt")?;
+
+    let root = temp.path().display().to_string().replace('\\', "/");
+    let config = format!(
+        r#"[[links]]
+source = "{root}/prompts/*.md"
+targets = ["{root}/claude", "{root}/gemini"]
+"#
+    );
+    fs::write(temp.path().join("prompt-sync.toml"), config)?;
+
     let link_code = run(Cli {
-        config: temp.path().join("prompt-sync.toml"),
-        verbose: false,
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
         command: Command::Link {
             only_missing: false,
             force: false,
+            owned_only: false,
             dry_run: false,
-            json: false,
+            atomic: false,
+            output: OutputArgs::default(),
             backup_dir: None,
+            mapping: MappingArgs::default(),
+            prune: false,
         },
     })?;
     assert_eq!(link_code, 0);
 
-    assert!(target_root.join("alpha").join("SKILL.md").exists());
-    assert!(target_root.join("gamma").join("SKILL.md").exists());
+    for dir in ["claude", "gemini"] {
+        let target_dir = temp.path().join(dir);
+        assert_eq!(
+            fs::read_to_string(target_dir.join("review.md"))?,
+            "review prompt"
+        );
+        assert_eq!(
+            fs::read_to_string(target_dir.join("refactor.md"))?,
+            "refactor prompt"
+        );
+        assert!(!target_dir.join("notes.txt").exists());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn link_renamed_target_lands_under_given_name() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    fs::write(&source, "master instruction")?;
+
+    let root = temp.path().display().to_string().replace('\\', "/");
+    let config = format!(
+        r#"[[links]]
+source = "{root}/master.md"
+targets = [
+  "{root}/claude/CLAUDE.md",
+  {{ path = "{root}/.github", name = "copilot-instructions.md" }},
+]
+"#
+    );
+    fs::write(temp.path().join("prompt-sync.toml"), config)?;
+
+    let link_code = run(Cli {
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
+        command: Command::Link {
+            only_missing: false,
+            force: false,
+            owned_only: false,
+            dry_run: false,
+            atomic: false,
+            output: OutputArgs::default(),
+            backup_dir: None,
+            mapping: MappingArgs::default(),
+            prune: false,
+        },
+    })?;
+    assert_eq!(link_code, 0);
+    assert!(temp.path().join("claude").join("CLAUDE.md").exists());
+    assert_eq!(
+        fs::read_to_string(temp.path().join(".github").join("copilot-instructions.md"))?,
+        "master instruction"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn init_template_keeps_plain_string_targets() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let config_path = temp.path().join("prompt-sync.toml");
+
+    let code = run(Cli {
+        config: vec![config_path.clone()],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
+        command: Command::Init {
+            force: false,
+            profiles: vec!["claude".parse::<Profile>()?],
+            format: None,
+            from_existing: false,
+        },
+    })?;
+    assert_eq!(code, 0);
+    let text = fs::read_to_string(&config_path)?;
     assert!(
-        !target_root.join("beta").join("SKILL.md").exists(),
-        "beta should be excluded by only_skills"
+        text.contains(r#"targets = ["~/.claude/CLAUDE.md"]"#),
+        "{text}"
     );
 
     Ok(())
 }
 
 #[test]
-fn link_skills_sets_exclude_skills_filters_dirs() -> anyhow::Result<()> {
+fn init_from_existing_proposes_newest_file_as_master() -> anyhow::Result<()> {
     let temp = TempDir::new()?;
-    let source_root = temp.path().join("skills");
+    let home = temp.path().join("home");
+    let repo = temp.path().join("repo");
+    fs::create_dir_all(home.join(".claude").join("skills"))?;
+    fs::create_dir_all(home.join(".agents").join("skills"))?;
+    fs::create_dir_all(home.join(".codex"))?;
+    fs::create_dir_all(&repo)?;
+    fs::write(home.join(".codex").join("AGENTS.md"), "old codex notes")?;
+    fs::write(repo.join("CLAUDE.md"), "current notes")?;
+    let old = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+    fs::File::options()
+        .write(true)
+        .open(home.join(".codex").join("AGENTS.md"))?
+        .set_modified(old)?;
 
-    // Create three skills
-    for name in &["alpha", "beta", "gamma"] {
-        let dir = source_root.join(name);
-        fs::create_dir_all(&dir)?;
-        fs::write(dir.join("SKILL.md"), format!("{name} content"))?;
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+        .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+        .args(["init", "--from-existing"])
+        .current_dir(&repo)
+        .env("HOME", &home)
+        .env_remove("XDG_CONFIG_HOME")
+        .output()?;
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("differs from the master"), "{stdout}");
+
+    let text = fs::read_to_string(repo.join("prompt-sync.toml"))?;
+    assert!(text.contains(r#"source = "<repo>/CLAUDE.md""#), "{text}");
+    assert!(
+        text.contains(r#"targets = ["~/.codex/AGENTS.md"]"#),
+        "{text}"
+    );
+    assert!(
+        text.contains(r#"source_root = "~/.agents/skills""#),
+        "{text}"
+    );
+    assert!(
+        text.contains(r#"target_roots = ["~/.claude/skills"]"#),
+        "{text}"
+    );
+    assert!(!text.contains("gemini"), "{text}");
+
+    Ok(())
+}
+
+#[test]
+fn yaml_config_links_and_init_round_trips() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    fs::write(&source, "master instruction")?;
+
+    let root = temp.path().display().to_string().replace('\\', "/");
+    let config_path = temp.path().join("prompt-sync.yaml");
+    fs::write(
+        &config_path,
+        format!(
+            r#"# shared instructions
+links:
+  - source: "{root}/master.md"
+    mode: copy
+    targets:
+      - {root}/out/AGENTS.md # plain scalar
+      - {{ path: "{root}/out", name: CLAUDE.md }}
+      - {root}/out/it's.md   # Bob's app
+  - source: '{root}/master.md'
+    targets: ["{root}/out/{escaped}.md", '{root}/out/d''s.md', {root}/out/c.md]  # note
+"#,
+            // `"` cannot be part of a file name on Windows.
+            escaped = if cfg!(windows) { r"a\\b" } else { r#"a\"b"# },
+        ),
+    )?;
+    let cli = |command| Cli {
+        config: vec![config_path.clone()],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
+        command,
+    };
+
+    let code = run(cli(Command::Link {
+        only_missing: false,
+        force: false,
+        owned_only: false,
+        dry_run: false,
+        atomic: false,
+        output: OutputArgs::default(),
+        backup_dir: None,
+        mapping: MappingArgs::default(),
+        prune: false,
+    }))?;
+    assert_eq!(code, 0);
+    assert_eq!(
+        fs::read_to_string(temp.path().join("out").join("CLAUDE.md"))?,
+        "master instruction"
+    );
+    assert_eq!(
+        fs::read_to_string(temp.path().join("out").join("AGENTS.md"))?,
+        "master instruction"
+    );
+    // A quote inside a plain scalar does not hide the comment after it, and
+    // an escaped quote does not end a quoted one.
+    let out = temp.path().join("out");
+    assert!(out.join("it's.md").exists());
+    assert!(out.join("c.md").exists());
+    assert!(out.join("d's.md").exists());
+    if cfg!(unix) {
+        assert!(out.join("a\"b.md").exists());
     }
 
-    let target_root = temp.path().join("target");
-    let source_str = source_root.display().to_string().replace('\\', "/");
-    let target_str = target_root.display().to_string().replace('\\', "/");
+    let toml_path = temp.path().join("init").join("prompt-sync.toml");
+    let code = run(Cli {
+        config: vec![toml_path.clone()],
+        ..cli(Command::Init {
+            force: false,
+            profiles: vec!["claude".parse::<Profile>()?],
+            format: Some(ConfigFormat::Yaml),
+            from_existing: false,
+        })
+    })?;
+    assert_eq!(code, 0);
+    assert!(!toml_path.exists());
+    let yaml_path = toml_path.with_extension("yaml");
+    let code = run(Cli {
+        config: vec![yaml_path],
+        ..cli(Command::List {
+            json: false,
+            mapping: MappingArgs::default(),
+        })
+    })?;
+    assert_eq!(code, 0);
+
+    Ok(())
+}
+
+#[test]
+fn config_migrate_upgrades_unversioned_config_with_backup() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let config_path = temp.path().join("prompt-sync.toml");
+    let original = r#"[[links]]
+source = "~/master.md"
+targets = ["~/AGENTS.md"]
+mode = "copy"
+"#;
+    fs::write(&config_path, original)?;
+    let migrate = || {
+        run(Cli {
+            config: vec![config_path.clone()],
+            verbose: 0,
+            no_user_config: false,
+            state_dir: Some(temp.path().join("state")),
+            backup_min_free: None,
+            wait: false,
+            no_lock: false,
+            command: Command::Config {
+                action: ConfigCommand::Migrate { dry_run: false },
+            },
+        })
+    };
+
+    assert_eq!(migrate()?, 0);
+    let migrated = fs::read_to_string(&config_path)?;
+    assert!(migrated.starts_with("version = 1\n"), "{migrated}");
+    assert!(migrated.contains(r#"link_type = "copy""#), "{migrated}");
+    assert!(!migrated.contains("mode"), "{migrated}");
+    assert_eq!(
+        fs::read_to_string(temp.path().join("prompt-sync.toml.v0.bak"))?,
+        original
+    );
+
+    assert_eq!(migrate()?, 0);
+    assert_eq!(fs::read_to_string(&config_path)?, migrated);
+
+    fs::write(&config_path, "version = 99\n")?;
+    assert!(migrate().is_err());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn on_cross_device_keeps_hardlinks_on_one_filesystem() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    fs::write(&source, "master instruction")?;
+    let root = temp.path().display().to_string().replace('\\', "/");
+    let write = |policy: &str| {
+        fs::write(
+            temp.path().join("prompt-sync.toml"),
+            format!(
+                r#"on_cross_device = "{policy}"
+
+[[links]]
+source = "{root}/master.md"
+targets = ["{root}/out/AGENTS.md"]
+on_cross_device = "symlink"
+"#
+            ),
+        )
+    };
+    let link = || {
+        run(Cli {
+            config: vec![temp.path().join("prompt-sync.toml")],
+            verbose: 0,
+            no_user_config: false,
+            state_dir: Some(temp.path().join("state")),
+            backup_min_free: None,
+            wait: false,
+            no_lock: false,
+            command: Command::Link {
+                only_missing: false,
+                force: false,
+                owned_only: false,
+                dry_run: false,
+                atomic: false,
+                output: OutputArgs::default(),
+                backup_dir: None,
+                mapping: MappingArgs::default(),
+                prune: false,
+            },
+        })
+    };
+
+    write("bogus")?;
+    assert!(link().is_err());
+
+    // The fallback only applies across devices; here the hardlink stays.
+    write("copy")?;
+    assert_eq!(link()?, 0);
+    let target = temp.path().join("out").join("AGENTS.md");
+    assert!(!fs::symlink_metadata(&target)?.file_type().is_symlink());
+    assert_eq!(fs::metadata(&target)?.ino(), fs::metadata(&source)?.ino());
+
+    Ok(())
+}
+
+#[test]
+fn reflink_targets_are_independent_copies() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    let target = temp.path().join("out").join("AGENTS.md");
+    fs::write(&source, "master instruction")?;
+    let root = temp.path().display().to_string().replace('\\', "/");
+    fs::write(
+        temp.path().join("prompt-sync.toml"),
+        format!(
+            r#"[[links]]
+source = "{root}/master.md"
+targets = ["{root}/out/AGENTS.md"]
+link_type = "reflink"
+"#
+        ),
+    )?;
+
+    let run_command = |command: &str| {
+        run(Cli {
+            config: vec![temp.path().join("prompt-sync.toml")],
+            verbose: 0,
+            no_user_config: false,
+            state_dir: Some(temp.path().join("state")),
+            backup_min_free: None,
+            wait: false,
+            no_lock: false,
+            command: match command {
+                "link" => Command::Link {
+                    only_missing: false,
+                    force: false,
+                    owned_only: false,
+                    dry_run: false,
+                    atomic: false,
+                    output: OutputArgs::default(),
+                    backup_dir: None,
+                    mapping: MappingArgs::default(),
+                    prune: false,
+                },
+                _ => Command::Verify {
+                    hash: false,
+                    fix: false,
+                    force: false,
+                    backup_dir: None,
+                    baseline: None,
+                    update_baseline: false,
+                    output: OutputArgs::default(),
+                    mapping: MappingArgs::default(),
+                },
+            },
+        })
+    };
+    assert_eq!(run_command("link")?, 0);
+    assert_eq!(fs::read_to_string(&target)?, "master instruction");
+    #[cfg(unix)]
+    assert_ne!(fs::metadata(&source)?.ino(), fs::metadata(&target)?.ino());
+    assert_eq!(run_command("verify")?, 0);
+
+    fs::write(&target, "rewritten by vendor tool")?;
+    assert_eq!(fs::read_to_string(&source)?, "master instruction");
+    assert_eq!(run_command("verify")?, 1);
+
+    Ok(())
+}
+
+#[test]
+fn reflink_fallback_warns_once_per_run() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let skills = temp.path().join("skills").join("review");
+    fs::create_dir_all(&skills)?;
+    for name in ["SKILL.md", "checklist.md", "examples.md"] {
+        fs::write(skills.join(name), name)?;
+    }
+    let config_path = temp.path().join("prompt-sync.toml");
+    fs::write(
+        &config_path,
+        "[[skills_sets]]\nsource_root = \"skills\"\ntarget_roots = [\"a\", \"b\"]\nlink_type = \"reflink\"\n",
+    )?;
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+        .args(["--config", &config_path.to_string_lossy()])
+        .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+        .arg("link")
+        .current_dir(temp.path())
+        .output()?;
+    assert!(output.status.success(), "{output:?}");
+    // None where the filesystem clones, one line where it cannot.
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.matches("cannot reflink").count() <= 1, "{stderr}");
+    assert_eq!(
+        fs::read_to_string(temp.path().join("b").join("review").join("examples.md"))?,
+        "examples.md"
+    );
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn auto_link_type_resolves_to_hardlink_off_windows() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    fs::write(&source, "master instruction")?;
+    let root = temp.path().display().to_string().replace('\\', "/");
+    fs::write(
+        temp.path().join("prompt-sync.toml"),
+        format!(
+            r#"[[links]]
+source = "{root}/master.md"
+targets = ["{root}/out/AGENTS.md"]
+link_type = "auto"
+"#
+        ),
+    )?;
+
+    let run_json = |command: &str| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_prompt-sync"))
+            .args([
+                "--config",
+                &temp.path().join("prompt-sync.toml").to_string_lossy(),
+            ])
+            .args(["--state-dir", &temp.path().join("state").to_string_lossy()])
+            .args([command, "--json"])
+            .output()
+    };
+    assert!(run_json("link")?.status.success());
+    let target = temp.path().join("out").join("AGENTS.md");
+    assert_eq!(fs::metadata(&target)?.ino(), fs::metadata(&source)?.ino());
+
+    let output = run_json("verify")?;
+    assert!(output.status.success(), "{output:?}");
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(report["records"][0]["link_type"], "hardlink");
+
+    Ok(())
+}
+
+#[test]
+fn link_honours_only_on_and_skip_on() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    fs::write(&source, "master instruction")?;
 
+    let root = temp.path().display().to_string().replace('\\', "/");
+    let os = std::env::consts::OS;
     let config = format!(
-        r#"[[skills_sets]]
-source_root = "{}"
-target_roots = ["{}"]
-exclude_skills = ["beta"]
-"#,
-        source_str, target_str
+        r#"[[links]]
+source = "{root}/master.md"
+targets = ["{root}/here/AGENTS.md"]
+only_on = ["{os}"]
+
+[[links]]
+source = "{root}/master.md"
+targets = ["{root}/skipped/AGENTS.md"]
+skip_on = ["{os}"]
+
+[[links]]
+source = "{root}/master.md"
+targets = ["{root}/elsewhere/AGENTS.md"]
+only_on = ["plan9"]
+"#
     );
     fs::write(temp.path().join("prompt-sync.toml"), config)?;
 
     let link_code = run(Cli {
-        config: temp.path().join("prompt-sync.toml"),
-        verbose: false,
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 1,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
         command: Command::Link {
             only_missing: false,
             force: false,
+            owned_only: false,
             dry_run: false,
-            json: false,
+            atomic: false,
+            output: OutputArgs::default(),
             backup_dir: None,
+            mapping: MappingArgs::default(),
+            prune: false,
         },
     })?;
     assert_eq!(link_code, 0);
+    assert!(temp.path().join("here").join("AGENTS.md").exists());
+    assert!(!temp.path().join("skipped").exists());
+    assert!(!temp.path().join("elsewhere").exists());
 
-    assert!(target_root.join("alpha").join("SKILL.md").exists());
-    assert!(target_root.join("gamma").join("SKILL.md").exists());
-    assert!(
-        !target_root.join("beta").join("SKILL.md").exists(),
-        "beta should be excluded by exclude_skills"
+    Ok(())
+}
+
+#[test]
+fn verify_profile_filter_limits_rules() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    fs::write(&source, "master instruction")?;
+
+    let root = temp.path().display().to_string().replace('\\', "/");
+    let config = format!(
+        r#"[[links]]
+source = "{root}/master.md"
+targets = ["{root}/claude/CLAUDE.md"]
+profiles = ["claude"]
+
+[[links]]
+source = "{root}/master.md"
+targets = ["{root}/gemini/GEMINI.md"]
+profiles = ["gemini"]
+"#
+    );
+    fs::write(temp.path().join("prompt-sync.toml"), config)?;
+
+    let claude_only = MappingArgs {
+        profiles: vec!["claude".parse::<Profile>()?],
+        ..MappingArgs::default()
+    };
+    let link_code = run(Cli {
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
+        command: Command::Link {
+            only_missing: false,
+            force: false,
+            owned_only: false,
+            dry_run: false,
+            atomic: false,
+            output: OutputArgs::default(),
+            backup_dir: None,
+            mapping: claude_only.clone(),
+            prune: false,
+        },
+    })?;
+    assert_eq!(link_code, 0);
+    assert!(temp.path().join("claude").join("CLAUDE.md").exists());
+    assert!(!temp.path().join("gemini").exists());
+
+    let verify = |mapping: MappingArgs| {
+        run(Cli {
+            config: vec![temp.path().join("prompt-sync.toml")],
+            verbose: 0,
+            no_user_config: false,
+            state_dir: Some(temp.path().join("state")),
+            backup_min_free: None,
+            wait: false,
+            no_lock: false,
+            command: Command::Verify {
+                hash: false,
+                fix: false,
+                force: false,
+                backup_dir: None,
+                baseline: None,
+                update_baseline: false,
+                output: OutputArgs::default(),
+                mapping,
+            },
+        })
+    };
+    assert_eq!(verify(claude_only)?, 0);
+    assert_eq!(verify(MappingArgs::default())?, 1);
+
+    Ok(())
+}
+
+#[test]
+fn kind_target_and_source_filters_narrow_mappings() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    fs::write(temp.path().join("master.md"), "master instruction")?;
+    let skill_dir = temp.path().join("skills").join("alpha");
+    fs::create_dir_all(&skill_dir)?;
+    fs::write(skill_dir.join("SKILL.md"), "alpha content")?;
+
+    let root = temp.path().display().to_string().replace('\\', "/");
+    let config = format!(
+        r#"[[links]]
+source = "{root}/master.md"
+targets = ["{root}/claude/CLAUDE.md", "{root}/gemini/GEMINI.md"]
+
+[[skills_sets]]
+source_root = "{root}/skills"
+target_roots = ["{root}/claude/skills"]
+"#
+    );
+    fs::write(temp.path().join("prompt-sync.toml"), config)?;
+
+    let link = |mapping: MappingArgs, prune: bool| {
+        run(Cli {
+            config: vec![temp.path().join("prompt-sync.toml")],
+            verbose: 0,
+            no_user_config: false,
+            state_dir: Some(temp.path().join("state")),
+            backup_min_free: None,
+            wait: false,
+            no_lock: false,
+            command: Command::Link {
+                only_missing: false,
+                force: false,
+                owned_only: false,
+                dry_run: false,
+                atomic: false,
+                output: OutputArgs::default(),
+                backup_dir: None,
+                mapping,
+                prune,
+            },
+        })
+    };
+    let verify = |mapping: MappingArgs| {
+        run(Cli {
+            config: vec![temp.path().join("prompt-sync.toml")],
+            verbose: 0,
+            no_user_config: false,
+            state_dir: Some(temp.path().join("state")),
+            backup_min_free: None,
+            wait: false,
+            no_lock: false,
+            command: Command::Verify {
+                hash: false,
+                fix: false,
+                force: false,
+                backup_dir: None,
+                baseline: None,
+                update_baseline: false,
+                output: OutputArgs::default(),
+                mapping,
+            },
+        })
+    };
+
+    let claude_only = MappingArgs {
+        target_contains: Some("/claude/".to_owned()),
+        ..MappingArgs::default()
+    };
+    assert_eq!(link(claude_only.clone(), false)?, 0);
+    let skill_target = temp.path().join("claude/skills/alpha/SKILL.md");
+    assert!(temp.path().join("claude/CLAUDE.md").exists());
+    assert!(skill_target.exists());
+    assert!(!temp.path().join("gemini").exists());
+
+    let skills_only = MappingArgs {
+        kind: Some(KindFilter::Skill),
+        ..MappingArgs::default()
+    };
+    let from_skills = MappingArgs {
+        source: Some(temp.path().join("skills")),
+        ..MappingArgs::default()
+    };
+    assert_eq!(verify(claude_only)?, 0);
+    assert_eq!(verify(skills_only)?, 0);
+    assert_eq!(verify(from_skills)?, 0);
+    assert_eq!(verify(MappingArgs::default())?, 1);
+
+    // Skill targets are filtered out, so --prune must not treat them as orphans.
+    let configs_only = MappingArgs {
+        kind: Some(KindFilter::Config),
+        ..MappingArgs::default()
+    };
+    assert_eq!(link(configs_only, true)?, 0);
+    assert!(temp.path().join("gemini/GEMINI.md").exists());
+    assert!(skill_target.exists());
+    assert_eq!(verify(MappingArgs::default())?, 0);
+
+    Ok(())
+}
+
+#[test]
+fn config_check_reports_errors_without_touching_files() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let source = temp.path().join("master.md");
+    let target = temp.path().join("out").join("AGENTS.md");
+    fs::write(&source, "master instruction")?;
+
+    write_config(temp.path(), &source, &target)?;
+    let check = || {
+        run(Cli {
+            config: vec![temp.path().join("prompt-sync.toml")],
+            verbose: 0,
+            no_user_config: false,
+            state_dir: Some(temp.path().join("state")),
+            backup_min_free: None,
+            wait: false,
+            no_lock: false,
+            command: Command::Config {
+                action: ConfigCommand::Check { json: false },
+            },
+        })
+    };
+    assert_eq!(check()?, 0);
+
+    let root = temp.path().display().to_string().replace('\\', "/");
+    let config = format!(
+        r#"[[links]]
+source = "{root}/master.md"
+targets = ["{root}/out/AGENTS.md", "{root}/.git/AGENTS.md", "<unknown>/AGENTS.md"]
+
+[[links]]
+source = "{root}/missing.md"
+targets = ["{root}/out/AGENTS.md"]
+"#
+    );
+    fs::write(temp.path().join("prompt-sync.toml"), config)?;
+    assert_eq!(check()?, 1);
+    assert!(!temp.path().join("out").exists());
+    assert!(!temp.path().join(".git").exists());
+
+    Ok(())
+}
+
+#[test]
+fn config_schema_lists_rule_fields_and_link_types() -> anyhow::Result<()> {
+    let temp = TempDir::new()?;
+    let output = temp.path().join("schema.json");
+
+    let code = run(Cli {
+        config: vec![temp.path().join("prompt-sync.toml")],
+        verbose: 0,
+        no_user_config: false,
+        state_dir: Some(temp.path().join("state")),
+        backup_min_free: None,
+        wait: false,
+        no_lock: false,
+        command: Command::Config {
+            action: ConfigCommand::Schema {
+                output: Some(output.clone()),
+            },
+        },
+    })?;
+    assert_eq!(code, 0);
+
+    let schema: serde_json::Value = serde_json::from_str(&fs::read_to_string(&output)?)?;
+    let rule = &schema["$defs"]["link_rule"]["properties"];
+    for field in [
+        "source",
+        "targets",
+        "link_type",
+        "only_on",
+        "skip_on",
+        "profiles",
+    ] {
+        assert!(rule.get(field).is_some(), "missing links field: {field}");
+    }
+    assert_eq!(
+        rule["link_type"]["enum"],
+        serde_json::json!(["hardlink", "symlink", "copy", "auto", "reflink", "generate"])
     );
+    assert!(schema["$defs"]["skills_set"]["properties"]["target_roots"].is_object());
 
     Ok(())
 }